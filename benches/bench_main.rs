@@ -5,4 +5,6 @@ mod benchmarks;
 criterion_main! {
     benchmarks::rsc_pathological_regex::benches,
     benchmarks::burntsushi_torture_regex::benches,
+    benchmarks::buffer_search_regex::benches,
+    benchmarks::text_storage::benches,
 }
@@ -0,0 +1,75 @@
+// Searching through a large buffer for relatively rare matches, which is the common case when
+// running something like "x/foo/" or a "g/foo/" guard over an entire file.
+//
+// Regexes with a literal prefix or a limited set of starting characters are able to skip
+// input that can't begin a match without running the VM. Where possible, the lazy DFA is used
+// to locate the region of the buffer containing each match so that the VM only needs to run
+// over that region. The "dfa vs vm" group compares "x//" over a large buffer against the same
+// search run entirely on the VM, as it was before the DFA was used to find matches.
+use ad_editor::{regex::Regex, GapBuffer, Program};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::io::sink;
+
+const LINE: &str = "the quick brown fox jumps over the lazy dog and keeps on running\n";
+
+fn haystack(n_lines: usize) -> String {
+    let mut s = LINE.repeat(n_lines);
+    s.push_str("fn search_target(n: usize) -> Option<usize> { Some(42) }\n");
+
+    s
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let s = haystack(10_000);
+    let mut gb = GapBuffer::from(s.as_str());
+    let mut group = c.benchmark_group("buffer x search");
+
+    for (name, re) in [
+        ("literal prefix", "search_target"),
+        ("class prefix", "[0-9]+"),
+        ("alternation prefix", "(Option|Result)<"),
+        ("no prefix", "@search_target"),
+    ] {
+        let mut prog = Program::try_parse(&format!(", x/{re}/ p/$0/")).unwrap();
+        group.bench_function(name, |b| {
+            b.iter(|| {
+                prog.execute(black_box(&mut gb), "bench", &mut sink())
+                    .unwrap()
+            })
+        });
+    }
+
+    group.finish();
+
+    let big = haystack(100_000);
+    let mut gb = GapBuffer::from(big.as_str());
+    let re = "[a-z]+_[a-z]+\\(";
+    let mut prog = Program::try_parse(&format!(", x/{re}/ p/$0/")).unwrap();
+    let mut vm = Regex::compile(re).unwrap().without_dfa();
+    let mut group = c.benchmark_group("x search dfa vs vm");
+
+    group.bench_function("dfa", |b| {
+        b.iter(|| {
+            prog.execute(black_box(&mut gb), "bench", &mut sink())
+                .unwrap()
+        })
+    });
+    group.bench_function("vm", |b| {
+        b.iter(|| assert_eq!(vm.match_str_all(black_box(&big)).count(), 1))
+    });
+
+    group.finish();
+
+    let mut group = c.benchmark_group("is match");
+    let mut r = Regex::compile("(abc|def)*search_target").unwrap();
+
+    group.bench_function("dfa", |b| b.iter(|| assert!(r.matches_str(black_box(&s)))));
+    group.bench_function("vm", |b| {
+        b.iter(|| assert!(r.match_str(black_box(&s)).is_some()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
@@ -1,2 +1,4 @@
+pub mod buffer_search_regex;
 pub mod burntsushi_torture_regex;
 pub mod rsc_pathological_regex;
+pub mod text_storage;
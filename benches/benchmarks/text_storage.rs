@@ -0,0 +1,112 @@
+// Comparing the two text storage backends on a large log file.
+//
+// The GapBuffer needs to read the whole file into a single allocation up front and copies
+// everything between the old and new positions of the gap each time an edit is made far from
+// the previous one. The PieceTable reads the file as a series of separately allocated blocks
+// and only records where edits are made, at the cost of needing to locate the piece containing
+// a given position on each lookup.
+//
+// Each iteration of the insert benchmarks edits a fresh clone of the document so that the
+// edits made by previous iterations do not accumulate.
+//
+// The size of the generated log (in MB) can be set using the AD_BENCH_LOG_MB environment
+// variable.
+use ad_editor::{GapBuffer, PieceTable};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use std::{env, fs, path::PathBuf, process};
+
+const LINE: &str =
+    "2024-01-01T12:00:00.000Z INFO  [worker-3] request completed status=200 duration_ms=42\n";
+const N_OFFSETS: usize = 100;
+
+fn log_file(mb: usize) -> PathBuf {
+    let path = env::temp_dir().join(format!("ad-bench-{}-{mb}MB.log", process::id()));
+    let s = LINE.repeat(mb * 1024 * 1024 / LINE.len());
+    fs::write(&path, s).unwrap();
+
+    path
+}
+
+// Spread offsets across the whole document in a fixed order that jumps back and forth
+// rather than moving steadily through the file.
+fn scattered(n: usize) -> Vec<usize> {
+    let mut x: u64 = 0x2545_f491_4f6c_dd1d;
+
+    (0..N_OFFSETS)
+        .map(|_| {
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            (x % n as u64) as usize
+        })
+        .collect()
+}
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let mb = env::var("AD_BENCH_LOG_MB")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(256);
+    let path = log_file(mb);
+
+    let mut group = c.benchmark_group(format!("{mb}MB log"));
+    group.sample_size(10);
+
+    group.bench_function("gap buffer open", |b| {
+        b.iter(|| GapBuffer::from(fs::read_to_string(black_box(&path)).unwrap()))
+    });
+    group.bench_function("piece table open", |b| {
+        b.iter(|| PieceTable::open(black_box(&path)).unwrap().unwrap())
+    });
+
+    let gb = GapBuffer::from(fs::read_to_string(&path).unwrap());
+    let pt = PieceTable::open(&path).unwrap().unwrap();
+    let chars = scattered(gb.len_chars());
+    let lines = scattered(gb.len_lines());
+
+    group.bench_function("gap buffer scattered inserts", |b| {
+        b.iter_batched(
+            || gb.clone(),
+            |mut gb| {
+                for &idx in chars.iter() {
+                    gb.insert_str(black_box(idx), "inserted\n");
+                }
+                gb
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("piece table scattered inserts", |b| {
+        b.iter_batched(
+            || pt.clone(),
+            |mut pt| {
+                for &idx in chars.iter() {
+                    pt.insert_str(black_box(idx), "inserted\n");
+                }
+                pt
+            },
+            BatchSize::LargeInput,
+        )
+    });
+
+    group.bench_function("gap buffer line lookup", |b| {
+        b.iter(|| {
+            for &idx in lines.iter() {
+                black_box(gb.line(black_box(idx)).chars().count());
+            }
+        })
+    });
+    group.bench_function("piece table line lookup", |b| {
+        b.iter(|| {
+            for &idx in lines.iter() {
+                black_box(pt.line(black_box(idx)).chars().count());
+            }
+        })
+    });
+
+    group.finish();
+    fs::remove_file(path).unwrap();
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
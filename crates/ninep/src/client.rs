@@ -45,7 +45,7 @@ fn err<T, E>(e: E) -> io::Result<T>
 where
     E: Into<Box<dyn std::error::Error + Send + Sync>>,
 {
-    Err(io::Error::new(io::ErrorKind::Other, e))
+    Err(io::Error::other(e))
 }
 
 /// A client that operates over an underlying [UnixStream].
@@ -1,5 +1,5 @@
 use crate::{
-    buffer::{Buffer, BufferKind, Cur},
+    buffer::{Buffer, BufferKind, Cur, JUMP_MARK},
     dot::TextObject,
     lsp::LspManagerHandle,
     ziplist,
@@ -170,10 +170,7 @@ impl Buffers {
 
         if let Some(id) = existing_id {
             self.focus_id(id);
-            self.inner.focus.txt = content.into();
-            let n = self.inner.focus.txt.len_chars();
-            self.inner.focus.dot.clamp_idx(n);
-            self.inner.focus.xdot.clamp_idx(n);
+            self.inner.focus.replace_txt(content.into());
             return id;
         }
 
@@ -242,7 +239,7 @@ impl Buffers {
         self.inner
             .iter()
             .find(|(_, b)| match &b.kind {
-                BufferKind::File(p) | BufferKind::Directory(p) => p == path,
+                BufferKind::File(p) | BufferKind::Directory(p) | BufferKind::Hex(p) => p == path,
                 _ => false,
             })
             .map(|(_, b)| b)
@@ -267,8 +264,9 @@ impl Buffers {
     }
 
     pub fn record_jump_position(&mut self) {
-        self.jump_list
-            .push(self.inner.focus.id, self.inner.focus.dot.active_cur());
+        let b = &mut self.inner.focus;
+        self.jump_list.push(b.id, b.dot.active_cur());
+        b.set_mark(JUMP_MARK, b.dot);
     }
 
     fn jump(&mut self, bufid: BufferId, cur: Cur) -> (BufferId, BufferId) {
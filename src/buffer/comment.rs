@@ -0,0 +1,220 @@
+//! Toggling line comments for the lines covered by dot.
+use crate::{
+    buffer::{ActionOutcome, Buffer},
+    config_handle,
+    dot::{Cur, Dot},
+};
+use ad_event::Source;
+
+impl Buffer {
+    /// Comment out the lines covered by dot using the line comment token for the [FileType] of
+    /// the buffer, or uncomment them if they are all already commented.
+    ///
+    /// Comment tokens are inserted at the smallest indentation of the lines being commented so
+    /// that the indentation of each line is preserved. Blank lines are left untouched and the
+    /// whole change is made as a single edit so it can be undone in one step. Lines only count
+    /// as commented if the token is followed by whitespace, so `///` is not taken to be a
+    /// commented out `/` in Rust.
+    ///
+    /// [FileType]: super::filetype::FileType
+    pub(super) fn toggle_comment(&mut self, source: Source) -> Option<ActionOutcome> {
+        let token = match self.filetype().and_then(|ft| ft.comment) {
+            Some(token) => token,
+            None => {
+                return Some(ActionOutcome::SetStatusMessage(
+                    "no comment token known for this buffer".to_string(),
+                ))
+            }
+        };
+
+        let (from, to) = self.dot.as_char_indices();
+        let first = self.txt.char_to_line(from);
+        let last = self.txt.char_to_line(to);
+        let tabstop = config_handle!().tabstop;
+        let lines: Vec<(usize, usize, usize, bool)> = (first..=last)
+            .flat_map(|y| {
+                let line = self.txt.line(y).to_string();
+                let trimmed = line.trim_start();
+                let indent = &line[..line.len() - trimmed.len()];
+
+                (!trimmed.trim_end().is_empty()).then(|| {
+                    let width = indent_width(indent, tabstop);
+                    (
+                        y,
+                        indent.chars().count(),
+                        width,
+                        is_commented(trimmed, token),
+                    )
+                })
+            })
+            .collect();
+
+        if lines.is_empty() {
+            return None;
+        }
+
+        let uncomment = lines.iter().all(|&(.., commented)| commented);
+        // Indentation is compared by width so that lines indented with a mix of tabs and
+        // spaces are still commented at the same column
+        let min_width = lines
+            .iter()
+            .map(|&(_, _, width, _)| width)
+            .min()
+            .unwrap_or(0);
+        let n_token = token.chars().count();
+
+        self.edit_log.new_transaction();
+        // Edit from the last line upwards so that the offsets for earlier lines remain valid
+        for &(y, indent, ..) in lines.iter().rev() {
+            let line_start = self.txt.line_to_char(y);
+
+            if uncomment {
+                let start = line_start + indent;
+                let mut end = start + n_token;
+                if self.txt.get_char(end) == Some(' ') {
+                    end += 1;
+                }
+                self.delete_dot(Dot::from_char_indices(start, end - 1), Some(source));
+            } else {
+                let c = Cur {
+                    idx: line_start + self.x_from_provided_rx(y, min_width),
+                };
+                self.insert_string(Dot::Cur { c }, format!("{token} "), Some(source));
+            }
+        }
+        self.edit_log.new_transaction();
+
+        let start = self.txt.line_to_char(first);
+        let end = if last + 1 < self.txt.len_lines() {
+            self.txt.line_to_char(last + 1)
+        } else {
+            self.txt.len_chars()
+        };
+        self.dot = Dot::from_char_indices(start, end.saturating_sub(1)).collapse_null_range();
+        self.xdot.clamp_idx(self.txt.len_chars());
+
+        None
+    }
+}
+
+/// Whether a line with its indentation removed begins with the given comment token.
+fn is_commented(trimmed: &str, token: &str) -> bool {
+    match trimmed.strip_prefix(token) {
+        Some(rest) => rest.chars().next().is_none_or(char::is_whitespace),
+        None => false,
+    }
+}
+
+/// The number of columns taken up by the given indentation when it is rendered.
+fn indent_width(indent: &str, tabstop: usize) -> usize {
+    indent.chars().fold(0, |width, ch| match ch {
+        '\t' => width + tabstop - (width % tabstop),
+        _ => width + 1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::tests::file_buffer, editor::Action};
+    use simple_test_case::test_case;
+
+    const RUST: &str = "fn main() {\n    let x = 1;\n\n    if x > 0 {\n        x += 1;\n    }\n}";
+
+    fn buffer(fname: &str, content: &str, first: usize, last: usize) -> Buffer {
+        let mut b = file_buffer(fname, content);
+        let start = b.txt.line_to_char(first);
+        let end = b.txt.line_to_char(last + 1) - 1;
+        b.dot = Dot::from_char_indices(start, end);
+
+        b
+    }
+
+    #[test_case(
+        "test.rs", RUST, 1, 1,
+        "fn main() {\n    // let x = 1;\n\n    if x > 0 {\n        x += 1;\n    }\n}";
+        "single line"
+    )]
+    #[test_case(
+        "test.rs", RUST, 1, 5,
+        "fn main() {\n    // let x = 1;\n\n    // if x > 0 {\n    //     x += 1;\n    // }\n}";
+        "multiple lines at different indentation levels"
+    )]
+    #[test_case(
+        "test.rs",
+        "fn main() {\n    // let x = 1;\n    //     x += 1;\n}", 1, 2,
+        "fn main() {\n    let x = 1;\n        x += 1;\n}";
+        "uncomment"
+    )]
+    #[test_case(
+        "test.rs",
+        "fn main() {\n    // let x = 1;\n    x += 1;\n}", 1, 2,
+        "fn main() {\n    // // let x = 1;\n    // x += 1;\n}";
+        "partially commented lines are commented"
+    )]
+    #[test_case(
+        "test.rs", "/// Docs\nfn f() {}\n", 0, 1,
+        "// /// Docs\n// fn f() {}\n";
+        "doc comments are not commented lines"
+    )]
+    #[test_case(
+        "test.rs", "//// Docs\n//x\n", 0, 1,
+        "// //// Docs\n// //x\n";
+        "the token must be followed by whitespace"
+    )]
+    #[test_case(
+        "test.rs", "//\n// x\n", 0, 1,
+        "\nx\n";
+        "bare tokens are uncommented"
+    )]
+    #[test_case(
+        "test.rs", "\tx\n    y\n", 0, 1,
+        "\t// x\n    // y\n";
+        "tabs are compared by width"
+    )]
+    #[test_case(
+        "test.rs", "\tx\n  y\n", 0, 1,
+        "// \tx\n  // y\n";
+        "tabs wider than the minimum indent"
+    )]
+    #[test_case(
+        "test.py", "def f():\n    pass\n", 0, 1,
+        "# def f():\n#     pass\n";
+        "python"
+    )]
+    #[test]
+    fn toggle_comment_works(fname: &str, content: &str, first: usize, last: usize, expected: &str) {
+        let mut b = buffer(fname, content, first, last);
+        let outcome = b.handle_action(Action::ToggleComment, Source::Keyboard);
+
+        assert_eq!(outcome, None);
+        assert_eq!(b.txt.to_string(), expected);
+    }
+
+    #[test]
+    fn toggle_comment_is_a_single_undo_step() {
+        let mut b = buffer("test.rs", RUST, 1, 5);
+        b.handle_action(Action::ToggleComment, Source::Keyboard);
+        b.handle_action(Action::Undo, Source::Keyboard);
+
+        assert_eq!(b.txt.to_string(), RUST);
+    }
+
+    #[test]
+    fn toggling_twice_restores_the_original_lines() {
+        let mut b = buffer("test.rs", RUST, 1, 5);
+        b.handle_action(Action::ToggleComment, Source::Keyboard);
+        b.handle_action(Action::ToggleComment, Source::Keyboard);
+
+        assert_eq!(b.txt.to_string(), RUST);
+    }
+
+    #[test]
+    fn unknown_filetypes_are_an_error() {
+        let mut b = buffer("test.txt", "foo\n", 0, 0);
+        let outcome = b.handle_action(Action::ToggleComment, Source::Keyboard);
+
+        assert!(matches!(outcome, Some(ActionOutcome::SetStatusMessage(_))));
+        assert_eq!(b.txt.to_string(), "foo\n");
+    }
+}
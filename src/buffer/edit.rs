@@ -1,12 +1,14 @@
 use crate::buffer::{Buffer, Cur};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(super) enum Kind {
     Insert,
     Delete,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(super) enum Txt {
     Char(char),
     String(String),
@@ -49,7 +51,7 @@ impl Txt {
 /// An Edit represents an atomic change to the state of a Buffer that can be rolled
 /// back if needed. Sequential edits to the Buffer are compressed from char based
 /// to String based where possible in order to simplify undo state.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct Edit {
     pub(super) kind: Kind,
     pub(super) cur: Cur,
@@ -132,56 +134,262 @@ impl Edit {
 
 pub type Transaction = Vec<Edit>;
 
+/// A step through the undo history for the `earlier` and `later` commands: either a number of
+/// changes or an amount of time.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum HistoryStep {
+    Count(usize),
+    Secs(u64),
+}
+
+impl HistoryStep {
+    /// Parse a plain count ("3") or a duration in seconds, minutes, hours or days ("30s", "5m").
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        let s = s.trim();
+        let err = || format!("'{s}' is not a valid count or duration (e.g. 3, 30s, 5m, 1h)");
+
+        if let Ok(n) = s.parse() {
+            return Ok(Self::Count(n));
+        }
+
+        let (n, mul) = [("s", 1), ("m", 60), ("h", 60 * 60), ("d", 24 * 60 * 60)]
+            .into_iter()
+            .find_map(|(unit, mul)| Some((s.strip_suffix(unit)?, mul)))
+            .ok_or_else(err)?;
+        let n: u64 = n.parse().map_err(|_| err())?;
+
+        n.checked_mul(mul)
+            .map(Self::Secs)
+            .ok_or_else(|| format!("'{s}' is too long a duration"))
+    }
+}
+
+/// Seconds since the unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// A state of the buffer within the undo tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct Node {
+    pub(super) parent: usize,
+    /// The child followed when redoing: the most recently visited branch
+    pub(super) redo: Option<usize>,
+    /// The edits that move the buffer from the state of the parent to this state
+    pub(super) edits: Transaction,
+    /// When this state was last modified in seconds since the unix epoch
+    pub(super) time: u64,
+    /// Whether the edits were all typed by the user, allowing them to be repeated
+    #[serde(default)]
+    pub(super) repeatable: bool,
+}
+
 /// An edit log represents the currently undo-able state changes made to a Buffer.
 ///
-/// The log can be unwound, restoring the buffer to a previous state, and rewound as long
-/// as no new edits have been made to the buffer (i.e. it is a flat timeline not a tree).
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+/// The log is stored as a tree of states so that making an edit after undoing starts a new
+/// branch rather than discarding the changes that were undone. Undo and redo move along the
+/// most recently visited branch while [EditLog::goto] can be used to restore any state in the
+/// tree. Nodes are stored in the order they were created so node ids are also a timeline of
+/// the changes made to the buffer.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub(crate) struct EditLog {
-    pub(super) edits: Vec<Transaction>,
-    pub(super) undone_edits: Vec<Transaction>,
+    /// The root node at index 0 is the state of the buffer before any edits were made
+    pub(super) nodes: Vec<Node>,
+    /// The node matching the current state of the buffer
+    pub(super) current: usize,
+    /// The node that was current when the buffer was last saved
+    pub(super) save_point: usize,
+    /// Whether or not new edits can be combined into the current node
+    #[serde(skip)]
+    pub(super) open: bool,
+    #[serde(skip)]
     pub(super) paused: bool,
 }
 
+impl Default for EditLog {
+    fn default() -> Self {
+        Self {
+            nodes: vec![Node {
+                parent: 0,
+                redo: None,
+                edits: Vec::new(),
+                time: now(),
+                repeatable: false,
+            }],
+            current: 0,
+            save_point: 0,
+            open: false,
+            paused: false,
+        }
+    }
+}
+
 impl EditLog {
     pub(crate) fn clear(&mut self) {
-        self.edits.clear();
-        self.undone_edits.clear();
-        self.paused = false;
+        *self = Self::default();
     }
 
     pub(crate) fn debug_edits(&self, b: &Buffer) -> Vec<String> {
-        self.edits
-            .iter()
-            .flat_map(|t| t.iter().map(|e| e.string_repr(b)))
+        self.path_from_root(self.current)
+            .into_iter()
+            .flat_map(|id| self.nodes[id].edits.iter().map(|e| e.string_repr(b)))
             .collect()
     }
 
+    /// The ids of the nodes between the root (exclusive) and id (inclusive).
+    fn path_from_root(&self, mut id: usize) -> Vec<usize> {
+        let mut path = Vec::new();
+        while id != 0 {
+            path.push(id);
+            id = self.nodes[id].parent;
+        }
+        path.reverse();
+
+        path
+    }
+
     pub(crate) fn undo(&mut self) -> Option<Transaction> {
-        let mut t = self.edits.pop()?;
-        while t.is_empty() {
-            t = self.edits.pop()?;
+        if self.current == 0 {
+            return None;
         }
 
-        self.undone_edits.push(t.clone());
-        t.reverse();
+        let Node { parent, edits, .. } = &self.nodes[self.current];
+        let t = edits.iter().rev().map(|e| e.clone().into_undo()).collect();
+        let parent = *parent;
 
-        Some(t.into_iter().map(|e| e.into_undo()).collect())
+        self.nodes[parent].redo = Some(self.current);
+        self.current = parent;
+        self.open = false;
+
+        Some(t)
     }
 
     pub(crate) fn redo(&mut self) -> Option<Transaction> {
-        let mut t = self.undone_edits.pop()?;
-        while t.is_empty() {
-            t = self.edits.pop()?;
+        let id = self.nodes[self.current].redo?;
+        self.current = id;
+        self.open = false;
+
+        Some(self.nodes[id].edits.clone())
+    }
+
+    /// Move to the given node in the tree, returning the edits required to transform the
+    /// current state of the buffer into the state for that node.
+    pub(crate) fn goto(&mut self, target: usize) -> Option<Transaction> {
+        if target >= self.nodes.len() {
+            return None;
         }
 
-        self.edits.push(t.clone());
+        let down = self.path_from_root(target);
+        let mut t = Vec::new();
+
+        while self.current != 0 && !down.contains(&self.current) {
+            t.extend(self.undo()?);
+        }
+
+        let start = down
+            .iter()
+            .position(|&id| id == self.current)
+            .map_or(0, |i| i + 1);
+        for &id in down[start..].iter() {
+            self.nodes[self.current].redo = Some(id);
+            t.extend(self.redo()?);
+        }
+        self.open = false;
 
         Some(t)
     }
 
-    pub(crate) fn is_empty(&self) -> bool {
-        self.edits.is_empty()
+    /// Move back through the history of the buffer by a number of changes or to the state that
+    /// the buffer was in a given amount of time ago.
+    pub(crate) fn earlier(&mut self, step: HistoryStep) -> Option<Transaction> {
+        let target = match step {
+            HistoryStep::Count(n) => self.current.saturating_sub(n),
+            HistoryStep::Secs(s) => {
+                self.last_node_before(self.nodes[self.current].time.saturating_sub(s))
+            }
+        };
+
+        self.goto_if_moved(target)
+    }
+
+    /// Move forward through the history of the buffer by a number of changes or to the state
+    /// that the buffer was in a given amount of time later.
+    pub(crate) fn later(&mut self, step: HistoryStep) -> Option<Transaction> {
+        let target = match step {
+            HistoryStep::Count(n) => (self.current + n).min(self.nodes.len() - 1),
+            HistoryStep::Secs(s) => self
+                .last_node_before(self.nodes[self.current].time + s)
+                .max(self.current),
+        };
+
+        self.goto_if_moved(target)
+    }
+
+    fn goto_if_moved(&mut self, target: usize) -> Option<Transaction> {
+        if target == self.current {
+            None
+        } else {
+            self.goto(target)
+        }
+    }
+
+    /// The most recently created node that was last modified no later than time.
+    fn last_node_before(&self, time: u64) -> usize {
+        self.nodes.iter().rposition(|n| n.time <= time).unwrap_or(0)
+    }
+
+    /// Render the tree as one line per node, with branches that are not the most recent
+    /// child of their parent indented below it. Each line begins with the id of its node.
+    pub(crate) fn tree_lines(&self) -> Vec<String> {
+        self.tree_lines_at(now())
+    }
+
+    fn tree_lines_at(&self, now: u64) -> Vec<String> {
+        let mut children = vec![Vec::new(); self.nodes.len()];
+        for (id, n) in self.nodes.iter().enumerate().skip(1) {
+            children[n.parent].push(id);
+        }
+
+        let mut lines = Vec::with_capacity(self.nodes.len());
+        let mut stack = vec![(0, 0)];
+
+        while let Some((id, depth)) = stack.pop() {
+            let n = &self.nodes[id];
+            let mut line = format!("{}{id:<4} {:>8}  ", "  ".repeat(depth), age(now, n.time));
+            if id == 0 {
+                line.push_str("original");
+            } else {
+                line.push_str(&summary(&n.edits));
+            }
+            if id == self.save_point {
+                line.push_str(" (saved)");
+            }
+            if id == self.current {
+                line.push_str(" (current)");
+            }
+            lines.push(line);
+
+            if let Some((&last, rest)) = children[id].split_last() {
+                stack.push((last, depth));
+                stack.extend(rest.iter().rev().map(|&c| (c, depth + 1)));
+            }
+        }
+
+        lines
+    }
+
+    /// Record the current state of the log as matching the content on disk.
+    pub(crate) fn mark_saved(&mut self) {
+        self.save_point = self.current;
+        self.open = false;
+    }
+
+    /// Whether or not the log is at the state it was in when the buffer was last saved.
+    pub(crate) fn is_at_save_point(&self) -> bool {
+        self.current == self.save_point
     }
 
     /// Record a single character being inserted at the given cursor position
@@ -228,35 +436,82 @@ impl EditLog {
         }
     }
 
-    pub(crate) fn new_transaction(&mut self) {
-        match self.edits.last() {
-            Some(t) if t.is_empty() => return,
-            _ => (),
+    /// The edits that moved the buffer into its current state if they can be repeated.
+    pub(crate) fn repeatable_transaction(&self) -> Option<&Transaction> {
+        let node = &self.nodes[self.current];
+        (self.current != 0 && node.repeatable).then_some(&node.edits)
+    }
+
+    /// Prevent the edits that moved the buffer into its current state from being repeated.
+    pub(crate) fn mark_unrepeatable(&mut self) {
+        if !self.paused {
+            self.nodes[self.current].repeatable = false;
         }
-        self.edits.push(Vec::new());
     }
 
-    fn push(&mut self, e: Edit) {
-        self.undone_edits.clear();
+    pub(crate) fn new_transaction(&mut self) {
+        self.open = false;
+    }
 
-        if self.edits.is_empty() {
-            self.edits.push(vec![e]);
+    fn push(&mut self, e: Edit) {
+        if self.open {
+            let node = &mut self.nodes[self.current];
+            node.time = now();
+
+            // So long as we have at least one existing edit we can try to extend it
+            // by combining it with this new one. If that fails we simply store the
+            // new edit as provided.
+            if let Some(e) = node.edits.last_mut().unwrap().try_combine(e) {
+                node.edits.push(e);
+            }
             return;
         }
 
-        let transaction = self.edits.last_mut().unwrap();
-        if transaction.is_empty() {
-            transaction.push(e);
-            return;
-        }
+        self.nodes.push(Node {
+            parent: self.current,
+            redo: None,
+            edits: vec![e],
+            time: now(),
+            repeatable: true,
+        });
+        let id = self.nodes.len() - 1;
+        self.nodes[self.current].redo = Some(id);
+        self.current = id;
+        self.open = true;
+    }
+}
+
+/// A short human readable description of how long ago time was.
+fn age(now: u64, time: u64) -> String {
+    match now.saturating_sub(time) {
+        0 => "just now".to_string(),
+        s if s < 60 => format!("{s}s ago"),
+        s if s < 60 * 60 => format!("{}m ago", s / 60),
+        s if s < 24 * 60 * 60 => format!("{}h ago", s / (60 * 60)),
+        s => format!("{}d ago", s / (24 * 60 * 60)),
+    }
+}
 
-        // So long as we have at least one existing edit we can try to extend it
-        // by combining it with this new one. If that fails we simply store the
-        // new edit as provided.
-        if let Some(e) = transaction.last_mut().unwrap().try_combine(e) {
-            self.edits.last_mut().unwrap().push(e);
+/// The number of characters inserted and deleted by a transaction.
+fn summary(t: &Transaction) -> String {
+    let (mut inserted, mut deleted) = (0, 0);
+    for e in t.iter() {
+        let n = match &e.txt {
+            Txt::Char(_) => 1,
+            Txt::String(s) => s.chars().count(),
+        };
+        match e.kind {
+            Kind::Insert => inserted += n,
+            Kind::Delete => deleted += n,
         }
     }
+
+    format!("+{inserted} -{deleted}")
+}
+
+/// Parse the node id from a line rendered by [EditLog::tree_lines].
+pub(crate) fn parse_tree_line(line: &str) -> Option<usize> {
+    line.split_whitespace().next()?.parse().ok()
 }
 
 #[cfg(test)]
@@ -296,6 +551,39 @@ pub(crate) mod tests {
         }
     }
 
+    impl EditLog {
+        /// The transactions between the root of the tree and the current node.
+        pub(crate) fn transactions(&self) -> Vec<Transaction> {
+            self.path_from_root(self.current)
+                .into_iter()
+                .map(|id| self.nodes[id].edits.clone())
+                .collect()
+        }
+    }
+
+    /// A log with the transactions "a", "b", "c" followed by undoing twice and then inserting
+    /// "d" so that the tree branches at node 1:
+    ///
+    ///   0 -> 1 (a) -> 2 (b) -> 3 (c)
+    ///          \-> 4 (d)
+    fn branching_log() -> EditLog {
+        let mut log = EditLog::default();
+        for (i, c) in "abc".chars().enumerate() {
+            log.new_transaction();
+            log.insert_char(Cur { idx: i }, c);
+        }
+        log.undo();
+        log.undo();
+        log.new_transaction();
+        log.insert_char(Cur { idx: 1 }, 'd');
+
+        for (i, n) in log.nodes.iter_mut().enumerate() {
+            n.time = 100 * i as u64;
+        }
+
+        log
+    }
+
     #[test_case(
         vec![in_c(0, 'a'), in_c(1, 'b')],
         &[in_s(0, "ab")];
@@ -343,7 +631,7 @@ pub(crate) mod tests {
             log.push(e);
         }
 
-        assert_eq!(&log.edits, &[expected.to_vec()]);
+        assert_eq!(log.transactions(), vec![expected.to_vec()]);
     }
 
     #[test_case(
@@ -393,6 +681,121 @@ pub(crate) mod tests {
             log.push(e);
         }
 
-        assert_eq!(&log.edits, &[expected.to_vec()]);
+        assert_eq!(log.transactions(), vec![expected.to_vec()]);
+    }
+
+    #[test]
+    fn edits_after_undo_start_a_new_branch() {
+        let mut log = branching_log();
+
+        assert_eq!(log.current, 4);
+        assert_eq!(
+            log.transactions(),
+            vec![vec![in_c(0, 'a')], vec![in_c(1, 'd')]]
+        );
+
+        // The undone branch is still reachable
+        assert_eq!(
+            log.goto(3),
+            Some(vec![del_c(1, 'd'), in_c(1, 'b'), in_c(2, 'c')])
+        );
+        assert_eq!(log.current, 3);
+        assert_eq!(log.undo(), Some(vec![del_c(2, 'c')]));
+        assert_eq!(log.undo(), Some(vec![del_c(1, 'b')]));
+
+        // Redo follows the most recently visited branch
+        assert_eq!(log.redo(), Some(vec![in_c(1, 'b')]));
+    }
+
+    #[test]
+    fn undo_and_redo_walk_the_tree() {
+        let mut log = branching_log();
+
+        assert_eq!(log.undo(), Some(vec![del_c(1, 'd')]));
+        assert_eq!(log.undo(), Some(vec![del_c(0, 'a')]));
+        assert_eq!(log.undo(), None);
+        assert_eq!(log.redo(), Some(vec![in_c(0, 'a')]));
+        assert_eq!(log.redo(), Some(vec![in_c(1, 'd')]));
+        assert_eq!(log.redo(), None);
+    }
+
+    #[test_case(HistoryStep::Count(1), Some(3); "one change")]
+    #[test_case(HistoryStep::Count(10), Some(0); "count past the start")]
+    #[test_case(HistoryStep::Secs(100), Some(3); "exact time")]
+    #[test_case(HistoryStep::Secs(150), Some(2); "between changes")]
+    #[test_case(HistoryStep::Secs(0), None; "no movement")]
+    #[test]
+    fn earlier_works(step: HistoryStep, expected: Option<usize>) {
+        let mut log = branching_log();
+        let moved = log.earlier(step).is_some();
+
+        assert_eq!(moved.then_some(log.current), expected);
+    }
+
+    #[test_case(HistoryStep::Count(2), Some(2); "two changes")]
+    #[test_case(HistoryStep::Count(10), Some(4); "count past the end")]
+    #[test_case(HistoryStep::Secs(150), Some(1); "between changes")]
+    #[test_case(HistoryStep::Secs(1000), Some(4); "past the end")]
+    #[test]
+    fn later_works(step: HistoryStep, expected: Option<usize>) {
+        let mut log = branching_log();
+        log.goto(0);
+        let moved = log.later(step).is_some();
+
+        assert_eq!(moved.then_some(log.current), expected);
+    }
+
+    #[test]
+    fn earlier_and_later_restore_content() {
+        let mut log = branching_log();
+        let t = log.earlier(HistoryStep::Count(2)).unwrap();
+
+        assert_eq!(t, vec![del_c(1, 'd'), in_c(1, 'b')]);
+        assert_eq!(
+            log.later(HistoryStep::Count(2)).unwrap(),
+            vec![del_c(1, 'b'), in_c(1, 'd')]
+        );
+    }
+
+    #[test_case("3", Ok(HistoryStep::Count(3)); "count")]
+    #[test_case("30s", Ok(HistoryStep::Secs(30)); "seconds")]
+    #[test_case("5m", Ok(HistoryStep::Secs(300)); "minutes")]
+    #[test_case("1h", Ok(HistoryStep::Secs(3600)); "hours")]
+    #[test_case("2d", Ok(HistoryStep::Secs(172800)); "days")]
+    #[test_case(" 5m ", Ok(HistoryStep::Secs(300)); "surrounding whitespace")]
+    #[test]
+    fn history_step_parse_works(s: &str, expected: Result<HistoryStep, String>) {
+        assert_eq!(HistoryStep::parse(s), expected);
+    }
+
+    #[test_case("5x"; "unknown unit")]
+    #[test_case("m"; "missing number")]
+    #[test_case(""; "empty")]
+    #[test_case("5é"; "non ascii unit")]
+    #[test_case("é"; "only non ascii")]
+    #[test_case("999999999999999999h"; "duration overflow")]
+    #[test_case("99999999999999999999999"; "count overflow")]
+    #[test]
+    fn history_step_parse_errors(s: &str) {
+        assert!(HistoryStep::parse(s).is_err());
+    }
+
+    #[test]
+    fn tree_lines_show_branches() {
+        let mut log = branching_log();
+        log.save_point = 2;
+        let lines = log.tree_lines_at(400);
+
+        let expected = vec![
+            "0      6m ago  original",
+            "1      5m ago  +1 -0",
+            "  2      3m ago  +1 -0 (saved)",
+            "  3      1m ago  +1 -0",
+            "4    just now  +1 -0 (current)",
+        ];
+        assert_eq!(lines, expected);
+
+        let ids: Vec<_> = lines.iter().flat_map(|l| parse_tree_line(l)).collect();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
     }
 }
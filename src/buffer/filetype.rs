@@ -0,0 +1,114 @@
+//! Language specific editing behaviour for buffers based on their file extension.
+use crate::buffer::Buffer;
+
+const BRACKETS_OPEN: &[char] = &['(', '[', '{'];
+const BRACKETS_CLOSE: &[char] = &[')', ']', '}'];
+
+/// Editing behaviour for a given language.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileType {
+    /// The name of the language
+    pub(crate) name: &'static str,
+    /// The file extensions associated with the language
+    pub(crate) extensions: &'static [&'static str],
+    /// The token used to start a line comment, if the language has line comments
+    pub(crate) comment: Option<&'static str>,
+    /// Characters that increase the indentation of the following line when they are the last
+    /// character before a newline is inserted
+    pub(crate) indent_after: &'static [char],
+    /// Characters that decrease the indentation of the current line when they are typed as
+    /// the first character on the line
+    pub(crate) outdent_before: &'static [char],
+}
+
+impl FileType {
+    const fn brackets(
+        name: &'static str,
+        extensions: &'static [&'static str],
+        comment: Option<&'static str>,
+    ) -> Self {
+        Self {
+            name,
+            extensions,
+            comment,
+            indent_after: BRACKETS_OPEN,
+            outdent_before: BRACKETS_CLOSE,
+        }
+    }
+
+    const fn plain(
+        name: &'static str,
+        extensions: &'static [&'static str],
+        comment: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            extensions,
+            comment: Some(comment),
+            indent_after: &[],
+            outdent_before: &[],
+        }
+    }
+}
+
+/// The built-in file types
+pub(crate) const FILETYPES: &[FileType] = &[
+    FileType::brackets("rust", &["rs"], Some("//")),
+    FileType::brackets("c", &["c", "h"], Some("//")),
+    FileType::brackets("cpp", &["cpp", "cc", "hpp"], Some("//")),
+    FileType::brackets("go", &["go"], Some("//")),
+    FileType::brackets("java", &["java"], Some("//")),
+    FileType::brackets("javascript", &["js", "jsx", "mjs"], Some("//")),
+    FileType::brackets("typescript", &["ts", "tsx"], Some("//")),
+    FileType::brackets("dart", &["dart"], Some("//")),
+    FileType::brackets("json", &["json"], None),
+    FileType {
+        name: "python",
+        extensions: &["py"],
+        comment: Some("#"),
+        indent_after: &[':', '(', '[', '{'],
+        outdent_before: BRACKETS_CLOSE,
+    },
+    FileType::plain("shell", &["sh", "bash", "zsh"], "#"),
+    FileType::plain("toml", &["toml"], "#"),
+    FileType::plain("yaml", &["yaml", "yml"], "#"),
+    FileType::plain("conf", &["conf"], "#"),
+    FileType::plain("lua", &["lua"], "--"),
+    FileType::plain("haskell", &["hs"], "--"),
+    FileType::plain("sql", &["sql"], "--"),
+];
+
+/// The file extensions associated with the named [FileType].
+pub(crate) fn extensions_for(name: &str) -> Vec<String> {
+    FILETYPES
+        .iter()
+        .filter(|ft| ft.name == name)
+        .flat_map(|ft| ft.extensions.iter().map(|ext| ext.to_string()))
+        .collect()
+}
+
+impl Buffer {
+    /// The [FileType] for this buffer based on the extension of its path, if it has one.
+    pub(crate) fn filetype(&self) -> Option<&'static FileType> {
+        let ext = self.path()?.extension()?.to_str()?;
+
+        FILETYPES.iter().find(|ft| ft.extensions.contains(&ext))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::buffer::tests::file_buffer;
+    use simple_test_case::test_case;
+
+    #[test_case("main.rs", Some("rust"); "rust")]
+    #[test_case("lib/foo.py", Some("python"); "python")]
+    #[test_case("README.md", None; "unknown extension")]
+    #[test_case("Makefile", None; "no extension")]
+    #[test]
+    fn filetype_works(path: &str, expected: Option<&str>) {
+        let b = file_buffer(path, "");
+
+        assert_eq!(b.filetype().map(|ft| ft.name), expected);
+    }
+}
@@ -0,0 +1,338 @@
+//! Folding regions of a buffer so that they are displayed as a single line.
+//!
+//! Folds are created manually from dot, from the indentation of the buffer or from the folding
+//! ranges reported by an LSP server. Only the first line of a fold is displayed (along with a
+//! summary of how many lines are hidden) and line based motions skip over the folded lines.
+use crate::{
+    buffer::{
+        marks::{shift_for_delete, shift_for_insert},
+        ActionOutcome, Buffer,
+    },
+    dot::{indent_of, Cur, Dot},
+};
+use std::cell::OnceCell;
+
+/// The closed folds for a buffer.
+///
+/// Folds are tracked as the character offsets of a position on their first and last line so
+/// that they can be updated by the [Buffer] as edits are made in the same way as
+/// [Marks](super::marks::Marks). Folds never overlap: creating a fold that overlaps existing
+/// folds merges them into a single fold.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Folds {
+    inner: Vec<(Cur, Cur)>,
+    /// The first and last lines of each fold. These are needed for every line that is rendered
+    /// and every line motion so they are computed once and then reused until the folds or the
+    /// content of the buffer change.
+    lines: OnceCell<Vec<(usize, usize)>>,
+}
+
+impl Folds {
+    fn set(&mut self, inner: Vec<(Cur, Cur)>) {
+        self.inner = inner;
+        self.lines.take();
+    }
+
+    /// Shift folds following an insertion of `n` characters at `idx`.
+    pub(super) fn insert(&mut self, idx: usize, n: usize) {
+        for (start, end) in self.inner.iter_mut() {
+            shift_for_insert(start, idx, n);
+            shift_for_insert(end, idx, n);
+        }
+        self.lines.take();
+    }
+
+    /// Shift folds following the deletion of `n` characters starting at `idx`.
+    pub(super) fn delete(&mut self, idx: usize, n: usize) {
+        for (start, end) in self.inner.iter_mut() {
+            shift_for_delete(start, idx, n);
+            shift_for_delete(end, idx, n);
+        }
+        self.lines.take();
+    }
+
+    /// Clamp folds to lie within a buffer of `n_chars` characters.
+    pub(super) fn clamp_idx(&mut self, n_chars: usize) {
+        for (start, end) in self.inner.iter_mut() {
+            start.idx = start.idx.min(n_chars);
+            end.idx = end.idx.min(n_chars);
+        }
+        self.lines.take();
+    }
+}
+
+impl Buffer {
+    /// The first and last lines of each fold in the buffer, ordered by their first line.
+    fn fold_lines(&self) -> &[(usize, usize)] {
+        self.folds.lines.get_or_init(|| {
+            self.folds
+                .inner
+                .iter()
+                .flat_map(|(start, end)| {
+                    let first = self.txt.char_to_line(start.idx);
+                    let last = self.txt.char_to_line(end.idx);
+
+                    // Deleting the contents of a fold can leave it covering a single line
+                    (last > first).then_some((first, last))
+                })
+                .collect()
+        })
+    }
+
+    /// The first and last lines of the fold containing line `y` if there is one.
+    pub(crate) fn fold_containing(&self, y: usize) -> Option<(usize, usize)> {
+        self.fold_lines()
+            .iter()
+            .find(|&&(first, last)| first <= y && y <= last)
+            .copied()
+    }
+
+    /// The line that is displayed in place of line `y`: the first line of the fold containing
+    /// `y` or `y` itself if it is not folded.
+    pub(crate) fn visible_line(&self, y: usize) -> usize {
+        self.fold_containing(y).map(|(first, _)| first).unwrap_or(y)
+    }
+
+    /// The next line after `y` that is not hidden by a fold.
+    pub(crate) fn next_visible_line(&self, y: usize) -> Option<usize> {
+        let last = self.fold_containing(y).map(|(_, last)| last).unwrap_or(y);
+
+        (last + 1 < self.len_lines()).then_some(last + 1)
+    }
+
+    /// The previous line before `y` that is not hidden by a fold.
+    pub(crate) fn prev_visible_line(&self, y: usize) -> Option<usize> {
+        match self.visible_line(y) {
+            0 => None,
+            first => Some(self.visible_line(first - 1)),
+        }
+    }
+
+    /// The number of screen rows needed to display the lines from `from` up to (but not
+    /// including) `to`.
+    pub(crate) fn rows_between(&self, from: usize, to: usize) -> usize {
+        let (from, to) = (self.visible_line(from), self.visible_line(to));
+        let hidden: usize = self
+            .fold_lines()
+            .iter()
+            .filter(|&&(first, _)| from <= first && first < to)
+            .map(|(first, last)| last - first)
+            .sum();
+
+        to.saturating_sub(from).saturating_sub(hidden)
+    }
+
+    /// The line displayed `n` screen rows below line `y`, stopping at the last line of the
+    /// buffer.
+    pub(crate) fn line_n_rows_below(&self, y: usize, n: usize) -> usize {
+        let mut y = self.visible_line(y);
+        for _ in 0..n {
+            match self.next_visible_line(y) {
+                Some(next) => y = next,
+                None => break,
+            }
+        }
+
+        y
+    }
+
+    /// The line displayed `n` screen rows above line `y`, stopping at the first line of the
+    /// buffer.
+    pub(crate) fn line_n_rows_above(&self, y: usize, n: usize) -> usize {
+        let mut y = self.visible_line(y);
+        for _ in 0..n {
+            match self.prev_visible_line(y) {
+                Some(prev) => y = prev,
+                None => break,
+            }
+        }
+
+        y
+    }
+
+    /// Fold the lines from `first` to `last` (inclusive), merging any folds that overlap them.
+    ///
+    /// Dot is moved to the start of the fold so that it remains visible.
+    pub(crate) fn fold(&mut self, first: usize, last: usize) -> Option<ActionOutcome> {
+        let last = last.min(self.len_lines().saturating_sub(1));
+        if first >= last {
+            return Some(ActionOutcome::SetStatusMessage(
+                "a fold must span multiple lines".to_string(),
+            ));
+        }
+
+        let (mut first, mut last) = (first, last);
+        let mut folds: Vec<(usize, usize)> = Vec::new();
+        for &(f, l) in self.fold_lines() {
+            if l < first || f > last {
+                folds.push((f, l));
+            } else {
+                first = first.min(f);
+                last = last.max(l);
+            }
+        }
+        folds.push((first, last));
+        folds.sort_unstable();
+
+        let folds = folds
+            .into_iter()
+            .map(|(f, l)| {
+                let start = Cur::new(self.txt.line_to_char(f));
+                let end = Cur::new(self.txt.line_to_char(l));
+                (start, end)
+            })
+            .collect();
+        self.folds.set(folds);
+        self.dot = Dot::from(Cur::new(self.txt.line_to_char(first)));
+
+        None
+    }
+
+    /// Fold the lines covered by dot.
+    pub(super) fn fold_dot(&mut self) -> Option<ActionOutcome> {
+        let (start, end) = self.dot.as_char_indices();
+        let first = self.txt.char_to_line(start);
+        let last = self.txt.char_to_line(end);
+
+        self.fold(first, last)
+    }
+
+    /// Fold the block of lines indented under the line containing dot, or the block of lines
+    /// containing dot along with the line that opens it if dot is not on the opening line.
+    pub(super) fn fold_indent(&mut self) -> Option<ActionOutcome> {
+        let (y, _) = self.dot.active_cur().as_yx(self);
+
+        match self.indent_fold_lines(y) {
+            Some((first, last)) => self.fold(first, last),
+            None => Some(ActionOutcome::SetStatusMessage(
+                "no indented block to fold".to_string(),
+            )),
+        }
+    }
+
+    fn indent_fold_lines(&self, y: usize) -> Option<(usize, usize)> {
+        let n_lines = self.len_lines();
+        let next_indent = |y: usize| (y + 1..n_lines).find_map(|y| indent_of(y, self));
+
+        let header = match (indent_of(y, self), next_indent(y)) {
+            (Some(level), Some(next)) if next > level => y,
+            (level, _) => {
+                let level = level.or_else(|| next_indent(y))?;
+                (0..y)
+                    .rev()
+                    .find(|&y| indent_of(y, self).is_some_and(|i| i < level))?
+            }
+        };
+
+        let level = indent_of(header, self)?;
+        let last = (header + 1..n_lines)
+            .map(|y| (y, indent_of(y, self)))
+            .take_while(|(_, indent)| indent.is_none_or(|i| i > level))
+            .filter(|(_, indent)| indent.is_some())
+            .last()?
+            .0;
+
+        Some((header, last))
+    }
+
+    /// Remove the fold containing the line dot is on.
+    pub(super) fn unfold(&mut self) -> Option<ActionOutcome> {
+        let (y, _) = self.dot.active_cur().as_yx(self);
+        let (first, _) = match self.fold_containing(y) {
+            Some(lines) => lines,
+            None => {
+                return Some(ActionOutcome::SetStatusMessage(
+                    "no fold at dot".to_string(),
+                ))
+            }
+        };
+
+        let mut folds = self.folds.inner.clone();
+        folds.retain(|(start, _)| self.txt.char_to_line(start.idx) != first);
+        self.folds.set(folds);
+
+        None
+    }
+
+    /// Remove all folds in the buffer.
+    pub(super) fn unfold_all(&mut self) {
+        self.folds.set(Vec::new());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::tests::buffer_from_lines, editor::Action};
+    use ad_event::Source;
+    use simple_test_case::test_case;
+
+    const LINES: &[&str] = &[
+        "fn main() {",
+        "    if true {",
+        "        one();",
+        "",
+        "        two();",
+        "    }",
+        "    three();",
+        "}",
+    ];
+
+    #[test_case(0, Some((0, 6)); "header line")]
+    #[test_case(1, Some((1, 4)); "nested header line")]
+    #[test_case(2, Some((1, 4)); "inside nested block")]
+    #[test_case(3, Some((1, 4)); "blank line inside nested block")]
+    #[test_case(6, Some((0, 6)); "inside outer block")]
+    #[test_case(7, None; "unindented closing line")]
+    #[test]
+    fn indent_fold_lines_works(y: usize, expected: Option<(usize, usize)>) {
+        let b = buffer_from_lines(LINES);
+
+        assert_eq!(b.indent_fold_lines(y), expected);
+    }
+
+    #[test]
+    fn folded_lines_are_skipped() {
+        let mut b = buffer_from_lines(LINES);
+        b.fold(1, 5);
+
+        assert_eq!(b.visible_line(3), 1);
+        assert_eq!(b.next_visible_line(0), Some(1));
+        assert_eq!(b.next_visible_line(1), Some(6));
+        assert_eq!(b.prev_visible_line(6), Some(1));
+        assert_eq!(b.prev_visible_line(1), Some(0));
+        assert_eq!(b.rows_between(0, 7), 3);
+        assert_eq!(b.line_n_rows_below(0, 2), 6);
+        assert_eq!(b.line_n_rows_above(7, 2), 1);
+    }
+
+    #[test]
+    fn overlapping_folds_are_merged() {
+        let mut b = buffer_from_lines(LINES);
+        b.fold(1, 3);
+        b.fold(5, 6);
+        b.fold(2, 5);
+
+        assert_eq!(b.fold_lines(), &[(1, 6)]);
+    }
+
+    #[test]
+    fn folds_track_edits() {
+        let mut b = buffer_from_lines(LINES);
+        b.fold(1, 5);
+        b.dot = Dot::from(Cur::new(0));
+        b.new_edit_log_transaction();
+        b.handle_action(
+            Action::InsertString {
+                s: "// comment\n".to_string(),
+            },
+            Source::Keyboard,
+        );
+
+        assert_eq!(b.fold_containing(3), Some((2, 6)));
+
+        b.handle_action(Action::Undo, Source::Keyboard);
+
+        assert_eq!(b.fold_containing(3), Some((1, 5)));
+    }
+}
@@ -0,0 +1,397 @@
+//! Tracking the on disk format of files so that it can be restored when saving.
+//!
+//! Buffer content is always held as UTF-8 with "\n" line endings and without a byte order mark.
+//! When a file is loaded we record what it originally looked like so that saving an unmodified
+//! file writes back the same bytes that were read.
+//!
+//! UTF-16 is only detected when the file begins with a byte order mark. Anything else that is
+//! not valid UTF-8 is assumed to be Windows-1252 (a superset of the printable characters in
+//! Latin-1), which can decode any sequence of bytes.
+use std::fmt;
+use tracing::warn;
+
+const BOM: char = '\u{feff}';
+pub(super) const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+pub(super) const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+pub(super) const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+/// The characters for bytes 0x80-0x9F in Windows-1252. The five bytes left undefined by the
+/// encoding map to the matching C1 control characters, as they do in Latin-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// The character encoding of a file on disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+    Windows1252,
+}
+
+impl Encoding {
+    /// Parse an encoding name as used in "reopen-with-encoding ...".
+    pub(crate) fn try_from_name(name: &str) -> Result<Self, String> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Self::Utf8),
+            "utf-16le" | "utf16le" | "utf-16" | "utf16" => Ok(Self::Utf16Le),
+            "utf-16be" | "utf16be" => Ok(Self::Utf16Be),
+            "latin-1" | "latin1" | "iso-8859-1" => Ok(Self::Latin1),
+            "windows-1252" | "cp1252" => Ok(Self::Windows1252),
+            s => Err(format!("'{s}' is not a supported encoding")),
+        }
+    }
+
+    fn bom(&self) -> &'static [u8] {
+        match self {
+            Self::Utf8 => UTF8_BOM,
+            Self::Utf16Le => UTF16LE_BOM,
+            Self::Utf16Be => UTF16BE_BOM,
+            Self::Latin1 | Self::Windows1252 => &[],
+        }
+    }
+
+    /// Whether or not the bytes can be decoded without any of them needing to be replaced.
+    pub(crate) fn can_decode(&self, bytes: &[u8]) -> bool {
+        let decode_utf16 = |f: fn([u8; 2]) -> u16| {
+            bytes.len().is_multiple_of(2)
+                && char::decode_utf16(bytes.chunks(2).map(|c| f([c[0], c[1]]))).all(|r| r.is_ok())
+        };
+
+        match self {
+            Self::Utf8 => std::str::from_utf8(bytes).is_ok(),
+            Self::Utf16Le => decode_utf16(u16::from_le_bytes),
+            Self::Utf16Be => decode_utf16(u16::from_be_bytes),
+            Self::Latin1 | Self::Windows1252 => true,
+        }
+    }
+
+    fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Self::Utf16Le | Self::Utf16Be => {
+                let units: Vec<u16> = bytes
+                    .chunks(2)
+                    .map(|c| match (self, c) {
+                        (Self::Utf16Le, &[lo, hi]) => u16::from_le_bytes([lo, hi]),
+                        (_, &[hi, lo]) => u16::from_be_bytes([hi, lo]),
+                        // A trailing odd byte can't be a valid code unit
+                        _ => 0xFFFD,
+                    })
+                    .collect();
+                String::from_utf16_lossy(&units)
+            }
+            Self::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+            Self::Windows1252 => bytes
+                .iter()
+                .map(|&b| match b {
+                    0x80..=0x9F => WINDOWS_1252_HIGH[(b - 0x80) as usize],
+                    b => b as char,
+                })
+                .collect(),
+        }
+    }
+
+    fn encode(&self, s: &str, buf: &mut Vec<u8>) -> Result<(), char> {
+        match self {
+            Self::Utf8 => buf.extend_from_slice(s.as_bytes()),
+            Self::Utf16Le => s.encode_utf16().for_each(|u| buf.extend(u.to_le_bytes())),
+            Self::Utf16Be => s.encode_utf16().for_each(|u| buf.extend(u.to_be_bytes())),
+            Self::Latin1 => {
+                for ch in s.chars() {
+                    buf.push(u8::try_from(ch).map_err(|_| ch)?);
+                }
+            }
+            Self::Windows1252 => {
+                for ch in s.chars() {
+                    let b = match WINDOWS_1252_HIGH.iter().position(|&c| c == ch) {
+                        Some(i) => 0x80 + i as u8,
+                        None if ('\u{80}'..='\u{9F}').contains(&ch) => return Err(ch),
+                        None => u8::try_from(ch).map_err(|_| ch)?,
+                    };
+                    buf.push(b);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Utf8 => write!(f, "UTF-8"),
+            Self::Utf16Le => write!(f, "UTF-16LE"),
+            Self::Utf16Be => write!(f, "UTF-16BE"),
+            Self::Latin1 => write!(f, "Latin-1"),
+            Self::Windows1252 => write!(f, "Windows-1252"),
+        }
+    }
+}
+
+/// The line ending style used when writing a buffer to disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// Parse a line ending name as used in "set line-ending=...".
+    pub(crate) fn try_from_name(name: &str) -> Result<Self, String> {
+        match name.trim() {
+            "lf" | "LF" => Ok(Self::Lf),
+            "crlf" | "CRLF" => Ok(Self::Crlf),
+            s => Err(format!(
+                "'{s}' is not a valid line ending: expected 'lf' or 'crlf'"
+            )),
+        }
+    }
+
+    /// The style of the first line ending in s. Files containing a mix of line endings are
+    /// normalized to whichever appears first (see [is_mixed]).
+    fn detect(s: &str) -> Self {
+        match s.find('\n') {
+            Some(i) if i > 0 && s.as_bytes()[i - 1] == b'\r' => Self::Crlf,
+            _ => Self::Lf,
+        }
+    }
+}
+
+/// Whether or not s contains both LF and CRLF line endings (or stray carriage returns), which
+/// can not be preserved when the content is saved.
+fn is_mixed(s: &str) -> bool {
+    let n_cr = s.bytes().filter(|&b| b == b'\r').count();
+    if n_cr == 0 {
+        return false;
+    }
+    let n_lf = s.bytes().filter(|&b| b == b'\n').count();
+    let n_crlf = s.matches("\r\n").count();
+
+    n_crlf != n_cr || n_crlf != n_lf
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Lf => write!(f, "LF"),
+            Self::Crlf => write!(f, "CRLF"),
+        }
+    }
+}
+
+/// How the content of a buffer should be written to disk.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FileFormat {
+    pub(crate) encoding: Encoding,
+    pub(crate) line_ending: LineEnding,
+    /// Whether or not the file began with a byte order mark
+    pub(crate) bom: bool,
+    /// Whether or not the file contained a mix of line endings that will be replaced by
+    /// line_ending when it is saved
+    pub(crate) mixed_line_endings: bool,
+}
+
+impl FileFormat {
+    /// Detect the format of the given file content, returning it along with the content
+    /// converted to the form used internally by buffers.
+    pub(crate) fn decode(bytes: Vec<u8>) -> (Self, String) {
+        let encoding = if bytes.starts_with(UTF16LE_BOM) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(UTF16BE_BOM) {
+            Encoding::Utf16Be
+        } else if std::str::from_utf8(&bytes).is_ok() {
+            Encoding::Utf8
+        } else {
+            warn!("file content is not valid utf-8: falling back to Windows-1252");
+            Encoding::Windows1252
+        };
+
+        Self::decode_as(bytes, encoding)
+    }
+
+    /// Decode file content using a known encoding, replacing any invalid sequences.
+    pub(crate) fn decode_as(bytes: Vec<u8>, encoding: Encoding) -> (Self, String) {
+        let s = match encoding {
+            // Avoid copying the content in the common case
+            Encoding::Utf8 => String::from_utf8(bytes)
+                .unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned()),
+            enc => enc.decode(&bytes),
+        };
+        let (bom, s) = match s.strip_prefix(BOM) {
+            Some(s) if !encoding.bom().is_empty() => (true, s.to_string()),
+            _ => (false, s),
+        };
+        let line_ending = LineEnding::detect(&s);
+        let mixed_line_endings = is_mixed(&s);
+        if mixed_line_endings {
+            warn!("file contains mixed line endings: they will be saved as {line_ending}");
+        }
+
+        let format = Self {
+            encoding,
+            line_ending,
+            bom,
+            mixed_line_endings,
+        };
+
+        (format, normalize(s))
+    }
+
+    /// Convert buffer content back to the format it was originally loaded in. If the content
+    /// contains a character that can not be represented in the encoding it is returned as an
+    /// error.
+    pub(crate) fn encode(&self, s: &str) -> Result<Vec<u8>, char> {
+        let mut buf = Vec::with_capacity(s.len());
+        if self.bom {
+            buf.extend_from_slice(self.encoding.bom());
+        }
+
+        match self.line_ending {
+            LineEnding::Lf => self.encoding.encode(s, &mut buf)?,
+            LineEnding::Crlf => self.encoding.encode(&s.replace('\n', "\r\n"), &mut buf)?,
+        }
+
+        Ok(buf)
+    }
+}
+
+impl fmt::Display for FileFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.encoding != Encoding::Utf8 {
+            write!(f, "{} ", self.encoding)?;
+        }
+        write!(f, "{}", self.line_ending)?;
+        if self.mixed_line_endings {
+            write!(f, " (mixed)")?;
+        }
+        if self.bom {
+            write!(f, " BOM")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn normalize(s: String) -> String {
+    if !s.contains('\r') {
+        return s;
+    }
+
+    // CRLF files are expected to contain '\r' so we only warn about stray carriage returns
+    let s = s.replace("\r\n", "\n");
+    if s.contains('\r') {
+        warn!("normalizing \\r characters to \\n");
+        s.replace('\r', "\n")
+    } else {
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    #[test_case("foo\nbar\n", LineEnding::Lf, false, "foo\nbar\n", false; "lf")]
+    #[test_case("foo\r\nbar\r\n", LineEnding::Crlf, false, "foo\nbar\n", false; "crlf")]
+    #[test_case("\u{feff}foo\r\n", LineEnding::Crlf, true, "foo\n", false; "crlf with bom")]
+    #[test_case("\u{feff}foo\n", LineEnding::Lf, true, "foo\n", false; "lf with bom")]
+    #[test_case("foo\r\nbar\nbaz\r", LineEnding::Crlf, false, "foo\nbar\nbaz\n", true; "mixed")]
+    #[test_case("foo\rbar", LineEnding::Lf, false, "foo\nbar", true; "lone cr")]
+    #[test_case("", LineEnding::Lf, false, "", false; "empty")]
+    #[test]
+    fn decode_works(s: &str, line_ending: LineEnding, bom: bool, expected: &str, mixed: bool) {
+        let (format, content) = FileFormat::decode(s.as_bytes().to_vec());
+        let expected_format = FileFormat {
+            encoding: Encoding::Utf8,
+            line_ending,
+            bom,
+            mixed_line_endings: mixed,
+        };
+
+        assert_eq!(format, expected_format);
+        assert_eq!(content, expected);
+    }
+
+    #[test_case(&[0xFF, 0xFE, b'h', 0, 0xE9, 0, b'\n', 0], Encoding::Utf16Le, true, "hé\n"; "utf16le")]
+    #[test_case(&[0xFE, 0xFF, 0, b'h', 0, 0xE9, 0, b'\n'], Encoding::Utf16Be, true, "hé\n"; "utf16be")]
+    #[test_case(&[0xFF, 0xFE, 0x3D, 0xD8, 0x00, 0xDE], Encoding::Utf16Le, true, "😀"; "utf16 surrogate pair")]
+    #[test_case(b"caf\xe9\n", Encoding::Windows1252, false, "café\n"; "latin1 fallback")]
+    #[test_case(b"\x93quoted\x94 \x80", Encoding::Windows1252, false, "“quoted” €"; "windows 1252 fallback")]
+    #[test_case("café".as_bytes(), Encoding::Utf8, false, "café"; "valid utf8")]
+    #[test]
+    fn detecting_encoding_works(bytes: &[u8], encoding: Encoding, bom: bool, expected: &str) {
+        let (format, content) = FileFormat::decode(bytes.to_vec());
+
+        assert_eq!(format.encoding, encoding);
+        assert_eq!(format.bom, bom);
+        assert_eq!(content, expected);
+    }
+
+    #[test_case(b"caf\xe9", Encoding::Latin1, "café"; "latin1")]
+    #[test_case(b"\x80", Encoding::Latin1, "\u{80}"; "latin1 c1 control")]
+    #[test_case(b"\x80", Encoding::Windows1252, "€"; "windows 1252 euro")]
+    #[test_case(b"caf\xe9", Encoding::Utf8, "caf\u{fffd}"; "invalid utf8 is replaced")]
+    #[test]
+    fn decode_as_works(bytes: &[u8], encoding: Encoding, expected: &str) {
+        let (format, content) = FileFormat::decode_as(bytes.to_vec(), encoding);
+
+        assert_eq!(format.encoding, encoding);
+        assert_eq!(content, expected);
+    }
+
+    #[test_case(b"caf\xc3\xa9", Encoding::Utf8, true; "utf8")]
+    #[test_case(b"caf\xe9", Encoding::Utf8, false; "invalid utf8")]
+    #[test_case(&[b'h', 0], Encoding::Utf16Le, true; "utf16le")]
+    #[test_case(&[b'h', 0, b'i'], Encoding::Utf16Le, false; "utf16le odd length")]
+    #[test_case(&[0, 0xD8, b'h', 0], Encoding::Utf16Le, false; "utf16le unpaired surrogate")]
+    #[test_case(&[0xD8, 0], Encoding::Utf16Be, false; "utf16be unpaired surrogate")]
+    #[test_case(b"caf\xe9", Encoding::Latin1, true; "latin1")]
+    #[test]
+    fn can_decode_works(bytes: &[u8], encoding: Encoding, expected: bool) {
+        assert_eq!(encoding.can_decode(bytes), expected);
+    }
+
+    #[test_case(Encoding::Latin1, "snow ☃", '☃'; "latin1")]
+    #[test_case(Encoding::Windows1252, "snow ☃", '☃'; "windows 1252")]
+    #[test_case(Encoding::Windows1252, "c1 \u{85}", '\u{85}'; "windows 1252 c1 control")]
+    #[test]
+    fn unrepresentable_characters_are_an_error(encoding: Encoding, s: &str, ch: char) {
+        let format = FileFormat {
+            encoding,
+            ..Default::default()
+        };
+
+        assert_eq!(format.encode(s), Err(ch));
+    }
+
+    #[test_case("foo\nbar\n"; "lf")]
+    #[test_case("foo\r\nbar\r\n"; "crlf")]
+    #[test_case("\u{feff}foo\r\nbar\r\n"; "crlf with bom")]
+    #[test_case("\u{feff}foo\nbar"; "lf with bom")]
+    #[test]
+    fn round_trip_is_lossless(s: &str) {
+        let (format, content) = FileFormat::decode(s.as_bytes().to_vec());
+
+        assert_eq!(format.encode(&content), Ok(s.as_bytes().to_vec()));
+    }
+
+    #[test_case(&[0xFF, 0xFE, b'h', 0, b'\r', 0, b'\n', 0]; "utf16le crlf")]
+    #[test_case(&[0xFE, 0xFF, 0, b'h', 0, b'\n']; "utf16be")]
+    #[test_case(b"caf\xe9\r\n\x93\x81\x94"; "windows 1252 crlf")]
+    #[test]
+    fn non_utf8_round_trip_is_lossless(bytes: &[u8]) {
+        let (format, content) = FileFormat::decode(bytes.to_vec());
+
+        assert_eq!(format.encode(&content), Ok(bytes.to_vec()));
+    }
+}
@@ -0,0 +1,152 @@
+//! Hex dump rendering of binary files.
+//!
+//! Each line of the dump shows the byte offset of the line, the hex value of up to 16 bytes
+//! and the printable ASCII characters for those bytes:
+//!
+//!   00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|
+//!
+//! When saving, only the hex column is read back: the offset and ASCII columns are for display
+//! only so edits to them are ignored. Bytes can be inserted or removed by adding or deleting
+//! pairs of hex digits within a line.
+use super::format::{UTF16BE_BOM, UTF16LE_BOM};
+use std::{cmp::min, fmt::Write};
+
+/// The number of bytes shown on each line of the dump
+const BYTES_PER_LINE: usize = 16;
+/// Width of the offset column plus the padding that follows it
+const OFFSET_WIDTH: usize = 10;
+/// How much of a file we check for NUL bytes when deciding if it is binary
+const SNIFF_LEN: usize = 8000;
+
+/// Files containing a NUL byte near their start are treated as binary unless they are UTF-16,
+/// which uses NUL bytes to encode ASCII characters.
+pub(crate) fn is_binary(bytes: &[u8]) -> bool {
+    !(bytes.starts_with(UTF16LE_BOM) || bytes.starts_with(UTF16BE_BOM))
+        && bytes[..min(bytes.len(), SNIFF_LEN)].contains(&0)
+}
+
+/// Render bytes as a hex dump without a trailing newline.
+pub(crate) fn dump(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() / BYTES_PER_LINE * 80 + 80);
+
+    for (i, chunk) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        if i > 0 {
+            s.push('\n');
+        }
+        _ = write!(s, "{:08x}  ", i * BYTES_PER_LINE);
+
+        for j in 0..BYTES_PER_LINE {
+            match chunk.get(j) {
+                Some(b) => _ = write!(s, "{b:02x} "),
+                None => s.push_str("   "),
+            }
+            if j == BYTES_PER_LINE / 2 - 1 {
+                s.push(' ');
+            }
+        }
+
+        s.push_str(" |");
+        s.extend(chunk.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        s.push('|');
+    }
+
+    s
+}
+
+/// Read the bytes back out of the hex column of a dump.
+pub(crate) fn parse(s: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(s.len() / 5);
+
+    for (n, line) in s.lines().enumerate() {
+        // The first token is the offset and the ASCII column begins with a '|'
+        let hex = line
+            .split_whitespace()
+            .skip(1)
+            .take_while(|tok| !tok.starts_with('|'));
+
+        for tok in hex {
+            match u8::from_str_radix(tok, 16) {
+                Ok(b) if tok.len() == 2 => bytes.push(b),
+                _ => return Err(format!("invalid byte '{tok}' on line {}", n + 1)),
+            }
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// The line and column of the first hex digit for a given byte offset within an unmodified
+/// dump.
+pub(crate) fn offset_to_line_and_col(offset: usize) -> (usize, usize) {
+    let (line, i) = (offset / BYTES_PER_LINE, offset % BYTES_PER_LINE);
+    let gap = if i >= BYTES_PER_LINE / 2 { 1 } else { 0 };
+
+    (line, OFFSET_WIDTH + 3 * i + gap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    const BYTES: &[u8] = b"\x7fELF\x02\x01\x01\x00\x00\x00\x00\x00\x00\x00\x00\x00\x03\x00>|";
+    const DUMP: &str = "\
+00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|
+00000010  03 00 3e 7c                                       |..>||";
+
+    #[test_case(b"\x7fELF\x02\x00", true; "nul byte")]
+    #[test_case(b"hello, world", false; "ascii")]
+    #[test_case(b"caf\xe9", false; "latin1")]
+    #[test_case(&[0xFF, 0xFE, b'h', 0], false; "utf16")]
+    #[test_case(b"", false; "empty")]
+    #[test]
+    fn is_binary_works(bytes: &[u8], expected: bool) {
+        assert_eq!(is_binary(bytes), expected);
+    }
+
+    #[test]
+    fn dump_works() {
+        assert_eq!(dump(BYTES), DUMP);
+    }
+
+    #[test]
+    fn parse_round_trips() {
+        assert_eq!(parse(DUMP).unwrap(), BYTES);
+    }
+
+    #[test_case("00000000  61 62  |ab|", b"ab"; "short line")]
+    #[test_case("00000000  61 62 63 64  |ab|", b"abcd"; "inserted bytes")]
+    #[test_case("00000000  61  |ab|\n00000010  62", b"ab"; "ascii column ignored")]
+    #[test_case("00000000  61\n\n00000010  62", b"ab"; "blank lines")]
+    #[test]
+    fn parse_edited_dump_works(s: &str, expected: &[u8]) {
+        assert_eq!(parse(s).unwrap(), expected);
+    }
+
+    #[test_case("00000000  61 6"; "single digit")]
+    #[test_case("00000000  61 zz"; "not hex")]
+    #[test_case("00000000  616"; "too long")]
+    #[test]
+    fn parse_invalid_bytes_errors(s: &str) {
+        assert!(parse(s).is_err());
+    }
+
+    #[test_case(0, (0, 10); "start")]
+    #[test_case(7, (0, 31); "end of first group")]
+    #[test_case(8, (0, 35); "start of second group")]
+    #[test_case(17, (1, 13); "second line")]
+    #[test]
+    fn offset_to_line_and_col_works(offset: usize, expected: (usize, usize)) {
+        assert_eq!(offset_to_line_and_col(offset), expected);
+
+        let (line, col) = expected;
+        let byte = &DUMP.lines().nth(line).unwrap()[col..col + 2];
+        assert_eq!(byte, format!("{:02x}", BYTES[offset]));
+    }
+}
@@ -8,6 +8,7 @@
 //! - https://nullprogram.com/blog/2017/09/07/
 //! - https://coredumped.dev/2023/08/09/text-showdown-gap-buffers-vs-ropes/
 //! - https://code.visualstudio.com/blogs/2018/03/23/text-buffer-reimplementation
+use super::piece_table::Pieces;
 use std::{
     cmp::{max, min, Ordering},
     collections::BTreeMap,
@@ -28,7 +29,7 @@ fn clamp_gap_size(len: usize, min_gap: usize) -> usize {
 }
 
 #[inline]
-fn count_chars(bytes: &[u8]) -> usize {
+pub(super) fn count_chars(bytes: &[u8]) -> usize {
     if bytes.is_empty() {
         return 0;
     }
@@ -277,7 +278,7 @@ impl GapBuffer {
     /// This method will panic if the given character index is out of bounds
     #[inline]
     pub fn char(&self, char_idx: usize) -> char {
-        let byte_idx = self.char_to_raw_byte(char_idx);
+        let byte_idx = self.char_start_raw_byte(char_idx);
 
         // SAFETY: we know that we have valid utf8 data internally
         unsafe { decode_char_at(byte_idx, &self.data) }
@@ -517,7 +518,7 @@ impl GapBuffer {
     /// Remove the requested character index from the visible region of the buffer
     pub fn remove_char(&mut self, char_idx: usize) {
         let idx = self.char_to_byte(char_idx);
-        let len = self.char_len(self.char_to_raw_byte(char_idx));
+        let len = self.char_len(self.char_start_raw_byte(char_idx));
 
         if idx != self.gap_start {
             self.move_gap_to(idx);
@@ -718,6 +719,16 @@ impl GapBuffer {
         self.offset_char_to_raw_byte(char_idx, 0, 0)
     }
 
+    /// The raw byte index of the start of the given character, skipping over the gap if the
+    /// character immediately follows it.
+    #[inline]
+    fn char_start_raw_byte(&self, char_idx: usize) -> usize {
+        match self.char_to_raw_byte(char_idx) {
+            idx if idx == self.gap_start => self.gap_end,
+            idx => idx,
+        }
+    }
+
     /// Allow for skipping to a given byte index before starting the search as an optimisation when we
     /// are searching for multiple positions in sequence (e.g. the start and end of a range).
     fn offset_char_to_raw_byte(
@@ -759,13 +770,16 @@ impl GapBuffer {
     }
 }
 
-/// A view on a region of the GapBuffer.
+/// A view on a region of a [GapBuffer] or [PieceTable][super::PieceTable].
 ///
-/// Slices will become invalidated if the gap is moved from the position they were created with
+/// A slice is made up of a left and right hand side, along with any pieces of a [PieceTable][super::PieceTable]
+/// that fall between them. Slices will become invalidated if the gap is moved from the
+/// position they were created with.
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Slice<'a> {
     from: usize,
     left: &'a [u8],
+    mid: Pieces<'a>,
     right: &'a [u8],
 }
 
@@ -778,6 +792,7 @@ impl<'a> Slice<'a> {
             return Slice {
                 from,
                 left: &gb.data[from..to],
+                mid: Pieces::default(),
                 right: &[],
             };
         }
@@ -787,11 +802,25 @@ impl<'a> Slice<'a> {
         Slice {
             from,
             left: &gb.data[from..gb.gap_start],
+            mid: Pieces::default(),
             right: &gb.data[gb.gap_end..to],
         }
     }
 
-    /// The two sides of this slice as &str references
+    #[inline]
+    pub(super) fn from_pieces(left: &'a [u8], mid: Pieces<'a>, right: &'a [u8]) -> Slice<'a> {
+        Slice {
+            from: 0,
+            left,
+            mid,
+            right,
+        }
+    }
+
+    /// The two sides of this slice as &str references.
+    ///
+    /// Slices of a [PieceTable][super::PieceTable] that span more than two pieces have further content between
+    /// the two sides: use [Slice::chunks] to access all of the content of the slice.
     pub fn as_strs(&self) -> (&str, &str) {
         // SAFETY: we know that we have valid utf8 data internally
         unsafe {
@@ -802,35 +831,64 @@ impl<'a> Slice<'a> {
         }
     }
 
+    /// Iterate over the contiguous regions of memory making up this slice.
+    pub fn chunks(&self) -> impl Iterator<Item = &'a str> + '_ {
+        (0..self.n_parts()).flat_map(|i| {
+            self.part(i).map(|b| {
+                // SAFETY: we know that we have valid utf8 data internally
+                unsafe { std::str::from_utf8_unchecked(b) }
+            })
+        })
+    }
+
     /// Iterate over the characters in this slice
     pub fn chars(self) -> Chars<'a> {
-        Chars { s: self, cur: 0 }
+        Chars {
+            s: self,
+            part: 0,
+            off: 0,
+            cur: 0,
+        }
     }
 
     /// Iterate over the characters in this slice with their corresponding character indices
     pub fn indexed_chars(self, from: usize, rev: bool) -> IdxChars<'a> {
-        let (cur, idx) = if rev {
-            (
-                self.left.len() + self.right.len(),
-                from + count_chars(self.left) + count_chars(self.right),
-            )
+        let (part, off, idx) = if rev {
+            let n_chars: usize = (0..self.n_parts())
+                .flat_map(|i| self.part(i))
+                .map(count_chars)
+                .sum();
+
+            (self.n_parts() - 1, self.right.len(), from + n_chars)
         } else {
-            (0, from)
+            (0, 0, from)
         };
 
         IdxChars {
             s: self,
-            cur,
+            part,
+            off,
             idx,
             rev,
         }
     }
 
-    fn cur_and_data(&self, cur: usize) -> (usize, &[u8]) {
-        if cur < self.left.len() {
-            (cur, self.left)
+    #[inline]
+    fn n_parts(&self) -> usize {
+        self.mid.len() + 2
+    }
+
+    /// The parts of the slice in order: left, each of the pieces in mid, and then right
+    #[inline]
+    fn part(&self, i: usize) -> Option<&'a [u8]> {
+        if i == 0 {
+            Some(self.left)
+        } else if i <= self.mid.len() {
+            self.mid.get(i - 1)
+        } else if i == self.mid.len() + 1 {
+            Some(self.right)
         } else {
-            (cur - self.left.len(), self.right)
+            None
         }
     }
 }
@@ -838,8 +896,9 @@ impl<'a> Slice<'a> {
 impl fmt::Display for Slice<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let mut v = Vec::with_capacity(self.left.len() + self.right.len());
-        v.extend_from_slice(self.left);
-        v.extend_from_slice(self.right);
+        for i in 0..self.n_parts() {
+            v.extend_from_slice(self.part(i).unwrap_or_default());
+        }
 
         match String::from_utf8(v) {
             Ok(s) => write!(f, "{s}"),
@@ -850,9 +909,16 @@ impl fmt::Display for Slice<'_> {
 
 impl<'b> PartialEq<&'b str> for Slice<'_> {
     fn eq(&self, other: &&'b str) -> bool {
-        let b = other.as_bytes();
+        let mut b = other.as_bytes();
+
+        for part in (0..self.n_parts()).flat_map(|i| self.part(i)) {
+            match b.strip_prefix(part) {
+                Some(rest) => b = rest,
+                None => return false,
+            }
+        }
 
-        &b[..self.left.len()] == self.left && &b[self.left.len()..] == self.right
+        b.is_empty()
     }
 }
 
@@ -866,6 +932,11 @@ impl PartialEq<String> for Slice<'_> {
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Chars<'a> {
     s: Slice<'a>,
+    /// The part of the slice we are currently reading from
+    part: usize,
+    /// Byte offset within the current part
+    off: usize,
+    /// Total number of bytes read from the slice
     cur: usize,
 }
 
@@ -873,16 +944,20 @@ impl Iterator for Chars<'_> {
     type Item = char;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.cur >= self.s.left.len() + self.s.right.len() {
-            return None;
-        }
+        loop {
+            let data = self.s.part(self.part)?;
+            if self.off < data.len() {
+                // SAFETY: we know we are in bounds and that we contain valid utf-8 data
+                let ch = unsafe { decode_char_at(self.off, data) };
+                let len = ch.len_utf8();
+                self.off += len;
+                self.cur += len;
+                return Some(ch);
+            }
 
-        let (cur, data) = self.s.cur_and_data(self.cur);
-        // SAFETY: we know we are in bounds and that we contain valid utf-8 data
-        let ch = unsafe { decode_char_at(cur, data) };
-        let len = ch.len_utf8();
-        self.cur += len;
-        Some(ch)
+            self.part += 1;
+            self.off = 0;
+        }
     }
 }
 
@@ -890,7 +965,10 @@ impl Iterator for Chars<'_> {
 #[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct IdxChars<'a> {
     s: Slice<'a>,
-    cur: usize,
+    /// The part of the slice we are currently reading from
+    part: usize,
+    /// Byte offset within the current part
+    off: usize,
     idx: usize,
     rev: bool,
 }
@@ -899,29 +977,35 @@ impl Iterator for IdxChars<'_> {
     type Item = (usize, char);
 
     fn next(&mut self) -> Option<Self::Item> {
-        if (!self.rev && self.cur >= self.s.left.len() + self.s.right.len())
-            || (self.rev && self.cur == 0)
-        {
-            return None;
-        }
+        loop {
+            let data = self.s.part(self.part)?;
+
+            if self.rev {
+                if self.off > 0 {
+                    // SAFETY: we know we are in bounds and that we contain valid utf-8 data
+                    let ch = unsafe { decode_char_ending_at(self.off - 1, data) };
+                    self.off -= ch.len_utf8();
+                    self.idx -= 1;
+                    return Some((self.idx, ch));
+                } else if self.part == 0 {
+                    return None;
+                }
 
-        if self.rev {
-            let (cur, data) = self.s.cur_and_data(self.cur - 1);
-            // SAFETY: we know we are in bounds and that we contain valid utf-8 data
-            let ch = unsafe { decode_char_ending_at(cur, data) };
-            let len = ch.len_utf8();
-            self.idx -= 1;
-            self.cur -= len;
-            Some((self.idx, ch))
-        } else {
-            let (cur, data) = self.s.cur_and_data(self.cur);
-            // SAFETY: we know we are in bounds and that we contain valid utf-8 data
-            let ch = unsafe { decode_char_at(cur, data) };
-            let len = ch.len_utf8();
-            let res = Some((self.idx, ch));
-            self.cur += len;
-            self.idx += 1;
-            res
+                self.part -= 1;
+                self.off = self.s.part(self.part).map_or(0, |d| d.len());
+            } else {
+                if self.off < data.len() {
+                    // SAFETY: we know we are in bounds and that we contain valid utf-8 data
+                    let ch = unsafe { decode_char_at(self.off, data) };
+                    let res = Some((self.idx, ch));
+                    self.off += ch.len_utf8();
+                    self.idx += 1;
+                    return res;
+                }
+
+                self.part += 1;
+                self.off = 0;
+            }
         }
     }
 }
@@ -949,14 +1033,14 @@ const fn utf8_acc_cont_byte(ch: u32, byte: u8) -> u32 {
 /// Checks whether the byte is a UTF-8 continuation byte (i.e., starts with the
 /// bits `10`).
 #[inline]
-const fn utf8_is_cont_byte(byte: u8) -> bool {
+pub(super) const fn utf8_is_cont_byte(byte: u8) -> bool {
     (byte as i8) < -64
 }
 
 /// Decode a utf-8 code point from `bytes` starting at `start`.
 /// `bytes` must contain valid utf-8 data beginning at `start`
 #[inline]
-unsafe fn decode_char_at(start: usize, bytes: &[u8]) -> char {
+pub(super) unsafe fn decode_char_at(start: usize, bytes: &[u8]) -> char {
     // Decode UTF-8
     // SAFETY: `bytes` contains UTF-8-like string data so we have the next byte,
     let x = bytes[start];
@@ -1639,4 +1723,16 @@ mod tests {
             assert_eq!(gb.char(idx), ch);
         }
     }
+
+    #[test]
+    fn char_works_for_the_character_following_the_gap() {
+        let mut gb = GapBuffer::from("hello, 世界!");
+        gb.insert_str(0, "foo");
+
+        assert_eq!(gb.char(3), 'h');
+        gb.move_gap_to(10);
+        assert_eq!(gb.char(10), '世');
+        gb.remove_char(10);
+        assert_eq!(gb.to_string(), "foohello, 界!");
+    }
 }
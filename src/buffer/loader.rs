@@ -0,0 +1,276 @@
+//! Loading the content of very large files on a background thread.
+//!
+//! Files larger than the `background-load-mb` config property are opened as an empty, read
+//! only buffer and their content is streamed in from a background thread as a sequence of
+//! [FileChunk]s so that the editor remains responsive while they load. Each chunk is appended
+//! to the [PieceTable](super::PieceTable) holding the buffer content as a new block.
+use super::{format::UTF8_BOM, hex};
+use crate::{config_handle, input::Event};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc::Sender,
+        Arc,
+    },
+    thread,
+};
+use tracing::{debug, warn};
+
+const MB: u64 = 1024 * 1024;
+const SNIFF_LEN: u64 = 64 * 1024;
+pub(super) const CHUNK_LEN: u64 = 4 * MB;
+
+static NEXT_LOAD_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A piece of a file being loaded in the background.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileChunk {
+    /// The next section of the file content
+    Data(String),
+    /// The whole file has been read
+    Done,
+    /// Reading the file failed
+    Failed(String),
+}
+
+/// The state of a file that is being loaded in the background.
+#[derive(Debug)]
+pub(crate) struct Loading {
+    pub(crate) load_id: u64,
+    path: PathBuf,
+    total: u64,
+    loaded: u64,
+    error: Option<String>,
+    cancelled: Option<Arc<AtomicBool>>,
+}
+
+impl Drop for Loading {
+    fn drop(&mut self) {
+        if let Some(cancelled) = self.cancelled.as_ref() {
+            cancelled.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Loading {
+    /// Check whether the file at the given path should be loaded in the background.
+    ///
+    /// Only large utf-8 files with LF line endings are loaded in the background as any other
+    /// file needs to be converted as a whole when it is read. The format is determined from the
+    /// start of the file.
+    pub(super) fn try_new(path: &Path) -> io::Result<Option<Self>> {
+        let total = match path.metadata() {
+            Ok(m) if m.is_file() && m.len() >= config_handle!().background_load_mb * MB => m.len(),
+            _ => return Ok(None),
+        };
+
+        let mut head = Vec::with_capacity(SNIFF_LEN as usize);
+        File::open(path)?.take(SNIFF_LEN).read_to_end(&mut head)?;
+        let is_utf8 = match std::str::from_utf8(&head) {
+            Ok(_) => true,
+            // The sniffed region may end part way through a character
+            Err(e) => e.error_len().is_none(),
+        };
+
+        if !is_utf8 || head.starts_with(UTF8_BOM) || hex::is_binary(&head) || head.contains(&b'\r')
+        {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            load_id: NEXT_LOAD_ID.fetch_add(1, Ordering::Relaxed),
+            path: path.to_path_buf(),
+            total,
+            loaded: 0,
+            error: None,
+            cancelled: None,
+        }))
+    }
+
+    #[cfg(test)]
+    pub(crate) fn new_stubbed(load_id: u64, total: u64) -> Self {
+        Self {
+            load_id,
+            path: PathBuf::new(),
+            total,
+            loaded: 0,
+            error: None,
+            cancelled: None,
+        }
+    }
+
+    pub(crate) fn is_started(&self) -> bool {
+        self.cancelled.is_some()
+    }
+
+    pub(super) fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub(super) fn record_chunk(&mut self, n_bytes: usize) {
+        self.loaded += n_bytes as u64;
+    }
+
+    pub(super) fn record_error(&mut self, e: String) {
+        self.error = Some(e);
+    }
+
+    /// How much of the file has been loaded so far as a percentage
+    pub(super) fn percent(&self) -> u64 {
+        self.loaded.min(self.total) * 100 / self.total.max(1)
+    }
+
+    /// Spawn a thread to read the file, sending each chunk to the editor as it is read.
+    pub(crate) fn start(&mut self, id: usize, tx: Sender<Event>) {
+        let cancelled = Arc::new(AtomicBool::new(false));
+        self.cancelled = Some(cancelled.clone());
+        let (path, load_id) = (self.path.clone(), self.load_id);
+        debug!(%id, %load_id, ?path, "loading file in the background");
+
+        thread::spawn(move || {
+            let send = |chunk| {
+                tx.send(Event::FileChunk { id, load_id, chunk }).is_ok()
+                    && !cancelled.load(Ordering::Relaxed)
+            };
+
+            match read_chunks(&path, send) {
+                Ok(()) => (),
+                Err(e) => {
+                    warn!(?path, "unable to load file: {e}");
+                    _ = tx.send(Event::FileChunk {
+                        id,
+                        load_id,
+                        chunk: FileChunk::Failed(e.to_string()),
+                    });
+                }
+            }
+        });
+    }
+}
+
+/// Read the file in chunks of valid utf-8, stopping early if send returns false.
+///
+/// Only the start of the file is checked before loading begins so any invalid utf-8 found
+/// later on is returned as an error rather than being replaced: saving the buffer would
+/// otherwise silently corrupt the file.
+fn read_chunks(path: &Path, mut send: impl FnMut(FileChunk) -> bool) -> io::Result<()> {
+    if for_each_chunk(File::open(path)?, |s| send(FileChunk::Data(s)))? {
+        send(FileChunk::Done);
+    }
+
+    Ok(())
+}
+
+/// Read utf-8 text in chunks of at most [CHUNK_LEN] bytes, passing each to f until it returns
+/// false. Returns whether the whole of the input was read.
+///
+/// Characters are never split across chunks and invalid utf-8 is returned as an
+/// [io::ErrorKind::InvalidData] error.
+pub(super) fn for_each_chunk(
+    mut r: impl Read,
+    mut f: impl FnMut(String) -> bool,
+) -> io::Result<bool> {
+    // Leave room for a held back partial character so that reading a chunk never reallocates
+    let cap = CHUNK_LEN as usize + 4;
+    let mut buf = Vec::with_capacity(cap);
+
+    loop {
+        let n = (&mut r).take(CHUNK_LEN).read_to_end(&mut buf)?;
+        if n == 0 {
+            if !buf.is_empty() {
+                let s = String::from_utf8(buf).map_err(|_| invalid_utf8())?;
+                return Ok(f(s));
+            }
+            return Ok(true);
+        }
+
+        // Hold back a character that is split across chunks until the rest of it has been read
+        let valid = match std::str::from_utf8(&buf) {
+            Ok(_) => buf.len(),
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            Err(_) => return Err(invalid_utf8()),
+        };
+        let rest = buf.split_off(valid);
+        let s = String::from_utf8(buf).map_err(|_| invalid_utf8())?;
+        buf = Vec::with_capacity(cap);
+        buf.extend_from_slice(&rest);
+
+        if !f(s) {
+            return Ok(false);
+        }
+    }
+}
+
+fn invalid_utf8() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "file contains invalid utf-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, process};
+
+    #[test]
+    fn read_chunks_does_not_split_characters() {
+        // The leading 'a' offsets the two byte characters so that one spans the chunk boundary
+        let s = format!("a{}", "ü".repeat(CHUNK_LEN as usize / 2 + 1));
+        let path = env::temp_dir().join(format!("ad-loader-{}", process::id()));
+        fs::write(&path, &s).unwrap();
+
+        let mut chunks = Vec::new();
+        read_chunks(&path, |chunk| {
+            chunks.push(chunk);
+            true
+        })
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(chunks.pop(), Some(FileChunk::Done));
+        assert!(chunks.len() > 1, "expected multiple chunks");
+        let loaded: String = chunks
+            .into_iter()
+            .map(|c| match c {
+                FileChunk::Data(s) => s,
+                c => panic!("unexpected chunk {c:?}"),
+            })
+            .collect();
+
+        assert_eq!(loaded, s);
+    }
+
+    #[test]
+    fn read_chunks_fails_on_invalid_utf8_after_the_sniffed_region() {
+        let mut bytes = vec![b'a'; SNIFF_LEN as usize + 10];
+        bytes.push(0xff);
+        bytes.extend_from_slice(b"more text\n");
+        let path = env::temp_dir().join(format!("ad-loader-invalid-{}", process::id()));
+        fs::write(&path, &bytes).unwrap();
+
+        let mut chunks = Vec::new();
+        let res = read_chunks(&path, |chunk| {
+            chunks.push(chunk);
+            true
+        });
+        fs::remove_file(&path).unwrap();
+
+        let err = res.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(chunks.is_empty(), "expected no chunks, got {chunks:?}");
+    }
+
+    #[test]
+    fn read_chunks_fails_on_a_truncated_character_at_the_end_of_the_file() {
+        let mut bytes = vec![b'a'; SNIFF_LEN as usize + 10];
+        bytes.extend_from_slice(&"ü".as_bytes()[..1]);
+        let path = env::temp_dir().join(format!("ad-loader-truncated-{}", process::id()));
+        fs::write(&path, &bytes).unwrap();
+
+        let res = read_chunks(&path, |_| true);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,120 @@
+//! Named marks that keep track of a position in a buffer as it is edited.
+use crate::dot::{Cur, Dot, Range};
+use std::collections::BTreeMap;
+
+/// The mark used by the sam `k` command and `'` address when no name is given
+pub(crate) const DEFAULT_MARK: char = '\'';
+
+/// The mark recording where dot was before the most recent jump within a buffer (vim's `''`)
+pub(crate) const JUMP_MARK: char = '`';
+
+/// Whether or not the given character is a valid mark name
+pub(crate) fn is_valid_mark(name: char) -> bool {
+    name.is_ascii_lowercase() || name == DEFAULT_MARK
+}
+
+/// The marks set for a buffer.
+///
+/// Marks are updated by the [Buffer](super::Buffer) as edits are made (including when undoing
+/// and redoing changes) so that they continue to refer to the same text: inserting before a mark
+/// shifts it forward, deleting before a mark shifts it back and deleting the text a mark refers
+/// to collapses the mark to where the deleted text used to be. Marks are held separately from
+/// the edit log so that they are kept when the buffer is reloaded or its undo history restored.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Marks {
+    inner: BTreeMap<char, Dot>,
+}
+
+impl Marks {
+    pub(crate) fn get(&self, name: char) -> Option<Dot> {
+        self.inner.get(&name).copied()
+    }
+
+    pub(crate) fn set(&mut self, name: char, dot: Dot) {
+        self.inner.insert(name, dot);
+    }
+
+    /// Clamp marks to lie within a buffer of `n_chars` characters.
+    pub(super) fn clamp_idx(&mut self, n_chars: usize) {
+        for dot in self.inner.values_mut() {
+            dot.clamp_idx(n_chars);
+        }
+    }
+
+    /// Shift marks following an insertion of `n` characters at `idx`.
+    pub(super) fn insert(&mut self, idx: usize, n: usize) {
+        self.update(|c| shift_for_insert(c, idx, n));
+    }
+
+    /// Shift marks following the deletion of `n` characters starting at `idx`.
+    pub(super) fn delete(&mut self, idx: usize, n: usize) {
+        self.update(|c| shift_for_delete(c, idx, n));
+    }
+
+    fn update(&mut self, f: impl Fn(&mut Cur)) {
+        for dot in self.inner.values_mut() {
+            let Range {
+                mut start,
+                mut end,
+                start_active,
+            } = dot.as_range();
+            f(&mut start);
+            f(&mut end);
+            *dot = Dot::from(Range::from_cursors(start, end, start_active)).collapse_null_range();
+        }
+    }
+}
+
+/// Shift a cursor following an insertion of `n` characters at `idx`.
+pub(super) fn shift_for_insert(c: &mut Cur, idx: usize, n: usize) {
+    if c.idx >= idx {
+        c.idx += n;
+    }
+}
+
+/// Shift a cursor following the deletion of `n` characters starting at `idx`.
+pub(super) fn shift_for_delete(c: &mut Cur, idx: usize, n: usize) {
+    if c.idx >= idx + n {
+        c.idx -= n;
+    } else if c.idx > idx {
+        c.idx = idx;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    fn marks(from: usize, to: usize) -> Marks {
+        let mut m = Marks::default();
+        m.set('a', Dot::from_char_indices(from, to).collapse_null_range());
+        m
+    }
+
+    #[test_case(0, 3, (8, 11); "before")]
+    #[test_case(5, 3, (8, 11); "at the start")]
+    #[test_case(6, 3, (5, 11); "inside")]
+    #[test_case(8, 3, (5, 11); "at the end")]
+    #[test_case(9, 3, (5, 8); "after")]
+    #[test]
+    fn insert_works(idx: usize, n: usize, expected: (usize, usize)) {
+        let mut m = marks(5, 8);
+        m.insert(idx, n);
+
+        assert_eq!(m.get('a').unwrap().as_char_indices(), expected);
+    }
+
+    #[test_case(0, 3, (2, 5); "before")]
+    #[test_case(3, 3, (3, 5); "overlapping the start")]
+    #[test_case(6, 2, (5, 6); "inside")]
+    #[test_case(4, 10, (4, 4); "containing the mark")]
+    #[test_case(9, 3, (5, 8); "after")]
+    #[test]
+    fn delete_works(idx: usize, n: usize, expected: (usize, usize)) {
+        let mut m = marks(5, 8);
+        m.delete(idx, n);
+
+        assert_eq!(m.get('a').unwrap().as_char_indices(), expected);
+    }
+}
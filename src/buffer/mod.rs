@@ -1,13 +1,13 @@
 //! A [Buffer] represents a single file or in memory text buffer open within the editor.
 use crate::{
     config_handle,
-    dot::{find::find_forward_wrapping, Cur, Dot, Range, TextObject},
+    dot::{find::find_forward_wrapping, Cur, Dot, Range, Scope, TextObject},
     editor::Action,
     exec::{Addr, Address, IterBoundedChars},
     fsys::InputFilter,
     key::Input,
     lsp::Coords,
-    util::normalize_line_endings,
+    util::{ad_dir, atomic_write, mirrored_path, normalize_line_endings},
     MAX_NAME_LEN, UNNAMED_BUFFER,
 };
 use ad_event::Source;
@@ -15,22 +15,48 @@ use std::{
     cmp::min,
     fs,
     io::{self, ErrorKind},
+    mem,
     path::{Path, PathBuf},
     time::SystemTime,
 };
 use tracing::debug;
 
 mod buffers;
+mod comment;
 mod edit;
+mod filetype;
+mod folds;
+mod format;
+mod hex;
 mod internal;
-
-use edit::{Edit, EditLog, Kind, Txt};
+mod loader;
+mod marks;
+mod piece_table;
+mod surround;
+mod text;
+mod typing;
+mod undo_file;
+
+pub(crate) use edit::parse_tree_line;
+use edit::{Edit, EditLog, HistoryStep, Kind, Transaction, Txt};
+pub(crate) use filetype::extensions_for;
+use folds::Folds;
+pub(crate) use format::{Encoding, FileFormat, LineEnding};
 pub use internal::{Chars, GapBuffer, IdxChars, Slice};
+pub use loader::FileChunk;
+pub(crate) use loader::Loading;
+use marks::{is_valid_mark, Marks};
+pub(crate) use marks::{DEFAULT_MARK, JUMP_MARK};
+pub use piece_table::PieceTable;
+pub use text::Text;
+use typing::AutoClosed;
 
 pub(crate) use buffers::{BufferId, Buffers};
+pub(crate) use surround::{ANY_BRACKET as SURROUND_ANY_BRACKET, TAG as SURROUND_TAG};
 
 pub(crate) const DEFAULT_OUTPUT_BUFFER: &str = "+output";
 const HTTPS: &str = "https://";
+const MB: u64 = 1024 * 1024;
 const HTTP: &str = "http://";
 
 // Used to inform the editor that further action needs to be taken by it after a Buffer has
@@ -42,33 +68,31 @@ pub(crate) enum ActionOutcome {
 }
 
 /// Buffer kinds control how each buffer interacts with the rest of the editor functionality
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub(crate) enum BufferKind {
     /// A regular buffer that is backed by a file on disk.
     File(PathBuf),
     /// A directory buffer that is modifyable but cannot be saved
     Directory(PathBuf),
+    /// A hex dump of a binary file on disk that is converted back to bytes when saved
+    Hex(PathBuf),
     /// An in-memory buffer that is not exposed through fsys
     Virtual(String),
     /// An in-memory buffer holding output from commands run within a given directory
     Output(String),
     /// A currently un-named buffer that can be converted to a File buffer when named
+    #[default]
     Unnamed,
     /// State for an active mini-buffer
     MiniBuffer,
 }
 
-impl Default for BufferKind {
-    fn default() -> Self {
-        Self::Unnamed
-    }
-}
-
 impl BufferKind {
     fn display_name(&self) -> String {
         match self {
             BufferKind::File(p) => p.display().to_string(),
             BufferKind::Directory(p) => p.display().to_string(),
+            BufferKind::Hex(p) => p.display().to_string(),
             BufferKind::Virtual(s) => s.clone(),
             BufferKind::Output(s) => s.clone(),
             BufferKind::Unnamed => UNNAMED_BUFFER.to_string(),
@@ -87,15 +111,20 @@ impl BufferKind {
     /// The directory containing the file backing this buffer (if any).
     fn dir(&self) -> Option<&Path> {
         match &self {
-            BufferKind::File(p) => p.parent(),
+            BufferKind::File(p) | BufferKind::Hex(p) => p.parent(),
             BufferKind::Directory(p) => Some(p.as_ref()),
             BufferKind::Output(s) => Path::new(s).parent(),
             _ => None,
         }
     }
 
+    /// Whether or not this buffer is saved to a file on disk
     pub(crate) fn is_file(&self) -> bool {
-        matches!(self, Self::File(_))
+        matches!(self, Self::File(_) | Self::Hex(_))
+    }
+
+    pub(crate) fn is_hex(&self) -> bool {
+        matches!(self, Self::Hex(_))
     }
 
     pub(crate) fn is_dir(&self) -> bool {
@@ -109,7 +138,14 @@ impl BufferKind {
         format!("{}/{DEFAULT_OUTPUT_BUFFER}", path.display())
     }
 
-    fn try_kind_and_content_from_path(path: PathBuf) -> io::Result<(Self, String)> {
+    /// Load the content at the given path, detecting the encoding of files unless one is
+    /// provided. Binary files are loaded as a hex dump if no encoding is given and files larger
+    /// than the large-file-mb config property are held in a piece table if they can be edited in
+    /// place.
+    fn try_kind_and_content_from_path(
+        path: PathBuf,
+        encoding: Option<Encoding>,
+    ) -> io::Result<(Self, Text, FileFormat)> {
         match path.metadata() {
             Ok(m) if m.is_dir() => {
                 let mut raw_entries = Vec::new();
@@ -126,26 +162,101 @@ impl BufferKind {
                 let mut raw = format!("{}\n\n..\n", path.display());
                 raw.push_str(&raw_entries.join("\n"));
 
-                Ok((Self::Directory(path), raw))
+                Ok((Self::Directory(path), raw.into(), FileFormat::default()))
             }
 
-            _ => {
-                let mut raw = match fs::read_to_string(&path) {
-                    Ok(contents) => normalize_line_endings(contents),
-                    Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
-                    Err(e) => return Err(e),
-                };
-
-                if raw.ends_with('\n') {
-                    raw.pop();
+            Ok(m) if encoding.is_none() && m.len() >= config_handle!().large_file_mb * MB => {
+                match PieceTable::open(&path)? {
+                    Some(pt) => Ok((Self::File(path), Text::Pieces(pt), FileFormat::default())),
+                    // Files needing conversion are read again in full to be decoded
+                    None => {
+                        Self::try_kind_and_content_from_bytes(path.clone(), fs::read(&path), None)
+                    }
                 }
+            }
+
+            _ => {
+                let bytes = fs::read(&path);
+                Self::try_kind_and_content_from_bytes(path, bytes, encoding)
+            }
+        }
+    }
 
-                Ok((Self::File(path), raw))
+    /// Decode the content of a file, falling back to a hex dump for binary files if no
+    /// encoding is given.
+    fn try_kind_and_content_from_bytes(
+        path: PathBuf,
+        bytes: io::Result<Vec<u8>>,
+        encoding: Option<Encoding>,
+    ) -> io::Result<(Self, Text, FileFormat)> {
+        let (format, mut raw) = match (bytes, encoding) {
+            (Ok(bytes), None) if hex::is_binary(&bytes) => {
+                return Ok((
+                    Self::Hex(path),
+                    hex::dump(&bytes).into(),
+                    FileFormat::default(),
+                ));
+            }
+            // Replacing invalid content would corrupt the file when the buffer is saved
+            (Ok(bytes), Some(enc)) if !enc.can_decode(&bytes) => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidData,
+                    format!("file content is not valid {enc}"),
+                ));
             }
+            (Ok(bytes), Some(enc)) => FileFormat::decode_as(bytes, enc),
+            (Ok(bytes), None) => FileFormat::decode(bytes),
+            (Err(e), _) if e.kind() == ErrorKind::NotFound => Default::default(),
+            (Err(e), _) => return Err(e),
+        };
+
+        if raw.ends_with('\n') {
+            raw.pop();
         }
+
+        Ok((Self::File(path), raw.into(), format))
     }
 }
 
+/// Whether or not an action modifies the content of a buffer
+fn is_edit(a: &Action) -> bool {
+    matches!(
+        a,
+        Action::Delete
+            | Action::InsertChar { .. }
+            | Action::InsertString { .. }
+            | Action::Earlier { .. }
+            | Action::Later { .. }
+            | Action::Redo
+            | Action::RepeatLastEdit
+            | Action::SurroundAdd { .. }
+            | Action::SurroundChange { .. }
+            | Action::SurroundDelete { .. }
+            | Action::ToggleComment
+            | Action::Undo
+            | Action::RawInput {
+                i: Input::Char(_) | Input::Return | Input::Tab
+            }
+    )
+}
+
+/// Copy the current state of a file on disk to ~/.ad/backup, mirroring its full path.
+fn backup_file(path: &Path) -> io::Result<()> {
+    let path = match path.canonicalize() {
+        Ok(p) => p,
+        // Nothing to back up for a new file
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let backup = mirrored_path(&ad_dir("backup"), &path);
+    if let Some(dir) = backup.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::copy(&path, &backup)?;
+
+    Ok(())
+}
+
 /// Internal state for a text buffer backed by a file on disk
 #[derive(Debug)]
 pub struct Buffer {
@@ -153,30 +264,59 @@ pub struct Buffer {
     pub(crate) kind: BufferKind,
     pub(crate) dot: Dot,
     pub(crate) xdot: Dot,
-    pub(crate) txt: GapBuffer,
+    pub(crate) txt: Text,
     pub(crate) cached_rx: usize,
     pub(crate) last_save: SystemTime,
     pub(crate) dirty: bool,
+    /// Whether the swap file for this buffer needs updating
+    pub(crate) swap_pending: bool,
     pub(crate) input_filter: Option<InputFilter>,
+    /// The line ending style and BOM to restore when saving
+    pub(crate) format: FileFormat,
+    /// Set while the content of a large file is being read in the background
+    pub(crate) loading: Option<Loading>,
     edit_log: EditLog,
+    /// Named marks that are kept up to date as edits are made to the buffer
+    marks: Marks,
+    /// Folded regions that are kept up to date as edits are made to the buffer
+    folds: Folds,
+    /// Closing characters inserted by auto-pairs that can be typed over
+    auto_closed: AutoClosed,
+    /// Incremented each time the content of the buffer changes
+    version: usize,
 }
 
 impl Buffer {
     /// As the name implies, this method MUST be called with the full cannonical file path
     pub(super) fn new_from_canonical_file_path(id: usize, path: PathBuf) -> io::Result<Self> {
-        let (kind, raw) = BufferKind::try_kind_and_content_from_path(path.clone())?;
+        let loading = Loading::try_new(&path)?;
+        let (kind, txt, format) = match loading {
+            Some(_) => (
+                BufferKind::File(path),
+                Text::Pieces(PieceTable::default()),
+                FileFormat::default(),
+            ),
+            None => BufferKind::try_kind_and_content_from_path(path, None)?,
+        };
 
         Ok(Self {
             id,
             kind,
             dot: Dot::default(),
             xdot: Dot::default(),
-            txt: GapBuffer::from(raw),
+            txt,
             cached_rx: 0,
             last_save: SystemTime::now(),
             dirty: false,
+            swap_pending: false,
             edit_log: EditLog::default(),
+            marks: Marks::default(),
+            folds: Folds::default(),
+            auto_closed: AutoClosed::default(),
+            version: 0,
             input_filter: None,
+            format,
+            loading,
         })
     }
 
@@ -187,7 +327,7 @@ impl Buffer {
         }
 
         let path = match &self.kind {
-            BufferKind::File(p) => p,
+            BufferKind::File(p) | BufferKind::Hex(p) => p,
             _ => return Ok(false),
         };
 
@@ -211,7 +351,18 @@ impl Buffer {
             }
         }
 
-        let contents = self.contents();
+        let encoded = if self.kind.is_hex() {
+            hex::parse(&self.txt.to_string())
+        } else {
+            self.format
+                .encode(&self.str_contents())
+                .map_err(|ch| format!("{ch:?} can not be encoded as {}", self.format.encoding))
+        };
+
+        let contents = match encoded {
+            Ok(contents) => contents,
+            Err(e) => return format!("Unable to save buffer: {e}"),
+        };
         let n_lines = self.len_lines();
         let display_path = match path.canonicalize() {
             Ok(cp) => cp.display().to_string(),
@@ -219,60 +370,191 @@ impl Buffer {
         };
         let n_bytes = contents.len();
 
-        match fs::write(path, contents) {
+        if config_handle!().backup {
+            if let Err(e) = backup_file(&path) {
+                return format!("Unable to write backup: {e}");
+            }
+        }
+
+        match atomic_write(&path, &contents) {
             Ok(_) => {
                 self.dirty = false;
+                self.edit_log.mark_saved();
+                self.swap_pending = true;
                 self.last_save = SystemTime::now();
-                format!("\"{display_path}\" {n_lines}L {n_bytes}B written")
+                let mut msg = format!("\"{display_path}\" {n_lines}L {n_bytes}B written");
+                if mem::take(&mut self.format.mixed_line_endings) && !self.kind.is_hex() {
+                    msg.push_str(&format!(
+                        " (mixed line endings converted to {})",
+                        self.format.line_ending
+                    ));
+                }
+
+                msg
             }
             Err(e) => format!("Unable to save buffer: {e}"),
         }
     }
 
     pub(super) fn reload_from_disk(&mut self) -> String {
+        self.reload_from_disk_with_encoding(None)
+    }
+
+    /// Reload the buffer from disk, decoding it using the given encoding rather than the one
+    /// that was detected when the file was first opened.
+    ///
+    /// As reloading discards the edit log, buffers with unsaved changes are left alone.
+    pub(super) fn reopen_with_encoding(&mut self, encoding: Encoding) -> String {
+        if !self.kind.is_file() {
+            return "Buffer is not backed by a file on disk".to_string();
+        } else if self.dirty {
+            return "No write since last change".to_string();
+        }
+
+        self.reload_from_disk_with_encoding(Some(encoding))
+    }
+
+    fn reload_from_disk_with_encoding(&mut self, encoding: Option<Encoding>) -> String {
         let path = match &self.kind {
-            BufferKind::File(p) | BufferKind::Directory(p) => p,
+            BufferKind::File(p) | BufferKind::Directory(p) | BufferKind::Hex(p) => p.clone(),
             _ => return "Buffer is not backed by a file on disk".to_string(),
         };
 
         debug!(id=%self.id, path=%path.as_os_str().to_string_lossy(), "reloading buffer state from disk");
-        let raw = match BufferKind::try_kind_and_content_from_path(path.to_path_buf()) {
-            Ok((_, raw)) => raw,
+        let loading = match encoding {
+            Some(_) => None,
+            None => match Loading::try_new(&path) {
+                Ok(loading) => loading,
+                Err(e) => return format!("Error reloading buffer: {e}"),
+            },
+        };
+
+        let res = match loading {
+            Some(_) => Ok((
+                BufferKind::File(path.clone()),
+                Text::Pieces(PieceTable::default()),
+                FileFormat::default(),
+            )),
+            None => BufferKind::try_kind_and_content_from_path(path.clone(), encoding),
+        };
+
+        let txt = match res {
+            // Files may switch between text and hex views if their content or encoding changes
+            Ok((kind, txt, format)) => {
+                self.kind = kind;
+                self.format = format;
+                txt
+            }
             Err(e) => return format!("Error reloading buffer: {e}"),
         };
 
-        let n_chars = raw.len();
-        self.txt = GapBuffer::from(raw);
-        self.dot.clamp_idx(n_chars);
-        self.xdot.clamp_idx(n_chars);
+        self.replace_txt(txt);
         self.edit_log.clear();
         self.dirty = false;
+        self.swap_pending = true;
         self.last_save = SystemTime::now();
-
-        let n_lines = self.txt.len_lines();
-        let n_bytes = self.txt.len();
-        debug!(%n_bytes, "reloaded buffer content");
+        self.loading = loading;
 
         let display_path = match path.canonicalize() {
             Ok(cp) => cp.display().to_string(),
             Err(_) => path.display().to_string(),
         };
 
+        if self.is_loading() {
+            return format!("\"{display_path}\" loading in the background");
+        }
+
+        let n_lines = self.txt.len_lines();
+        let n_bytes = self.txt.len();
+        debug!(%n_bytes, "reloaded buffer content");
+
         format!("\"{display_path}\" {n_lines}L {n_bytes}B loaded")
     }
 
+    /// Whether or not the content of this buffer is still being loaded in the background.
+    ///
+    /// Buffers are read only while loading: searches and other operations that only read from the
+    /// buffer see the content that has been loaded so far.
+    pub(crate) fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    /// Whether or not loading this buffer in the background failed
+    pub(crate) fn load_failed(&self) -> bool {
+        self.loading.as_ref().is_some_and(|l| l.error().is_some())
+    }
+
+    /// A summary of the progress of a background load for display in the status bar
+    pub(crate) fn load_status(&self) -> Option<String> {
+        let loading = self.loading.as_ref()?;
+        match loading.error() {
+            Some(_) => Some("[load failed]".to_string()),
+            None => Some(format!("[loading {}%]", loading.percent())),
+        }
+    }
+
+    /// Append the next chunk of a file being loaded in the background, returning a message to
+    /// display to the user once loading has finished.
+    ///
+    /// Chunks from a previous load of the file (before the buffer was reloaded) are ignored.
+    pub(crate) fn handle_file_chunk(&mut self, load_id: u64, chunk: FileChunk) -> Option<String> {
+        let loading = match self.loading.as_mut() {
+            Some(loading) if loading.load_id == load_id => loading,
+            _ => return None,
+        };
+
+        match chunk {
+            FileChunk::Data(s) => {
+                loading.record_chunk(s.len());
+                self.txt.append_block(s);
+                self.version += 1;
+                None
+            }
+
+            FileChunk::Done => {
+                self.loading = None;
+                let n_chars = self.txt.len_chars();
+                if n_chars > 0 && self.txt.char(n_chars - 1) == '\n' {
+                    self.txt.remove_char(n_chars - 1);
+                    self.version += 1;
+                }
+                self.dot.clamp_idx(self.txt.len_chars());
+                self.xdot.clamp_idx(self.txt.len_chars());
+
+                Some(format!(
+                    "\"{}\" {}L {}B loaded",
+                    self.full_name(),
+                    self.txt.len_lines(),
+                    self.txt.len()
+                ))
+            }
+
+            FileChunk::Failed(e) => {
+                loading.record_error(e.clone());
+                Some(format!("Error loading {}: {e}", self.display_name()))
+            }
+        }
+    }
+
     pub(super) fn new_minibuffer() -> Self {
         Self {
             id: usize::MAX,
             kind: BufferKind::MiniBuffer,
             dot: Default::default(),
             xdot: Default::default(),
-            txt: GapBuffer::from(""),
+            txt: Text::from(""),
             cached_rx: 0,
             last_save: SystemTime::now(),
             dirty: false,
+            swap_pending: false,
             edit_log: Default::default(),
+            marks: Default::default(),
+            folds: Default::default(),
+            auto_closed: Default::default(),
+            version: 0,
             input_filter: None,
+            format: FileFormat::default(),
+            loading: None,
         }
     }
 
@@ -283,12 +565,19 @@ impl Buffer {
             kind: BufferKind::Unnamed,
             dot: Dot::default(),
             xdot: Dot::default(),
-            txt: GapBuffer::from(normalize_line_endings(content.to_string())),
+            txt: Text::from(normalize_line_endings(content.to_string())),
             cached_rx: 0,
             last_save: SystemTime::now(),
             dirty: false,
+            swap_pending: false,
             edit_log: EditLog::default(),
+            marks: Marks::default(),
+            folds: Folds::default(),
+            auto_closed: AutoClosed::default(),
+            version: 0,
             input_filter: None,
+            format: FileFormat::default(),
+            loading: None,
         }
     }
 
@@ -307,12 +596,19 @@ impl Buffer {
             kind: BufferKind::Virtual(name.into()),
             dot: Dot::default(),
             xdot: Dot::default(),
-            txt: GapBuffer::from(content),
+            txt: Text::from(content),
             cached_rx: 0,
             last_save: SystemTime::now(),
             dirty: false,
+            swap_pending: false,
             edit_log: EditLog::default(),
+            marks: Marks::default(),
+            folds: Folds::default(),
+            auto_closed: AutoClosed::default(),
+            version: 0,
             input_filter: None,
+            format: FileFormat::default(),
+            loading: None,
         }
     }
 
@@ -324,12 +620,19 @@ impl Buffer {
             kind: BufferKind::Output(name),
             dot: Dot::default(),
             xdot: Dot::default(),
-            txt: GapBuffer::from(normalize_line_endings(content)),
+            txt: Text::from(normalize_line_endings(content)),
             cached_rx: 0,
             last_save: SystemTime::now(),
             dirty: false,
+            swap_pending: false,
             edit_log: EditLog::default(),
+            marks: Marks::default(),
+            folds: Folds::default(),
+            auto_closed: AutoClosed::default(),
+            version: 0,
             input_filter: None,
+            format: FileFormat::default(),
+            loading: None,
         }
     }
 
@@ -345,6 +648,7 @@ impl Buffer {
         match &self.kind {
             BufferKind::File(p) => p.to_str().expect("valid unicode"),
             BufferKind::Directory(p) => p.to_str().expect("valid unicode"),
+            BufferKind::Hex(p) => p.to_str().expect("valid unicode"),
             BufferKind::Virtual(s) => s,
             BufferKind::Output(s) => s,
             BufferKind::Unnamed => UNNAMED_BUFFER,
@@ -671,6 +975,13 @@ impl Buffer {
 
     /// The error result of this function is an error string that should be displayed to the user
     pub(crate) fn handle_action(&mut self, a: Action, source: Source) -> Option<ActionOutcome> {
+        if self.is_loading() && is_edit(&a) {
+            return Some(ActionOutcome::SetStatusMessage(format!(
+                "{} is read only while it is loading",
+                self.display_name()
+            )));
+        }
+
         match a {
             Action::Delete => {
                 let (c, deleted) = self.delete_dot(self.dot, Some(source));
@@ -679,6 +990,14 @@ impl Buffer {
                 self.xdot.clamp_idx(self.txt.len_chars());
                 return deleted.map(ActionOutcome::SetClipboard);
             }
+            Action::InsertChar { c } if source == Source::Keyboard => {
+                self.dot = Dot::Cur {
+                    c: self.insert_typed_char(c),
+                };
+                self.dot.clamp_idx(self.txt.len_chars());
+                self.xdot.clamp_idx(self.txt.len_chars());
+                return None;
+            }
             Action::InsertChar { c } => {
                 let (c, _) = self.insert_char(self.dot, c, Some(source));
                 self.dot = Dot::Cur { c };
@@ -694,7 +1013,14 @@ impl Buffer {
                 return None;
             }
 
+            Action::Earlier { step } => return self.earlier(&step),
+            Action::Later { step } => return self.later(&step),
             Action::Redo => return self.redo(),
+            Action::RepeatLastEdit => return self.repeat_last_edit(),
+            Action::SurroundAdd { s } => return self.surround_add(&s, source),
+            Action::SurroundChange { from, to } => return self.surround_change(from, &to, source),
+            Action::SurroundDelete { c } => return self.surround_delete(c, source),
+            Action::ToggleComment => return self.toggle_comment(source),
             Action::Undo => return self.undo(),
 
             Action::DotCollapseFirst => self.dot = self.dot.collapse_to_first_cur(),
@@ -704,6 +1030,20 @@ impl Buffer {
             Action::DotFlip => self.dot.flip(),
             Action::DotSet(t, count) => self.set_dot(t, count),
             Action::DotSetFromCoords { coords } => self.set_dot_from_coords(coords),
+            Action::FoldDot => return self.fold_dot(),
+            Action::FoldIndent => return self.fold_indent(),
+            Action::FoldLines { first, last } => return self.fold(first, last),
+            Action::Unfold => return self.unfold(),
+            Action::UnfoldAll => self.unfold_all(),
+            Action::SetMark { name } if is_valid_mark(name) => self.set_mark(name, self.dot),
+            Action::SetMark { name } => {
+                return Some(ActionOutcome::SetStatusMessage(format!(
+                    "invalid mark name: {name}"
+                )))
+            }
+            Action::DotSetFromSymbol { coords, scope } => {
+                self.set_dot_from_symbol_coords(coords, scope)
+            }
 
             Action::RawInput { i } => return self.handle_raw_input(i),
 
@@ -714,30 +1054,16 @@ impl Buffer {
     }
 
     fn handle_raw_input(&mut self, k: Input) -> Option<ActionOutcome> {
-        let (match_indent, expand_tab, tabstop) = {
+        let (expand_tab, tabstop) = {
             let conf = config_handle!();
-            (conf.match_indent, conf.expand_tab, conf.tabstop)
+            (conf.expand_tab, conf.tabstop)
         };
 
         match k {
             Input::Return => {
-                let prefix = if match_indent {
-                    let cur = self.dot.first_cur();
-                    let y = self.txt.char_to_line(cur.idx);
-                    let line = self.txt.line(y).to_string();
-                    line.find(|c: char| !c.is_whitespace())
-                        .map(|ix| line.split_at(ix).0.to_string())
-                } else {
-                    None
+                self.dot = Dot::Cur {
+                    c: self.insert_typed_newline(),
                 };
-
-                let (c, _) = self.insert_char(self.dot, '\n', Some(Source::Keyboard));
-                let c = match prefix {
-                    Some(s) => self.insert_string(Dot::Cur { c }, s, None).0,
-                    None => c,
-                };
-
-                self.dot = Dot::Cur { c };
                 return None;
             }
 
@@ -753,8 +1079,9 @@ impl Buffer {
             }
 
             Input::Char(ch) => {
-                let (c, _) = self.insert_char(self.dot, ch, Some(Source::Keyboard));
-                self.dot = Dot::Cur { c };
+                self.dot = Dot::Cur {
+                    c: self.insert_typed_char(ch),
+                };
                 return None;
             }
 
@@ -782,6 +1109,34 @@ impl Buffer {
         self.xdot.clamp_idx(self.txt.len_chars());
     }
 
+    /// Select the full lines of a symbol reported by an LSP server, or for an inner selection
+    /// only the lines between the first and last line of the symbol if it has a body.
+    fn set_dot_from_symbol_coords(&mut self, coords: Coords, scope: Scope) {
+        self.set_dot_from_coords(coords);
+        let Range { start, end, .. } = self.dot.as_range();
+        let (first, last) = (
+            self.txt.char_to_line(start.idx),
+            self.txt.char_to_line(end.idx),
+        );
+
+        let r = if scope == Scope::Inner && last > first + 1 {
+            Range::from_cursors(
+                Cur {
+                    idx: self.txt.line_to_char(first + 1),
+                },
+                Cur {
+                    idx: self.txt.line_to_char(last - 1),
+                },
+                false,
+            )
+        } else {
+            self.dot.as_range()
+        };
+
+        self.dot =
+            Dot::from(r.extend_to_line_start(self).extend_to_line_end(self)).collapse_null_range();
+    }
+
     /// Extend dot foward and clamp to ensure it is within bounds
     fn extend_dot_forward(&mut self, t: TextObject, n: usize) {
         for _ in 0..n {
@@ -800,21 +1155,41 @@ impl Buffer {
         self.xdot.clamp_idx(self.txt.len_chars());
     }
 
+    /// Write the undo history of this buffer to disk so that it can be restored the next time
+    /// that the file is opened.
+    pub(crate) fn write_undo_file(&self) -> io::Result<()> {
+        match self.kind.path() {
+            Some(path) => undo_file::write(&undo_file::undo_path(path), &self.txt, &self.edit_log),
+            None => Ok(()),
+        }
+    }
+
+    /// Restore the undo history written by a previous session if it is still valid for the
+    /// current content of the buffer, returning whether or not history was restored.
+    pub(crate) fn restore_undo_file(&mut self) -> io::Result<bool> {
+        match self.kind.path() {
+            Some(path) => self.restore_undo_file_from(&undo_file::undo_path(path)),
+            None => Ok(false),
+        }
+    }
+
+    fn restore_undo_file_from(&mut self, undo: &Path) -> io::Result<bool> {
+        match undo_file::read(undo, &self.txt)? {
+            Some(log) => {
+                self.edit_log = log;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
     pub(crate) fn new_edit_log_transaction(&mut self) {
         self.edit_log.new_transaction()
     }
 
     fn undo(&mut self) -> Option<ActionOutcome> {
         match self.edit_log.undo() {
-            Some(edits) => {
-                self.edit_log.paused = true;
-                for edit in edits.into_iter() {
-                    self.apply_edit(edit);
-                }
-                self.edit_log.paused = false;
-                self.dirty = !self.edit_log.is_empty();
-                None
-            }
+            Some(edits) => self.apply_transaction(edits),
             None => Some(ActionOutcome::SetStatusMessage(
                 "Nothing to undo".to_string(),
             )),
@@ -823,20 +1198,116 @@ impl Buffer {
 
     fn redo(&mut self) -> Option<ActionOutcome> {
         match self.edit_log.redo() {
-            Some(edits) => {
-                self.edit_log.paused = true;
-                for edit in edits.into_iter() {
-                    self.apply_edit(edit);
-                }
-                self.edit_log.paused = false;
-                None
-            }
+            Some(edits) => self.apply_transaction(edits),
             None => Some(ActionOutcome::SetStatusMessage(
                 "Nothing to redo".to_string(),
             )),
         }
     }
 
+    fn earlier(&mut self, step: &str) -> Option<ActionOutcome> {
+        let step = match HistoryStep::parse(step) {
+            Ok(step) => step,
+            Err(e) => return Some(ActionOutcome::SetStatusMessage(e)),
+        };
+
+        match self.edit_log.earlier(step) {
+            Some(edits) => self.apply_transaction(edits),
+            None => Some(ActionOutcome::SetStatusMessage(
+                "Already at oldest change".to_string(),
+            )),
+        }
+    }
+
+    fn later(&mut self, step: &str) -> Option<ActionOutcome> {
+        let step = match HistoryStep::parse(step) {
+            Ok(step) => step,
+            Err(e) => return Some(ActionOutcome::SetStatusMessage(e)),
+        };
+
+        match self.edit_log.later(step) {
+            Some(edits) => self.apply_transaction(edits),
+            None => Some(ActionOutcome::SetStatusMessage(
+                "Already at newest change".to_string(),
+            )),
+        }
+    }
+
+    /// Restore the state of the buffer recorded by the given node of the undo tree, returning
+    /// false if there is no such node.
+    pub(crate) fn undo_tree_goto(&mut self, node: usize) -> bool {
+        match self.edit_log.goto(node) {
+            Some(edits) => {
+                self.apply_transaction(edits);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The undo tree of this buffer rendered as one line per node, see [EditLog::tree_lines].
+    pub(crate) fn undo_tree_lines(&self) -> Vec<String> {
+        self.edit_log.tree_lines()
+    }
+
+    /// Replay the change that moved the buffer into its current state at dot as a new
+    /// transaction. Only changes typed by the user can be repeated.
+    ///
+    /// The position of each edit is taken relative to the start of the original change. If the
+    /// change began by deleting a selection and dot is currently a range then dot is deleted
+    /// in its place, so changing one word can be repeated on a word of a different length.
+    fn repeat_last_edit(&mut self) -> Option<ActionOutcome> {
+        let edits = match self.edit_log.repeatable_transaction() {
+            Some(edits) if !edits.is_empty() => edits.clone(),
+            _ => {
+                return Some(ActionOutcome::SetStatusMessage(
+                    "Nothing to repeat".to_string(),
+                ))
+            }
+        };
+
+        self.edit_log.new_transaction();
+        let base = edits[0].cur.idx;
+        let mut start = self.dot.first_cur().idx;
+        let mut edits = edits.into_iter().peekable();
+
+        if let (
+            Dot::Range { .. },
+            Some(Edit {
+                kind: Kind::Delete, ..
+            }),
+        ) = (self.dot, edits.peek())
+        {
+            start = self.delete_dot(self.dot, None).0.idx;
+            edits.next();
+        }
+
+        for Edit { kind, cur, txt } in edits {
+            let idx = (start + cur.idx).saturating_sub(base);
+            let cur = Cur {
+                idx: idx.min(self.txt.len_chars()),
+            };
+            self.apply_edit(Edit { kind, cur, txt });
+        }
+
+        self.edit_log.new_transaction();
+        self.dot.clamp_idx(self.txt.len_chars());
+        self.xdot.clamp_idx(self.txt.len_chars());
+
+        None
+    }
+
+    fn apply_transaction(&mut self, edits: Transaction) -> Option<ActionOutcome> {
+        self.edit_log.paused = true;
+        for edit in edits.into_iter() {
+            self.apply_edit(edit);
+        }
+        self.edit_log.paused = false;
+        self.dirty = self.kind.is_file() && !self.edit_log.is_at_save_point();
+
+        None
+    }
+
     fn apply_edit(&mut self, Edit { kind, cur, txt }: Edit) {
         let new_cur = match (kind, txt) {
             (Kind::Insert, Txt::Char(c)) => self.insert_char(Dot::Cur { c: cur }, c, None).0,
@@ -860,10 +1331,37 @@ impl Buffer {
         self.dot = Dot::Cur { c: new_cur };
     }
 
+    /// The character index of the first hex digit for a byte offset in a hex buffer.
+    pub(crate) fn hex_offset_to_char(&self, offset: usize) -> Option<usize> {
+        let (line, col) = hex::offset_to_line_and_col(offset);
+        let idx = self.txt.try_line_to_char(line)? + col;
+
+        (idx < self.txt.len_chars()).then_some(idx)
+    }
+
+    /// Change the line ending style used when this buffer is next saved.
+    pub(crate) fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.format.line_ending != line_ending {
+            self.format.line_ending = line_ending;
+            self.mark_dirty();
+        }
+    }
+
+    /// Prevent edits that were not typed by the user, such as those made by edit commands, from
+    /// being repeated. Edits without a source are being replayed from the edit log so they keep
+    /// the repeatability of the state they are part of.
+    fn record_edit_source(&mut self, source: Option<Source>) {
+        if matches!(source, Some(s) if s != Source::Keyboard) {
+            self.edit_log.mark_unrepeatable();
+        }
+    }
+
     /// Only files get marked as dirty to ensure that they are prompted for saving before being
     /// closed.
     fn mark_dirty(&mut self) {
+        self.version += 1;
         self.dirty = self.kind.is_file();
+        self.swap_pending = self.dirty;
     }
 
     /// Returns true if a filter was present and the notification was sent
@@ -907,6 +1405,10 @@ impl Buffer {
         }
 
         self.edit_log.insert_char(cur, ch);
+        self.record_edit_source(source);
+        self.marks.insert(idx, 1);
+        self.folds.insert(idx, 1);
+        self.auto_closed.insert(idx, 1);
         self.mark_dirty();
 
         (Cur { idx: idx + 1 }, deleted)
@@ -937,6 +1439,10 @@ impl Buffer {
             }
 
             self.edit_log.insert_string(cur, s);
+            self.record_edit_source(source);
+            self.marks.insert(idx, len);
+            self.folds.insert(idx, len);
+            self.auto_closed.insert(idx, len);
             cur.idx += len;
         }
 
@@ -965,6 +1471,10 @@ impl Buffer {
             }
 
             self.edit_log.delete_char(cur, ch);
+            self.record_edit_source(source);
+            self.marks.delete(idx, 1);
+            self.folds.delete(idx, 1);
+            self.auto_closed.delete(idx, 1);
             self.mark_dirty();
         }
 
@@ -986,11 +1496,42 @@ impl Buffer {
         }
 
         self.edit_log.delete_string(r.start, s.clone());
+        self.record_edit_source(source);
+        self.marks.delete(from, to - from);
+        self.folds.delete(from, to - from);
+        self.auto_closed.delete(from, to - from);
         self.mark_dirty();
 
         (r.start, Some(s))
     }
 
+    /// The current position of the named mark if it has been set.
+    pub(crate) fn get_mark(&self, name: char) -> Option<Dot> {
+        self.marks.get(name)
+    }
+
+    /// A counter that changes each time the content of the buffer is modified.
+    pub(crate) fn version(&self) -> usize {
+        self.version
+    }
+
+    pub(crate) fn set_mark(&mut self, name: char, dot: Dot) {
+        self.marks.set(name, dot);
+    }
+
+    /// Replace the content of the buffer wholesale, keeping dot, marks and folds within its
+    /// bounds.
+    pub(super) fn replace_txt(&mut self, txt: Text) {
+        let n_chars = txt.len_chars();
+        self.txt = txt;
+        self.version += 1;
+        self.dot.clamp_idx(n_chars);
+        self.xdot.clamp_idx(n_chars);
+        self.marks.clamp_idx(n_chars);
+        self.folds.clamp_idx(n_chars);
+        self.auto_closed.clear();
+    }
+
     pub(crate) fn find_forward(&mut self, s: &str) {
         if let Some(dot) = find_forward_wrapping(&s, self) {
             self.dot = dot;
@@ -1016,6 +1557,7 @@ fn n_digits(mut n: usize) -> usize {
 pub(crate) mod tests {
     use super::*;
     use crate::key::Arrow;
+    use crate::util::TempPath;
     use edit::tests::{del_c, del_s, in_c, in_s};
     use simple_test_case::test_case;
     use std::env;
@@ -1034,6 +1576,62 @@ pub(crate) mod tests {
         assert_eq!(n_digits(n), digits);
     }
 
+    #[test]
+    fn buffers_are_read_only_until_background_loading_completes() {
+        let mut b = Buffer::new_unnamed(0, "");
+        b.txt = Text::Pieces(PieceTable::default());
+        b.loading = Some(Loading::new_stubbed(1, 8));
+
+        let outcome = b.handle_action(Action::InsertChar { c: 'x' }, Source::Keyboard);
+        assert!(matches!(outcome, Some(ActionOutcome::SetStatusMessage(_))));
+
+        assert_eq!(
+            b.handle_file_chunk(1, FileChunk::Data("foo\n".to_string())),
+            None
+        );
+        assert_eq!(b.load_status().as_deref(), Some("[loading 50%]"));
+        // Chunks from an earlier load of the file are ignored
+        assert_eq!(
+            b.handle_file_chunk(0, FileChunk::Data("old".to_string())),
+            None
+        );
+        b.handle_file_chunk(1, FileChunk::Data("bar\n".to_string()));
+        assert_eq!(b.str_contents(), "foo\nbar\n\n");
+        assert!(b.is_loading());
+
+        assert!(b.handle_file_chunk(1, FileChunk::Done).is_some());
+        assert!(!b.is_loading());
+        assert!(b.txt.is_piece_table());
+        assert_eq!(b.str_contents(), "foo\nbar\n");
+
+        b.handle_action(Action::InsertChar { c: 'x' }, Source::Keyboard);
+        assert_eq!(b.str_contents(), "xfoo\nbar\n");
+    }
+
+    #[test]
+    fn failed_background_loads_stay_read_only() {
+        let mut b = Buffer::new_unnamed(0, "");
+        b.loading = Some(Loading::new_stubbed(1, 8));
+
+        b.handle_file_chunk(1, FileChunk::Data("foo".to_string()));
+        assert!(b
+            .handle_file_chunk(1, FileChunk::Failed("boom".to_string()))
+            .is_some());
+
+        assert!(b.load_failed());
+        assert_eq!(b.load_status().as_deref(), Some("[load failed]"));
+        b.handle_action(Action::InsertChar { c: 'x' }, Source::Keyboard);
+        assert_eq!(b.str_contents(), "foo\n");
+    }
+
+    /// A buffer for a (non-existent) file so that its filetype is known.
+    pub fn file_buffer(fname: &str, content: &str) -> Buffer {
+        let mut b = Buffer::new_unnamed(0, content);
+        b.kind = BufferKind::File(PathBuf::from(fname));
+
+        b
+    }
+
     pub fn buffer_from_lines(lines: &[&str]) -> Buffer {
         let mut b = Buffer::new_unnamed(0, "");
         let s = lines.join("\n");
@@ -1060,7 +1658,7 @@ pub(crate) mod tests {
         assert_eq!(lines[1], LINE_2);
         assert_eq!(b.dot, Dot::Cur { c });
         assert_eq!(
-            b.edit_log.edits,
+            b.edit_log.transactions(),
             vec![vec![in_s(0, &format!("{LINE_1}\n{LINE_2}"))]]
         );
     }
@@ -1118,7 +1716,7 @@ pub(crate) mod tests {
         assert_eq!(lines[0], LINE_1);
         assert_eq!(lines[1], "x");
         assert_eq!(
-            b.edit_log.edits,
+            b.edit_log.transactions(),
             vec![vec![
                 in_s(0, &format!("{LINE_1}\n{LINE_2}")),
                 del_s(LINE_1.len() + 1, LINE_2),
@@ -1146,7 +1744,7 @@ pub(crate) mod tests {
         assert_eq!(b.dot, Dot::Cur { c });
         assert_eq!(lines.len(), 1);
         assert_eq!(lines[0], "");
-        assert!(b.edit_log.edits.is_empty());
+        assert!(b.edit_log.transactions().is_empty());
     }
 
     #[test]
@@ -1166,7 +1764,7 @@ pub(crate) mod tests {
         assert_eq!(lines[0], LINE_1);
         assert_eq!(lines[1], "involving multiple line");
         assert_eq!(
-            b.edit_log.edits,
+            b.edit_log.transactions(),
             vec![vec![
                 in_s(0, &format!("{LINE_1}\n{LINE_2}")),
                 del_c(LINE_1.len() + 24, 's')
@@ -1174,6 +1772,82 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn repeat_last_edit_replaces_a_range_dot() {
+        let mut b = Buffer::new_unnamed(0, "foo bar bazzz");
+        b.dot = Dot::Range {
+            r: Range::from_cursors(Cur { idx: 0 }, Cur { idx: 2 }, true),
+        };
+        b.new_edit_log_transaction();
+        b.handle_action(Action::Delete, Source::Keyboard);
+        b.handle_action(
+            Action::InsertString { s: "X".to_string() },
+            Source::Keyboard,
+        );
+
+        b.dot = Dot::Range {
+            r: Range::from_cursors(Cur { idx: 6 }, Cur { idx: 10 }, true),
+        };
+        b.handle_action(Action::RepeatLastEdit, Source::Keyboard);
+
+        assert_eq!(b.str_contents(), "X bar X\n");
+        assert_eq!(b.dot, Dot::Cur { c: Cur { idx: 7 } });
+    }
+
+    #[test]
+    fn repeat_last_edit_inserts_at_a_cursor_dot() {
+        let mut b = Buffer::new_unnamed(0, "a\nb");
+        b.dot = Dot::Cur { c: Cur { idx: 1 } };
+        b.new_edit_log_transaction();
+        for c in "!?".chars() {
+            b.handle_action(Action::InsertChar { c }, Source::Keyboard);
+        }
+
+        b.dot = Dot::Cur { c: Cur { idx: 5 } };
+        b.handle_action(Action::RepeatLastEdit, Source::Keyboard);
+        b.handle_action(Action::Undo, Source::Keyboard);
+        b.handle_action(Action::RepeatLastEdit, Source::Keyboard);
+
+        assert_eq!(b.str_contents(), "a!?\nb!?\n");
+        assert_eq!(b.edit_log.transactions().len(), 2);
+    }
+
+    #[test]
+    fn repeat_last_edit_uses_the_current_state() {
+        let mut b = Buffer::new_unnamed(0, "a\nb");
+        b.new_edit_log_transaction();
+        b.handle_action(Action::InsertChar { c: '!' }, Source::Keyboard);
+        b.new_edit_log_transaction();
+        b.handle_action(Action::InsertChar { c: '?' }, Source::Keyboard);
+        b.handle_action(Action::Undo, Source::Keyboard);
+
+        b.dot = Dot::Cur { c: Cur { idx: 3 } };
+        b.handle_action(Action::RepeatLastEdit, Source::Keyboard);
+
+        assert_eq!(b.str_contents(), "!a\n!b\n");
+    }
+
+    #[test]
+    fn repeat_last_edit_ignores_edits_that_were_not_typed() {
+        let mut b = Buffer::new_unnamed(0, "a\nb");
+        b.new_edit_log_transaction();
+        b.handle_action(Action::InsertChar { c: '!' }, Source::Keyboard);
+        b.new_edit_log_transaction();
+        b.handle_action(Action::InsertString { s: "?".to_string() }, Source::Fsys);
+        b.new_edit_log_transaction();
+
+        b.dot = Dot::Cur { c: Cur { idx: 4 } };
+        let outcome = b.handle_action(Action::RepeatLastEdit, Source::Keyboard);
+
+        assert_eq!(
+            outcome,
+            Some(ActionOutcome::SetStatusMessage(
+                "Nothing to repeat".to_string()
+            ))
+        );
+        assert_eq!(b.str_contents(), "!?a\nb\n");
+    }
+
     #[test]
     fn delete_range_works() {
         let mut b = simple_initial_buffer();
@@ -1188,7 +1862,7 @@ pub(crate) mod tests {
         assert_eq!(lines[0], LINE_1);
         assert_eq!(lines[1], "");
         assert_eq!(
-            b.edit_log.edits,
+            b.edit_log.transactions(),
             vec![vec![
                 in_s(0, &format!("{LINE_1}\n{LINE_2}")),
                 del_s(LINE_1.len() + 1, "involving multiple lines")
@@ -1196,6 +1870,108 @@ pub(crate) mod tests {
         );
     }
 
+    #[test]
+    fn restoring_an_undo_file_restores_usable_history() {
+        let undo = TempPath::new("undo-restore");
+        let mut b = simple_initial_buffer();
+        let original = b.str_contents();
+        b.new_edit_log_transaction();
+        b.set_dot(TextObject::BufferStart, 1);
+        b.handle_action(Action::InsertString { s: "new ".into() }, Source::Keyboard);
+        undo_file::write(&undo, &b.txt, &b.edit_log).unwrap();
+
+        let mut reopened = Buffer::new_unnamed(0, &b.txt.to_string());
+        let restored = reopened.restore_undo_file_from(&undo).unwrap();
+        reopened.handle_action(Action::Undo, Source::Keyboard);
+
+        assert!(restored);
+        assert_eq!(reopened.str_contents(), original);
+    }
+
+    #[test]
+    fn undo_files_are_rejected_if_the_content_has_changed() {
+        let undo = TempPath::new("undo-reject");
+        let mut b = simple_initial_buffer();
+        b.handle_action(Action::InsertString { s: "new ".into() }, Source::Keyboard);
+        undo_file::write(&undo, &b.txt, &b.edit_log).unwrap();
+
+        let mut reopened = Buffer::new_unnamed(0, "changed elsewhere");
+        let restored = reopened.restore_undo_file_from(&undo).unwrap();
+
+        assert!(!restored);
+        assert_eq!(reopened.edit_log, EditLog::default());
+    }
+
+    #[test]
+    fn reopening_with_an_encoding_keeps_unsaved_changes() {
+        let path = TempPath::new("reopen-dirty");
+        fs::write(&path, "café\n").unwrap();
+        let mut b = Buffer::new_from_canonical_file_path(0, path.to_path_buf()).unwrap();
+        b.handle_action(Action::InsertString { s: "new ".into() }, Source::Keyboard);
+
+        let msg = b.reopen_with_encoding(Encoding::Latin1);
+
+        assert_eq!(msg, "No write since last change");
+        assert_eq!(b.str_contents(), "new café\n");
+        assert!(b.dirty);
+    }
+
+    #[test]
+    fn reopening_as_an_encoding_the_file_is_not_valid_in_is_an_error() {
+        let path = TempPath::new("reopen-invalid");
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let mut b = Buffer::new_from_canonical_file_path(0, path.to_path_buf()).unwrap();
+
+        let msg = b.reopen_with_encoding(Encoding::Utf8);
+
+        assert_eq!(
+            msg,
+            "Error reloading buffer: file content is not valid UTF-8"
+        );
+        assert_eq!(b.str_contents(), "café\n");
+        assert_eq!(b.format.encoding, Encoding::Windows1252);
+    }
+
+    #[test]
+    fn marks_survive_reloading_and_restoring_undo_files() {
+        let (path, undo) = (TempPath::new("marks-reload"), TempPath::new("marks-undo"));
+        fs::write(&path, "one two three\n").unwrap();
+        let mut b = Buffer::new_from_canonical_file_path(0, path.to_path_buf()).unwrap();
+        b.set_mark('a', Dot::from_char_indices(4, 6));
+        b.new_edit_log_transaction();
+        b.handle_action(Action::InsertString { s: "new ".into() }, Source::Keyboard);
+        undo_file::write(&undo, &b.txt, &b.edit_log).unwrap();
+
+        assert!(b.restore_undo_file_from(&undo).unwrap());
+        assert_eq!(b.get_mark('a'), Some(Dot::from_char_indices(8, 10)));
+
+        // The file is shorter on disk than the position of the mark
+        fs::write(&path, "one\n").unwrap();
+        b.reload_from_disk();
+        assert_eq!(b.get_mark('a'), Some(Dot::from_char_indices(3, 3)));
+    }
+
+    #[test]
+    fn folds_survive_reloading_and_restoring_undo_files() {
+        let (path, undo) = (TempPath::new("folds-reload"), TempPath::new("folds-undo"));
+        fs::write(&path, "one\ntwo\nthree\nfour\n").unwrap();
+        let mut b = Buffer::new_from_canonical_file_path(0, path.to_path_buf()).unwrap();
+        b.fold(1, 2);
+        b.new_edit_log_transaction();
+        b.handle_action(
+            Action::InsertString { s: "zero\n".into() },
+            Source::Keyboard,
+        );
+        undo_file::write(&undo, &b.txt, &b.edit_log).unwrap();
+
+        assert!(b.restore_undo_file_from(&undo).unwrap());
+        assert_eq!(b.fold_containing(2), Some((2, 3)));
+
+        fs::write(&path, "zero\none\ntwo\nthree\nfour\n").unwrap();
+        b.reload_from_disk();
+        assert_eq!(b.fold_containing(2), Some((2, 3)));
+    }
+
     #[test]
     fn delete_undo_works() {
         let mut b = simple_initial_buffer();
@@ -1266,6 +2042,37 @@ pub(crate) mod tests {
         assert_eq!(b.string_lines(), vec!["foo foo foo", ""]);
     }
 
+    #[test]
+    fn undo_tree_goto_restores_undone_branches() {
+        let mut b = Buffer::new_unnamed(0, "foo");
+
+        b.new_edit_log_transaction();
+        b.insert_string(Dot::Cur { c: c(3) }, " bar".to_string(), None);
+        b.handle_action(Action::Undo, Source::Keyboard);
+        b.new_edit_log_transaction();
+        b.insert_string(Dot::Cur { c: c(3) }, " baz".to_string(), None);
+        assert_eq!(b.str_contents(), "foo baz\n");
+
+        assert!(b.undo_tree_goto(1));
+        assert_eq!(b.str_contents(), "foo bar\n");
+
+        b.handle_action(
+            Action::Later {
+                step: "1".to_string(),
+            },
+            Source::Keyboard,
+        );
+        assert_eq!(b.str_contents(), "foo baz\n");
+
+        b.handle_action(
+            Action::Earlier {
+                step: "1h".to_string(),
+            },
+            Source::Keyboard,
+        );
+        assert_eq!(b.str_contents(), "foo\n");
+    }
+
     // Tests are executed from the root of the crate so existing file paths are relative to there
     #[test_case("foo", None; "unknown format")]
     #[test_case("someFunc()", None; "camel case function call")]
@@ -1319,6 +2126,67 @@ pub(crate) mod tests {
         assert_eq!(b.str_contents(), format!("{expected}\n"));
     }
 
+    #[test_case("foo\r\nbar\r\n", None, "foo\r\nbar\r\nbaz\r\n"; "crlf preserved")]
+    #[test_case("\u{feff}foo\nbar\n", None, "\u{feff}foo\nbar\nbaz\n"; "bom preserved")]
+    #[test_case("foo\r\nbar\r\n", Some(LineEnding::Lf), "foo\nbar\nbaz\n"; "converted to lf")]
+    #[test_case("foo\nbar\n", Some(LineEnding::Crlf), "foo\r\nbar\r\nbaz\r\n"; "converted to crlf")]
+    #[test]
+    fn saving_restores_file_format(initial: &str, line_ending: Option<LineEnding>, expected: &str) {
+        let path = TempPath::new("format");
+        fs::write(&path, initial).unwrap();
+
+        let mut b = Buffer::new_from_canonical_file_path(0, path.to_path_buf()).unwrap();
+        let idx = b.txt.len_chars();
+        b.insert_string(Dot::Cur { c: c(idx) }, "\nbaz".to_string(), None);
+        if let Some(le) = line_ending {
+            b.set_line_ending(le);
+        }
+        b.save_to_disk_at(path.to_path_buf(), true);
+        let saved = fs::read_to_string(&path).unwrap();
+
+        assert_eq!(saved, expected);
+    }
+
+    #[test]
+    fn saving_mixed_line_endings_reports_their_conversion() {
+        let path = TempPath::new("mixed-line-endings");
+        fs::write(&path, "foo\r\nbar\nbaz\r\n").unwrap();
+
+        let mut b = Buffer::new_from_canonical_file_path(0, path.to_path_buf()).unwrap();
+        assert_eq!(b.format.to_string(), "CRLF (mixed)");
+        b.handle_action(Action::InsertString { s: "x".into() }, Source::Keyboard);
+        let msg = b.save_to_disk_at(path.to_path_buf(), true);
+
+        assert!(
+            msg.ends_with("written (mixed line endings converted to CRLF)"),
+            "{msg}"
+        );
+        assert!(!b.format.mixed_line_endings);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xfoo\r\nbar\r\nbaz\r\n");
+    }
+
+    #[test]
+    fn binary_files_are_edited_as_hex() {
+        let path = TempPath::new("hex");
+        let mut bytes: Vec<u8> = (0..20).collect();
+        fs::write(&path, &bytes).unwrap();
+
+        let mut b = Buffer::new_from_canonical_file_path(0, path.to_path_buf()).unwrap();
+        assert!(b.kind.is_hex());
+
+        // "#17" addresses the second byte of the second line
+        let dot = b.map_addr(&mut Addr::parse(&mut "#17".chars().peekable()).unwrap());
+        let idx = dot.first_cur().idx;
+        assert_eq!(b.txt.slice(idx, idx + 2).to_string(), "11");
+
+        b.insert_string(Dot::from_char_indices(idx, idx + 1), "ff".to_string(), None);
+        b.save_to_disk_at(path.to_path_buf(), true);
+        let saved = fs::read(&path).unwrap();
+
+        bytes[17] = 0xff;
+        assert_eq!(saved, bytes);
+    }
+
     #[test_case('\r', "\n"; "CR")]
     #[test_case('\n', "\n"; "LF")]
     #[test_case('a', "a"; "ascii")]
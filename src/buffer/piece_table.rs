@@ -0,0 +1,799 @@
+//! A piece table for editing very large files without holding them in a single allocation.
+//!
+//! The original content of a file is read once and never modified: inserted text is appended
+//! to a separate add buffer and the content of the document is described by a list of pieces,
+//! each referencing a region of one of the stores. Opening a file only requires a single pass
+//! over it to index its line endings and edits never need to move existing text.
+//!
+//! The original content is read into memory as a sequence of fixed size blocks rather than
+//! being memory mapped: the large files this is used for are often logs that are rotated or
+//! truncated while open, which would otherwise kill the editor with SIGBUS or change the
+//! content out from under validated utf-8 data. Each block is a separate allocation that is
+//! shared between clones of the piece table, so neither opening a file nor taking a snapshot
+//! of the document requires a single allocation the size of the file.
+//!
+//! Locating a character or line walks the list of pieces before binary searching the line
+//! endings of the piece containing it, so lookups are linear in the number of pieces (which
+//! grows by at most two for each edit and one for each block of the file) rather than in the
+//! size of the file.
+use super::{
+    format::UTF8_BOM,
+    hex,
+    internal::{count_chars, decode_char_at, utf8_is_cont_byte, Slice},
+    loader::for_each_chunk,
+};
+use std::{
+    fmt,
+    fs::File,
+    io::{self, ErrorKind},
+    path::Path,
+    sync::Arc,
+};
+
+/// A block of utf-8 text along with the byte and character offsets of each of its line endings.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Store {
+    data: Vec<u8>,
+    nl_bytes: Vec<usize>,
+    nl_chars: Vec<usize>,
+    n_chars: usize,
+}
+
+impl Store {
+    fn new(data: Vec<u8>) -> Self {
+        let mut s = Self {
+            data,
+            ..Default::default()
+        };
+        s.index_from(0);
+
+        s
+    }
+
+    #[inline]
+    fn bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Index the line endings of the data following the given byte offset.
+    fn index_from(&mut self, from: usize) {
+        for (i, &b) in self.data[from..].iter().enumerate() {
+            if b == b'\n' {
+                self.nl_bytes.push(from + i);
+                self.nl_chars.push(self.n_chars);
+            }
+            if !utf8_is_cont_byte(b) {
+                self.n_chars += 1;
+            }
+        }
+    }
+
+    /// Append s to the store, returning the byte and character offsets it was written to.
+    fn append(&mut self, s: &str) -> (usize, usize) {
+        let (start, char_start) = (self.data.len(), self.n_chars);
+        self.data.extend_from_slice(s.as_bytes());
+        self.index_from(start);
+
+        (start, char_start)
+    }
+
+    #[inline]
+    fn newlines_before(&self, byte_idx: usize) -> usize {
+        self.nl_bytes.partition_point(|&b| b < byte_idx)
+    }
+
+    /// The byte offset of a character, scanning forward from a known (byte, char) position if
+    /// that is closer than the start of the line containing the character.
+    fn char_to_byte_from(&self, char_idx: usize, (mut b, mut c): (usize, usize)) -> usize {
+        let i = self.nl_chars.partition_point(|&c| c < char_idx);
+        if i > 0 && self.nl_chars[i - 1] + 1 > c {
+            (b, c) = (self.nl_bytes[i - 1] + 1, self.nl_chars[i - 1] + 1);
+        }
+
+        let bytes = self.bytes();
+        for _ in c..char_idx {
+            b += 1;
+            while b < bytes.len() && utf8_is_cont_byte(bytes[b]) {
+                b += 1;
+            }
+        }
+
+        b
+    }
+
+    /// The character offset of a byte offset which must lie on a character boundary.
+    fn byte_to_char_from(&self, byte_idx: usize, (mut b, mut c): (usize, usize)) -> usize {
+        let i = self.newlines_before(byte_idx);
+        if i > 0 && self.nl_bytes[i - 1] + 1 > b {
+            (b, c) = (self.nl_bytes[i - 1] + 1, self.nl_chars[i - 1] + 1);
+        }
+
+        c + count_chars(&self.bytes()[b..byte_idx])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Src {
+    /// The block of original content with the given index
+    Original(usize),
+    Add,
+}
+
+/// A region of one of the stores that makes up part of the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Piece {
+    src: Src,
+    /// Byte offset of the start of the piece within its store
+    start: usize,
+    /// Length of the piece in bytes
+    len: usize,
+    /// Character offset of the start of the piece within its store
+    char_start: usize,
+    n_chars: usize,
+    n_newlines: usize,
+}
+
+/// A run of whole pieces forming the middle of a [Slice].
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) struct Pieces<'a> {
+    pieces: &'a [Piece],
+    original: &'a [Arc<Store>],
+    add: &'a [u8],
+}
+
+impl<'a> Pieces<'a> {
+    #[inline]
+    pub(super) fn len(&self) -> usize {
+        self.pieces.len()
+    }
+
+    #[inline]
+    pub(super) fn get(&self, i: usize) -> Option<&'a [u8]> {
+        let p = self.pieces.get(i)?;
+        let data = match p.src {
+            Src::Original(block) => self.original[block].bytes(),
+            Src::Add => self.add,
+        };
+
+        Some(&data[p.start..p.start + p.len])
+    }
+}
+
+/// A piece table providing the same API as [GapBuffer](super::GapBuffer) for use with files
+/// that are too large to comfortably hold in memory as a single allocation.
+#[derive(Debug, Default, Clone)]
+pub struct PieceTable {
+    /// The original content of the document, shared between clones of the piece table
+    original: Vec<Arc<Store>>,
+    add: Store,
+    pieces: Vec<Piece>,
+    n_bytes: usize,
+    n_chars: usize,
+    n_newlines: usize,
+}
+
+impl From<&str> for PieceTable {
+    fn from(s: &str) -> Self {
+        Self::from(s.to_string())
+    }
+}
+
+impl From<String> for PieceTable {
+    fn from(s: String) -> Self {
+        let mut pt = Self::default();
+        pt.append_block(s);
+
+        pt
+    }
+}
+
+impl fmt::Display for PieceTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match String::from_utf8(self.bytes()) {
+            Ok(s) => write!(f, "{s}"),
+            Err(_) => Err(fmt::Error),
+        }
+    }
+}
+
+impl PieceTable {
+    /// Open a file as a piece table over its content.
+    ///
+    /// Files that are not utf-8 text with LF line endings and no byte order mark need to be
+    /// converted before they can be edited so None is returned for them. As with other buffers,
+    /// a trailing newline is not included in the content as it is added back when saving.
+    pub fn open(path: &Path) -> io::Result<Option<Self>> {
+        let mut pt = Self::default();
+        let mut needs_converting = false;
+
+        let res = for_each_chunk(File::open(path)?, |s| {
+            let bytes = s.as_bytes();
+            if (pt.is_empty() && (bytes.starts_with(UTF8_BOM) || hex::is_binary(bytes)))
+                || bytes.contains(&b'\r')
+            {
+                needs_converting = true;
+                return false;
+            }
+            pt.append_block(s);
+            true
+        });
+
+        match res {
+            Ok(_) if needs_converting => Ok(None),
+            Err(e) if e.kind() == ErrorKind::InvalidData => Ok(None),
+            Err(e) => Err(e),
+            Ok(_) => {
+                if pt.n_chars > 0 && pt.char(pt.n_chars - 1) == '\n' {
+                    pt.remove_char(pt.n_chars - 1);
+                }
+                Ok(Some(pt))
+            }
+        }
+    }
+
+    /// Append a block of text to the end of the document without copying it.
+    ///
+    /// This is used to build up the content of a file as it is read so the block becomes part
+    /// of the original content of the document rather than being recorded as an insert.
+    pub fn append_block(&mut self, s: String) {
+        if s.is_empty() {
+            return;
+        }
+
+        let store = Store::new(s.into_bytes());
+        let piece = Piece {
+            src: Src::Original(self.original.len()),
+            start: 0,
+            len: store.data.len(),
+            char_start: 0,
+            n_chars: store.n_chars,
+            n_newlines: store.nl_bytes.len(),
+        };
+
+        self.pieces.push(piece);
+        self.original.push(Arc::new(store));
+        self.n_bytes += piece.len;
+        self.n_chars += piece.n_chars;
+        self.n_newlines += piece.n_newlines;
+    }
+
+    #[inline]
+    fn store(&self, src: Src) -> &Store {
+        match src {
+            Src::Original(block) => &self.original[block],
+            Src::Add => &self.add,
+        }
+    }
+
+    #[inline]
+    fn piece_bytes(&self, p: &Piece) -> &[u8] {
+        &self.store(p.src).bytes()[p.start..p.start + p.len]
+    }
+
+    /// The byte offset within a piece of a character offset within that piece.
+    #[inline]
+    fn piece_char_to_byte(&self, p: &Piece, char_offset: usize) -> usize {
+        self.store(p.src)
+            .char_to_byte_from(p.char_start + char_offset, (p.start, p.char_start))
+            - p.start
+    }
+
+    /// The index of the piece containing char_idx along with the number of characters and
+    /// bytes that precede it. An index equal to the number of pieces is returned when char_idx
+    /// is at the end of the document.
+    fn find_char(&self, char_idx: usize) -> (usize, usize, usize) {
+        let (mut chars, mut bytes) = (0, 0);
+        for (i, p) in self.pieces.iter().enumerate() {
+            if char_idx < chars + p.n_chars {
+                return (i, chars, bytes);
+            }
+            chars += p.n_chars;
+            bytes += p.len;
+        }
+
+        (self.pieces.len(), chars, bytes)
+    }
+
+    /// The number of bytes in the document
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.n_bytes
+    }
+
+    /// Whether or not the document is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.n_bytes == 0
+    }
+
+    /// The raw content of the document
+    pub fn bytes(&self) -> Vec<u8> {
+        let mut v = Vec::with_capacity(self.n_bytes);
+        for p in self.pieces.iter() {
+            v.extend_from_slice(self.piece_bytes(p));
+        }
+
+        v
+    }
+
+    /// Iterate over the lines of the document
+    pub fn iter_lines(&self) -> impl Iterator<Item = Slice<'_>> {
+        (0..self.len_lines()).map(|i| self.line(i))
+    }
+
+    /// The number of lines within the document
+    #[inline]
+    pub fn len_lines(&self) -> usize {
+        self.n_newlines + 1
+    }
+
+    /// The number of characters in the document
+    #[inline]
+    pub fn len_chars(&self) -> usize {
+        self.n_chars
+    }
+
+    /// Clear the contents of the document.
+    ///
+    /// # Note
+    /// This does not release the memory held for the original content or previously inserted
+    /// text, it simply removes all pieces from the table.
+    pub fn clear(&mut self) {
+        self.pieces.clear();
+        self.n_bytes = 0;
+        self.n_chars = 0;
+        self.n_newlines = 0;
+    }
+
+    /// The character at the specified character index.
+    ///
+    /// # Panics
+    /// This method will panic if the given character index is out of bounds
+    pub fn char(&self, char_idx: usize) -> char {
+        match self.get_char(char_idx) {
+            Some(ch) => ch,
+            None => panic!(
+                "char index was {char_idx} but the buffer char length is {}",
+                self.n_chars
+            ),
+        }
+    }
+
+    /// The character at the specified character index.
+    pub fn get_char(&self, char_idx: usize) -> Option<char> {
+        if char_idx >= self.n_chars {
+            return None;
+        }
+
+        let (i, chars, _) = self.find_char(char_idx);
+        let p = &self.pieces[i];
+        let b = p.start + self.piece_char_to_byte(p, char_idx - chars);
+
+        // SAFETY: b is the start of a character within the valid utf-8 data of the store
+        Some(unsafe { decode_char_at(b, self.store(p.src).bytes()) })
+    }
+
+    /// The requested line as a [Slice].
+    ///
+    /// # Panics
+    /// This method will panic if the given line index is out of bounds
+    pub fn line(&self, line_idx: usize) -> Slice<'_> {
+        let from = self.line_to_char(line_idx);
+        let to = self.try_line_to_char(line_idx + 1).unwrap_or(self.n_chars);
+
+        self.slice(from, to)
+    }
+
+    /// The number of characters in the requested line.
+    ///
+    /// # Panics
+    /// This method will panic if the given line index is out of bounds
+    pub fn line_len_chars(&self, line_idx: usize) -> usize {
+        let from = self.line_to_char(line_idx);
+        let to = self.try_line_to_char(line_idx + 1).unwrap_or(self.n_chars);
+
+        to - from
+    }
+
+    /// An exclusive range of characters from the document
+    pub fn slice(&self, char_from: usize, char_to: usize) -> Slice<'_> {
+        let (i, ci, _) = self.find_char(char_from);
+        if i == self.pieces.len() {
+            return Slice::default();
+        }
+        let (j, cj, _) = self.find_char(char_to);
+
+        let p = &self.pieces[i];
+        let a = self.piece_char_to_byte(p, char_from - ci);
+        if i == j {
+            let b = self.piece_char_to_byte(p, char_to - cj);
+            return Slice::from_pieces(&self.piece_bytes(p)[a..b], Pieces::default(), &[]);
+        }
+
+        let right = match self.pieces.get(j) {
+            Some(p) => &self.piece_bytes(p)[..self.piece_char_to_byte(p, char_to - cj)],
+            None => &[],
+        };
+        let mid = Pieces {
+            pieces: &self.pieces[i + 1..j],
+            original: &self.original,
+            add: self.add.bytes(),
+        };
+
+        Slice::from_pieces(&self.piece_bytes(p)[a..], mid, right)
+    }
+
+    /// Convert a byte index to a character index
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        let (mut chars, mut bytes) = (0, 0);
+        for p in self.pieces.iter() {
+            if byte_idx < bytes + p.len {
+                let c = self
+                    .store(p.src)
+                    .byte_to_char_from(p.start + byte_idx - bytes, (p.start, p.char_start));
+                return chars + c - p.char_start;
+            }
+            chars += p.n_chars;
+            bytes += p.len;
+        }
+
+        self.n_chars
+    }
+
+    /// Convert a character index to a byte index
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        let (i, chars, bytes) = self.find_char(char_idx);
+        match self.pieces.get(i) {
+            Some(p) => bytes + self.piece_char_to_byte(p, char_idx - chars),
+            None => bytes,
+        }
+    }
+
+    /// Convert a character index to the index of the line containing it
+    ///
+    /// # Panics
+    /// This method will panic if the given char index is out of bounds
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        match self.try_char_to_line(char_idx) {
+            Some(line_idx) => line_idx,
+            None => panic!(
+                "char index was {char_idx} but the buffer char length is {}",
+                self.n_chars
+            ),
+        }
+    }
+
+    /// Convert a character index to the index of the line containing it
+    pub fn try_char_to_line(&self, char_idx: usize) -> Option<usize> {
+        if char_idx > self.n_chars {
+            return None;
+        }
+
+        let (mut lines, mut chars) = (0, 0);
+        for p in self.pieces.iter() {
+            if char_idx < chars + p.n_chars {
+                let store = self.store(p.src);
+                let c = p.char_start + char_idx - chars;
+                let before = store.nl_chars.partition_point(|&nl| nl < c);
+                return Some(lines + before - store.newlines_before(p.start));
+            }
+            lines += p.n_newlines;
+            chars += p.n_chars;
+        }
+
+        Some(self.n_newlines)
+    }
+
+    /// Convert a line index to the character index of its first character
+    ///
+    /// # Panics
+    /// This method will panic if the given line index is out of bounds
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        match self.try_line_to_char(line_idx) {
+            Some(char_idx) => char_idx,
+            None => panic!(
+                "line index was {line_idx} but the buffer has {} lines",
+                self.len_lines()
+            ),
+        }
+    }
+
+    /// Convert a line index to the character index of its first character
+    pub fn try_line_to_char(&self, line_idx: usize) -> Option<usize> {
+        if line_idx >= self.len_lines() {
+            return None;
+        } else if line_idx == 0 {
+            return Some(0);
+        }
+
+        // The line starts after the newline ending the previous line
+        let k = line_idx - 1;
+        let (mut lines, mut chars) = (0, 0);
+        for p in self.pieces.iter() {
+            if k < lines + p.n_newlines {
+                let store = self.store(p.src);
+                let nl = store.newlines_before(p.start) + k - lines;
+                return Some(chars + store.nl_chars[nl] - p.char_start + 1);
+            }
+            lines += p.n_newlines;
+            chars += p.n_chars;
+        }
+
+        None
+    }
+
+    /// Split the piece containing char_idx so that a piece begins at char_idx, returning the
+    /// index of that piece.
+    fn split_at(&mut self, char_idx: usize) -> usize {
+        let (i, chars, _) = self.find_char(char_idx);
+        if i == self.pieces.len() || chars == char_idx {
+            return i;
+        }
+
+        let p = self.pieces[i];
+        let k = char_idx - chars;
+        let split = p.start + self.piece_char_to_byte(&p, k);
+        let store = self.store(p.src);
+        let left_newlines = store.newlines_before(split) - store.newlines_before(p.start);
+
+        let left = Piece {
+            len: split - p.start,
+            n_chars: k,
+            n_newlines: left_newlines,
+            ..p
+        };
+        let right = Piece {
+            start: split,
+            len: p.len - left.len,
+            char_start: p.char_start + k,
+            n_chars: p.n_chars - k,
+            n_newlines: p.n_newlines - left_newlines,
+            ..p
+        };
+        self.pieces.splice(i..=i, [left, right]);
+
+        i + 1
+    }
+
+    /// Insert a single character at the specified character index.
+    pub fn insert_char(&mut self, char_idx: usize, ch: char) {
+        self.insert_str(char_idx, ch.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Insert a string at the specified character index.
+    ///
+    /// Consecutive inserts (such as those made while typing) extend the same piece rather than
+    /// adding a new one.
+    pub fn insert_str(&mut self, char_idx: usize, s: &str) {
+        if s.is_empty() {
+            return;
+        }
+
+        let i = self.split_at(char_idx);
+        let (start, char_start) = self.add.append(s);
+        let n_chars = s.chars().count();
+        let n_newlines = s.bytes().filter(|&b| b == b'\n').count();
+
+        match self.pieces[..i].last_mut() {
+            Some(p) if p.src == Src::Add && p.start + p.len == start => {
+                p.len += s.len();
+                p.n_chars += n_chars;
+                p.n_newlines += n_newlines;
+            }
+            _ => self.pieces.insert(
+                i,
+                Piece {
+                    src: Src::Add,
+                    start,
+                    len: s.len(),
+                    char_start,
+                    n_chars,
+                    n_newlines,
+                },
+            ),
+        }
+
+        self.n_bytes += s.len();
+        self.n_chars += n_chars;
+        self.n_newlines += n_newlines;
+    }
+
+    /// Remove the requested character index from the document
+    pub fn remove_char(&mut self, char_idx: usize) {
+        self.remove_range(char_idx, char_idx + 1);
+    }
+
+    /// Remove the requested range (from..to) from the document.
+    ///
+    /// # Panics
+    /// This method will panic if `char_from < char_to`
+    pub fn remove_range(&mut self, char_from: usize, char_to: usize) {
+        if char_from == char_to {
+            return;
+        }
+
+        assert!(
+            char_from < char_to,
+            "invalid range: from={char_from} > to={char_to}"
+        );
+
+        let i = self.split_at(char_from);
+        let j = self.split_at(char_to);
+        for p in self.pieces.drain(i..j) {
+            self.n_bytes -= p.len;
+            self.n_chars -= p.n_chars;
+            self.n_newlines -= p.n_newlines;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        buffer::{loader::CHUNK_LEN, GapBuffer},
+        util::TempPath,
+    };
+    use simple_test_case::test_case;
+    use std::{env, fs, process};
+
+    const CONTENT: &str = "hello, world!\nhow are you?\nthis is a ünïcödé line\n\nend";
+
+    /// Check that every query against pt gives the same result as for a gap buffer.
+    fn assert_matches_gap_buffer(pt: &PieceTable, gb: &GapBuffer) {
+        assert_eq!(pt.to_string(), gb.to_string());
+        assert_eq!(pt.len(), gb.len());
+        assert_eq!(pt.len_chars(), gb.len_chars());
+        assert_eq!(pt.len_lines(), gb.len_lines());
+
+        for i in 0..gb.len_lines() {
+            assert_eq!(pt.line(i).to_string(), gb.line(i).to_string(), "line {i}");
+            assert_eq!(pt.line_len_chars(i), gb.line_len_chars(i), "line {i}");
+            assert_eq!(pt.line_to_char(i), gb.line_to_char(i), "line {i}");
+        }
+
+        for i in 0..=gb.len_chars() {
+            assert_eq!(pt.get_char(i), gb.get_char(i), "char {i}");
+            assert_eq!(pt.char_to_line(i), gb.char_to_line(i), "char {i}");
+            assert_eq!(pt.char_to_byte(i), gb.char_to_byte(i), "char {i}");
+            assert_eq!(pt.byte_to_char(gb.char_to_byte(i)), i, "char {i}");
+        }
+    }
+
+    #[test]
+    fn from_str_matches_gap_buffer() {
+        assert_matches_gap_buffer(&PieceTable::from(CONTENT), &GapBuffer::from(CONTENT));
+    }
+
+    #[test]
+    fn empty_matches_gap_buffer() {
+        assert_matches_gap_buffer(&PieceTable::from(""), &GapBuffer::from(""));
+    }
+
+    #[test_case(0, "foo"; "start")]
+    #[test_case(5, "foo"; "middle of first line")]
+    #[test_case(13, "\n"; "newline before existing newline")]
+    #[test_case(36, "ä\nb"; "after multibyte chars")]
+    #[test_case(53, "\nbar"; "end")]
+    #[test]
+    fn insert_str_works(idx: usize, s: &str) {
+        let (mut pt, mut gb) = (PieceTable::from(CONTENT), GapBuffer::from(CONTENT));
+        pt.insert_str(idx, s);
+        gb.insert_str(idx, s);
+
+        assert_matches_gap_buffer(&pt, &gb);
+    }
+
+    #[test_case(0, 5; "start")]
+    #[test_case(10, 20; "across a line ending")]
+    #[test_case(30, 40; "multibyte chars")]
+    #[test_case(0, 53; "everything")]
+    #[test]
+    fn remove_range_works(from: usize, to: usize) {
+        let (mut pt, mut gb) = (PieceTable::from(CONTENT), GapBuffer::from(CONTENT));
+        pt.remove_range(from, to);
+        gb.remove_range(from, to);
+
+        assert_matches_gap_buffer(&pt, &gb);
+    }
+
+    #[test]
+    fn interleaved_edits_work() {
+        let (mut pt, mut gb) = (PieceTable::from(CONTENT), GapBuffer::from(CONTENT));
+
+        for (i, ch) in "typing\nsome text".chars().enumerate() {
+            pt.insert_char(7 + i, ch);
+            gb.insert_char(7 + i, ch);
+        }
+        assert_matches_gap_buffer(&pt, &gb);
+        assert_eq!(pt.pieces.len(), 3, "typing should extend a single piece");
+
+        pt.remove_char(3);
+        gb.remove_char(3);
+        pt.insert_str(40, "ö\n\n");
+        gb.insert_str(40, "ö\n\n");
+        pt.remove_range(5, 45);
+        gb.remove_range(5, 45);
+        pt.insert_str(0, "start\n");
+        gb.insert_str(0, "start\n");
+
+        assert_matches_gap_buffer(&pt, &gb);
+    }
+
+    #[test]
+    fn slices_spanning_many_pieces_work() {
+        let mut pt = PieceTable::from("abcdef");
+        pt.insert_char(2, 'X');
+        pt.insert_char(5, 'Y');
+        pt.insert_char(1, 'Z');
+        assert_eq!(pt.to_string(), "aZbXcdYef");
+
+        let slice = pt.slice(1, 8);
+        assert_eq!(slice.to_string(), "ZbXcdYe");
+        assert_eq!(slice, "ZbXcdYe");
+        assert_eq!(slice.chars().collect::<String>(), "ZbXcdYe");
+        assert_eq!(
+            slice.chunks().collect::<Vec<_>>(),
+            vec!["Z", "b", "X", "cd", "Y", "e"]
+        );
+
+        let fwd: Vec<(usize, char)> = slice.indexed_chars(1, false).collect();
+        let mut rev: Vec<(usize, char)> = slice.indexed_chars(1, true).collect();
+        rev.reverse();
+        assert_eq!(fwd, rev);
+        assert_eq!(fwd[0], (1, 'Z'));
+        assert_eq!(fwd[6], (7, 'e'));
+    }
+
+    #[test_case("plain\ntext\n", Some("plain\ntext"); "trailing newline removed")]
+    #[test_case("no trailing newline", Some("no trailing newline"); "no trailing newline")]
+    #[test_case("", Some(""); "empty")]
+    #[test_case("windows\r\nline endings", None; "crlf")]
+    #[test_case("\u{feff}bom", None; "bom")]
+    #[test_case("bin\0ary", None; "binary")]
+    #[test]
+    fn open_works(content: &str, expected: Option<&str>) {
+        let path = env::temp_dir().join(format!(
+            "ad-piece-table-{}-{}",
+            process::id(),
+            content.len()
+        ));
+        fs::write(&path, content).unwrap();
+        let res = PieceTable::open(&path).unwrap().map(|pt| pt.to_string());
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(res.as_deref(), expected);
+    }
+
+    #[test]
+    fn truncating_the_file_after_opening_does_not_change_the_content() {
+        let path = env::temp_dir().join(format!("ad-piece-table-truncate-{}", process::id()));
+        fs::write(&path, "some log lines\nmore log lines\n").unwrap();
+        let pt = PieceTable::open(&path).unwrap().unwrap();
+
+        fs::File::create(&path).unwrap(); // truncate as log rotation would
+        let content = pt.to_string();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(content, "some log lines\nmore log lines");
+    }
+
+    #[test]
+    fn files_larger_than_a_block_are_held_in_multiple_blocks() {
+        // The leading 'a' offsets the two byte characters so that one spans a block boundary
+        let content = format!("a{}\n", "ü\n".repeat(CHUNK_LEN as usize / 3 + 1));
+        let path = TempPath::new("piece-table-blocks");
+        fs::write(&path, &content).unwrap();
+        let pt = PieceTable::open(&path).unwrap().unwrap();
+        let gb = GapBuffer::from(&content[..content.len() - 1]);
+
+        assert!(pt.original.len() > 1, "expected multiple blocks");
+        assert_eq!(pt.to_string(), gb.to_string());
+        assert_eq!(pt.len_lines(), gb.len_lines());
+        // The block boundary falls a few lines before the end of the file
+        for i in (0..3).chain(gb.len_lines() - 10..gb.len_lines()) {
+            assert_eq!(pt.line(i).to_string(), gb.line(i).to_string(), "line {i}");
+            assert_eq!(pt.line_to_char(i), gb.line_to_char(i), "line {i}");
+        }
+    }
+}
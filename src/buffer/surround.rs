@@ -0,0 +1,474 @@
+//! Adding, changing and deleting the delimiters surrounding dot.
+//!
+//! Delimiters are specified using a single character, with either half of a bracket pair
+//! standing for the full pair, or as an XML style tag such as `<div class="foo">`. When
+//! searching for the delimiters around dot the character `t` matches the nearest enclosing
+//! pair of tags and `b` matches the nearest enclosing pair of brackets of any kind.
+use crate::{
+    buffer::{ActionOutcome, Buffer},
+    dot::{
+        find::{find_backward, find_forward},
+        Cur, Dot, FindDelimited,
+    },
+    exec::IterBoundedChars,
+};
+use ad_event::Source;
+
+/// The character used to refer to a pair of tags when changing or deleting delimiters
+pub(crate) const TAG: char = 't';
+
+/// The character used to refer to any pair of brackets when changing or deleting delimiters
+pub(crate) const ANY_BRACKET: char = 'b';
+
+/// A region of the buffer denoted by its start and (exclusive) end character offsets
+type Span = (usize, usize);
+
+/// The opening and closing delimiters for the given character.
+fn pair_for(c: char) -> (char, char) {
+    match c {
+        '(' | ')' => ('(', ')'),
+        '[' | ']' => ('[', ']'),
+        '{' | '}' => ('{', '}'),
+        '<' | '>' => ('<', '>'),
+        c => (c, c),
+    }
+}
+
+/// Parse a delimiter specification into the strings to insert before and after dot.
+fn delimiters_for(s: &str) -> Result<(String, String), String> {
+    let mut chars = s.chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => {
+            let (l, r) = pair_for(c);
+            Ok((l.to_string(), r.to_string()))
+        }
+
+        _ if s.len() > 2 && s.starts_with('<') && s.ends_with('>') => {
+            let name: String = s[1..s.len() - 1]
+                .chars()
+                .take_while(|c| !c.is_whitespace() && *c != '/')
+                .collect();
+            if name.is_empty() {
+                return Err(format!("invalid tag: {s}"));
+            }
+
+            Ok((s.to_string(), format!("</{name}>")))
+        }
+
+        _ => Err(format!("invalid delimiter: {s}")),
+    }
+}
+
+impl Buffer {
+    /// Wrap dot in the given delimiters.
+    pub(super) fn surround_add(&mut self, s: &str, source: Source) -> Option<ActionOutcome> {
+        let (l, r) = match delimiters_for(s) {
+            Ok(delims) => delims,
+            Err(e) => return Some(ActionOutcome::SetStatusMessage(e)),
+        };
+        let (from, to) = self.dot.as_char_indices();
+        let after = (to + 1).min(self.txt.len_chars());
+
+        self.replace_surround(((from, from), (after, after)), &l, &r, source);
+
+        None
+    }
+
+    /// Replace the nearest delimiters of the given kind surrounding dot.
+    pub(super) fn surround_change(
+        &mut self,
+        c: char,
+        s: &str,
+        source: Source,
+    ) -> Option<ActionOutcome> {
+        let (l, r) = match delimiters_for(s) {
+            Ok(delims) => delims,
+            Err(e) => return Some(ActionOutcome::SetStatusMessage(e)),
+        };
+
+        match self.enclosing_delimiters(c) {
+            Some(spans) => self.replace_surround(spans, &l, &r, source),
+            None => return Some(no_enclosing_delimiters(c)),
+        }
+
+        None
+    }
+
+    /// Remove the nearest delimiters of the given kind surrounding dot.
+    pub(super) fn surround_delete(&mut self, c: char, source: Source) -> Option<ActionOutcome> {
+        match self.enclosing_delimiters(c) {
+            Some(spans) => self.replace_surround(spans, "", "", source),
+            None => return Some(no_enclosing_delimiters(c)),
+        }
+
+        None
+    }
+
+    /// Replace the opening and closing spans with new delimiters as a single transaction in the
+    /// edit log, setting dot to the text between them.
+    fn replace_surround(&mut self, (open, close): (Span, Span), l: &str, r: &str, source: Source) {
+        self.edit_log.new_transaction();
+        // Replace the closing delimiter first so that the offsets for the opening delimiter
+        // remain valid
+        self.replace_span(close, r, source);
+        self.replace_span(open, l, source);
+        self.edit_log.new_transaction();
+
+        let start = open.0 + l.chars().count();
+        let len = close.0 - open.1;
+        self.dot = if len == 0 {
+            Dot::Cur {
+                c: Cur { idx: start },
+            }
+        } else {
+            Dot::from_char_indices(start, start + len - 1).collapse_null_range()
+        };
+        self.dot.clamp_idx(self.txt.len_chars());
+        self.xdot.clamp_idx(self.txt.len_chars());
+    }
+
+    fn replace_span(&mut self, (from, to): Span, s: &str, source: Source) {
+        match to - from {
+            0 => (),
+            1 => {
+                _ = self.delete_dot(
+                    Dot::Cur {
+                        c: Cur { idx: from },
+                    },
+                    Some(source),
+                )
+            }
+            _ => _ = self.delete_dot(Dot::from_char_indices(from, to - 1), Some(source)),
+        }
+
+        if !s.is_empty() {
+            self.insert_string(
+                Dot::Cur {
+                    c: Cur { idx: from },
+                },
+                s.to_string(),
+                Some(source),
+            );
+        }
+    }
+
+    /// Locate the opening and closing delimiters of the given kind surrounding dot.
+    fn enclosing_delimiters(&self, c: char) -> Option<(Span, Span)> {
+        let (open, close) = match c {
+            TAG => return self.enclosing_tags(),
+            ANY_BRACKET => ['(', '[', '{']
+                .into_iter()
+                .filter_map(|l| self.enclosing_pair(l, pair_for(l).1))
+                .max_by_key(|&(open, _)| open)?,
+            c => match pair_for(c) {
+                (l, r) if l == r => self.enclosing_quotes(l)?,
+                (l, r) => self.enclosing_pair(l, r)?,
+            },
+        };
+
+        Some(((open, open + 1), (close, close + 1)))
+    }
+
+    /// Bracket pairs are located using [FindDelimited] so that nested pairs are skipped.
+    fn enclosing_pair(&self, l: char, r: char) -> Option<(usize, usize)> {
+        let (mut from, mut to) = self.dot.as_char_indices();
+        if from >= self.txt.len_chars() {
+            return None;
+        }
+
+        // Allow dot to start on the opening delimiter itself
+        if self.txt.char(from) == l {
+            from += 1;
+            to = to.max(from);
+        }
+
+        let fd = FindDelimited::new(l, r);
+        let open = find_backward(&fd, Cur { idx: from }, self)?.first_cur().idx;
+        let close = find_forward(&fd, Cur { idx: to }, self)?.last_cur().idx;
+
+        Some((open.checked_sub(1)?, close + 1))
+    }
+
+    /// Quotes can't be nested so they are paired up from the start of the line containing dot,
+    /// ignoring any that are escaped with a backslash.
+    fn enclosing_quotes(&self, q: char) -> Option<(usize, usize)> {
+        let (from, to) = self.dot.as_char_indices();
+        let y = self.txt.char_to_line(from);
+        let line_start = self.txt.line_to_char(y);
+        let mut escaped = false;
+        let mut quotes = Vec::new();
+
+        for (i, ch) in self.txt.line(y).chars().enumerate() {
+            match ch {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                ch if ch == q => quotes.push(line_start + i),
+                _ => (),
+            }
+        }
+
+        quotes
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .find(|&(open, close)| open <= from && to <= close)
+    }
+
+    /// Tags are matched by name, ignoring self-closing tags, comments and declarations.
+    ///
+    /// The buffer is scanned outward from dot: closing tags after dot that were not opened
+    /// after it are paired with opening tags before dot that were not closed before it, with
+    /// the first closing tag that can be paired giving the nearest enclosing tags.
+    fn enclosing_tags(&self) -> Option<(Span, Span)> {
+        let (from, to) = self.dot.as_char_indices();
+        // Dot is enclosed by the tags it is within so the scans start on the far side of them
+        let before = match self.tag_containing(from) {
+            Some(Tag {
+                span: (start, _),
+                is_close: true,
+                ..
+            }) => start,
+            Some(Tag { span: (_, end), .. }) => end,
+            None => from,
+        };
+        let after = match self.tag_containing(to) {
+            Some(Tag {
+                span: (_, end),
+                is_close: false,
+                ..
+            }) => end,
+            Some(Tag {
+                span: (start, _), ..
+            }) => start,
+            None => to,
+        };
+
+        let mut opened = Vec::new();
+        let closes = self
+            .tags_after(after.max(before))
+            .filter(move |tag| unmatched(&mut opened, tag, |t| !t.is_close));
+
+        let mut closed = Vec::new();
+        let mut opens = self
+            .tags_before(before)
+            .filter(move |tag| unmatched(&mut closed, tag, |t| t.is_close));
+
+        // Unclosed tags such as <br> and stray closing tags are skipped over
+        let mut seen: Vec<Tag> = Vec::new();
+        for close in closes {
+            if let Some(open) = seen.iter().find(|t| t.name == close.name) {
+                return Some((open.span, close.span));
+            }
+
+            for open in opens.by_ref() {
+                if open.name == close.name {
+                    return Some((open.span, close.span));
+                }
+                seen.push(open);
+            }
+        }
+
+        None
+    }
+
+    /// The tags starting at or after idx, nearest first.
+    fn tags_after(&self, idx: usize) -> impl Iterator<Item = Tag> + '_ {
+        let mut start = None;
+        self.iter_between(idx, self.txt.len_chars())
+            .filter_map(move |(i, ch)| match ch {
+                '<' => {
+                    start = Some(i);
+                    None
+                }
+                '>' => start.take().and_then(|start| self.tag((start, i + 1))),
+                _ => None,
+            })
+    }
+
+    /// The tags ending before idx, nearest first.
+    fn tags_before(&self, idx: usize) -> impl Iterator<Item = Tag> + '_ {
+        let mut end = None;
+        self.rev_iter_between(idx, 0)
+            .filter_map(move |(i, ch)| match ch {
+                '>' => {
+                    end = Some(i + 1);
+                    None
+                }
+                '<' => end.take().and_then(|end| self.tag((i, end))),
+                _ => None,
+            })
+    }
+
+    /// The tag that the character at idx is part of.
+    fn tag_containing(&self, idx: usize) -> Option<Tag> {
+        let is_bracket = |&(_, ch): &(usize, char)| ch == '<' || ch == '>';
+        let start = match self.txt.get_char(idx)? {
+            '<' => idx,
+            _ => match self.rev_iter_between(idx, 0).find(is_bracket)? {
+                (i, '<') => i,
+                _ => return None,
+            },
+        };
+
+        match self
+            .iter_between(start + 1, self.txt.len_chars())
+            .find(is_bracket)?
+        {
+            (end, '>') if end >= idx => self.tag((start, end + 1)),
+            _ => None,
+        }
+    }
+
+    /// Parse the tag covering the given span, returning None for self-closing tags, comments
+    /// and declarations.
+    fn tag(&self, (start, end): Span) -> Option<Tag> {
+        let inner = self.txt.slice(start + 1, end - 1).to_string();
+        if inner.starts_with(['!', '?']) || inner.ends_with('/') {
+            return None;
+        }
+
+        let (is_close, tag) = match inner.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, inner.as_str()),
+        };
+        let name = tag.chars().take_while(|c| !c.is_whitespace()).collect();
+
+        Some(Tag {
+            span: (start, end),
+            name,
+            is_close,
+        })
+    }
+}
+
+/// An opening or closing tag found while searching for the tags enclosing dot.
+#[derive(Debug)]
+struct Tag {
+    span: Span,
+    name: String,
+    is_close: bool,
+}
+
+/// Track the tags seen while scanning away from dot, returning true for tags whose partner
+/// must be on the other side of dot.
+///
+/// Tags for which `is_open` returns true are pushed onto the stack of unmatched tags and
+/// matching tags pop the stack back to their partner, dropping any unclosed tags between them.
+fn unmatched(stack: &mut Vec<String>, tag: &Tag, is_open: impl Fn(&Tag) -> bool) -> bool {
+    if is_open(tag) {
+        stack.push(tag.name.clone());
+        return false;
+    }
+
+    match stack.iter().rposition(|n| *n == tag.name) {
+        Some(ix) => {
+            stack.truncate(ix);
+            false
+        }
+        None => true,
+    }
+}
+
+fn no_enclosing_delimiters(c: char) -> ActionOutcome {
+    let msg = match c {
+        TAG => "no enclosing tags".to_string(),
+        ANY_BRACKET => "no enclosing brackets".to_string(),
+        c => {
+            let (l, r) = pair_for(c);
+            format!("no enclosing {l}{r}")
+        }
+    };
+
+    ActionOutcome::SetStatusMessage(msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::editor::Action;
+    use simple_test_case::test_case;
+
+    #[test_case("(", Ok(("(", ")")); "opening bracket")]
+    #[test_case("]", Ok(("[", "]")); "closing bracket")]
+    #[test_case("*", Ok(("*", "*")); "symmetric")]
+    #[test_case("<div class=\"x\">", Ok(("<div class=\"x\">", "</div>")); "tag with attributes")]
+    #[test_case("<>", Err("invalid delimiter: <>"); "empty tag")]
+    #[test_case("ab", Err("invalid delimiter: ab"); "multiple chars")]
+    #[test]
+    fn delimiters_for_works(s: &str, expected: Result<(&str, &str), &str>) {
+        let res = delimiters_for(s);
+        let expected = expected
+            .map(|(l, r)| (l.to_string(), r.to_string()))
+            .map_err(|e| e.to_string());
+
+        assert_eq!(res, expected);
+    }
+
+    #[test_case(Action::SurroundAdd { s: "(".to_string() }, 4, 6, "foo (bar) \"baz\" (q(u)x) <p><b>hi</b></p>"; "add around range")]
+    #[test_case(Action::SurroundAdd { s: "<i>".to_string() }, 4, 6, "foo <i>bar</i> \"baz\" (q(u)x) <p><b>hi</b></p>"; "add tag")]
+    #[test_case(Action::SurroundDelete { c: '"' }, 9, 9, "foo bar baz (q(u)x) <p><b>hi</b></p>"; "delete quotes")]
+    #[test_case(Action::SurroundDelete { c: ')' }, 15, 15, "foo bar \"baz\" q(u)x <p><b>hi</b></p>"; "delete outer parens")]
+    #[test_case(Action::SurroundDelete { c: '(' }, 16, 16, "foo bar \"baz\" (qux) <p><b>hi</b></p>"; "delete inner parens")]
+    #[test_case(Action::SurroundDelete { c: '(' }, 14, 14, "foo bar \"baz\" q(u)x <p><b>hi</b></p>"; "dot on opening delimiter")]
+    #[test_case(Action::SurroundChange { from: '"', to: "'".to_string() }, 10, 10, "foo bar 'baz' (q(u)x) <p><b>hi</b></p>"; "change quotes")]
+    #[test_case(Action::SurroundChange { from: '(', to: "[".to_string() }, 17, 17, "foo bar \"baz\" (q[u]x) <p><b>hi</b></p>"; "change parens")]
+    #[test_case(Action::SurroundChange { from: TAG, to: "<em>".to_string() }, 31, 31, "foo bar \"baz\" (q(u)x) <p><em>hi</em></p>"; "change tag")]
+    #[test_case(Action::SurroundChange { from: ANY_BRACKET, to: "{".to_string() }, 17, 17, "foo bar \"baz\" (q{u}x) <p><b>hi</b></p>"; "change any bracket")]
+    #[test_case(Action::SurroundDelete { c: TAG }, 24, 24, "foo bar \"baz\" (q(u)x) <b>hi</b>"; "delete outer tag")]
+    #[test]
+    fn surround_actions_work(a: Action, from: usize, to: usize, expected: &str) {
+        let mut b = Buffer::new_virtual(0, "test", "foo bar \"baz\" (q(u)x) <p><b>hi</b></p>");
+        b.dot = Dot::from_char_indices(from, to).collapse_null_range();
+        let outcome = b.handle_action(a, Source::Keyboard);
+
+        assert_eq!(outcome, None);
+        assert_eq!(b.str_contents(), format!("{expected}\n"));
+    }
+
+    #[test_case("<p><br>x</p>", 7, 7, Some(((0, 3), (8, 12))); "unclosed tags are skipped")]
+    #[test_case("<a><b></b>x</a>", 10, 10, Some(((0, 3), (11, 15))); "siblings are skipped")]
+    #[test_case("<a></b>x</a>", 7, 7, Some(((0, 3), (8, 12))); "stray closing tags are skipped")]
+    #[test_case("<a><!-- <b> -->x</a>", 15, 15, Some(((0, 3), (16, 20))); "comments are skipped")]
+    #[test_case("<p>x</p>", 1, 1, Some(((0, 3), (4, 8))); "dot within opening tag")]
+    #[test_case("<p>x</p>", 6, 6, Some(((0, 3), (4, 8))); "dot within closing tag")]
+    #[test_case("<a><b>x</b></a>", 3, 10, Some(((3, 6), (7, 11))); "dot covering an element")]
+    #[test_case("<a>x</a> y", 9, 9, None; "dot outside of the tags")]
+    #[test]
+    fn enclosing_tags_works(s: &str, from: usize, to: usize, expected: Option<(Span, Span)>) {
+        let mut b = Buffer::new_virtual(0, "test", s);
+        b.dot = Dot::from_char_indices(from, to).collapse_null_range();
+
+        assert_eq!(b.enclosing_tags(), expected);
+    }
+
+    #[test]
+    fn surround_is_a_single_undo_step() {
+        let s = "foo (bar) baz";
+        let mut b = Buffer::new_virtual(0, "test", s);
+        b.dot = Dot::Cur { c: Cur { idx: 6 } };
+        b.handle_action(
+            Action::SurroundChange {
+                from: '(',
+                to: "{".to_string(),
+            },
+            Source::Keyboard,
+        );
+        assert_eq!(b.str_contents(), "foo {bar} baz\n");
+
+        b.handle_action(Action::Undo, Source::Keyboard);
+        assert_eq!(b.str_contents(), format!("{s}\n"));
+    }
+
+    #[test]
+    fn missing_delimiters_are_reported() {
+        let mut b = Buffer::new_virtual(0, "test", "foo bar");
+        let outcome = b.handle_action(Action::SurroundDelete { c: '[' }, Source::Keyboard);
+
+        assert_eq!(
+            outcome,
+            Some(ActionOutcome::SetStatusMessage(
+                "no enclosing []".to_string()
+            ))
+        );
+    }
+}
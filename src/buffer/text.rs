@@ -0,0 +1,205 @@
+//! The storage used for the text of a [Buffer](super::Buffer).
+use super::{GapBuffer, PieceTable, Slice};
+use std::fmt;
+
+/// Call the same method on whichever storage is in use.
+macro_rules! dispatch {
+    ($self:expr, $method:ident($($arg:expr),*)) => {
+        match $self {
+            Text::Gap(gb) => gb.$method($($arg),*),
+            Text::Pieces(pt) => pt.$method($($arg),*),
+        }
+    };
+}
+
+/// The text of a buffer.
+///
+/// Most buffers use a [GapBuffer] but files larger than the `large-file-mb` config property
+/// (including those loaded in the background) are opened as a [PieceTable] so that edits never
+/// need to move the existing content of the file and clones share it rather than copying it.
+#[derive(Debug, Clone)]
+pub enum Text {
+    Gap(GapBuffer),
+    Pieces(PieceTable),
+}
+
+impl Default for Text {
+    fn default() -> Self {
+        Self::Gap(GapBuffer::default())
+    }
+}
+
+impl From<String> for Text {
+    fn from(s: String) -> Self {
+        Self::Gap(GapBuffer::from(s))
+    }
+}
+
+impl From<&str> for Text {
+    fn from(s: &str) -> Self {
+        Self::Gap(GapBuffer::from(s))
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Text::Gap(gb) => fmt::Display::fmt(gb, f),
+            Text::Pieces(pt) => fmt::Display::fmt(pt, f),
+        }
+    }
+}
+
+impl Text {
+    /// Whether or not this text is stored in a [PieceTable]
+    pub fn is_piece_table(&self) -> bool {
+        matches!(self, Self::Pieces(_))
+    }
+
+    /// The number of bytes in the text
+    #[inline]
+    pub fn len(&self) -> usize {
+        dispatch!(self, len())
+    }
+
+    /// Whether or not the text is empty
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        dispatch!(self, is_empty())
+    }
+
+    /// The raw content of the text
+    pub fn bytes(&self) -> Vec<u8> {
+        dispatch!(self, bytes())
+    }
+
+    /// Iterate over the lines of the text
+    pub fn iter_lines(&self) -> impl Iterator<Item = Slice<'_>> {
+        (0..self.len_lines()).map(|i| self.line(i))
+    }
+
+    /// The number of lines within the text
+    #[inline]
+    pub fn len_lines(&self) -> usize {
+        dispatch!(self, len_lines())
+    }
+
+    /// The number of characters in the text
+    #[inline]
+    pub fn len_chars(&self) -> usize {
+        dispatch!(self, len_chars())
+    }
+
+    /// Clear the contents of the text
+    pub fn clear(&mut self) {
+        dispatch!(self, clear())
+    }
+
+    /// The character at the specified character index.
+    ///
+    /// # Panics
+    /// This method will panic if the given character index is out of bounds
+    #[inline]
+    pub fn char(&self, char_idx: usize) -> char {
+        dispatch!(self, char(char_idx))
+    }
+
+    /// The character at the specified character index.
+    #[inline]
+    pub fn get_char(&self, char_idx: usize) -> Option<char> {
+        dispatch!(self, get_char(char_idx))
+    }
+
+    /// The requested line as a [Slice].
+    ///
+    /// # Panics
+    /// This method will panic if the given line index is out of bounds
+    #[inline]
+    pub fn line(&self, line_idx: usize) -> Slice<'_> {
+        dispatch!(self, line(line_idx))
+    }
+
+    /// The number of characters in the requested line.
+    ///
+    /// # Panics
+    /// This method will panic if the given line index is out of bounds
+    #[inline]
+    pub fn line_len_chars(&self, line_idx: usize) -> usize {
+        dispatch!(self, line_len_chars(line_idx))
+    }
+
+    /// An exclusive range of characters from the text
+    #[inline]
+    pub fn slice(&self, char_from: usize, char_to: usize) -> Slice<'_> {
+        dispatch!(self, slice(char_from, char_to))
+    }
+
+    /// Convert a byte index to a character index
+    pub fn byte_to_char(&self, byte_idx: usize) -> usize {
+        dispatch!(self, byte_to_char(byte_idx))
+    }
+
+    /// Convert a character index to a byte index
+    pub fn char_to_byte(&self, char_idx: usize) -> usize {
+        dispatch!(self, char_to_byte(char_idx))
+    }
+
+    /// Convert a character index to the index of the line containing it
+    ///
+    /// # Panics
+    /// This method will panic if the given char index is out of bounds
+    pub fn char_to_line(&self, char_idx: usize) -> usize {
+        dispatch!(self, char_to_line(char_idx))
+    }
+
+    /// Convert a character index to the index of the line containing it
+    pub fn try_char_to_line(&self, char_idx: usize) -> Option<usize> {
+        dispatch!(self, try_char_to_line(char_idx))
+    }
+
+    /// Convert a line index to the character index of its first character
+    ///
+    /// # Panics
+    /// This method will panic if the given line index is out of bounds
+    pub fn line_to_char(&self, line_idx: usize) -> usize {
+        dispatch!(self, line_to_char(line_idx))
+    }
+
+    /// Convert a line index to the character index of its first character
+    pub fn try_line_to_char(&self, line_idx: usize) -> Option<usize> {
+        dispatch!(self, try_line_to_char(line_idx))
+    }
+
+    /// Insert a single character at the specified character index.
+    pub fn insert_char(&mut self, char_idx: usize, ch: char) {
+        dispatch!(self, insert_char(char_idx, ch))
+    }
+
+    /// Insert a string at the specified character index.
+    pub fn insert_str(&mut self, char_idx: usize, s: &str) {
+        dispatch!(self, insert_str(char_idx, s))
+    }
+
+    /// Append a block of a file that is being loaded to the end of the text.
+    ///
+    /// A [PieceTable] takes ownership of the block rather than copying it into its add buffer.
+    pub fn append_block(&mut self, s: String) {
+        match self {
+            Text::Gap(gb) => gb.insert_str(gb.len_chars(), &s),
+            Text::Pieces(pt) => pt.append_block(s),
+        }
+    }
+
+    /// Remove the requested character index from the text
+    pub fn remove_char(&mut self, char_idx: usize) {
+        dispatch!(self, remove_char(char_idx))
+    }
+
+    /// Remove the requested range (from..to) from the text.
+    ///
+    /// # Panics
+    /// This method will panic if `char_from < char_to`
+    pub fn remove_range(&mut self, char_from: usize, char_to: usize) {
+        dispatch!(self, remove_range(char_from, char_to))
+    }
+}
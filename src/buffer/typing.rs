@@ -0,0 +1,301 @@
+//! Handling for text typed by the user in INSERT mode.
+//!
+//! Opening brackets and quotes are automatically closed (as configured by the `auto-pairs`
+//! property) and typing a closing character over one that was automatically inserted moves
+//! past it rather than inserting a second copy. When `smart-indent` is enabled the [FileType](super::filetype::FileType) of the
+//! buffer determines which characters increase the indentation of the following line and
+//! which characters decrease the indentation of the current line.
+use crate::{
+    buffer::Buffer,
+    config_handle,
+    dot::{Cur, Dot},
+};
+use ad_event::Source;
+
+/// The positions of closing characters that were inserted by auto-pairs, kept up to date as
+/// edits are made to the buffer. Only these are typed over: closing characters that were
+/// already in the buffer (or have been pasted) are left alone.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(super) struct AutoClosed {
+    inner: Vec<Cur>,
+}
+
+impl AutoClosed {
+    /// Shift closers following an insertion of `n` characters at `idx`.
+    pub(super) fn insert(&mut self, idx: usize, n: usize) {
+        for c in self.inner.iter_mut() {
+            if c.idx >= idx {
+                c.idx += n;
+            }
+        }
+    }
+
+    /// Forget closers that have been deleted and shift those following the deletion of `n`
+    /// characters starting at `idx`.
+    pub(super) fn delete(&mut self, idx: usize, n: usize) {
+        self.inner.retain(|c| c.idx < idx || c.idx >= idx + n);
+        for c in self.inner.iter_mut() {
+            if c.idx >= idx + n {
+                c.idx -= n;
+            }
+        }
+    }
+
+    pub(super) fn clear(&mut self) {
+        self.inner.clear();
+    }
+
+    /// Remove the closer at `idx` if there is one, returning whether it was present.
+    fn take(&mut self, idx: usize) -> bool {
+        let n = self.inner.len();
+        self.inner.retain(|c| c.idx != idx);
+
+        self.inner.len() < n
+    }
+}
+
+impl Buffer {
+    /// Insert a character typed by the user at dot.
+    pub(super) fn insert_typed_char(&mut self, ch: char) -> Cur {
+        let (auto_pairs, smart_indent) = {
+            let conf = config_handle!();
+            (conf.auto_pairs.clone(), conf.smart_indent)
+        };
+
+        let c = match self.dot {
+            Dot::Cur { c } => c,
+            // Typing over a selection replaces it as normal
+            Dot::Range { .. } => return self.insert_char(self.dot, ch, Some(Source::Keyboard)).0,
+        };
+        let prev = c.idx.checked_sub(1).and_then(|idx| self.txt.get_char(idx));
+        let next = self.txt.get_char(c.idx);
+
+        if next == Some(ch) && self.auto_closed.take(c.idx) {
+            return Cur { idx: c.idx + 1 };
+        }
+
+        let outdent = smart_indent
+            && self
+                .filetype()
+                .is_some_and(|ft| ft.outdent_before.contains(&ch));
+        let dot = if outdent { self.outdent(c) } else { self.dot };
+
+        match auto_pairs.iter().find(|&&(open, _)| open == ch) {
+            Some(&(open, close)) if should_close(open, close, prev, next, &auto_pairs) => {
+                let (c, _) =
+                    self.insert_string(dot, format!("{open}{close}"), Some(Source::Keyboard));
+                let c = Cur { idx: c.idx - 1 };
+                self.auto_closed.inner.push(c);
+
+                c
+            }
+            _ => self.insert_char(dot, ch, Some(Source::Keyboard)).0,
+        }
+    }
+
+    /// Remove one level of indentation from the line containing `c` if it contains only
+    /// whitespace before the cursor.
+    fn outdent(&mut self, c: Cur) -> Dot {
+        let (y, x) = c.as_yx(self);
+        let line_start = self.txt.line_to_char(y);
+        let leading: String = self.txt.line(y).chars().take(x).collect();
+        if leading.is_empty() || !leading.chars().all(|ch| ch == ' ' || ch == '\t') {
+            return Dot::Cur { c };
+        }
+
+        let tabstop = config_handle!().tabstop;
+        let n = match leading.ends_with('\t') {
+            true => 1,
+            false => leading
+                .chars()
+                .rev()
+                .take_while(|&ch| ch == ' ')
+                .count()
+                .min(tabstop),
+        };
+        let from = line_start + x - n;
+        let (c, _) = self.delete_dot(Dot::from_char_indices(from, from + n - 1), None);
+
+        Dot::Cur { c }
+    }
+
+    /// Insert a newline typed by the user at dot, matching the indentation of the current line
+    /// if `match-indent` is set and adjusting it for the file type if `smart-indent` is set.
+    ///
+    /// Typing a newline between an indent and outdent trigger (such as between a pair of
+    /// braces) places the closing character on its own line below the cursor.
+    pub(super) fn insert_typed_newline(&mut self) -> Cur {
+        let (match_indent, smart_indent, expand_tab, tabstop) = {
+            let conf = config_handle!();
+            (
+                conf.match_indent,
+                conf.smart_indent,
+                conf.expand_tab,
+                conf.tabstop,
+            )
+        };
+
+        let y = self.txt.char_to_line(self.dot.first_cur().idx);
+        let line = self.txt.line(y).to_string();
+        let prefix = match match_indent {
+            true => line
+                .find(|c: char| !c.is_whitespace())
+                .map(|ix| line.split_at(ix).0.to_string())
+                .unwrap_or_default(),
+            false => String::new(),
+        };
+
+        let ft = self.filetype().filter(|_| smart_indent);
+        let (indent, outdent) = match (ft, &self.dot) {
+            (Some(ft), Dot::Cur { c }) => {
+                let (_, x) = c.as_yx(self);
+                let before = line.chars().take(x).filter(|ch| !ch.is_whitespace()).last();
+                let after = self.txt.get_char(c.idx);
+
+                (
+                    before.is_some_and(|ch| ft.indent_after.contains(&ch)),
+                    after.is_some_and(|ch| ft.outdent_before.contains(&ch)),
+                )
+            }
+            _ => (false, false),
+        };
+
+        let unit = match expand_tab {
+            true => " ".repeat(tabstop),
+            false => "\t".to_string(),
+        };
+        let mut s = format!("\n{prefix}");
+        if indent {
+            s.push_str(&unit);
+        }
+        if indent && outdent {
+            s.push_str(&format!("\n{prefix}"));
+        }
+
+        let (c, _) = self.insert_string(self.dot, s, Some(Source::Keyboard));
+
+        // The cursor is left at the end of the indented line rather than the end of the
+        // inserted text when we have also moved the outdent trigger to its own line.
+        match indent && outdent {
+            true => Cur {
+                idx: c.idx - (prefix.chars().count() + 1),
+            },
+            false => c,
+        }
+    }
+}
+
+/// Whether or not an opening character should be automatically closed based on the
+/// characters either side of the cursor.
+fn should_close(
+    open: char,
+    close: char,
+    prev: Option<char>,
+    next: Option<char>,
+    auto_pairs: &[(char, char)],
+) -> bool {
+    let next_ok = match next {
+        None => true,
+        Some(ch) => ch.is_whitespace() || auto_pairs.iter().any(|&(_, c)| c == ch),
+    };
+
+    // Quotes are not closed when they follow a word character as they are likely to be an
+    // apostrophe or the end of a string that is being closed.
+    let prev_ok = open != close || !prev.is_some_and(|ch| ch.is_alphanumeric() || ch == open);
+
+    next_ok && prev_ok
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{buffer::tests::file_buffer, editor::Action, key::Input};
+    use simple_test_case::test_case;
+
+    fn type_keys(fname: &str, initial: &str, idx: usize, keys: &str) -> Buffer {
+        let mut b = file_buffer(fname, initial);
+        b.dot = Dot::Cur { c: Cur { idx } };
+
+        for ch in keys.chars() {
+            let a = match ch {
+                '\n' => Action::RawInput { i: Input::Return },
+                c => Action::InsertChar { c },
+            };
+            b.handle_action(a, Source::Keyboard);
+        }
+
+        b
+    }
+
+    #[test_case("(", "()", 1; "brackets are closed")]
+    #[test_case("\"", "\"\"", 1; "quotes are closed")]
+    #[test_case("()", "()", 2; "closing bracket is skipped")]
+    #[test_case("f(x)", "f(x)", 4; "closing bracket is skipped after content")]
+    #[test_case("\"a\"", "\"a\"", 3; "closing quote is skipped")]
+    #[test_case("don\"", "don\"", 4; "quotes after a word are not closed")]
+    #[test_case("'", "'", 1; "unconfigured pairs are not closed")]
+    #[test]
+    fn auto_pairs_work(keys: &str, expected: &str, idx: usize) {
+        let b = type_keys("test.txt", "", 0, keys);
+
+        assert_eq!(b.str_contents(), format!("{expected}\n"));
+        assert_eq!(b.dot, Dot::Cur { c: Cur { idx } });
+    }
+
+    #[test_case(")", "foo()", 4, "foo())", 5; "existing closing bracket")]
+    #[test_case("\"", "\"a\"", 2, "\"a\"\"", 3; "existing closing quote")]
+    #[test]
+    fn only_auto_inserted_closers_are_skipped(
+        keys: &str,
+        initial: &str,
+        idx: usize,
+        expected: &str,
+        expected_idx: usize,
+    ) {
+        let b = type_keys("test.txt", initial, idx, keys);
+
+        assert_eq!(b.str_contents(), format!("{expected}\n"));
+        assert_eq!(
+            b.dot,
+            Dot::Cur {
+                c: Cur { idx: expected_idx }
+            }
+        );
+    }
+
+    #[test]
+    fn brackets_are_not_closed_before_a_word() {
+        let b = type_keys("test.txt", "foo", 0, "(");
+
+        assert_eq!(b.str_contents(), "(foo\n");
+    }
+
+    #[test_case("test.rs", "fn main() {\n", "fn main() {\n    \n}"; "indent after brace")]
+    #[test_case("test.py", "def f():\npass", "def f():\n    pass"; "indent after colon in python")]
+    #[test_case("test.txt", "if x {\nfoo", "if x {\nfoo}"; "no triggers for unknown filetypes")]
+    #[test]
+    fn smart_indent_works(fname: &str, keys: &str, expected: &str) {
+        let b = type_keys(fname, "", 0, keys);
+
+        assert_eq!(b.str_contents(), format!("{expected}\n"));
+    }
+
+    #[test_case("test.rs", "}", "if x {\n    foo\n}"; "outdent before brace")]
+    #[test_case("test.py", "}", "if x {\n    foo\n}"; "outdent before brace in python")]
+    #[test_case("test.rs", "x}", "if x {\n    foo\n    x}"; "no outdent after other characters")]
+    #[test_case("test.txt", "}", "if x {\n    foo\n    }"; "no outdent for unknown filetypes")]
+    #[test]
+    fn outdent_works(fname: &str, keys: &str, expected: &str) {
+        let initial = "if x {\n    foo\n    ";
+        let b = type_keys(fname, initial, initial.len(), keys);
+
+        assert_eq!(b.str_contents(), format!("{expected}\n"));
+    }
+
+    #[test]
+    fn newline_between_braces_moves_closing_brace_to_its_own_line() {
+        let b = type_keys("test.rs", "    if x ", 9, "{\nfoo");
+
+        assert_eq!(b.str_contents(), "    if x {\n        foo\n    }\n");
+    }
+}
@@ -0,0 +1,120 @@
+//! Persisting undo history between editing sessions.
+//!
+//! When a file is saved its [EditLog] is written to ~/.ad/undo (mirroring the path of the file)
+//! along with a hash of the saved content. When the file is next opened the history is only
+//! restored if the content still has the same hash, as the edits would not apply cleanly to a
+//! file that has been modified by something else in the meantime. As the history includes any
+//! deleted text, undo files are only readable by the current user.
+use super::{edit::EditLog, Text};
+use crate::util::{ad_dir, atomic_write_private, mirrored_path};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+struct UndoFile {
+    hash: u64,
+    log: EditLog,
+}
+
+/// The undo file used for a given file path.
+pub(super) fn undo_path(path: &Path) -> PathBuf {
+    let mut p = mirrored_path(&ad_dir("undo"), path).into_os_string();
+    p.push(".undo");
+
+    p.into()
+}
+
+/// A 64-bit FNV-1a hash of the given content. This is used rather than the standard library
+/// hasher as it needs to be stable across builds of the editor.
+fn content_hash<'a>(chunks: impl IntoIterator<Item = &'a str>) -> u64 {
+    chunks
+        .into_iter()
+        .flat_map(|chunk| chunk.bytes())
+        .fold(0xcbf29ce484222325, |h, b| {
+            (h ^ b as u64).wrapping_mul(0x100000001b3)
+        })
+}
+
+/// Hash the content of txt without first copying it into a single String.
+fn text_hash(txt: &Text) -> u64 {
+    content_hash(txt.slice(0, txt.len_chars()).chunks())
+}
+
+/// Write the history for txt to the undo file at the given path.
+pub(super) fn write(undo: &Path, txt: &Text, log: &EditLog) -> io::Result<()> {
+    if let Some(dir) = undo.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let f = UndoFile {
+        hash: text_hash(txt),
+        log: log.clone(),
+    };
+    let raw = serde_json::to_vec(&f).map_err(io::Error::other)?;
+
+    atomic_write_private(undo, &raw)
+}
+
+/// Returns the history stored in the given undo file if there is one that is valid for txt.
+pub(super) fn read(undo: &Path, txt: &Text) -> io::Result<Option<EditLog>> {
+    let raw = match fs::read(undo) {
+        Ok(raw) => raw,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let f: UndoFile = serde_json::from_slice(&raw).map_err(io::Error::other)?;
+
+    if f.hash == text_hash(txt) {
+        Ok(Some(f.log))
+    } else {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dot::Cur;
+
+    #[test]
+    fn content_hash_is_stable() {
+        assert_eq!(content_hash([""]), 0xcbf29ce484222325);
+        assert_eq!(content_hash(["a"]), 0xaf63dc4c8601ec8c);
+    }
+
+    #[test]
+    fn text_hash_matches_the_hash_of_the_full_content() {
+        let mut txt = Text::from("hello world");
+        txt.insert_str(5, ",");
+        txt.insert_str(0, "oh ");
+
+        assert_eq!(text_hash(&txt), content_hash(["oh hello, world"]));
+    }
+
+    #[test]
+    fn undo_files_round_trip() {
+        let mut log = EditLog::default();
+        log.insert_string(Cur { idx: 0 }, "hello".to_string());
+        log.mark_saved();
+        log.insert_char(Cur { idx: 5 }, '!');
+        log.undo();
+
+        let f = UndoFile { hash: 42, log };
+        let raw = serde_json::to_string(&f).unwrap();
+        let parsed: UndoFile = serde_json::from_str(&raw).unwrap();
+
+        assert_eq!(parsed.hash, 42);
+        assert_eq!(parsed.log, f.log);
+    }
+
+    #[test]
+    fn undo_path_mirrors_the_file_path() {
+        let p = undo_path(Path::new("/home/me/notes.txt"));
+
+        assert!(p.ends_with(".ad/undo/home/me/notes.txt.undo"), "{p:?}");
+    }
+}
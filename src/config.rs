@@ -9,6 +9,12 @@ pub struct Config {
     pub(crate) expand_tab: bool,
     pub(crate) auto_mount: bool,
     pub(crate) match_indent: bool,
+    pub(crate) smart_indent: bool,
+    pub(crate) auto_pairs: Vec<(char, char)>,
+    pub(crate) backup: bool,
+    pub(crate) persistent_undo: bool,
+    pub(crate) large_file_mb: u64,
+    pub(crate) background_load_mb: u64,
     pub(crate) status_timeout: u64,
     pub(crate) double_click_ms: u128,
     pub(crate) minibuffer_lines: usize,
@@ -24,6 +30,12 @@ impl Default for Config {
             expand_tab: true,
             auto_mount: false,
             match_indent: true,
+            smart_indent: true,
+            auto_pairs: vec![('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('`', '`')],
+            backup: false,
+            persistent_undo: true,
+            large_file_mb: 64,
+            background_load_mb: 512,
             status_timeout: 3,
             double_click_ms: 200,
             minibuffer_lines: 8,
@@ -43,6 +55,7 @@ pub struct ColorScheme {
     pub(crate) dot_bg: Color,
     pub(crate) load_bg: Color,
     pub(crate) exec_bg: Color,
+    pub(crate) search_bg: Color,
     pub(crate) bar_bg: Color,
     pub(crate) signcol_fg: Color,
     pub(crate) minibuffer_hl: Color,
@@ -64,6 +77,7 @@ impl Default for ColorScheme {
             dot_bg: "#336677".try_into().unwrap(),
             load_bg: "#957FB8".try_into().unwrap(),
             exec_bg: "#Bf616A".try_into().unwrap(),
+            search_bg: "#5E503F".try_into().unwrap(),
             bar_bg: "#4E415C".try_into().unwrap(),
             signcol_fg: "#544863".try_into().unwrap(),
             minibuffer_hl: "#3E3549".try_into().unwrap(),
@@ -160,17 +174,23 @@ impl Config {
         match prop {
             // Strings
             "find-command" => self.find_command = val.trim().to_string(),
+            "auto-pairs" => self.auto_pairs = parse_pairs(prop, val)?,
 
             // Numbers
             "tabstop" => self.tabstop = parse_usize(prop, val)?,
             "minibuffer-lines" => self.minibuffer_lines = parse_usize(prop, val)?,
             "status-timeout" => self.status_timeout = parse_usize(prop, val)? as u64,
             "double-click-ms" => self.double_click_ms = parse_usize(prop, val)? as u128,
+            "large-file-mb" => self.large_file_mb = parse_usize(prop, val)? as u64,
+            "background-load-mb" => self.background_load_mb = parse_usize(prop, val)? as u64,
 
             // Flags
             "expand-tab" => self.expand_tab = parse_bool(prop, val)?,
             "auto-mount" => self.auto_mount = parse_bool(prop, val)?,
             "match-indent" => self.match_indent = parse_bool(prop, val)?,
+            "smart-indent" => self.smart_indent = parse_bool(prop, val)?,
+            "backup" => self.backup = parse_bool(prop, val)?,
+            "persistent-undo" => self.persistent_undo = parse_bool(prop, val)?,
 
             // Colors
             "bg-color" => self.colorscheme.bg = parse_color(prop, val)?,
@@ -178,6 +198,7 @@ impl Config {
             "dot-bg-color" => self.colorscheme.dot_bg = parse_color(prop, val)?,
             "load-bg-color" => self.colorscheme.load_bg = parse_color(prop, val)?,
             "exec-bg-color" => self.colorscheme.exec_bg = parse_color(prop, val)?,
+            "search-bg-color" => self.colorscheme.search_bg = parse_color(prop, val)?,
             "bar-bg-color" => self.colorscheme.bar_bg = parse_color(prop, val)?,
             "signcol-fg-color" => self.colorscheme.signcol_fg = parse_color(prop, val)?,
             "minibuffer-hl-color" => self.colorscheme.minibuffer_hl = parse_color(prop, val)?,
@@ -212,6 +233,17 @@ fn parse_bool(prop: &str, val: &str) -> Result<bool, String> {
     }
 }
 
+fn parse_pairs(prop: &str, val: &str) -> Result<Vec<(char, char)>, String> {
+    let chars: Vec<char> = val.trim().chars().collect();
+    if !chars.len().is_multiple_of(2) {
+        return Err(format!(
+            "expected pairs of characters for '{prop}' but found '{val}'"
+        ));
+    }
+
+    Ok(chars.chunks(2).map(|pair| (pair[0], pair[1])).collect())
+}
+
 fn parse_color(prop: &str, val: &str) -> Result<Color, String> {
     Color::try_from(val)
         .map_err(|_| format!("expected #RRGGBB string for '{prop}' but found '{val}'"))
@@ -258,6 +290,7 @@ set tabstop=7
 
 set expand-tab=false
 set match-indent=false
+set auto-pairs=()<>
 
 map G G => my-prog
 ";
@@ -290,6 +323,7 @@ map G G => my-prog
             tabstop: 7,
             expand_tab: false,
             match_indent: false,
+            auto_pairs: vec![('(', ')'), ('<', '>')],
             bindings: Trie::from_pairs(vec![(
                 vec![Input::Char('G'), Input::Char('G')],
                 "my-prog".to_string(),
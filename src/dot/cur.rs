@@ -1,7 +1,8 @@
 use crate::{buffer::Buffer, key::Arrow};
+use serde::{Deserialize, Serialize};
 use std::cmp::min;
 
-#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Cur {
     pub idx: usize,
 }
@@ -60,17 +61,16 @@ impl Cur {
         let mut cur = *self;
 
         match arr {
+            // Moving between lines skips over any lines hidden inside of a fold
             Arrow::Up => {
-                let (mut y, _) = self.as_yx(b);
-                if y != 0 {
-                    y -= 1;
+                let (y, _) = self.as_yx(b);
+                if let Some(y) = b.prev_visible_line(y) {
                     cur.idx = b.txt.line_to_char(y) + b.x_from_rx(y);
                 }
             }
             Arrow::Down => {
-                let (mut y, _) = self.as_yx(b);
-                if !b.is_empty() && y < b.len_lines() - 1 {
-                    y += 1;
+                let (y, _) = self.as_yx(b);
+                if let Some(y) = b.next_visible_line(y).filter(|_| !b.is_empty()) {
                     cur.idx = b.txt.line_to_char(y) + b.x_from_rx(y);
                 }
             }
@@ -127,6 +127,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn arr_up_and_down_skip_folded_lines() {
+        let lines = &["a", "b", "c", "d", "e"];
+        let mut b = buffer_from_lines(lines);
+        b.fold(1, 3);
+        let c = Cur::new(0);
+
+        let c = c.arr(Arrow::Down, &b);
+        assert_eq!(c.as_yx(&b), (1, 0));
+        let c = c.arr(Arrow::Down, &b);
+        assert_eq!(c.as_yx(&b), (4, 0));
+        let c = c.arr(Arrow::Up, &b);
+        assert_eq!(c.as_yx(&b), (1, 0));
+    }
+
     #[test]
     fn arr_right_at_eof_focuses_eof() {
         let lines = &["a", "ab", "abc", "abcd"];
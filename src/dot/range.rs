@@ -38,14 +38,14 @@ impl Range {
 
     /// Extends the STARTING cursor to its line start
     #[must_use]
-    pub(super) fn extend_to_line_start(mut self, b: &Buffer) -> Self {
+    pub(crate) fn extend_to_line_start(mut self, b: &Buffer) -> Self {
         self.start = self.start.move_to_line_start(b);
         self
     }
 
     /// Extends the ENDING cursor to its line start
     #[must_use]
-    pub(super) fn extend_to_line_end(mut self, b: &Buffer) -> Self {
+    pub(crate) fn extend_to_line_end(mut self, b: &Buffer) -> Self {
         self.end = self.end.move_to_line_end(b);
         self
     }
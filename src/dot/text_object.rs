@@ -1,23 +1,39 @@
 //! Vim style text objects
 use crate::{
     buffer::Buffer,
+    config_handle,
     dot::{
         find::{find_backward_start, find_forward_end, Find},
         Cur, Dot, Range,
     },
+    exec::IterBoundedChars,
     key::Arrow,
 };
 
+const OPENING: &str = "([{";
+const CLOSING: &str = ")]}";
+
+/// How much of the surrounding text a structural text object should select.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Scope {
+    /// Only the content of the object itself
+    Inner,
+    /// The object along with the text that delimits it from its surroundings
+    Around,
+}
+
 /// A vim-like text object which can be used to manipulate the current Dot in a Buffer
 #[allow(dead_code)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TextObject {
     Arr(Arrow),
+    Argument(Scope),
     BufferEnd,
     BufferStart,
     Character,
     FindChar(char),
     Delimited(char, char),
+    IndentBlock(Scope),
     Line,
     LineEnd,
     LineStart,
@@ -31,11 +47,13 @@ impl TextObject {
 
         let dot = match self {
             Arr(arr) => b.dot.active_cur().arr(*arr, b).into(),
+            Argument(scope) => find_argument(*scope, b).unwrap_or(b.dot),
             BufferEnd => Cur::buffer_end(b).into(),
             BufferStart => Cur::buffer_start().into(),
             Character => b.dot.active_cur().arr(Arrow::Right, b).into(),
             FindChar(ch) => find_forward_end(ch, b.dot.active_cur(), b).into(),
             Delimited(l, r) => FindDelimited::new(*l, *r).expand(b.dot, b),
+            IndentBlock(scope) => find_indent_block(*scope, b).unwrap_or(b.dot),
             LineEnd => b.dot.active_cur().move_to_line_end(b).into(),
             LineStart => b.dot.active_cur().move_to_line_start(b).into(),
             Line => Dot::from(
@@ -80,7 +98,7 @@ impl TextObject {
             (Word, true) => (find_forward_end(&FindWord::Fwd, start, b), end),
             (Word, false) => (start, find_forward_end(&FindWord::Fwd, end, b)),
             // Can't move forward to the buffer start or move forward between delimiters
-            (BufferStart | Delimited(_, _), _) => return,
+            (BufferStart | Delimited(_, _) | Argument(_) | IndentBlock(_), _) => return,
         };
 
         b.dot = Dot::from(Range::from_cursors(start, end, start_active)).collapse_null_range();
@@ -114,13 +132,215 @@ impl TextObject {
             (Word, true) => (find_backward_start(&FindWord::Fwd, start, b), end),
             (Word, false) => (start, find_backward_start(&FindWord::Fwd, end, b)),
             // Can't move back to the buffer end or move back between delimiters
-            (BufferEnd | Delimited(_, _), _) => return,
+            (BufferEnd | Delimited(_, _) | Argument(_) | IndentBlock(_), _) => return,
         };
 
         b.dot = Dot::from(Range::from_cursors(start, end, start_active)).collapse_null_range();
     }
 }
 
+/// The indentation width of the given line, or None if the line is blank.
+pub(crate) fn indent_of(y: usize, b: &Buffer) -> Option<usize> {
+    let tabstop = config_handle!().tabstop;
+    let mut width = 0;
+
+    for ch in b.txt.line(y).chars() {
+        match ch {
+            ' ' => width += 1,
+            '\t' => width += tabstop,
+            ch if ch.is_whitespace() => return None,
+            _ => return Some(width),
+        }
+    }
+
+    None
+}
+
+/// Select the full lines from `first` to `last` (inclusive).
+fn select_lines(first: usize, last: usize, b: &Buffer) -> Dot {
+    let start = Cur {
+        idx: b.txt.line_to_char(first),
+    };
+    let end = Cur {
+        idx: b.txt.line_to_char(last),
+    }
+    .move_to_line_end(b);
+
+    Dot::from(Range::from_cursors(start, end, false)).collapse_null_range()
+}
+
+/// Find the block of lines around dot that are indented at least as far as the line dot is on
+/// (or the next non-blank line if dot is on a blank line).
+///
+/// Blank lines at either edge of the block are not included. Selecting around the block also
+/// includes the less indented line that opens the block and the line that closes it, if that
+/// line starts with a closing delimiter.
+fn find_indent_block(scope: Scope, b: &Buffer) -> Option<Dot> {
+    let n_lines = b.txt.len_lines();
+    let (y, _) = b.dot.first_cur().as_yx(b);
+    let (y, level) = (y..n_lines).find_map(|y| indent_of(y, b).map(|level| (y, level)))?;
+    let in_block = |y| match indent_of(y, b) {
+        Some(indent) => indent >= level,
+        None => true,
+    };
+
+    let (mut first, mut last) = (y, y);
+    while first > 0 && in_block(first - 1) {
+        first -= 1;
+    }
+    while last + 1 < n_lines && in_block(last + 1) {
+        last += 1;
+    }
+    while indent_of(first, b).is_none() {
+        first += 1;
+    }
+    while indent_of(last, b).is_none() {
+        last -= 1;
+    }
+
+    if scope == Scope::Around {
+        if let Some(y) = (0..first).rev().find(|&y| indent_of(y, b).is_some()) {
+            first = y;
+        }
+
+        // "end" only closes a block as a whole word: "endpoint = x" is a statement
+        let closes_block = |y| {
+            let line = b.txt.line(y).to_string();
+            let trimmed = line.trim_start();
+            trimmed.starts_with(|c| CLOSING.contains(c))
+                || trimmed.strip_prefix("end").is_some_and(|rest| {
+                    !rest.starts_with(|c: char| c.is_alphanumeric() || c == '_')
+                })
+        };
+        if let Some(y) = (last + 1..n_lines).find(|&y| indent_of(y, b).is_some()) {
+            if closes_block(y) {
+                last = y;
+            }
+        }
+    }
+
+    Some(select_lines(first, last, b))
+}
+
+/// Whether the character at `idx` opens a string literal when parsing function arguments.
+///
+/// Single quotes are also used for lifetimes and character literals in some languages so they
+/// are only treated as opening a string when they follow a delimiter, separator or whitespace
+/// and are closed later on the same line.
+fn opens_string(ch: char, prev: char, idx: usize, b: &Buffer) -> bool {
+    match ch {
+        '"' | '`' => true,
+        '\'' if prev.is_whitespace() || "([{,=:".contains(prev) => {
+            let line_end = Cur { idx }.move_to_line_end(b).idx;
+            idx < line_end && b.iter_between(idx + 1, line_end).any(|(_, c)| c == '\'')
+        }
+        _ => false,
+    }
+}
+
+/// Parse the arguments following the opening delimiter at `open`, returning the index of the
+/// matching closing delimiter and the positions of the top level commas separating the
+/// arguments. Delimiters and commas inside of string literals are ignored.
+fn parse_arguments(open: usize, b: &Buffer) -> Option<(usize, Vec<usize>)> {
+    let close = CLOSING.chars().nth(OPENING.find(b.txt.char(open))?)?;
+    let mut expected = Vec::new();
+    let mut separators = Vec::new();
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    let mut prev = b.txt.char(open);
+
+    for (i, ch) in b.iter_between(open + 1, b.txt.len_chars()) {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if ch == '\\' => escaped = true,
+            Some(q) if ch == q => quote = None,
+            Some(_) => (),
+            None if opens_string(ch, prev, i, b) => quote = Some(ch),
+            None if ch == ',' && expected.is_empty() => separators.push(i),
+            None => {
+                if let Some(ix) = OPENING.find(ch) {
+                    expected.push(CLOSING.chars().nth(ix)?);
+                } else if CLOSING.contains(ch) {
+                    match expected.pop() {
+                        Some(c) if c == ch => (),
+                        Some(_) => return None,
+                        None if ch == close => return Some((i, separators)),
+                        None => return None,
+                    }
+                }
+            }
+        }
+        prev = ch;
+    }
+
+    None
+}
+
+/// Find the function call argument (or list element) that dot is currently within.
+///
+/// Selecting around an argument also includes the comma separating it from the following
+/// argument, or from the preceding argument if it is the last one in the list.
+fn find_argument(scope: Scope, b: &Buffer) -> Option<Dot> {
+    let idx = b.dot.first_cur().idx;
+    if idx == 0 {
+        return None;
+    }
+
+    // Strings can't be reliably detected when scanning backwards so rather than tracking the
+    // nesting depth, each opening delimiter is parsed forwards (skipping over any strings) to
+    // find the closest one that is closed after dot.
+    let (open, close, separators) = b
+        .rev_iter_between(idx, 0)
+        .filter(|&(_, ch)| OPENING.contains(ch))
+        .find_map(|(i, _)| match parse_arguments(i, b) {
+            Some((close, separators)) if close >= idx => Some((i, close, separators)),
+            _ => None,
+        })?;
+    let bounds: Vec<usize> = std::iter::once(open)
+        .chain(separators)
+        .chain(std::iter::once(close))
+        .collect();
+    let i = bounds.windows(2).position(|w| idx > w[0] && idx <= w[1])?;
+
+    // The first and last non-whitespace characters strictly between two bounds
+    let trimmed = |from: usize, to: usize| -> Option<(usize, usize)> {
+        if to <= from + 1 {
+            return None;
+        }
+        let mut it = b
+            .iter_between(from + 1, to)
+            .filter(|(_, c)| !c.is_whitespace());
+        let (s, _) = it.next()?;
+        let e = it.last().map(|(e, _)| e).unwrap_or(s);
+
+        Some((s, e))
+    };
+
+    let (mut start, mut end) = trimmed(bounds[i], bounds[i + 1])?;
+    if scope == Scope::Around {
+        if i + 2 < bounds.len() {
+            end = match trimmed(bounds[i + 1], bounds[i + 2]) {
+                Some((next_start, _)) => next_start - 1,
+                None => bounds[i + 1],
+            };
+        } else if i > 0 {
+            start = match trimmed(bounds[i - 1], bounds[i]) {
+                Some((_, prev_end)) => prev_end + 1,
+                None => bounds[i],
+            };
+        }
+    }
+
+    Some(
+        Dot::from(Range::from_cursors(
+            Cur { idx: start },
+            Cur { idx: end },
+            false,
+        ))
+        .collapse_null_range(),
+    )
+}
+
 pub struct FindDelimited {
     l: String,
     r: String,
@@ -365,4 +585,83 @@ mod tests {
         assert!(matches!(expanded, Dot::Cur { c: Cur { idx: 16 } }));
         assert_eq!(content, " ");
     }
+
+    const ARGS: &str = "foo(a, bar(b, c), \"d, e\", [f, g])";
+
+    #[test_case(4, Scope::Inner, "a"; "first argument")]
+    #[test_case(4, Scope::Around, "a, "; "around first argument")]
+    #[test_case(10, Scope::Inner, "bar(b, c)"; "nested call as argument")]
+    #[test_case(11, Scope::Inner, "b"; "argument of nested call")]
+    #[test_case(14, Scope::Around, ", c"; "around last argument of nested call")]
+    #[test_case(20, Scope::Inner, "\"d, e\""; "string containing a comma")]
+    #[test_case(26, Scope::Inner, "[f, g]"; "last argument")]
+    #[test_case(26, Scope::Around, ", [f, g]"; "around last argument")]
+    #[test]
+    fn argument_works(idx: usize, scope: Scope, expected: &str) {
+        let mut b = Buffer::new_virtual(0, "test", ARGS);
+        b.dot = Dot::Cur { c: Cur { idx } };
+        TextObject::Argument(scope).set_dot(&mut b);
+
+        assert_eq!(b.dot.content(&b), expected);
+    }
+
+    #[test_case("f(\")\", xy)", 7, "xy"; "closing delimiter in a string")]
+    #[test_case("f(\"(\", xy)", 7, "xy"; "opening delimiter in a string")]
+    #[test]
+    fn argument_ignores_delimiters_in_strings(s: &str, idx: usize, expected: &str) {
+        let mut b = Buffer::new_virtual(0, "test", s);
+        b.dot = Dot::Cur { c: Cur { idx } };
+        TextObject::Argument(Scope::Inner).set_dot(&mut b);
+
+        assert_eq!(b.dot.content(&b), expected);
+    }
+
+    #[test]
+    fn argument_outside_of_delimiters_leaves_dot_unchanged() {
+        let mut b = Buffer::new_virtual(0, "test", ARGS);
+        b.dot = Dot::Cur { c: Cur { idx: 3 } };
+        TextObject::Argument(Scope::Inner).set_dot(&mut b);
+
+        assert_eq!(b.dot, Dot::Cur { c: Cur { idx: 3 } });
+    }
+
+    const BLOCKS: &str = "fn main() {
+    let x = 1;
+
+    if x > 0 {
+        println!(\"positive\");
+    }
+}
+";
+
+    #[test_case(1, Scope::Inner, "    let x = 1;\n\n    if x > 0 {\n        println!(\"positive\");\n    }\n"; "inner function body")]
+    #[test_case(2, Scope::Inner, "    let x = 1;\n\n    if x > 0 {\n        println!(\"positive\");\n    }\n"; "from blank line")]
+    #[test_case(4, Scope::Inner, "        println!(\"positive\");\n"; "inner nested block")]
+    #[test_case(4, Scope::Around, "    if x > 0 {\n        println!(\"positive\");\n    }\n"; "around nested block")]
+    #[test_case(1, Scope::Around, BLOCKS.trim_end(); "around function body")]
+    #[test]
+    fn indent_block_works(y: usize, scope: Scope, expected: &str) {
+        let mut b = Buffer::new_virtual(0, "test", BLOCKS);
+        b.dot = Dot::Cur {
+            c: Cur::from_yx(y, 0, &b),
+        };
+        TextObject::IndentBlock(scope).set_dot(&mut b);
+
+        assert_eq!(b.dot.content(&b), expected);
+    }
+
+    #[test_case("if x\n    y\nend\n", "if x\n    y\nend"; "end keyword")]
+    #[test_case("if x\n    y\nend # if\n", "if x\n    y\nend # if"; "end keyword then comment")]
+    #[test_case("def f():\n    y\nendpoint = 1\n", "def f():\n    y\n"; "word starting with end")]
+    #[test_case("def f():\n    y\nend_x = 1\n", "def f():\n    y\n"; "identifier starting with end")]
+    #[test]
+    fn around_indent_block_only_includes_end_as_a_word(s: &str, expected: &str) {
+        let mut b = Buffer::new_virtual(0, "test", s);
+        b.dot = Dot::Cur {
+            c: Cur::from_yx(1, 0, &b),
+        };
+        TextObject::IndentBlock(Scope::Around).set_dot(&mut b);
+
+        assert_eq!(b.dot.content(&b), expected);
+    }
 }
@@ -1,12 +1,13 @@
 //! Editor actions in response to user input
 use crate::{
-    buffer::BufferKind,
+    buffer::{parse_tree_line, BufferKind, Encoding, FileChunk, LineEnding},
     config::Config,
     config_handle,
-    dot::{Cur, Dot, Range, TextObject},
+    dot::{Cur, Dot, Range, Scope, TextObject},
     editor::{Editor, MbSelector, MiniBufferSelection},
     exec::{Addr, Address, Program},
     fsys::LogEvent,
+    input::Event,
     key::{Arrow, Input},
     lsp::Coords,
     mode::Mode,
@@ -19,6 +20,7 @@ use crate::{
 };
 use ad_event::Source;
 use std::{
+    collections::HashSet,
     env, fs,
     path::{Path, PathBuf},
     process::{Command, Stdio},
@@ -26,12 +28,82 @@ use std::{
 };
 use tracing::{debug, error, info, trace, warn};
 
+const UNDO_TREE_BUFFER: &str = "+undo-tree";
+/// The largest count that can be applied to a set of actions: larger counts are clamped to it
+pub(crate) const MAX_COUNT: usize = 9999;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Actions {
     Single(Action),
     Multi(Vec<Action>),
 }
 
+impl Actions {
+    /// Apply a numeric count prefix (such as the 3 in "3w") to these actions.
+    ///
+    /// Actions that take a repeat count have it multiplied by n and actions that edit the
+    /// buffer or step through a history are repeated n times. Everything else, such as changing
+    /// mode, is only run once. Delete removes the current selection and replaces dot with an
+    /// empty range so it is also only run once: repeating it would delete the characters that
+    /// follow the selection.
+    pub(crate) fn with_count(self, n: usize) -> Self {
+        use Action::*;
+
+        let n = n.min(MAX_COUNT);
+        if n <= 1 {
+            return self;
+        }
+
+        let actions = match self {
+            Actions::Single(a) => vec![a],
+            Actions::Multi(actions) => actions,
+        };
+
+        let mut counted = Vec::with_capacity(actions.len());
+        for a in actions.into_iter() {
+            match a {
+                DotSet(t, c) => counted.push(DotSet(t, c.saturating_mul(n))),
+                DotExtendForward(t, c) => counted.push(DotExtendForward(t, c.saturating_mul(n))),
+                DotExtendBackward(t, c) => counted.push(DotExtendBackward(t, c.saturating_mul(n))),
+                InsertChar { .. }
+                | InsertString { .. }
+                | Paste
+                | Undo
+                | Redo
+                | RepeatLastEdit
+                | SearchNext
+                | SearchPrevious
+                | JumpListBack
+                | JumpListForward
+                | NextBuffer
+                | PreviousBuffer
+                | RawInput { .. }
+                | ReplayMacro { .. } => {
+                    counted.extend(std::iter::repeat_n(a, n));
+                }
+                a => counted.push(a),
+            }
+        }
+
+        if counted.len() == 1 {
+            Actions::Single(counted.remove(0))
+        } else {
+            Actions::Multi(counted)
+        }
+    }
+
+    /// Select a register to be used by any yank or paste actions within these actions.
+    pub(crate) fn with_register(self, r: char) -> Self {
+        let mut actions = match self {
+            Actions::Single(a) => vec![a],
+            Actions::Multi(actions) => actions,
+        };
+        actions.insert(0, Action::SelectRegister { r });
+
+        Actions::Multi(actions)
+    }
+}
+
 /// How the current viewport should be set in relation to dot.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ViewPort {
@@ -48,6 +120,7 @@ pub enum ViewPort {
 pub enum Action {
     AppendToOutputBuffer { bufid: usize, content: String },
     ChangeDirectory { path: Option<String> },
+    ClearSearchHighlights,
     CommandMode,
     Delete,
     DeleteBuffer { force: bool },
@@ -60,7 +133,9 @@ pub enum Action {
     DotFlip,
     DotSet(TextObject, usize),
     DotSetFromCoords { coords: Coords },
+    DotSetFromSymbol { coords: Coords, scope: Scope },
     DragWindow { direction: Arrow },
+    Earlier { step: String },
     EditCommand { cmd: String },
     EnsureFileIsOpen { path: String },
     ExecuteDot,
@@ -70,16 +145,23 @@ pub enum Action {
     FindFile { new_window: bool },
     FindRepoFile { new_window: bool },
     FocusBuffer { id: usize },
+    FoldDot,
+    FoldIndent,
+    FoldLines { first: usize, last: usize },
     InsertChar { c: char },
     InsertString { s: String },
     JumpListForward,
     JumpListBack,
+    JumpToMark { name: char },
+    Later { step: String },
     LoadDot { new_window: bool },
+    LspFold,
     LspGotoDeclaration,
     LspGotoDefinition,
     LspGotoTypeDefinition,
     LspHover,
     LspReferences,
+    LspSelectSymbol { scope: Scope },
     LspShowCapabilities,
     LspShowDiagnostics,
     LspStart,
@@ -100,17 +182,27 @@ pub enum Action {
     PreviousColumn,
     PreviousWindowInColumn,
     RawInput { i: Input },
+    RecordMacro { r: char },
     Redo,
     ReloadActiveBuffer,
     ReloadBuffer { id: usize },
     ReloadConfig,
+    ReopenWithEncoding { encoding: String },
+    RepeatLastEdit,
+    ReplayMacro { r: char },
+    ReverseSearchInCurrentBuffer,
     RunMode,
     SamMode,
     SaveBuffer { force: bool },
     SaveBufferAs { path: String, force: bool },
     SearchInCurrentBuffer,
+    SearchNext,
+    SearchPrevious,
     SelectBuffer,
+    SelectLineInCurrentBuffer,
+    SelectRegister { r: char },
     SetViewPort(ViewPort),
+    SetMark { name: char },
     SetMode { m: &'static str },
     SetStatusMessage { message: String },
     ShellPipe { cmd: String },
@@ -118,7 +210,14 @@ pub enum Action {
     ShellRun { cmd: String },
     ShellSend { cmd: String },
     ShowHelp,
+    ShowUndoTree,
+    SurroundAdd { s: String },
+    SurroundChange { from: char, to: String },
+    SurroundDelete { c: char },
+    ToggleComment,
     Undo,
+    Unfold,
+    UnfoldAll,
     UpdateConfig { input: String },
     ViewLogs,
     Yank,
@@ -178,7 +277,10 @@ where
         let was_empty_scratch = self.layout.is_empty_scratch();
         let current_id = self.active_buffer_id();
 
-        match self.layout.open_or_focus(path, new_window) {
+        let res = self.layout.open_or_focus(path, new_window);
+        self.update_watched_paths();
+
+        match res {
             Err(e) => self.set_status_message(&format!("Error opening file: {e}")),
 
             Ok(Some(new_id)) => {
@@ -187,6 +289,13 @@ where
                 }
                 _ = self.tx_fsys.send(LogEvent::Open(new_id));
                 _ = self.tx_fsys.send(LogEvent::Focus(new_id));
+                // Both of these compare against the content of the buffer which is not yet
+                // available for files that are loading in the background: they are run by
+                // handle_file_chunk once loading completes instead
+                if !self.layout.active_buffer().is_loading() {
+                    self.restore_undo_history(new_id);
+                    self.check_for_swap_file(new_id);
+                }
             }
 
             Ok(None) => {
@@ -211,6 +320,20 @@ where
         };
     }
 
+    fn restore_undo_history(&mut self, id: usize) {
+        if !config_handle!().persistent_undo {
+            return;
+        }
+
+        if let Some(b) = self.layout.buffer_with_id_mut(id) {
+            match b.restore_undo_file() {
+                Ok(true) => debug!(%id, "restored undo history"),
+                Ok(false) => (),
+                Err(e) => warn!("unable to restore undo history: {e}"),
+            }
+        }
+    }
+
     fn find_file_under_dir(&mut self, d: &Path, new_window: bool) {
         let cmd = config_handle!().find_command.clone();
 
@@ -271,11 +394,15 @@ where
         match self.layout.buffer_with_id(id) {
             Some(b) if b.dirty && !force => self.set_status_message("No write since last change"),
             None => warn!("attempt to close unknown buffer, id={id}"),
-            _ => {
+            Some(b) => {
+                if let Some(path) = b.path() {
+                    self.swap.remove(path.to_path_buf());
+                }
                 _ = self.tx_fsys.send(LogEvent::Close(id));
                 self.clear_input_filter(id);
                 let was_last_buffer = self.layout.close_buffer(id);
                 self.running = !was_last_buffer;
+                self.update_watched_paths();
             }
         }
     }
@@ -309,10 +436,17 @@ where
 
         let b = self.layout.active_buffer_mut();
         let msg = b.save_to_disk_at(p, force);
+        if !b.dirty && config_handle!().persistent_undo {
+            if let Err(e) = b.write_undo_file() {
+                warn!("unable to write undo file: {e}");
+            }
+        }
         self.lsp_manager.document_changed(b);
         self.set_status_message(&msg);
         let id = self.active_buffer_id();
         _ = self.tx_fsys.send(LogEvent::Save(id));
+        // Saving to a new path renames the buffer
+        self.update_watched_paths();
     }
 
     fn get_buffer_save_path(&mut self, fname: Option<String>) -> Option<PathBuf> {
@@ -321,10 +455,10 @@ where
         let desired_path = match (fname, &self.layout.active_buffer().kind) {
             // File has a known name which is either where we loaded it from or a
             // path that has been set and verified from the Some(s) case that follows
-            (None, Bk::File(ref p)) => return Some(p.clone()),
+            (None, Bk::File(ref p) | Bk::Hex(ref p)) => return Some(p.clone()),
             // Renaming an existing file or attempting to save a new file created in
             // the editor: both need verifying
-            (Some(s), Bk::File(_) | Bk::Unnamed) => PathBuf::from(s),
+            (Some(s), Bk::File(_) | Bk::Hex(_) | Bk::Unnamed) => PathBuf::from(s),
             // Attempting to save without a name so we prompt for one and verify it
             (None, Bk::Unnamed) => match self.minibuffer_prompt("Save As: ") {
                 Some(s) => s.into(),
@@ -347,7 +481,12 @@ where
             }
         }
 
-        self.layout.active_buffer_mut().kind = BufferKind::File(desired_path.clone());
+        let b = self.layout.active_buffer_mut();
+        b.kind = if b.kind.is_hex() {
+            BufferKind::Hex(desired_path.clone())
+        } else {
+            BufferKind::File(desired_path.clone())
+        };
 
         Some(desired_path)
     }
@@ -362,6 +501,154 @@ where
         self.set_status_message(&msg);
     }
 
+    /// Reload clean buffers and directory listings that have changed on disk, warning about
+    /// changes to files with unsaved edits.
+    pub(super) fn handle_path_changed(&mut self, path: PathBuf) {
+        let ids: Vec<usize> = self
+            .layout
+            .buffers()
+            .iter()
+            .filter(|b| match &b.kind {
+                BufferKind::File(p) | BufferKind::Hex(p) => p == &path,
+                BufferKind::Directory(p) => Some(p.as_path()) == path.parent(),
+                _ => false,
+            })
+            .map(|b| b.id)
+            .collect();
+
+        for id in ids {
+            let b = match self.layout.buffer_with_id_mut(id) {
+                Some(b) => b,
+                None => continue,
+            };
+
+            if b.kind.is_dir() {
+                b.reload_from_disk();
+                continue;
+            }
+
+            // Content appended to a file while it is loading is picked up by the loader
+            if b.is_loading() {
+                continue;
+            }
+
+            // Our own saves update last_save so they are not seen as changes here
+            if !matches!(b.state_changed_on_disk(), Ok(true)) {
+                continue;
+            }
+
+            let name = b.display_name();
+            if b.dirty {
+                self.set_status_message(&format!(
+                    "Warning: {name} changed on disk, unsaved changes kept (use :Get to reload)"
+                ));
+            } else {
+                b.reload_from_disk();
+                self.lsp_manager.document_changed(b);
+                self.set_status_message(&format!("{name} changed on disk and was reloaded"));
+            }
+        }
+    }
+
+    /// Start background threads for any newly opened buffers that need their content loading.
+    pub(super) fn start_background_loads(&mut self) {
+        let pending: Vec<usize> = self
+            .layout
+            .buffers()
+            .iter()
+            .filter(|b| matches!(&b.loading, Some(l) if !l.is_started()))
+            .map(|b| b.id)
+            .collect();
+
+        for id in pending {
+            if let Some(loading) = self
+                .layout
+                .buffer_with_id_mut(id)
+                .and_then(|b| b.loading.as_mut())
+            {
+                loading.start(id, self.tx_events.clone());
+            }
+        }
+    }
+
+    pub(super) fn handle_file_chunk(&mut self, id: usize, load_id: u64, chunk: FileChunk) {
+        let msg = match self.layout.buffer_with_id_mut(id) {
+            Some(b) => b.handle_file_chunk(load_id, chunk),
+            None => None,
+        };
+
+        if let Some(msg) = msg {
+            let mut loaded = false;
+            if let Some(b) = self.layout.buffer_with_id(id) {
+                self.lsp_manager.document_changed(b);
+                loaded = !b.is_loading();
+            }
+            self.set_status_message(&msg);
+
+            // Deferred from open_file until the full content of the buffer is available
+            if loaded {
+                self.restore_undo_history(id);
+                self.check_for_swap_file(id);
+            }
+        }
+    }
+
+    /// Block until the given buffer has finished loading in the background, handling other
+    /// events as they arrive. User input is held back until loading completes so that it is
+    /// not applied to the wrong buffer. Returns false if the buffer could not be loaded.
+    ///
+    /// Pressing Escape or Ctrl-C stops waiting and returns false, leaving the buffer read only
+    /// until it finishes loading in the background.
+    fn wait_for_load(&mut self, id: usize) -> bool {
+        self.start_background_loads();
+        let mut held = Vec::new();
+
+        let loaded = loop {
+            match self.layout.buffer_with_id(id) {
+                Some(b) if !b.is_loading() => break true,
+                Some(b) if b.load_failed() => break false,
+                Some(_) => (),
+                None => break false,
+            }
+
+            self.refresh_screen_w_minibuffer(None);
+            match self.rx_events.recv() {
+                Ok(Event::Input(Input::Esc | Input::Ctrl('c'))) => break false,
+                Ok(e @ (Event::Input(_) | Event::Paste(_))) => held.push(e),
+                Ok(event) => self.handle_event(event),
+                Err(_) => break false,
+            }
+        };
+
+        for e in held {
+            _ = self.tx_events.send(e);
+        }
+
+        loaded
+    }
+
+    /// Make sure that the watcher is tracking the files and directories that are currently open.
+    ///
+    /// This needs to be called whenever buffers are opened, closed or renamed.
+    pub(super) fn update_watched_paths(&mut self) {
+        let watcher = match self.watcher.as_ref() {
+            Some(w) => w,
+            None => return,
+        };
+
+        let mut files = HashSet::new();
+        let mut dirs = HashSet::new();
+        for b in self.layout.buffers().iter() {
+            match &b.kind {
+                BufferKind::File(p) | BufferKind::Hex(p) => _ = files.insert(p.clone()),
+                BufferKind::Directory(p) => _ = dirs.insert(p.clone()),
+                _ => (),
+            }
+        }
+
+        watcher.set_watched(files, dirs);
+    }
+
     pub(super) fn reload_config(&mut self) {
         info!("reloading config");
         let msg = match Config::try_load() {
@@ -382,9 +669,36 @@ where
         self.set_status_message(&msg);
     }
 
+    pub(super) fn reopen_active_buffer_with_encoding(&mut self, encoding: &str) {
+        let msg = match Encoding::try_from_name(encoding) {
+            Ok(enc) => self.layout.active_buffer_mut().reopen_with_encoding(enc),
+            Err(msg) => msg,
+        };
+        self.set_status_message(&msg);
+    }
+
     pub(super) fn update_config(&mut self, input: &str) {
         info!(%input, "updating config");
-        if let Err(msg) = update_config(input) {
+        // Line endings are a property of the active buffer rather than global config
+        let mut lines = Vec::new();
+        for line in input.lines() {
+            let prop_val = line
+                .trim()
+                .strip_prefix("set ")
+                .and_then(|s| s.split_once('='));
+            match prop_val {
+                Some(("line-ending", val)) => match LineEnding::try_from_name(val) {
+                    Ok(le) => self.layout.active_buffer_mut().set_line_ending(le),
+                    Err(msg) => return self.set_status_message(&msg),
+                },
+                _ => lines.push(line),
+            }
+        }
+
+        if lines.is_empty() {
+            return;
+        }
+        if let Err(msg) = update_config(&lines.join("\n")) {
             self.set_status_message(&msg);
         }
         self.ui.state_change(StateChange::ConfigUpdated);
@@ -416,6 +730,51 @@ where
         }
     }
 
+    /// Store yanked or deleted text in the selected register, falling back to the system
+    /// clipboard if no register has been selected.
+    pub(super) fn yank(&mut self, s: String) {
+        match self.register {
+            Some(r) => match self.registers.set(r, s) {
+                Ok(()) => self.set_status_message(&format!("Yanked selection to register {r}")),
+                Err(e) => self.set_status_message(&e),
+            },
+            None => self.set_clipboard(s),
+        }
+    }
+
+    /// Paste from the selected register, falling back to the system clipboard if no register
+    /// has been selected.
+    pub(super) fn paste(&mut self, source: Source) {
+        let r = match self.register {
+            Some(r) => r,
+            None => return self.paste_from_clipboard(source),
+        };
+
+        match self.registers.get(r) {
+            Some(s) => {
+                let s = s.to_string();
+                self.handle_action(Action::InsertString { s }, source);
+            }
+            None => self.set_status_message(&format!("unknown register: {r}")),
+        }
+    }
+
+    /// Insert text pasted into the terminal at dot as a single edit.
+    ///
+    /// Pasted text is inserted as it is rather than being handled as typed keys so that it is
+    /// not auto-paired or re-indented.
+    pub(super) fn paste_text(&mut self, s: String) {
+        self.pending_keys.clear();
+        self.handle_actions(
+            Actions::Multi(vec![
+                Action::NewEditLogTransaction,
+                Action::InsertString { s },
+                Action::NewEditLogTransaction,
+            ]),
+            Source::Keyboard,
+        );
+    }
+
     pub(super) fn paste_from_clipboard(&mut self, source: Source) {
         trace!("pasting from clipboard");
         match self.system.read_clipboard() {
@@ -424,7 +783,7 @@ where
         }
     }
 
-    pub(super) fn search_in_current_buffer(&mut self) {
+    pub(super) fn select_line_in_current_buffer(&mut self) {
         let numbered_lines = self
             .layout
             .active_buffer()
@@ -499,6 +858,50 @@ where
         self.layout.open_virtual("+help", gen_help_docs(), false)
     }
 
+    /// Open a virtual buffer showing the undo tree of the active buffer. Loading a node in the
+    /// tree restores the buffer to the state recorded by that node.
+    pub(super) fn show_undo_tree(&mut self) {
+        let b = self.layout.active_buffer();
+        let id = match (&b.kind, self.undo_tree_for) {
+            (BufferKind::Virtual(name), Some(id)) if name == UNDO_TREE_BUFFER => id,
+            _ => b.id,
+        };
+        let lines = match self.layout.buffer_with_id(id) {
+            Some(b) => b.undo_tree_lines(),
+            None => return,
+        };
+
+        self.undo_tree_for = Some(id);
+        self.layout
+            .open_virtual(UNDO_TREE_BUFFER, lines.join("\n"), false);
+    }
+
+    /// Restore the state of the node on the line containing dot in the undo tree buffer.
+    fn load_undo_tree_node(&mut self) {
+        let b = self.layout.active_buffer();
+        let (y, _) = b.dot.active_cur().as_yx(b);
+        let node = b.line(y).and_then(|l| parse_tree_line(&l.to_string()));
+        let (id, node) = match (self.undo_tree_for, node) {
+            (Some(id), Some(node)) => (id, node),
+            _ => return self.set_status_message("No undo tree node on this line"),
+        };
+
+        match self
+            .layout
+            .buffer_with_id_mut(id)
+            .map(|b| b.undo_tree_goto(node))
+        {
+            Some(true) => {
+                let dot = self.layout.active_buffer().dot;
+                self.show_undo_tree();
+                self.layout.active_buffer_mut().dot = dot;
+                self.set_status_message(&format!("Restored undo tree node {node}"));
+            }
+            Some(false) => self.set_status_message(&format!("Unknown undo tree node: {node}")),
+            None => self.set_status_message("Buffer for undo tree has been closed"),
+        }
+    }
+
     pub(super) fn debug_edit_log(&mut self) {
         self.minibuffer_select_from("<EDIT LOG> ", self.layout.active_buffer().debug_edit_log());
     }
@@ -520,6 +923,11 @@ where
     /// materials available at http://acme.cat-v.org/ to learn more about what is possible with
     /// such a system.
     pub(super) fn default_load_dot(&mut self, source: Source, load_in_new_window: bool) {
+        if matches!(&self.layout.active_buffer().kind, BufferKind::Virtual(name) if name == UNDO_TREE_BUFFER)
+        {
+            return self.load_undo_tree_node();
+        }
+
         let b = self.layout.active_buffer_mut();
         b.expand_cur_dot();
         if b.notify_load(source) {
@@ -719,6 +1127,16 @@ where
             }
         };
 
+        self.registers.set_last_edit(cmd);
+        let id = self.active_buffer_id();
+        if self.layout.active_buffer().is_loading() {
+            self.set_status_message("Waiting for buffer to finish loading (Esc to cancel)...");
+            if !self.wait_for_load(id) || self.active_buffer_id() != id {
+                self.set_status_message("Edit command cancelled: buffer did not finish loading");
+                return;
+            }
+        }
+
         let mut buf = Vec::new();
         let fname = self.layout.active_buffer().full_name().to_string();
         match prog.execute(self.layout.active_buffer_mut(), &fname, &mut buf) {
@@ -738,7 +1156,6 @@ where
                     return;
                 }
             };
-            let id = self.active_buffer_id();
             self.layout.write_output_for_buffer(id, s, &self.cwd);
         }
     }
@@ -815,7 +1232,12 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{editor::EditorMode, LogBuffer, PlumbingRules};
+    use crate::{
+        buffer::{Loading, JUMP_MARK},
+        dot::Cur,
+        editor::EditorMode,
+        LogBuffer, PlumbingRules,
+    };
     use simple_test_case::test_case;
 
     macro_rules! assert_recv {
@@ -892,4 +1314,83 @@ recv {}({})",
             assert_recv!(brx, Focus, expected);
         }
     }
+
+    #[test_case(Input::Esc; "escape")]
+    #[test_case(Input::Ctrl('c'); "ctrl c")]
+    #[test]
+    fn waiting_for_a_buffer_to_load_can_be_cancelled(i: Input) {
+        let mut ed = Editor::new(
+            Config::default(),
+            PlumbingRules::default(),
+            EditorMode::Headless,
+            LogBuffer::default(),
+        );
+        ed.update_window_size(100, 80); // Needed in order to render while waiting
+        let id = ed.active_buffer_id();
+        ed.layout.active_buffer_mut().loading = Some(Loading::new_stubbed(1, 8));
+        ed.tx_events.send(Event::Input(i)).unwrap();
+
+        assert!(!ed.wait_for_load(id));
+        let b = ed.layout.active_buffer();
+        assert!(b.is_loading(), "buffer should still be loading");
+        assert!(!b.load_failed());
+    }
+
+    #[test_case("set line-ending=crlf", LineEnding::Crlf; "crlf")]
+    #[test_case("  set line-ending=CRLF  ", LineEnding::Crlf; "surrounding whitespace")]
+    #[test_case("set line-ending=bad", LineEnding::Lf; "invalid")]
+    #[test_case("set line-ending-style=crlf", LineEnding::Lf; "longer property name")]
+    #[test]
+    fn setting_the_line_ending_updates_the_active_buffer(input: &str, expected: LineEnding) {
+        let mut ed = Editor::new(
+            Config::default(),
+            PlumbingRules::default(),
+            EditorMode::Headless,
+            LogBuffer::default(),
+        );
+        ed.update_config(input);
+
+        assert_eq!(ed.layout.active_buffer().format.line_ending, expected);
+    }
+
+    #[test]
+    fn pasted_text_is_not_auto_paired_or_indented() {
+        let mut ed = Editor::new(
+            Config::default(),
+            PlumbingRules::default(),
+            EditorMode::Headless,
+            LogBuffer::default(),
+        );
+        let s = "fn main() {\n    x\n}";
+        ed.handle_event(Event::Paste(s.to_string()));
+
+        assert_eq!(ed.layout.active_buffer().str_contents(), format!("{s}\n"));
+    }
+
+    #[test]
+    fn jumping_to_the_previous_jump_position_toggles_between_positions() {
+        let mut ed = Editor::new(
+            Config::default(),
+            PlumbingRules::default(),
+            EditorMode::Headless,
+            LogBuffer::default(),
+        );
+        ed.handle_action(
+            Action::InsertString {
+                s: "one two three".to_string(),
+            },
+            Source::Keyboard,
+        );
+        ed.layout.active_buffer_mut().dot = Cur::new(8).into();
+        ed.handle_action(Action::SetMark { name: 'a' }, Source::Keyboard);
+        ed.layout.active_buffer_mut().dot = Cur::new(4).into();
+
+        let mut positions = Vec::new();
+        for name in ['a', JUMP_MARK, JUMP_MARK] {
+            ed.handle_action(Action::JumpToMark { name }, Source::Keyboard);
+            positions.push(ed.layout.active_buffer().dot.active_cur().idx);
+        }
+
+        assert_eq!(positions, vec![8, 4, 8]);
+    }
 }
@@ -16,6 +16,10 @@ pub fn built_in_commands() -> Vec<(Vec<&'static str>, &'static str)> {
             vec!["cd", "change-directory"],
             "change ad's working directory ('cd ../src')",
         ),
+        (
+            vec!["comment"],
+            "toggle line comments for the lines covered by dot using the comment token for the file type",
+        ),
         (
             vec!["db", "delete-buffer"],
             "delete the active buffer as long as there are no pending changes",
@@ -46,6 +50,10 @@ pub fn built_in_commands() -> Vec<(Vec<&'static str>, &'static str)> {
         (vec!["next-column"], "move focus to the next column"),
         (vec!["prev-window"], "move focus to the previous window in the current column"),
         (vec!["prev-column"], "move focus to the previous column"),
+        (
+            vec!["earlier"],
+            "undo changes by count or to the state from a given time ago ('earlier 5m')",
+        ),
         (
             vec!["echo"],
             "display the given string in the status line ('echo hello, world!')",
@@ -63,6 +71,10 @@ pub fn built_in_commands() -> Vec<(Vec<&'static str>, &'static str)> {
             "smart expand the current cursor position into a range",
         ),
         (vec!["help"], "display this help file"),
+        (
+            vec!["later"],
+            "redo changes by count or to the state from a given time later ('later 30s')",
+        ),
         (
             vec!["Load"],
             "load the contents of the current dot in the focused buffer",
@@ -96,9 +108,29 @@ pub fn built_in_commands() -> Vec<(Vec<&'static str>, &'static str)> {
             vec!["reload-config"],
             "reload the editor config file located at ~/.ad/init.conf",
         ),
+        (
+            vec!["reopen-with-encoding"],
+            "reload the current buffer from disk using the given encoding (utf-8, utf-16le, utf-16be, latin-1, windows-1252)",
+        ),
         (
             vec!["set"],
-            "set a config property ('set bg-color=#ebdbb2')",
+            "set a config property ('set bg-color=#ebdbb2') or the current buffer's line endings ('set line-ending=crlf')",
+        ),
+        (
+            vec!["surround"],
+            "wrap dot in a pair of delimiters or an XML style tag ('surround (' or 'surround <div>')",
+        ),
+        (
+            vec!["surround-change"],
+            "replace the delimiters surrounding dot, using 't' for tags and 'b' for any bracket by default ('surround-change ( [')",
+        ),
+        (
+            vec!["surround-delete"],
+            "remove the delimiters surrounding dot, using 't' for tags ('surround-delete \"')",
+        ),
+        (
+            vec!["undo-tree"],
+            "show the undo tree of the current buffer: Load a node to restore that state",
         ),
         (vec!["view-logs"], "open ad's internal logs in a new buffer"),
         (
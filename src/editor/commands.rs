@@ -1,5 +1,6 @@
 //! Command mode commands for ad
 use crate::{
+    buffer::SURROUND_ANY_BRACKET,
     editor::{
         Action::*,
         Actions::{self, *},
@@ -42,6 +43,8 @@ fn parse_command(input: &str, active_buffer_id: usize, cwd: &Path) -> Result<Act
             }
         }
 
+        "comment" => Ok(Single(ToggleComment)),
+
         "mark-clean" => {
             let bufid = if args.is_empty() {
                 active_buffer_id
@@ -62,6 +65,10 @@ fn parse_command(input: &str, active_buffer_id: usize, cwd: &Path) -> Result<Act
         "dw" | "delete-window" => Ok(Single(DeleteWindow { force: false })),
         "dw!" | "delete-window!" => Ok(Single(DeleteWindow { force: true })),
 
+        "earlier" => Ok(Single(Earlier {
+            step: if args.is_empty() { "1" } else { args }.to_string(),
+        })),
+
         "echo" => Ok(Single(SetStatusMessage {
             message: args.to_string(),
         })),
@@ -80,6 +87,10 @@ fn parse_command(input: &str, active_buffer_id: usize, cwd: &Path) -> Result<Act
 
         "Execute" => Ok(Single(ExecuteDot)),
         "help" => Ok(Single(ShowHelp)),
+        "later" => Ok(Single(Later {
+            step: if args.is_empty() { "1" } else { args }.to_string(),
+        })),
+
         "Load" => Ok(Single(LoadDot { new_window: false })),
 
         "LspFindReferences" => Ok(Single(LspReferences)),
@@ -119,6 +130,38 @@ fn parse_command(input: &str, active_buffer_id: usize, cwd: &Path) -> Result<Act
             message: cwd.display().to_string(),
         })),
 
+        "surround" => {
+            if args.is_empty() {
+                Err("No delimiter provided".to_string())
+            } else {
+                Ok(Single(SurroundAdd {
+                    s: args.to_string(),
+                }))
+            }
+        }
+
+        "surround-change" => match args.split_once(' ') {
+            Some((from, to)) if from.chars().count() == 1 && !to.is_empty() => {
+                Ok(Single(SurroundChange {
+                    from: from.chars().next().unwrap(),
+                    to: to.to_string(),
+                }))
+            }
+            None if !args.is_empty() => Ok(Single(SurroundChange {
+                from: SURROUND_ANY_BRACKET,
+                to: args.to_string(),
+            })),
+            _ => Err("usage: surround-change [delimiter] <new delimiter>".to_string()),
+        },
+
+        "surround-delete" => {
+            let mut chars = args.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Ok(Single(SurroundDelete { c })),
+                _ => Err("usage: surround-delete <delimiter>".to_string()),
+            }
+        }
+
         "q" | "quit" | "Exit" => Ok(Single(Exit { force: false })),
         "q!" | "quit!" | "Exit!" => Ok(Single(Exit { force: true })),
 
@@ -134,10 +177,22 @@ fn parse_command(input: &str, active_buffer_id: usize, cwd: &Path) -> Result<Act
             }
         }
 
+        "reopen-with-encoding" => {
+            if args.is_empty() {
+                Err("No encoding provided".to_string())
+            } else {
+                Ok(Single(ReopenWithEncoding {
+                    encoding: args.to_string(),
+                }))
+            }
+        }
+
         "set" => Ok(Single(UpdateConfig {
             input: input.to_string(),
         })),
 
+        "undo-tree" => Ok(Single(ShowUndoTree)),
+
         "view-logs" => Ok(Single(ViewLogs)),
 
         "w" | "write" => {
@@ -0,0 +1,191 @@
+//! Recording and replaying keyboard macros.
+//!
+//! Pressing `q` followed by the name of a register in NORMAL mode starts recording the keys
+//! that are pressed and pressing `q` again in NORMAL mode stops the recording. The recorded
+//! keys are stored in the register as text (see [keys_to_string]) so that they can be edited
+//! before being replayed using `@` followed by the name of the register.
+use crate::{
+    editor::Editor,
+    key::{keys_to_string, parse_keys, Input},
+    system::System,
+};
+use std::collections::VecDeque;
+
+/// The maximum number of keys that can be replayed for a single macro, guarding against macros
+/// that recursively replay themselves.
+const MAX_REPLAYED_KEYS: usize = 100_000;
+
+/// A macro that is currently being recorded.
+#[derive(Debug)]
+pub(crate) struct Recording {
+    r: char,
+    keys: Vec<Input>,
+}
+
+/// Keys from macros that are waiting to be replayed.
+#[derive(Debug, Default)]
+pub(crate) struct Replay {
+    pending: VecDeque<Input>,
+    active: bool,
+    n_replayed: usize,
+}
+
+impl<S> Editor<S>
+where
+    S: System,
+{
+    /// Whether or not the given input should stop the current recording.
+    pub(super) fn is_stop_recording(&self, input: Input) -> bool {
+        self.recording.is_some()
+            && input == Input::Char('q')
+            && self.pending_keys.is_empty()
+            && self.modes[0].name == "NORMAL"
+    }
+
+    /// Record a key that was pressed by the user if a macro is being recorded.
+    pub(super) fn record_input(&mut self, input: Input) {
+        if let Some(rec) = self.recording.as_mut() {
+            rec.keys.push(input);
+        }
+    }
+
+    pub(super) fn start_recording(&mut self, r: char) {
+        if !r.is_ascii_alphabetic() {
+            return self.set_status_message(&format!("unknown register: {r}"));
+        }
+
+        self.recording = Some(Recording {
+            r,
+            keys: Vec::new(),
+        });
+        self.set_status_message(&format!("recording macro to register {r}"));
+    }
+
+    pub(super) fn stop_recording(&mut self) {
+        let Recording { r, keys } = match self.recording.take() {
+            Some(rec) => rec,
+            None => return,
+        };
+
+        match self.registers.set(r, keys_to_string(&keys)) {
+            Ok(()) => self.set_status_message(&format!("recorded macro to register {r}")),
+            Err(e) => self.set_status_message(&e),
+        }
+    }
+
+    /// Replay the keys held in the given register as if they had been typed by the user.
+    ///
+    /// Macros replayed from within another macro are run before the remaining keys of the
+    /// outer macro.
+    pub(super) fn replay_macro(&mut self, r: char) {
+        let keys = match self.registers.get(r).map(parse_keys) {
+            Some(Ok(keys)) => keys,
+            Some(Err(e)) => return self.set_status_message(&format!("invalid macro: {e}")),
+            None => return self.set_status_message(&format!("unknown register: {r}")),
+        };
+
+        for k in keys.into_iter().rev() {
+            self.replay.pending.push_front(k);
+        }
+
+        if self.replay.active {
+            return;
+        }
+
+        self.replay.active = true;
+        self.replay.n_replayed = 0;
+        while let Some(k) = self.next_replayed_key() {
+            self.handle_input(k);
+            if !self.running {
+                break;
+            }
+        }
+        self.replay.active = false;
+        self.replay.pending.clear();
+    }
+
+    /// The next key to be replayed from a macro, if there is one.
+    pub(super) fn next_replayed_key(&mut self) -> Option<Input> {
+        if self.replay.n_replayed >= MAX_REPLAYED_KEYS {
+            if !self.replay.pending.is_empty() {
+                self.replay.pending.clear();
+                self.set_status_message("macro stopped: too many keys replayed");
+            }
+            return None;
+        }
+
+        let k = self.replay.pending.pop_front()?;
+        self.replay.n_replayed += 1;
+
+        Some(k)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        config::Config,
+        dot::{Cur, Dot},
+        editor::{Editor, EditorMode},
+        input::Event,
+        key::Input,
+        plumb::PlumbingRules,
+        system::DefaultSystem,
+        LogBuffer,
+    };
+
+    fn editor_with_content(s: &str) -> Editor<DefaultSystem> {
+        let mut ed = Editor::new(
+            Config::default(),
+            PlumbingRules::default(),
+            EditorMode::Headless,
+            LogBuffer::default(),
+        );
+        ed.layout.open_virtual("test", s, false);
+        ed.layout.active_buffer_mut().dot = Dot::Cur { c: Cur { idx: 0 } };
+
+        ed
+    }
+
+    fn press(ed: &mut Editor<DefaultSystem>, keys: &str) {
+        for c in keys.chars() {
+            ed.handle_event(Event::Input(Input::Char(c)));
+        }
+    }
+
+    #[test]
+    fn recording_a_macro_stores_the_keys_in_the_register() {
+        let mut ed = editor_with_content("hello, world!");
+        press(&mut ed, "qallq");
+
+        assert_eq!(ed.registers.get('a'), Some("ll"));
+        assert_eq!(
+            ed.layout.active_buffer().dot,
+            Dot::Cur { c: Cur { idx: 2 } }
+        );
+    }
+
+    #[test]
+    fn replaying_a_macro_works_with_counts() {
+        let mut ed = editor_with_content("hello, world!");
+        press(&mut ed, "qalq3@a");
+
+        assert_eq!(
+            ed.layout.active_buffer().dot,
+            Dot::Cur { c: Cur { idx: 4 } }
+        );
+    }
+
+    #[test]
+    fn recursive_macros_are_stopped() {
+        let mut ed = editor_with_content("hello, world!");
+        ed.registers.set('a', "l@a".to_string()).unwrap();
+        press(&mut ed, "@a");
+
+        assert_eq!(
+            ed.layout.active_buffer().dot,
+            Dot::Cur { c: Cur { idx: 13 } }
+        );
+        assert!(ed.replay.pending.is_empty());
+    }
+}
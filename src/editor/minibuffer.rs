@@ -3,7 +3,7 @@
 //!
 //! Conceptually this is operates as an embedded dmenu.
 use crate::{
-    buffer::{Buffer, Buffers, GapBuffer},
+    buffer::{Buffer, Buffers, Text},
     config_handle,
     dot::TextObject,
     editor::Actions,
@@ -102,13 +102,21 @@ where
     #[inline]
     fn handle_on_change(&mut self) {
         if let Some(lines) = (self.on_change)(&self.input) {
-            self.b.txt = GapBuffer::from(lines.join("\n"));
+            self.b.txt = Text::from(lines.join("\n"));
             self.b.dot.clamp_idx(self.b.txt.len_chars());
         };
     }
 
+    pub(super) fn input(&self) -> &str {
+        &self.input
+    }
+
+    pub(super) fn set_prompt(&mut self, prompt: String) {
+        self.prompt = prompt;
+    }
+
     #[inline]
-    fn update_state(&mut self) {
+    pub(super) fn update_state(&mut self) {
         self.b.txt.clear();
         self.line_indices.clear();
 
@@ -130,7 +138,7 @@ where
             }
         }
 
-        self.b.txt = GapBuffer::from(visible_lines.join("\n"));
+        self.b.txt = Text::from(visible_lines.join("\n"));
         self.b.dot.clamp_idx(self.b.txt.len_chars());
 
         let n_visible_lines = min(visible_lines.len(), self.max_height);
@@ -154,7 +162,7 @@ where
     }
 
     #[inline]
-    fn current_state(&self) -> MiniBufferState<'_> {
+    pub(super) fn current_state(&self) -> MiniBufferState<'_> {
         MiniBufferState {
             cx: self.x + self.prompt.len(),
             n_visible_lines: self.n_visible_lines,
@@ -172,19 +180,19 @@ where
     }
 
     #[inline]
-    fn handle_input(&mut self, input: Input) -> Option<MiniBufferSelection> {
+    pub(super) fn handle_input(&mut self, input: Input) -> Option<MiniBufferSelection> {
         match input {
             Input::Char(c) => {
                 self.input.insert(self.x, c);
                 self.x += 1;
                 self.handle_on_change();
             }
-            Input::Ctrl('h') | Input::Backspace | Input::Del => {
-                if self.x > 0 && self.x <= self.input.len() {
-                    self.input.remove(self.x - 1);
-                    self.x = self.x.saturating_sub(1);
-                    self.handle_on_change();
-                }
+            Input::Ctrl('h') | Input::Backspace | Input::Del
+                if self.x > 0 && self.x <= self.input.len() =>
+            {
+                self.input.remove(self.x - 1);
+                self.x = self.x.saturating_sub(1);
+                self.handle_on_change();
             }
 
             Input::Esc => return Some(MiniBufferSelection::Cancelled),
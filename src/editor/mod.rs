@@ -15,6 +15,7 @@ use crate::{
     system::{DefaultSystem, System},
     term::CurShape,
     ui::{Layout, StateChange, Ui, UserInterface},
+    watcher::Watcher,
     LogBuffer,
 };
 use ad_event::Source;
@@ -22,7 +23,7 @@ use std::{
     env, panic,
     path::PathBuf,
     sync::{
-        mpsc::{channel, Receiver, Sender},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender},
         Arc,
     },
     time::Instant,
@@ -32,13 +33,21 @@ use tracing::{debug, trace, warn};
 mod actions;
 mod built_in_commands;
 mod commands;
+mod macros;
 mod minibuffer;
 mod mouse;
+mod registers;
+mod search;
+mod swap;
 
-pub(crate) use actions::{Action, Actions, ViewPort};
+pub(crate) use actions::{Action, Actions, ViewPort, MAX_COUNT};
 pub(crate) use built_in_commands::built_in_commands;
+use macros::{Recording, Replay};
 pub(crate) use minibuffer::{MbSelect, MbSelector, MiniBufferSelection, MiniBufferState};
 pub(crate) use mouse::Click;
+use registers::Registers;
+use search::Search;
+use swap::{SwapWriter, SWAP_INTERVAL};
 
 /// The mode that the [Editor] will run in following a call to [Editor::run].
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -72,6 +81,17 @@ where
     held_click: Option<Click>,
     last_click_was_left: bool,
     last_click_time: Instant,
+    search: Option<Search>,
+    show_search_highlights: bool,
+    swap: SwapWriter,
+    last_swap: Instant,
+    watcher: Option<Watcher>,
+    undo_tree_for: Option<usize>,
+    registers: Registers,
+    /// The register selected for the key sequence currently being handled
+    register: Option<char>,
+    recording: Option<Recording>,
+    replay: Replay,
 }
 
 impl Editor<DefaultSystem> {
@@ -127,6 +147,16 @@ where
             held_click: None,
             last_click_was_left: false,
             last_click_time: Instant::now(),
+            search: None,
+            show_search_highlights: false,
+            swap: SwapWriter::spawn(),
+            last_swap: Instant::now(),
+            watcher: None,
+            undo_tree_for: None,
+            registers: Registers::default(),
+            register: None,
+            recording: None,
+            replay: Replay::default(),
         }
     }
 
@@ -155,6 +185,14 @@ where
     pub fn run(mut self) {
         let rx_fsys = self.rx_fsys.take().expect("to have fsys channels");
         AdFs::new(self.tx_events.clone(), rx_fsys).run_threaded();
+        self.watcher = match Watcher::spawn(self.tx_events.clone()) {
+            Ok(w) => Some(w),
+            Err(e) => {
+                warn!("unable to watch files for changes: {e}");
+                None
+            }
+        };
+        self.update_watched_paths();
         self.ensure_correct_fsys_state();
         self.run_event_loop();
     }
@@ -162,16 +200,24 @@ where
     #[inline]
     fn handle_event(&mut self, event: Event) {
         match event {
-            Event::Input(i) => self.handle_input(i),
+            Event::Input(i) if self.is_stop_recording(i) => self.stop_recording(),
+            Event::Input(i) => {
+                self.record_input(i);
+                self.handle_input(i);
+            }
+            Event::Paste(s) => self.paste_text(s),
             Event::Action(a) => self.handle_action(a, Source::Fsys),
             Event::Actions(a) => self.handle_actions(a, Source::Fsys),
             Event::Message(msg) => self.handle_message(msg),
             Event::WinsizeChanged { rows, cols } => self.update_window_size(rows, cols),
+            Event::PathChanged { path } => self.handle_path_changed(path),
+            Event::FileChunk { id, load_id, chunk } => self.handle_file_chunk(id, load_id, chunk),
         }
     }
 
     pub(super) fn refresh_screen_w_minibuffer(&mut self, mb: Option<MiniBufferState<'_>>) {
         self.layout.clamp_scroll();
+        self.update_search_highlights();
         self.ui.refresh(
             &self.modes[0].name,
             &self.layout,
@@ -187,15 +233,31 @@ where
         self.update_window_size(screen_rows, screen_cols);
         self.ui.set_cursor_shape(self.current_cursor_shape());
 
+        let mut needs_refresh = true;
+
         while self.running {
-            self.refresh_screen_w_minibuffer(None);
+            if needs_refresh {
+                self.refresh_screen_w_minibuffer(None);
+            }
 
-            match self.rx_events.recv() {
-                Ok(next_event) => self.handle_event(next_event),
-                _ => break,
+            // Waking up to write swap files only needs a redraw if a swap file was written
+            needs_refresh = match self.rx_events.recv_timeout(SWAP_INTERVAL) {
+                Ok(next_event) => {
+                    self.handle_event(next_event);
+                    true
+                }
+                Err(RecvTimeoutError::Timeout) => false,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            self.start_background_loads();
+            if self.last_swap.elapsed() >= SWAP_INTERVAL {
+                needs_refresh |= self.write_swap_files();
+                self.last_swap = Instant::now();
             }
         }
 
+        self.remove_swap_files();
         self.ui.shutdown();
     }
 
@@ -211,13 +273,30 @@ where
     }
 
     pub(crate) fn block_for_input(&mut self) -> Input {
+        if let Some(k) = self.next_replayed_key() {
+            return k;
+        }
+
         loop {
             match self.rx_events.recv().unwrap() {
-                Event::Input(k) => return k,
+                Event::Input(k) => {
+                    self.record_input(k);
+                    return k;
+                }
+                // Only the first line of a paste is used as the rest would submit the input
+                Event::Paste(s) => {
+                    for c in s.lines().next().unwrap_or_default().chars() {
+                        _ = self.tx_events.send(Event::Input(Input::Char(c)));
+                    }
+                }
                 Event::Action(a) => self.handle_action(a, Source::Fsys),
                 Event::Actions(a) => self.handle_actions(a, Source::Fsys),
                 Event::Message(msg) => self.handle_message(msg),
                 Event::WinsizeChanged { rows, cols } => self.update_window_size(rows, cols),
+                Event::PathChanged { path } => self.handle_path_changed(path),
+                Event::FileChunk { id, load_id, chunk } => {
+                    self.handle_file_chunk(id, load_id, chunk)
+                }
             }
         }
     }
@@ -344,6 +423,25 @@ where
                 self.execute_explicit_string(id, txt, Source::Fsys);
                 default_handled();
             }
+
+            ReadRegister { r } => {
+                let res = match self.registers.get(r) {
+                    Some(s) => Ok(s.to_string()),
+                    None => Err(format!("unknown register: {r}")),
+                };
+                _ = tx.send(res);
+            }
+
+            // Upper case register names append to the existing content
+            AppendRegister { r, s } => {
+                let res = self.registers.set(r.to_ascii_uppercase(), s);
+                _ = tx.send(res.map(|_| "handled".to_string()));
+            }
+
+            ClearRegister { r } => {
+                let res = self.registers.set(r, String::new());
+                _ = tx.send(res.map(|_| "handled".to_string()));
+            }
         }
     }
 
@@ -352,6 +450,7 @@ where
 
         if let Some(actions) = self.modes[0].handle_keys(&mut self.pending_keys) {
             self.handle_actions(actions, Source::Keyboard);
+            self.register = None;
         }
     }
 
@@ -394,7 +493,10 @@ where
                 direction: Arrow::Right,
             } => self.layout.drag_right(),
             EditCommand { cmd } => self.execute_edit_command(&cmd),
-            EnsureFileIsOpen { path } => self.layout.ensure_file_is_open(&path),
+            EnsureFileIsOpen { path } => {
+                self.layout.ensure_file_is_open(&path);
+                self.update_watched_paths();
+            }
             ExecuteDot => self.default_execute_dot(None, source),
             ExecuteString { s } => self.execute_explicit_string(self.active_buffer_id(), s, source),
             Exit { force } => self.exit(force),
@@ -404,6 +506,7 @@ where
             FocusBuffer { id } => self.focus_buffer(id),
             JumpListForward => self.jump_forward(),
             JumpListBack => self.jump_backward(),
+            JumpToMark { name } => self.jump_to_mark(name),
             LoadDot { new_window } => self.default_load_dot(source, new_window),
             LspShowCapabilities => {
                 if let Some((name, txt)) = self
@@ -425,6 +528,12 @@ where
                 }
             }
             LspStop => self.lsp_manager.stop_client(self.layout.active_buffer()),
+            LspFold => {
+                if !self.lsp_manager.fold(self.layout.active_buffer()) {
+                    // Without an LSP server we fall back to folding based on indentation
+                    self.forward_action_to_active_buffer(FoldIndent, source);
+                }
+            }
             LspGotoDeclaration => self
                 .lsp_manager
                 .goto_declaration(self.layout.active_buffer()),
@@ -438,6 +547,18 @@ where
             LspReferences => self
                 .lsp_manager
                 .find_references(self.layout.active_buffer()),
+            LspSelectSymbol { scope } => {
+                if !self
+                    .lsp_manager
+                    .select_symbol(self.layout.active_buffer(), scope)
+                {
+                    // Without an LSP server the best we can do is the surrounding indent block
+                    self.forward_action_to_active_buffer(
+                        DotSet(TextObject::IndentBlock(scope), 1),
+                        source,
+                    );
+                }
+            }
             MarkClean { bufid } => self.mark_clean(bufid),
             MbSelect(selector) => selector.run(self),
             NewEditLogTransaction => self.layout.active_buffer_mut().new_edit_log_transaction(),
@@ -460,7 +581,7 @@ where
             OpenFile { path } => self.open_file_relative_to_cwd(&path, false),
             OpenFileInNewWindow { path } => self.open_file_relative_to_cwd(&path, true),
             OpenVirtualFile { name, txt } => self.layout.open_virtual(name, txt, true),
-            Paste => self.paste_from_clipboard(source),
+            Paste => self.paste(source),
             PreviousBuffer => {
                 let id = self.layout.focus_previous_buffer();
                 _ = self.tx_fsys.send(LogEvent::Focus(id));
@@ -478,12 +599,19 @@ where
             ReloadActiveBuffer => self.reload_active_buffer(),
             ReloadBuffer { id } => self.reload_buffer(id),
             ReloadConfig => self.reload_config(),
+            ReopenWithEncoding { encoding } => self.reopen_active_buffer_with_encoding(&encoding),
             RunMode => self.run_mode(),
             SamMode => self.sam_mode(),
             SaveBufferAs { path, force } => self.save_current_buffer(Some(path), force),
             SaveBuffer { force } => self.save_current_buffer(None, force),
-            SearchInCurrentBuffer => self.search_in_current_buffer(),
+            ClearSearchHighlights => self.clear_search_highlights(),
+            ReverseSearchInCurrentBuffer => self.incremental_search(true),
+            SearchInCurrentBuffer => self.incremental_search(false),
+            SearchNext => self.repeat_search(false),
+            SearchPrevious => self.repeat_search(true),
+            SelectLineInCurrentBuffer => self.select_line_in_current_buffer(),
             SelectBuffer => self.select_buffer(),
+            SelectRegister { r } => self.register = Some(r),
             SetMode { m } => self.set_mode(m),
             SetStatusMessage { message } => self.set_status_message(&message),
             SetViewPort(vp) => self.layout.set_viewport(vp),
@@ -491,13 +619,16 @@ where
             ShellReplace { cmd } => self.replace_dot_with_shell_cmd(&cmd),
             ShellRun { cmd } => self.run_shell_cmd(&cmd),
             ShowHelp => self.show_help(),
+            ShowUndoTree => self.show_undo_tree(),
             UpdateConfig { input } => self.update_config(&input),
             ViewLogs => self.view_logs(),
-            Yank => self.set_clipboard(self.layout.active_buffer().dot_contents()),
+            Yank => self.yank(self.layout.active_buffer().dot_contents()),
 
             DebugBufferContents => self.debug_buffer_contents(),
             DebugEditLog => self.debug_edit_log(),
 
+            RecordMacro { r } => self.start_recording(r),
+            ReplayMacro { r } => self.replay_macro(r),
             RawInput { i } if i == Input::PageUp || i == Input::PageDown => {
                 let arr = if i == Input::PageUp {
                     Arrow::Up
@@ -530,11 +661,28 @@ where
         }
     }
 
+    fn jump_to_mark(&mut self, name: char) {
+        // Recording the jump updates the mark for the previous jump position so the target
+        // needs to be looked up first
+        let dot = match self.layout.active_buffer().get_mark(name) {
+            Some(dot) => dot,
+            None => {
+                self.set_status_message(&format!("mark {name} is not set"));
+                return;
+            }
+        };
+
+        self.layout.record_jump_position();
+        let b = self.layout.active_buffer_mut();
+        b.dot = dot;
+        b.dot.clamp_idx(b.txt.len_chars());
+    }
+
     fn forward_action_to_active_buffer(&mut self, a: Action, source: Source) {
         if let Some(o) = self.layout.active_buffer_mut().handle_action(a, source) {
             match o {
                 ActionOutcome::SetStatusMessage(msg) => self.set_status_message(&msg),
-                ActionOutcome::SetClipboard(s) => self.set_clipboard(s),
+                ActionOutcome::SetClipboard(s) => self.yank(s),
             }
         }
     }
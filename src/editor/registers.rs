@@ -0,0 +1,101 @@
+//! Named registers for yanking and pasting text.
+//!
+//! Registers are selected in NORMAL mode by prefixing a key binding with `"` and the name of the
+//! register, so `"ay` yanks dot into register a and `"ap` pastes it. Registers are named using
+//! the letters a-z: using an upper case letter appends to the register rather than replacing its
+//! content. Two further read only registers hold the last search (`/`) and the last Edit command
+//! (`:`) that were run.
+//!
+//! Without a register yanking and pasting use the system clipboard.
+use std::collections::BTreeMap;
+
+pub(crate) const SEARCH_REGISTER: char = '/';
+pub(crate) const EDIT_REGISTER: char = ':';
+
+/// The contents of the named and read only registers.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct Registers {
+    named: BTreeMap<char, String>,
+    last_search: String,
+    last_edit: String,
+}
+
+impl Registers {
+    /// The content of the given register, if there is such a register.
+    pub(crate) fn get(&self, r: char) -> Option<&str> {
+        match r {
+            SEARCH_REGISTER => Some(&self.last_search),
+            EDIT_REGISTER => Some(&self.last_edit),
+            r if r.is_ascii_alphabetic() => Some(
+                self.named
+                    .get(&r.to_ascii_lowercase())
+                    .map(|s| s.as_str())
+                    .unwrap_or_default(),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Set the content of a register: upper case names append to the existing content of
+    /// the register.
+    pub(crate) fn set(&mut self, r: char, s: String) -> Result<(), String> {
+        match r {
+            r if r.is_ascii_lowercase() => _ = self.named.insert(r, s),
+            r if r.is_ascii_uppercase() => self
+                .named
+                .entry(r.to_ascii_lowercase())
+                .or_default()
+                .push_str(&s),
+            SEARCH_REGISTER | EDIT_REGISTER => return Err(format!("register {r} is read only")),
+            r => return Err(format!("unknown register: {r}")),
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn set_last_search(&mut self, s: &str) {
+        self.last_search = s.to_string();
+    }
+
+    pub(crate) fn set_last_edit(&mut self, s: &str) {
+        self.last_edit = s.to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    #[test_case('a', "bar"; "lower case replaces")]
+    #[test_case('A', "foobar"; "upper case appends")]
+    #[test]
+    fn set_works(r: char, expected: &str) {
+        let mut regs = Registers::default();
+        regs.set('a', "foo".to_string()).unwrap();
+        regs.set(r, "bar".to_string()).unwrap();
+
+        assert_eq!(regs.get('a'), Some(expected));
+        assert_eq!(regs.get('A'), Some(expected));
+    }
+
+    #[test_case(SEARCH_REGISTER; "search")]
+    #[test_case(EDIT_REGISTER; "edit")]
+    #[test]
+    fn special_registers_are_read_only(r: char) {
+        let mut regs = Registers::default();
+        regs.set_last_search("foo");
+        regs.set_last_edit(", x/foo/ d");
+
+        assert!(regs.set(r, "bar".to_string()).is_err());
+        assert_ne!(regs.get(r), Some("bar"));
+    }
+
+    #[test]
+    fn unknown_registers_are_rejected() {
+        let mut regs = Registers::default();
+
+        assert_eq!(regs.get('1'), None);
+        assert!(regs.set('1', "foo".to_string()).is_err());
+    }
+}
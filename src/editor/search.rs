@@ -0,0 +1,387 @@
+//! Interactive searching within the active buffer.
+//!
+//! Searches are run incrementally as the user types into the minibuffer, moving dot to the
+//! first match and highlighting all visible matches. The last search is remembered so that it
+//! can be repeated in either direction once the minibuffer has been closed.
+use crate::{
+    buffer::Buffer,
+    dot::{Cur, Dot},
+    editor::{minibuffer::MiniBuffer, Editor, MiniBufferSelection},
+    exec::IterBoundedChars,
+    key::Input,
+    regex::{self, Match, Regex},
+    system::System,
+    ui::SearchHighlights,
+};
+use std::cmp::min;
+
+/// How the input for a search should be interpreted
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SearchKind {
+    #[default]
+    Regex,
+    Literal,
+}
+
+impl SearchKind {
+    fn toggle(self) -> Self {
+        match self {
+            Self::Regex => Self::Literal,
+            Self::Literal => Self::Regex,
+        }
+    }
+}
+
+/// A compiled search that can be run in either direction through a buffer.
+#[derive(Debug, Clone)]
+pub(crate) struct Search {
+    /// Whether the search was originally run backwards through the buffer
+    reverse: bool,
+    fwd: Regex,
+    rev: Regex,
+}
+
+impl Search {
+    pub(crate) fn try_new(
+        pattern: &str,
+        kind: SearchKind,
+        reverse: bool,
+    ) -> Result<Self, regex::Error> {
+        let re = match kind {
+            SearchKind::Regex => pattern.to_string(),
+            SearchKind::Literal => regex::escape(pattern),
+        };
+
+        Ok(Self {
+            reverse,
+            fwd: Regex::compile(&re)?,
+            rev: Regex::compile_reverse(&re)?,
+        })
+    }
+
+    /// The first match in the direction of the search, including one beginning at dot.
+    pub(crate) fn first_match(&mut self, b: &Buffer, dot: Dot) -> Option<Dot> {
+        let idx = dot.first_cur().idx;
+        if self.reverse {
+            self.find_backward(b, idx)
+        } else {
+            self.find_forward(b, idx)
+        }
+    }
+
+    /// The next match following dot in the direction of the search, or in the opposite
+    /// direction if `flip` is true.
+    pub(crate) fn next_match(&mut self, b: &Buffer, dot: Dot, flip: bool) -> Option<Dot> {
+        if self.reverse != flip {
+            self.find_backward(b, dot.first_cur().idx)
+        } else {
+            self.find_forward(b, dot.last_cur().idx + 1)
+        }
+    }
+
+    /// All non-empty matches between the given character offsets.
+    pub(crate) fn matches_between(
+        &mut self,
+        b: &Buffer,
+        mut from: usize,
+        to: usize,
+    ) -> Vec<(usize, usize)> {
+        let mut matches = Vec::new();
+
+        while from < to {
+            let m = match self.fwd.match_iter(&mut b.iter_between(from, to), from) {
+                Some(m) => m,
+                None => break,
+            };
+
+            let (start, end) = m.loc();
+            if start == end {
+                from = end + 1;
+            } else {
+                matches.push((start, end));
+                from = end;
+            }
+        }
+
+        matches
+    }
+
+    /// Search forward from idx (inclusive), wrapping around to the start of the buffer.
+    fn find_forward(&mut self, b: &Buffer, idx: usize) -> Option<Dot> {
+        let n = b.txt.len_chars();
+        let idx = min(idx, n);
+
+        self.fwd
+            .match_iter(&mut b.iter_between(idx, n), idx)
+            .or_else(|| self.fwd.match_iter(&mut b.iter_between(0, idx), 0))
+            .map(match_to_dot)
+    }
+
+    /// Search backward from idx (exclusive), wrapping around to the end of the buffer.
+    fn find_backward(&mut self, b: &Buffer, idx: usize) -> Option<Dot> {
+        let n = b.txt.len_chars();
+        let idx = min(idx, n);
+
+        self.rev
+            .match_iter(&mut b.rev_iter_between(idx, 0), idx)
+            .or_else(|| self.rev.match_iter(&mut b.rev_iter_between(n, idx), n))
+            .map(match_to_dot)
+    }
+}
+
+fn match_to_dot(m: Match) -> Dot {
+    let (from, to) = m.loc();
+    if from == to {
+        Cur { idx: from }.into()
+    } else {
+        Dot::from_char_indices(from, to - 1)
+    }
+}
+
+fn search_prompt(kind: SearchKind, reverse: bool) -> String {
+    let dir = if reverse { "?" } else { "/" };
+    match kind {
+        SearchKind::Regex => dir.to_string(),
+        SearchKind::Literal => format!("[literal] {dir}"),
+    }
+}
+
+impl<S> Editor<S>
+where
+    S: System,
+{
+    /// Search the active buffer, moving dot to the first match as the user types.
+    ///
+    /// Tab toggles between regex and literal searches, Return accepts the current match and
+    /// Escape restores the original dot.
+    pub(super) fn incremental_search(&mut self, reverse: bool) {
+        let origin = self.layout.active_buffer().dot;
+        let mut kind = SearchKind::default();
+        let mut mb = MiniBuffer::new(search_prompt(kind, reverse), vec![], 0, |_| None);
+        self.show_search_highlights = true;
+
+        loop {
+            mb.update_state();
+            self.refresh_screen_w_minibuffer(Some(mb.current_state()));
+
+            match self.block_for_input() {
+                Input::Tab => {
+                    kind = kind.toggle();
+                    mb.set_prompt(search_prompt(kind, reverse));
+                }
+
+                input => match mb.handle_input(input) {
+                    Some(MiniBufferSelection::UserInput { input }) if !input.is_empty() => {
+                        self.registers.set_last_search(&input);
+                        // The jump list needs to hold the position we started from
+                        let b = self.layout.active_buffer_mut();
+                        let found = b.dot;
+                        b.dot = origin;
+                        self.layout.record_jump_position();
+                        self.layout.active_buffer_mut().dot = found;
+                        return;
+                    }
+                    Some(_) => {
+                        self.layout.active_buffer_mut().dot = origin;
+                        self.clear_search_highlights();
+                        return;
+                    }
+                    None => (),
+                },
+            }
+
+            self.update_search(mb.input(), kind, reverse, origin);
+        }
+    }
+
+    fn update_search(&mut self, input: &str, kind: SearchKind, reverse: bool, origin: Dot) {
+        self.search = if input.is_empty() {
+            None
+        } else {
+            Search::try_new(input, kind, reverse).ok()
+        };
+        self.layout.search_highlights = None;
+
+        let b = self.layout.active_buffer_mut();
+        b.dot = match self.search.as_mut() {
+            Some(s) => s.first_match(b, origin).unwrap_or(origin),
+            None => origin,
+        };
+    }
+
+    /// Move dot to the next match of the last search, reversing the direction of the search if
+    /// `flip` is true.
+    pub(super) fn repeat_search(&mut self, flip: bool) {
+        let search = match self.search.as_mut() {
+            Some(s) => s,
+            None => {
+                self.set_status_message("no previous search");
+                return;
+            }
+        };
+
+        let b = self.layout.active_buffer();
+        match search.next_match(b, b.dot, flip) {
+            Some(dot) => {
+                self.layout.record_jump_position();
+                self.layout.active_buffer_mut().dot = dot;
+                self.show_search_highlights = true;
+            }
+            None => self.set_status_message("no matches"),
+        }
+    }
+
+    pub(super) fn clear_search_highlights(&mut self) {
+        self.show_search_highlights = false;
+        self.layout.search_highlights = None;
+    }
+
+    /// Locate all matches of the current search that are visible in the active window.
+    ///
+    /// The previous matches are kept if neither the buffer nor the visible region have changed
+    /// since they were found. Changing the search itself clears them.
+    pub(super) fn update_search_highlights(&mut self) {
+        let search = match self.search.as_mut() {
+            Some(s) if self.show_search_highlights => s,
+            _ => {
+                self.layout.search_highlights = None;
+                return;
+            }
+        };
+
+        let row_off = self.layout.focused_view().row_off;
+        let n_rows = self.layout.active_window_rows();
+        let b = self.layout.active_buffer();
+        let from = b.txt.line_to_char(min(row_off, b.txt.len_lines()));
+        // Folded lines don't take up any rows on screen so the last visible row may be further
+        // down the buffer than row_off + n_rows
+        let last = b.line_n_rows_below(row_off, n_rows.saturating_sub(1));
+        let last = b.fold_containing(last).map(|(_, l)| l).unwrap_or(last);
+        let to = if last + 1 < b.txt.len_lines() {
+            b.txt.line_to_char(last + 1)
+        } else {
+            b.txt.len_chars()
+        };
+
+        let (id, version) = (b.id, b.version());
+        if let Some(hl) = self.layout.search_highlights.as_ref() {
+            if (hl.id, hl.version, hl.from, hl.to) == (id, version, from, to) {
+                return;
+            }
+        }
+
+        self.layout.search_highlights = Some(SearchHighlights {
+            id,
+            version,
+            from,
+            to,
+            matches: search.matches_between(b, from, to),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        editor::{Action, EditorMode},
+        LogBuffer, PlumbingRules,
+    };
+    use ad_event::Source;
+    use simple_test_case::test_case;
+
+    const TEXT: &str = "foo bar\nfoo.baz\nbar foo";
+
+    #[test_case("ba.", SearchKind::Regex, false, 0, (4, 6); "regex forward")]
+    #[test_case("o\\.b", SearchKind::Regex, false, 0, (10, 12); "regex escaped dot")]
+    #[test_case("o.b", SearchKind::Literal, false, 0, (10, 12); "literal")]
+    #[test_case("bar", SearchKind::Regex, false, 5, (16, 18); "forward from dot")]
+    #[test_case("foo", SearchKind::Regex, false, 21, (0, 2); "forward wraps")]
+    #[test_case("foo", SearchKind::Regex, true, 21, (8, 10); "reverse")]
+    #[test_case("baz", SearchKind::Regex, true, 0, (12, 14); "reverse wraps")]
+    #[test]
+    fn first_match_works(
+        pattern: &str,
+        kind: SearchKind,
+        reverse: bool,
+        idx: usize,
+        expected: (usize, usize),
+    ) {
+        let b = Buffer::new_unnamed(0, TEXT);
+        let mut s = Search::try_new(pattern, kind, reverse).unwrap();
+        let dot = s.first_match(&b, Cur { idx }.into()).unwrap();
+
+        assert_eq!(dot.as_char_indices(), expected);
+    }
+
+    #[test_case(false, &[8, 20, 0, 8]; "forward")]
+    #[test_case(true, &[20, 8, 0, 20]; "flipped")]
+    #[test]
+    fn next_match_works(flip: bool, starts: &[usize]) {
+        let b = Buffer::new_unnamed(0, TEXT);
+        let mut s = Search::try_new("foo", SearchKind::Regex, false).unwrap();
+        let mut dot = Dot::from_char_indices(0, 2);
+
+        for &start in starts {
+            dot = s.next_match(&b, dot, flip).unwrap();
+            assert_eq!(dot.content(&b), "foo");
+            assert_eq!(dot.first_cur().idx, start);
+        }
+    }
+
+    #[test]
+    fn matches_between_works() {
+        let b = Buffer::new_unnamed(0, TEXT);
+        let mut s = Search::try_new("ba.", SearchKind::Regex, false).unwrap();
+
+        assert_eq!(
+            s.matches_between(&b, 0, 23),
+            vec![(4, 7), (12, 15), (16, 19)]
+        );
+        assert_eq!(s.matches_between(&b, 8, 16), vec![(12, 15)]);
+    }
+
+    #[test]
+    fn empty_matches_are_not_highlighted() {
+        let b = Buffer::new_unnamed(0, TEXT);
+        let mut s = Search::try_new("x*", SearchKind::Regex, false).unwrap();
+
+        assert!(s.matches_between(&b, 0, 23).is_empty());
+    }
+
+    #[test]
+    fn search_highlights_are_updated_when_the_buffer_changes() {
+        let mut ed = Editor::new(
+            Config::default(),
+            PlumbingRules::default(),
+            EditorMode::Headless,
+            LogBuffer::default(),
+        );
+        ed.handle_action(
+            Action::InsertString {
+                s: TEXT.to_string(),
+            },
+            Source::Keyboard,
+        );
+        ed.layout.active_buffer_mut().dot = Cur { idx: 0 }.into();
+        ed.search = Search::try_new("foo", SearchKind::Regex, false).ok();
+        ed.show_search_highlights = true;
+
+        ed.update_search_highlights();
+        let hl = ed.layout.search_highlights.clone().expect("highlights");
+        assert_eq!(hl.matches, vec![(0, 3)]);
+
+        // Nothing has changed so the previous matches are kept
+        ed.update_search_highlights();
+        assert_eq!(ed.layout.search_highlights.as_ref(), Some(&hl));
+
+        ed.handle_action(
+            Action::InsertString { s: "x".to_string() },
+            Source::Keyboard,
+        );
+        ed.update_search_highlights();
+        let matches = ed.layout.search_highlights.map(|hl| hl.matches);
+        assert_eq!(matches, Some(vec![(1, 4)]));
+    }
+}
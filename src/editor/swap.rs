@@ -0,0 +1,402 @@
+//! Crash recovery for unsaved changes.
+//!
+//! The content of dirty file buffers is periodically written to a swap file under ~/.ad/swap
+//! (mirroring the path of the file being edited) from a background thread. Swap files are only
+//! readable by the current user as they hold the content of the files being edited. Swap files are
+//! removed once a buffer is saved or closed, so finding a swap file that was written by a
+//! process that is no longer running when opening a file means that a previous session exited
+//! without saving. The user is then offered the chance to recover the unsaved content, diff it
+//! against the file on disk or discard it.
+//!
+//! A swap file written by another ad session that is still running is never updated or removed
+//! so that session keeps its own recovery journal for the file.
+use crate::{
+    buffer::Text,
+    dot::TextObject,
+    editor::{Action, Editor, MiniBufferSelection},
+    system::System,
+    util::{ad_dir, atomic_write_private, mirrored_path},
+};
+use ad_event::Source;
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind},
+    path::{Path, PathBuf},
+    process,
+    sync::mpsc::{channel, Sender},
+    thread::spawn,
+    time::Duration,
+};
+use tracing::{debug, error};
+
+/// How often dirty buffers are written to their swap files
+pub(super) const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+const HEADER_PREFIX: &str = "ad-swap pid=";
+const SWAP_PROMPT: &str = "Unsaved changes found from a previous session> ";
+
+/// The swap file used for a given file path.
+pub(crate) fn swap_path(path: &Path) -> PathBuf {
+    let mut p = mirrored_path(&ad_dir("swap"), path).into_os_string();
+    p.push(".swp");
+
+    p.into()
+}
+
+/// The contents of a swap file along with the process that wrote it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct SwapFile {
+    pid: u32,
+    content: String,
+}
+
+impl SwapFile {
+    fn read(path: &Path) -> io::Result<Option<Self>> {
+        let raw = match fs::read_to_string(swap_path(path)) {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self::parse(&raw))
+    }
+
+    fn parse(raw: &str) -> Option<Self> {
+        let (header, content) = raw.split_once('\n')?;
+        let pid = header.strip_prefix(HEADER_PREFIX)?.parse().ok()?;
+
+        Some(Self {
+            pid,
+            content: content.to_string(),
+        })
+    }
+
+    fn serialize(pid: u32, content: &str) -> String {
+        format!("{HEADER_PREFIX}{pid}\n{content}")
+    }
+
+    /// A swap file is stale if the process that wrote it is no longer running.
+    fn is_stale(&self) -> bool {
+        self.pid == process::id() || !process_is_running(self.pid)
+    }
+}
+
+/// Whether the given swap file was written by another ad session that is still running.
+fn owned_by_another_session(swap: &Path) -> bool {
+    let owner = fs::File::open(swap).ok().and_then(|f| {
+        let mut header = String::new();
+        BufReader::new(f).read_line(&mut header).ok()?;
+        header.trim_end().strip_prefix(HEADER_PREFIX)?.parse().ok()
+    });
+
+    owner.is_some_and(|pid| pid != process::id() && process_is_running(pid))
+}
+
+fn process_is_running(pid: u32) -> bool {
+    // Signal 0 performs error checking without sending anything: EPERM means that the
+    // process exists but is owned by another user.
+    // SAFETY: kill has no memory safety requirements and signal 0 is never delivered
+    let res = unsafe { libc::kill(pid as libc::pid_t, 0) };
+
+    res == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+#[derive(Debug)]
+enum SwapReq {
+    Write { path: PathBuf, txt: Box<Text> },
+    Remove { path: PathBuf },
+    Flush { tx: Sender<()> },
+}
+
+/// A handle to the background thread responsible for writing swap files.
+#[derive(Debug)]
+pub(crate) struct SwapWriter {
+    tx: Sender<SwapReq>,
+}
+
+impl SwapWriter {
+    pub(crate) fn spawn() -> Self {
+        let (tx, rx) = channel();
+
+        spawn(move || {
+            let pid = process::id();
+            for req in rx.into_iter() {
+                let res = match req {
+                    SwapReq::Write { path, .. } | SwapReq::Remove { path }
+                        if owned_by_another_session(&swap_path(&path)) =>
+                    {
+                        debug!(path=%path.display(), "swap file owned by another session");
+                        Ok(())
+                    }
+
+                    SwapReq::Write { path, txt } => {
+                        debug!(path=%path.display(), "writing swap file");
+                        let mut content = txt.to_string();
+                        content.push('\n');
+                        let swap = swap_path(&path);
+                        swap.parent()
+                            .map_or(Ok(()), fs::create_dir_all)
+                            .and_then(|_| {
+                                atomic_write_private(
+                                    &swap,
+                                    SwapFile::serialize(pid, &content).as_bytes(),
+                                )
+                            })
+                    }
+
+                    SwapReq::Remove { path } => match fs::remove_file(swap_path(&path)) {
+                        Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+                        res => res,
+                    },
+
+                    SwapReq::Flush { tx } => {
+                        _ = tx.send(());
+                        Ok(())
+                    }
+                };
+
+                if let Err(e) = res {
+                    error!("unable to update swap file: {e}");
+                }
+            }
+        });
+
+        Self { tx }
+    }
+
+    /// Write the given content to the swap file for path. The content is serialized on the
+    /// background thread so that doing so does not block the UI.
+    pub(crate) fn write(&self, path: PathBuf, txt: Text) {
+        _ = self.tx.send(SwapReq::Write {
+            path,
+            txt: Box::new(txt),
+        });
+    }
+
+    pub(crate) fn remove(&self, path: PathBuf) {
+        _ = self.tx.send(SwapReq::Remove { path });
+    }
+
+    /// Block until all pending requests have been handled.
+    pub(crate) fn flush(&self) {
+        let (tx, rx) = channel();
+        if self.tx.send(SwapReq::Flush { tx }).is_ok() {
+            _ = rx.recv();
+        }
+    }
+}
+
+impl<S> Editor<S>
+where
+    S: System,
+{
+    /// Write the content of any file buffers that have changed since they were last swapped,
+    /// removing the swap files of buffers that are no longer dirty. Returns true if any swap
+    /// files were updated.
+    pub(super) fn write_swap_files(&mut self) -> bool {
+        let pending: Vec<usize> = self
+            .layout
+            .buffers()
+            .iter()
+            .filter(|b| b.swap_pending)
+            .map(|b| b.id)
+            .collect();
+        let updated = !pending.is_empty();
+
+        for id in pending {
+            let b = match self.layout.buffer_with_id_mut(id) {
+                Some(b) => b,
+                None => continue,
+            };
+            b.swap_pending = false;
+
+            if let Some(path) = b.path().map(|p| p.to_path_buf()) {
+                if b.dirty {
+                    self.swap.write(path, b.txt.clone());
+                } else {
+                    self.swap.remove(path);
+                }
+            }
+        }
+
+        updated
+    }
+
+    /// Remove the swap files for all open buffers, waiting for the removal to complete.
+    pub(super) fn remove_swap_files(&mut self) {
+        for b in self.layout.buffers().iter() {
+            if let Some(path) = b.path() {
+                self.swap.remove(path.to_path_buf());
+            }
+        }
+        self.swap.flush();
+    }
+
+    /// Check for a stale swap file for a newly opened buffer and ask the user what to do with
+    /// it if one is found.
+    pub(super) fn check_for_swap_file(&mut self, id: usize) {
+        let (path, current) = match self.layout.buffer_with_id(id) {
+            Some(b) => match b.path() {
+                Some(p) => (p.to_path_buf(), b.str_contents()),
+                None => return,
+            },
+            None => return,
+        };
+
+        let swap = match SwapFile::read(&path) {
+            Ok(Some(swap)) => swap,
+            Ok(None) => return,
+            Err(e) => {
+                self.set_status_message(&format!("Unable to read swap file: {e}"));
+                return;
+            }
+        };
+
+        if !swap.is_stale() {
+            self.set_status_message(&format!(
+                "Warning: file is being edited by another ad session (pid {}): changes will not be journaled",
+                swap.pid
+            ));
+            return;
+        } else if swap.content == current {
+            self.swap.remove(path);
+            return;
+        }
+
+        let options = ["recover", "diff", "discard"].map(String::from).to_vec();
+        let mut diff_id = None;
+
+        let choice = loop {
+            match self.minibuffer_select_from(SWAP_PROMPT, options.clone()) {
+                MiniBufferSelection::Line { line, .. } if line == "diff" => {
+                    diff_id = Some(self.show_swap_diff(&path, &swap.content));
+                }
+                MiniBufferSelection::Line { line, .. } => break Some(line),
+                MiniBufferSelection::UserInput { input } if options.contains(&input) => {
+                    break Some(input)
+                }
+                MiniBufferSelection::UserInput { .. } => (),
+                MiniBufferSelection::Cancelled => break None,
+            }
+        };
+
+        if let Some(diff_id) = diff_id {
+            self.layout.close_buffer(diff_id);
+            self.layout.focus_id(id);
+        }
+
+        match choice.as_deref() {
+            Some("recover") => self.recover_from_swap(id, swap.content),
+            Some(_) => {
+                self.swap.remove(path);
+                self.set_status_message("Swap file discarded");
+            }
+            None => self.set_status_message(&format!(
+                "Swap file left in place: {}",
+                swap_path(&path).display()
+            )),
+        }
+    }
+
+    /// Open a virtual buffer containing the diff between the file on disk and the swap file
+    /// content, returning its id.
+    fn show_swap_diff(&mut self, path: &Path, content: &str) -> usize {
+        let id = self.active_buffer_id();
+        let diff = self
+            .system
+            .pipe_through_command(
+                "diff",
+                [&path.display().to_string(), "-"],
+                content,
+                &self.cwd,
+                id,
+            )
+            .unwrap_or_else(|e| format!("unable to run diff: {e}"));
+
+        self.layout.open_virtual("+swap-diff", diff, false);
+
+        self.active_buffer_id()
+    }
+
+    fn recover_from_swap(&mut self, id: usize, mut content: String) {
+        let b = match self.layout.buffer_with_id_mut(id) {
+            Some(b) => b,
+            None => return,
+        };
+
+        // Buffer content is written to swap files with a trailing newline
+        if content.ends_with('\n') {
+            content.pop();
+        }
+
+        for a in [
+            Action::DotSet(TextObject::BufferStart, 1),
+            Action::DotExtendForward(TextObject::BufferEnd, 1),
+            Action::InsertString { s: content },
+            Action::DotSet(TextObject::BufferStart, 1),
+        ] {
+            b.handle_action(a, Source::Keyboard);
+        }
+
+        self.set_status_message("Recovered unsaved changes: save to keep them");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+    use std::env;
+
+    #[test]
+    fn swap_files_round_trip() {
+        let raw = SwapFile::serialize(42, "foo\nbar\n");
+        let expected = SwapFile {
+            pid: 42,
+            content: "foo\nbar\n".to_string(),
+        };
+
+        assert_eq!(SwapFile::parse(&raw), Some(expected));
+    }
+
+    #[test_case("foo\nbar"; "missing header")]
+    #[test_case("ad-swap pid=abc\nbar"; "invalid pid")]
+    #[test_case("ad-swap pid=42"; "missing newline")]
+    #[test]
+    fn invalid_swap_files_are_ignored(raw: &str) {
+        assert_eq!(SwapFile::parse(raw), None);
+    }
+
+    #[test]
+    fn swap_files_from_this_process_are_stale() {
+        let swap = SwapFile {
+            pid: process::id(),
+            content: String::new(),
+        };
+
+        assert!(swap.is_stale());
+    }
+
+    #[test_case(Some(1), true; "another running session")]
+    #[test_case(Some(process::id()), false; "this session")]
+    #[test_case(None, false; "no swap file")]
+    #[test]
+    fn owned_by_another_session_works(pid: Option<u32>, expected: bool) {
+        let swap = env::temp_dir().join(format!("ad-swap-test-{}-{pid:?}.swp", process::id()));
+        if let Some(pid) = pid {
+            // pid 1 is always running
+            fs::write(&swap, SwapFile::serialize(pid, "content\n")).unwrap();
+        }
+
+        let owned = owned_by_another_session(&swap);
+        _ = fs::remove_file(&swap);
+
+        assert_eq!(owned, expected);
+    }
+
+    #[test]
+    fn swap_path_mirrors_the_file_path() {
+        let p = swap_path(Path::new("/home/me/notes.txt"));
+
+        assert!(p.ends_with(".ad/swap/home/me/notes.txt.swp"), "{p:?}");
+    }
+}
@@ -16,9 +16,10 @@
 //! e1     => set dot to e1
 //! e1,    => set dot to e1_start..=EOF
 //! e1,e2  => set dot to e1_start..=e2_end
+//! 'a     => the mark named a (or the default mark set by k for a bare ')
 //! ```
 use crate::{
-    buffer::{Buffer, GapBuffer},
+    buffer::{Buffer, GapBuffer, DEFAULT_MARK},
     dot::{Cur, Dot, Range},
     exec::char_iter::IterBoundedChars,
     regex::{self, Regex},
@@ -135,6 +136,8 @@ pub enum AddrBase {
     Regex(Regex),
     /// -/re/
     RegexBack(Regex),
+    /// 'a or '
+    Mark(char),
 }
 
 impl From<AddrBase> for SimpleAddr {
@@ -243,6 +246,17 @@ impl AddrBase {
                 parse_delimited_regex(it, dir.unwrap_or(Dir::Fwd))
             }
 
+            (Some('\''), None) => {
+                it.next();
+                match it.peek() {
+                    Some(&c) if c.is_ascii_lowercase() => {
+                        it.next();
+                        Ok(Self::Mark(c))
+                    }
+                    _ => Ok(Self::Mark(DEFAULT_MARK)),
+                }
+            }
+
             (_, Some(Dir::Fwd)) => Ok(Self::Eol),
             (_, Some(Dir::Bck)) => Ok(Self::Bol),
 
@@ -285,10 +299,20 @@ pub trait Address: IterBoundedChars {
     fn char_to_line_end(&self, char_idx: usize) -> Option<usize>;
     fn char_to_line_start(&self, char_idx: usize) -> Option<usize>;
 
+    /// The current position of the named mark if it has been set.
+    fn mark(&self, _name: char) -> Option<Dot> {
+        None
+    }
+
     fn max_iter(&self) -> usize {
         self.len_chars()
     }
 
+    /// Map the offset used in a "#n" address to a character index.
+    fn offset_to_char(&self, offset: usize) -> Option<usize> {
+        Some(offset)
+    }
+
     fn map_addr(&self, a: &mut Addr) -> Dot {
         let maybe_dot = match a {
             Addr::Explicit(d) => Some(*d),
@@ -343,7 +367,10 @@ pub trait Address: IterBoundedChars {
                 self.full_line(line_idx)?
             }
 
-            Char(idx) => Cur { idx: *idx }.into(),
+            Char(idx) => Cur {
+                idx: self.offset_to_char(*idx)?,
+            }
+            .into(),
             RelativeChar(offset) => {
                 let mut c = cur_dot.active_cur();
                 c.idx = (c.idx as isize + *offset) as usize;
@@ -369,6 +396,8 @@ pub trait Address: IterBoundedChars {
                 let (from, to) = m.loc();
                 Dot::from_char_indices(from, to.saturating_sub(1))
             }
+
+            Mark(name) => self.mark(*name)?,
         };
 
         Some(dot)
@@ -453,6 +482,19 @@ impl Address for Buffer {
         let line_idx = self.txt.try_char_to_line(char_idx)?;
         Some(self.txt.line_to_char(line_idx))
     }
+
+    fn mark(&self, name: char) -> Option<Dot> {
+        self.get_mark(name)
+    }
+
+    // Offsets in hex buffers refer to bytes in the file rather than characters in the dump
+    fn offset_to_char(&self, offset: usize) -> Option<usize> {
+        if self.kind.is_hex() {
+            self.hex_offset_to_char(offset)
+        } else {
+            Some(offset)
+        }
+    }
 }
 
 #[cfg(test)]
@@ -488,6 +530,8 @@ mod tests {
     #[test_case("/foo/", Simple(Regex(re("foo")).into()); "regex")]
     #[test_case("+/baz/", Simple(Regex(re("baz")).into()); "regex explicit forward")]
     #[test_case("-/bar/", Simple(RegexBack(Regex::compile_reverse("bar").unwrap()).into()); "regex back")]
+    #[test_case("'a", Simple(Mark('a').into()); "named mark")]
+    #[test_case("'", Simple(Mark(DEFAULT_MARK).into()); "default mark")]
     // Simple with suffix
     #[test_case(
         "#5+",
@@ -516,6 +560,7 @@ mod tests {
     #[test_case("5,9", Compound(Line(4).into(), Line(8).into()); "from n to m")]
     #[test_case("25,90", Compound(Line(24).into(), Line(89).into()); "from n to m multi digit")]
     #[test_case("/foo/,/bar/", Compound(Regex(re("foo")).into(), Regex(re("bar")).into()); "regex range")]
+    #[test_case("'a,'b", Compound(Mark('a').into(), Mark('b').into()); "mark range")]
     // Compound with suffix
     #[test_case(
         "-/\\s/+#1,/\\s/-#1",
@@ -537,6 +582,9 @@ mod tests {
     #[test_case("/something/", Dot::from_char_indices(33, 41), "something"; "regex forward")]
     #[test_case("-/line/", Dot::from_char_indices(10, 13), "line"; "regex back")]
     #[test_case("-/his/", Dot::from_char_indices(1, 3), "his"; "regex back 2")]
+    #[test_case("/(?<=\\] )\\w+/", Dot::from_char_indices(33, 41), "something"; "regex forward with lookbehind")]
+    #[test_case("-/\\w+(?= a )/", Dot::from_char_indices(5, 6), "is"; "regex back with lookahead")]
+    #[test_case("-/(?<=a )\\w+/", Dot::from_char_indices(10, 13), "line"; "regex back with lookbehind")]
     #[test_case("-/a/,/a/", Dot::from_char_indices(15, 19), "and a"; "regex range")]
     #[test_case("-/\\s/+#1,/\\s/-#1", Dot::from_char_indices(15, 17), "and"; "regex range boundaries")]
     #[test]
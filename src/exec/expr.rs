@@ -1,21 +1,24 @@
 use super::{consume_whitespace, Error};
-use crate::regex::Regex;
+use crate::{buffer::DEFAULT_MARK, regex::Regex};
 use std::{iter::Peekable, str::Chars};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(super) enum Expr {
-    LoopMatches(Regex),
-    LoopBetweenMatches(Regex),
+    LoopMatches(Box<Regex>),
+    LoopBetweenMatches(Box<Regex>),
 
-    IfContains(Regex),
-    IfNotContains(Regex),
+    IfContains(Box<Regex>),
+    IfNotContains(Box<Regex>),
 
     Insert(String),
     Append(String),
     Change(String),
-    Sub(Regex, String),
+    /// Substitute the nth match of the regex (counting from 1)
+    Sub(Box<Regex>, String, usize),
     Print(String),
     Delete,
+    /// Set the named mark to the current match (sam's "k")
+    Mark(char),
 
     Group(Vec<Vec<Expr>>),
 }
@@ -33,10 +36,10 @@ impl Expr {
 
         match it.next() {
             Some('x') => Ok(Single(LoopMatches(parse_delimited_regex(it, "x")?))),
-            Some('X') => Ok(Single(LoopMatches(Regex::compile(".*")?))),
+            Some('X') => Ok(Single(LoopMatches(Box::new(Regex::compile(".*")?)))),
 
             Some('y') => Ok(Single(LoopBetweenMatches(parse_delimited_regex(it, "y")?))),
-            Some('Y') => Ok(Single(LoopBetweenMatches(Regex::compile(".*")?))),
+            Some('Y') => Ok(Single(LoopBetweenMatches(Box::new(Regex::compile(".*")?)))),
 
             Some('g') => Ok(Single(IfContains(parse_delimited_regex(it, "g")?))),
             Some('v') => Ok(Single(IfNotContains(parse_delimited_regex(it, "v")?))),
@@ -46,6 +49,16 @@ impl Expr {
             Some('c') => Ok(Single(Change(parse_delimited_str(it, "c")?))),
             Some('s') => parse_sub(it),
             Some('d') => Ok(Single(Delete)),
+            // Named marks are given as "k'a" so that the name can't be mistaken for a command
+            Some('k') if it.peek() == Some(&'\'') => {
+                it.next();
+                match it.next() {
+                    Some(c) if c.is_ascii_lowercase() => Ok(Single(Mark(c))),
+                    Some(c) => Err(Error::UnexpectedCharacter(c)),
+                    None => Err(Error::Eof),
+                }
+            }
+            Some('k') => Ok(Single(Mark(DEFAULT_MARK))),
 
             Some('p') => Ok(Single(Print(parse_delimited_str(it, "p")?))),
             Some('P') => Ok(Single(Print("$0\n".to_string()))),
@@ -70,9 +83,12 @@ impl Expr {
     }
 }
 
-fn parse_delimited_regex(it: &mut Peekable<Chars<'_>>, kind: &'static str) -> Result<Regex, Error> {
+fn parse_delimited_regex(
+    it: &mut Peekable<Chars<'_>>,
+    kind: &'static str,
+) -> Result<Box<Regex>, Error> {
     let s = parse_delimited_str(it, kind)?;
-    Ok(Regex::compile(&s)?)
+    Ok(Box::new(Regex::compile(&s)?))
 }
 
 fn parse_delimited_str(it: &mut Peekable<Chars<'_>>, kind: &'static str) -> Result<String, Error> {
@@ -99,15 +115,30 @@ fn read_until(
     Err(Error::UnclosedDelimiter(kind, delim))
 }
 
+// As in sam, "s/re/template/" replaces the first match, "sN/re/template/" replaces the Nth
+// match and "s/re/template/g" replaces all matches.
 fn parse_sub(it: &mut Peekable<Chars<'_>>) -> Result<ParseOutput, Error> {
+    let mut n = None;
+    while let Some(d) = it.peek().and_then(|ch| ch.to_digit(10)) {
+        it.next();
+        n = Some(n.unwrap_or(0) * 10 + d as usize);
+    }
+
+    if n == Some(0) {
+        return Err(Error::InvalidOccurrence(0));
+    }
+
     let delim = it.next().ok_or(Error::MissingDelimiter("s"))?;
-    let re = Regex::compile(&read_until(delim, it, "s")?)?;
+    let re = Box::new(Regex::compile(&read_until(delim, it, "s")?)?);
     let s = read_until(delim, it, "s")?;
-    if let Some('g') = it.peek() {
-        it.next();
-        Ok(ParseOutput::Pair(Expr::LoopMatches(re), Expr::Change(s)))
-    } else {
-        Ok(ParseOutput::Single(Expr::Sub(re, s)))
+
+    match (it.peek(), n) {
+        (Some('g'), None) => {
+            it.next();
+            Ok(ParseOutput::Pair(Expr::LoopMatches(re), Expr::Change(s)))
+        }
+        (Some('g'), Some(_)) => Err(Error::InvalidSuffix),
+        (_, n) => Ok(ParseOutput::Single(Expr::Sub(re, s, n.unwrap_or(1)))),
     }
 }
 
@@ -160,8 +191,8 @@ mod tests {
     use simple_test_case::test_case;
     use Expr::*;
 
-    fn re(s: &str) -> Regex {
-        Regex::compile(s).unwrap()
+    fn re(s: &str) -> Box<Regex> {
+        Box::new(Regex::compile(s).unwrap())
     }
 
     fn s(e: Expr) -> ParseOutput {
@@ -179,11 +210,15 @@ mod tests {
     #[test_case("i/foo/", s(Insert("foo".to_string())); "insert")]
     #[test_case("a/foo/", s(Append("foo".to_string())); "append")]
     #[test_case("c/foo/", s(Change("foo".to_string())); "change")]
-    #[test_case("s/.*/foo/", s(Sub(re(".*"), "foo".to_string())); "substitute")]
+    #[test_case("s/.*/foo/", s(Sub(re(".*"), "foo".to_string(), 1)); "substitute")]
+    #[test_case("s2/.*/foo/", s(Sub(re(".*"), "foo".to_string(), 2)); "substitute nth")]
+    #[test_case("s12/.*/foo/", s(Sub(re(".*"), "foo".to_string(), 12)); "substitute multi digit nth")]
     #[test_case("s/.*/foo/g", p(LoopMatches(re(".*")), Change("foo".to_string())); "substitute all")]
     #[test_case("p/$0/", s(Print("$0".to_string())); "print")]
     #[test_case("P", s(Print("$0\n".to_string())); "print full match")]
     #[test_case("d", s(Delete); "delete")]
+    #[test_case("k", s(Mark(DEFAULT_MARK)); "default mark")]
+    #[test_case("k'a", s(Mark('a')); "named mark")]
     #[test_case(
         "{P; g/bar/ a/foo/;}",
         s(Group(vec![
@@ -197,4 +232,12 @@ mod tests {
         let a = Expr::try_parse(&mut input.chars().peekable()).expect("valid input");
         assert_eq!(a, expected);
     }
+
+    #[test_case("s0/.*/foo/", Error::InvalidOccurrence(0); "zero occurrence")]
+    #[test_case("s2/.*/foo/g", Error::InvalidSuffix; "nth with global")]
+    #[test]
+    fn parse_sub_errors_correctly(input: &str, expected: Error) {
+        let res = Expr::try_parse(&mut input.chars().peekable());
+        assert_eq!(res, Err(expected));
+    }
 }
@@ -3,7 +3,7 @@ use crate::{
     buffer::{Buffer, GapBuffer},
     dot::{Cur, Dot},
     editor::Action,
-    regex::{self, Match},
+    regex::{self, Match, Regex},
 };
 use ad_event::Source;
 use std::{cmp::min, io::Write, iter::Peekable, str::Chars};
@@ -38,6 +38,10 @@ pub enum Error {
     InvalidRegex(regex::Error),
     /// Invalid substitution
     InvalidSubstitution(usize),
+    /// Invalid named substitution
+    InvalidNamedSubstitution(String),
+    /// Invalid substitution occurrence (these start from 1)
+    InvalidOccurrence(usize),
     /// Invalid suffix
     InvalidSuffix,
     /// Missing action
@@ -68,12 +72,21 @@ pub trait Edit: Address {
         Some(self.iter_between(from, to).map(|(_, ch)| ch).collect())
     }
 
+    /// Extract the content of a previous named submatch so it can be used in templating
+    fn named_submatch(&self, m: &Match, name: &str) -> Option<String> {
+        let (from, to) = m.sub_loc_by_name(name)?;
+        Some(self.iter_between(from, to).map(|(_, ch)| ch).collect())
+    }
+
     /// Insert a string at the specified index
     fn insert(&mut self, ix: usize, s: &str);
 
     /// Remove all characters from (from..to)
     fn remove(&mut self, from: usize, to: usize);
 
+    /// Set the named mark to the given dot
+    fn set_mark(&mut self, _name: char, _dot: Dot) {}
+
     /// Mark the start of an edit transaction
     fn begin_edit_transaction(&mut self) {}
 
@@ -105,6 +118,10 @@ impl Edit for Buffer {
         self.handle_action(Action::Delete, Source::Fsys);
     }
 
+    fn set_mark(&mut self, name: char, dot: Dot) {
+        Buffer::set_mark(self, name, dot);
+    }
+
     fn begin_edit_transaction(&mut self) {
         self.new_edit_log_transaction()
     }
@@ -239,7 +256,7 @@ impl Program {
 
             Expr::LoopMatches(mut re) => {
                 let mut initial_matches = Vec::new();
-                while let Some(m) = re.match_iter(&mut ed.iter_between(from, to), from) {
+                while let Some(m) = next_match(&mut re, ed, from, to, !initial_matches.is_empty()) {
                     // It's possible for the Regex we're using to match a 0-length string which
                     // would cause us to get stuck trying to advance to the next match position.
                     // If this happens we advance from by a character to ensure that we search
@@ -262,8 +279,10 @@ impl Program {
 
             Expr::LoopBetweenMatches(mut re) => {
                 let mut initial_matches = Vec::new();
+                let mut resume = false;
 
-                while let Some(m) = re.match_iter(&mut ed.iter_between(from, to), from) {
+                while let Some(m) = next_match(&mut re, ed, from, to, resume) {
+                    resume = true;
                     let (new_from, new_to) = m.loc();
                     if from < new_from {
                         initial_matches.push(Match::synthetic(from, new_from));
@@ -327,7 +346,13 @@ impl Program {
                 Ok(Dot::from_char_indices(from, from))
             }
 
-            Expr::Sub(mut re, pat) => match re.match_iter(&mut ed.iter_between(from, to), from) {
+            Expr::Mark(name) => {
+                let dot = Dot::from_char_indices(from, to.saturating_sub(1).max(from));
+                ed.set_mark(name, dot.collapse_null_range());
+                Ok(Dot::from_char_indices(from, to))
+            }
+
+            Expr::Sub(mut re, pat, n) => match nth_match(&mut re, n, ed, from, to) {
                 Some(m) => {
                     let (mfrom, mto) = m.loc();
                     let s = template_match(&pat, &m, ed, fname)?;
@@ -406,7 +431,7 @@ fn validate(exprs: &[Expr]) -> Result<(), Error> {
     // Must end with an action
     if !matches!(
         exprs[exprs.len() - 1],
-        Group(_) | Insert(_) | Append(_) | Change(_) | Sub(_, _) | Print(_) | Delete
+        Group(_) | Insert(_) | Append(_) | Change(_) | Sub(_, _, _) | Print(_) | Delete | Mark(_)
     ) {
         return Err(Error::MissingAction);
     }
@@ -414,33 +439,153 @@ fn validate(exprs: &[Expr]) -> Result<(), Error> {
     Ok(())
 }
 
-// FIXME: if a previous sub-match replacement injects a valid var name for a subsequent one
-// then we end up attempting to template THAT in a later iteration of the loop.
-fn template_match<E>(s: &str, m: &Match, ed: &E, fname: &str) -> Result<String, Error>
+/// Find the next match of re within from..to, resuming from the previous match if this is not
+/// the first search of a loop so that lookbehinds are able to see the earlier input.
+fn next_match<E>(re: &mut Regex, ed: &E, from: usize, to: usize, resume: bool) -> Option<Match>
 where
     E: Edit,
 {
-    let mut output = if s.contains(FNAME_VAR) {
-        s.replace(FNAME_VAR, fname)
-    } else {
-        s.to_string()
-    };
-
-    // replace newline and tab escapes with their literal equivalents
-    output = output.replace("\\n", "\n").replace("\\t", "\t");
-
-    let vars = ["$0", "$1", "$2", "$3", "$4", "$5", "$6", "$7", "$8", "$9"];
-    for (n, var) in vars.iter().enumerate() {
-        if !s.contains(var) {
-            continue;
+    let mut it = ed.iter_between(from, to);
+    match resume {
+        true => re.resume_match_iter(&mut it, from),
+        false => re.match_iter(&mut it, from),
+    }
+}
+
+/// Find the nth (1-based) match of re within from..to
+fn nth_match<E>(re: &mut Regex, n: usize, ed: &E, mut from: usize, to: usize) -> Option<Match>
+where
+    E: Edit,
+{
+    let mut seen = 0;
+
+    while let Some(m) = next_match(re, ed, from, to, seen > 0) {
+        seen += 1;
+        if seen == n {
+            return Some(m);
+        }
+
+        // As with x// loops we need to step past 0-length matches to avoid getting stuck
+        let (mfrom, mto) = m.loc();
+        from = if mfrom == mto { mto + 1 } else { mto };
+
+        if from >= to || from >= ed.max_iter() {
+            break;
         }
-        match ed.submatch(m, n) {
-            Some(sm) => output = output.replace(var, &sm.to_string()),
-            None => return Err(Error::InvalidSubstitution(n)),
+    }
+
+    None
+}
+
+/// Case conversion requested by an escape in a template
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Case {
+    Upper,
+    Lower,
+}
+
+impl Case {
+    fn push(&self, ch: char, s: &mut String) {
+        match self {
+            Case::Upper => s.extend(ch.to_uppercase()),
+            Case::Lower => s.extend(ch.to_lowercase()),
+        }
+    }
+}
+
+/// The rendered output of a template along with any pending case conversions
+#[derive(Debug, Default)]
+struct TemplateOutput {
+    s: String,
+    /// Set by \u and \l: applies to the next character only
+    next: Option<Case>,
+    /// Set by \U and \L: applies until \E or the end of the template
+    span: Option<Case>,
+}
+
+impl TemplateOutput {
+    fn push(&mut self, ch: char) {
+        match self.next.take().or(self.span) {
+            Some(case) => case.push(ch, &mut self.s),
+            None => self.s.push(ch),
+        }
+    }
+
+    fn push_str(&mut self, s: &str) {
+        for ch in s.chars() {
+            self.push(ch);
+        }
+    }
+}
+
+/// Render a template for the given match.
+///
+/// Templates support the following:
+///   - $0 to $9 for numbered submatches (or ${n} for any submatch)
+///   - ${name} for named submatches
+///   - $FILENAME for the name of the file being edited
+///   - \n and \t for newlines and tabs
+///   - \u and \l to upper or lower case the next character
+///   - \U and \L to upper or lower case everything up until \E
+fn template_match<E>(s: &str, m: &Match, ed: &E, fname: &str) -> Result<String, Error>
+where
+    E: Edit,
+{
+    let mut output = TemplateOutput::default();
+    let mut it = s.char_indices().peekable();
+
+    while let Some((i, ch)) = it.next() {
+        match (ch, it.peek().map(|&(_, c)| c)) {
+            ('\\', Some(c @ ('n' | 't' | 'u' | 'l' | 'U' | 'L' | 'E'))) => {
+                it.next();
+                match c {
+                    'n' => output.push('\n'),
+                    't' => output.push('\t'),
+                    'u' => output.next = Some(Case::Upper),
+                    'l' => output.next = Some(Case::Lower),
+                    'U' => output.span = Some(Case::Upper),
+                    'L' => output.span = Some(Case::Lower),
+                    _ => output.span = None,
+                }
+            }
+
+            ('$', Some(c)) if c.is_ascii_digit() => {
+                it.next();
+                let n = c as usize - '0' as usize;
+                let sm = ed.submatch(m, n).ok_or(Error::InvalidSubstitution(n))?;
+                output.push_str(&sm);
+            }
+
+            ('$', Some('{')) => {
+                it.next();
+                let mut var = String::new();
+                loop {
+                    match it.next() {
+                        Some((_, '}')) => break,
+                        Some((_, c)) => var.push(c),
+                        None => return Err(Error::UnclosedDelimiter("template", '}')),
+                    }
+                }
+
+                let sm = match var.parse::<usize>() {
+                    Ok(n) => ed.submatch(m, n).ok_or(Error::InvalidSubstitution(n))?,
+                    Err(_) => ed
+                        .named_submatch(m, &var)
+                        .ok_or(Error::InvalidNamedSubstitution(var))?,
+                };
+                output.push_str(&sm);
+            }
+
+            ('$', _) if s[i..].starts_with(FNAME_VAR) => {
+                it.nth(FNAME_VAR.len() - 2);
+                output.push_str(fname);
+            }
+
+            (ch, _) => output.push(ch),
         }
     }
 
-    Ok(output)
+    Ok(output.s)
 }
 
 #[cfg(test)]
@@ -450,12 +595,12 @@ mod tests {
     use simple_test_case::test_case;
     use Expr::*;
 
-    fn re(s: &str) -> Regex {
-        Regex::compile(s).unwrap()
+    fn re(s: &str) -> Box<Regex> {
+        Box::new(Regex::compile(s).unwrap())
     }
 
     #[test_case(", p/$0/", vec![Print("$0".to_string())]; "print all")]
-    #[test_case(", x/^.*$/ s/foo/bar/", vec![LoopMatches(re("^.*$")), Sub(re("foo"), "bar".to_string())]; "simple loop")]
+    #[test_case(", x/^.*$/ s/foo/bar/", vec![LoopMatches(re("^.*$")), Sub(re("foo"), "bar".to_string(), 1)]; "simple loop")]
     #[test_case(", x/^.*$/ g/emacs/ d", vec![LoopMatches(re("^.*$")), IfContains(re("emacs")), Delete]; "loop filter")]
     #[test]
     fn parse_program_works(s: &str, expected: Vec<Expr>) {
@@ -481,7 +626,9 @@ mod tests {
     #[test_case(vec![Append("X".to_string())], "foo foo fooX", (0, 12); "append")]
     #[test_case(vec![Change("X".to_string())], "X", (0, 1); "change")]
     #[test_case(vec![Delete], "", (0, 0); "delete")]
-    #[test_case(vec![Sub(re("oo"), "X".to_string())], "fX foo foo", (0, 10); "sub single")]
+    #[test_case(vec![Sub(re("oo"), "X".to_string(), 1)], "fX foo foo", (0, 10); "sub single")]
+    #[test_case(vec![Sub(re("oo"), "X".to_string(), 2)], "foo fX foo", (0, 10); "sub nth")]
+    #[test_case(vec![Sub(re("oo"), "X".to_string(), 4)], "foo foo foo", (0, 11); "sub nth missing")]
     #[test_case(vec![LoopMatches(re("foo")), Delete], "  ", (2, 2); "loop delete")]
     #[test_case(vec![LoopBetweenMatches(re("foo")), Delete], "foofoofoo", (6, 6); "loop between delete")]
     #[test_case(vec![LoopMatches(re("foo")), Append("X".to_string())], "fooX fooX fooX", (10, 14); "loop change")]
@@ -504,6 +651,15 @@ mod tests {
     #[test_case(", x/(t.)/ c/$1X/", "thXis is a teXst XstrXing"; "x c")]
     #[test_case(", x/(t.)/ i/$1/", "ththis is a tetest t strtring"; "x i")]
     #[test_case(", x/(t.)/ a/$1/", "ththis is a tetest t strtring"; "x a")]
+    #[test_case(", x/(t.)/ c/\\U$1/", "THis is a TEsT sTRing"; "upper case")]
+    #[test_case(", x/(t.)/ c/\\u$1/", "This is a TesT sTring"; "upper case first")]
+    #[test_case(", x/(\\w+)/ c/\\L\\u$1/", "This Is A Test String"; "title case")]
+    #[test_case(", x/(\\w+)/ c/\\U$1\\E!/", "THIS! IS! A! TEST! STRING!"; "upper case until end")]
+    #[test_case(", x/(?<first>t.)/ c/${first}X/", "thXis is a teXst XstrXing"; "named submatch")]
+    #[test_case(", x/(t.)/ c/${1}1/", "th1is is a te1st 1str1ing"; "braced numbered submatch")]
+    #[test_case(", s2/t/X/", "this is a Xest string"; "nth substitution")]
+    #[test_case(", x/(?<=\\w)\\w/ c/X/", "tXXX iX a tXXX sXXXXX"; "lookbehind across loop matches")]
+    #[test_case(", s/(t)(\\w+)/$2$1/", "hist is a test string"; "reorder submatches")]
     #[test]
     fn substitution_of_submatches_works(s: &str, expected: &str) {
         let mut prog = Program::try_parse(s).unwrap();
@@ -513,6 +669,27 @@ mod tests {
         assert_eq!(&b.txt.to_string(), expected);
     }
 
+    #[test_case(", x/(t.)/ c/$2/", Error::InvalidSubstitution(2); "missing numbered")]
+    #[test_case(", x/(t.)/ c/${foo}/", Error::InvalidNamedSubstitution("foo".to_string()); "missing named")]
+    #[test_case(", x/(t.)/ c/${1/", Error::UnclosedDelimiter("template", '}'); "unclosed named")]
+    #[test]
+    fn invalid_substitutions_error(s: &str, expected: Error) {
+        let mut prog = Program::try_parse(s).unwrap();
+
+        let mut b = Buffer::new_unnamed(0, "this is a test string");
+        let res = prog.execute(&mut b, "test", &mut vec![]);
+        assert_eq!(res, Err(expected));
+    }
+
+    #[test]
+    fn filename_is_templated() {
+        let mut prog = Program::try_parse(", x/test/ c/$FILENAME $x/").unwrap();
+        let mut b = Buffer::new_unnamed(0, "a test");
+        prog.execute(&mut b, "foo.txt", &mut vec![]).unwrap();
+
+        assert_eq!(&b.txt.to_string(), "a foo.txt $x");
+    }
+
     #[test]
     fn loop_between_generates_the_correct_blocks() {
         let mut prog = Program::try_parse(", y/ / p/>$0<\n/").unwrap();
@@ -601,6 +778,34 @@ mod tests {
         assert_eq!(&final_content, initial_content);
     }
 
+    #[test]
+    fn marks_can_be_set_and_used_as_addresses() {
+        let mut b = Buffer::new_unnamed(0, "one two three four");
+        Program::try_parse("/two/ k'a")
+            .unwrap()
+            .execute(&mut b, "test", &mut vec![])
+            .unwrap();
+        Program::try_parse("/three/ k'b")
+            .unwrap()
+            .execute(&mut b, "test", &mut vec![])
+            .unwrap();
+
+        // Inserting before the marks shifts them to continue to refer to the same text
+        b.dot = Cur::new(0).into();
+        b.handle_action(
+            Action::InsertString {
+                s: "zero ".to_string(),
+            },
+            Source::Keyboard,
+        );
+        Program::try_parse("'a,'b d")
+            .unwrap()
+            .execute(&mut b, "test", &mut vec![])
+            .unwrap();
+
+        assert_eq!(&b.txt.to_string(), "zero one  four");
+    }
+
     // regression test: gh#30
     #[test]
     fn regression_edit_landing_on_gap_end() {
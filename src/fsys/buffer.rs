@@ -5,7 +5,8 @@ use crate::{
         event::{run_threaded_input_listener, send_event_to_editor, InputFilter, InputRequest},
         log::{Log, LogEvent},
         InternalRead, Message, Req, Result, BUFFERS_DIR, BUFFERS_QID, CURRENT_BUFFER,
-        CURRENT_BUFFER_QID, E_UNKNOWN_FILE, INDEX_BUFFER, INDEX_BUFFER_QID, QID_OFFSET,
+        CURRENT_BUFFER_QID, E_UNKNOWN_FILE, FIRST_BUFFER_QID, INDEX_BUFFER, INDEX_BUFFER_QID,
+        QID_OFFSET,
     },
     input::Event,
 };
@@ -38,11 +39,11 @@ pub(super) const BUFFER_FILES: [(u64, &str); QID_OFFSET as usize - 1] = [
 ];
 
 fn parent_and_fname(qid: u64) -> (u64, &'static str) {
-    assert!(qid > CURRENT_BUFFER_QID, "invalid buffer file qid");
+    assert!(qid > FIRST_BUFFER_QID, "invalid buffer file qid");
 
-    let (cur, off) = (CURRENT_BUFFER_QID, QID_OFFSET);
-    let parent = cur + 1 + ((qid - cur - 1) / off) * off;
-    let fname = BUFFER_FILES[((qid - cur - 2) % off) as usize].1;
+    let (first, off) = (FIRST_BUFFER_QID, QID_OFFSET);
+    let parent = first + ((qid - first) / off) * off;
+    let fname = BUFFER_FILES[((qid - first - 1) % off) as usize].1;
 
     (parent, fname)
 }
@@ -76,7 +77,7 @@ impl BufferNodes {
         Self {
             known: BTreeMap::default(),
             log: Log::new(log_tx),
-            next_qid: CURRENT_BUFFER_QID + 1,
+            next_qid: FIRST_BUFFER_QID,
             current_buffid: 1,
             stat: empty_dir_stat(BUFFERS_QID, BUFFERS_DIR),
             current_buff_stat: empty_file_stat(CURRENT_BUFFER_QID, CURRENT_BUFFER),
@@ -366,7 +367,7 @@ impl BufferNode {
         trace!(id=%self.id, %fname, "refreshing file stat");
         let content = self.current_file_content_as_string(fname, tx)?;
         let stat = self.file_stats.get_mut(fname)?;
-        stat.n_bytes = content.as_bytes().len() as u64;
+        stat.n_bytes = content.len() as u64;
 
         Some(stat.clone())
     }
@@ -473,9 +474,9 @@ mod tests {
     use super::*;
     use simple_test_case::test_case;
 
-    #[test_case(CURRENT_BUFFER_QID + 1 + 1, CURRENT_BUFFER_QID + 1, FILENAME; "filename first buffer")]
-    #[test_case(9, 7, DOT; "dot second buffer")]
-    #[test_case(22, 16, BODY; "body second buffer")]
+    #[test_case(FIRST_BUFFER_QID + 1, FIRST_BUFFER_QID, FILENAME; "filename first buffer")]
+    #[test_case(FIRST_BUFFER_QID + 2, FIRST_BUFFER_QID, DOT; "dot first buffer")]
+    #[test_case(FIRST_BUFFER_QID + QID_OFFSET + 6, FIRST_BUFFER_QID + QID_OFFSET, BODY; "body second buffer")]
     #[test]
     fn parent_and_fname_works(qid: u64, parent: u64, fname: &str) {
         let (p, f) = parent_and_fname(qid);
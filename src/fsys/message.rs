@@ -101,4 +101,14 @@ pub enum Req {
         id: usize,
         txt: String,
     },
+    ReadRegister {
+        r: char,
+    },
+    AppendRegister {
+        r: char,
+        s: String,
+    },
+    ClearRegister {
+        r: char,
+    },
 }
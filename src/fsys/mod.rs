@@ -19,6 +19,10 @@
 //!   ctl
 //!   minibuffer
 //!   log
+//!   registers/
+//!     a-z
+//!     search
+//!     edit
 //!   buffers/
 //!     [n]/
 //!       filename
@@ -50,6 +54,7 @@ mod buffer;
 mod event;
 mod log;
 mod message;
+mod registers;
 
 pub(crate) use event::InputFilter;
 pub(crate) use log::LogEvent;
@@ -57,6 +62,7 @@ pub(crate) use message::{Message, Req};
 
 use buffer::{BufferNodes, QidCheck};
 use log::spawn_log_listener;
+use registers::{RegisterNodes, N_REGISTER_FILES};
 
 const DEFAULT_SOCKET_NAME: &str = "ad";
 const MOUNT_DIR: &str = ".ad/mnt";
@@ -83,6 +89,12 @@ const INDEX_BUFFER: &str = "index";
 //    6      /current   -> the fsys filename of the current buffer
 const CURRENT_BUFFER_QID: u64 = 6;
 const CURRENT_BUFFER: &str = "current";
+///   7    /registers/  -> parent directory for the contents of registers
+const REGISTERS_QID: u64 = 7;
+const REGISTERS_DIR: &str = "registers";
+//    8-35   /[a-z]     -> the named registers along with the read only search and edit registers
+/// The qid of the first buffer directory
+const FIRST_BUFFER_QID: u64 = REGISTERS_QID + N_REGISTER_FILES + 1;
 
 /// The number of qids required to serve both the directory and contents
 /// of a buffer node (used to generate qid values for buffers):
@@ -98,7 +110,7 @@ const CURRENT_BUFFER: &str = "current";
 ///   9.   output       -> Write only output connected to stdout/err of commands run within the buffer
 const QID_OFFSET: u64 = 9;
 
-const TOP_LEVEL_QIDS: [u64; 7] = [
+const TOP_LEVEL_QIDS: [u64; 8] = [
     MOUNT_ROOT_QID,
     CONTROL_FILE_QID,
     MINIBUFFER_QID,
@@ -106,6 +118,7 @@ const TOP_LEVEL_QIDS: [u64; 7] = [
     BUFFERS_QID,
     INDEX_BUFFER_QID,
     CURRENT_BUFFER_QID,
+    REGISTERS_QID,
 ];
 
 const E_UNKNOWN_FILE: &str = "unknown file";
@@ -146,6 +159,7 @@ enum MiniBufferContent {
 pub(crate) struct AdFs {
     tx: Sender<Event>,
     buffer_nodes: BufferNodes,
+    register_nodes: RegisterNodes,
     minibuffer_content: MiniBufferContent,
     minibuffer_prompt: Option<String>,
     /// map of qids to client IDs with that qid open
@@ -188,11 +202,13 @@ impl AdFs {
         spawn_log_listener(brx, listener_tx, log_rx);
 
         let buffer_nodes = BufferNodes::new(tx.clone(), listener_rx, log_tx);
+        let register_nodes = RegisterNodes::new(tx.clone());
         let auto_mount = config_handle!().auto_mount;
 
         Self {
             tx,
             buffer_nodes,
+            register_nodes,
             open_cids: HashMap::new(),
             minibuffer_content: MiniBufferContent::Data(Vec::new()),
             minibuffer_prompt: None,
@@ -382,6 +398,10 @@ impl Serve9p for AdFs {
             MINIBUFFER_QID => Ok(self.minibuffer_stat.clone()),
             LOG_FILE_QID => Ok(self.log_file_stat.clone()),
             BUFFERS_QID => Ok(self.buffer_nodes.stat().clone()),
+            qid if self.register_nodes.is_register_qid(qid) => self
+                .register_nodes
+                .get_stat_for_qid(qid)
+                .ok_or_else(|| E_UNKNOWN_FILE.to_string()),
             qid => match self.buffer_nodes.get_stat_for_qid(qid) {
                 Some(stat) => Ok(stat.clone()),
                 None => Err(E_UNKNOWN_FILE.to_string()),
@@ -397,6 +417,9 @@ impl Serve9p for AdFs {
             trace!(%qid, %uname, "stat n_bytes=0, truncating file");
             match qid {
                 MOUNT_ROOT_QID | CONTROL_FILE_QID | MINIBUFFER_QID | LOG_FILE_QID => (),
+                qid if self.register_nodes.is_register_qid(qid) => {
+                    self.register_nodes.truncate(qid)
+                }
                 qid => self.buffer_nodes.truncate(qid),
             }
         }
@@ -420,12 +443,18 @@ impl Serve9p for AdFs {
                 MINIBUFFER => Ok(self.minibuffer_stat.fm.clone()),
                 LOG_FILE => Ok(self.log_file_stat.fm.clone()),
                 BUFFERS_DIR => Ok(self.buffer_nodes.stat().fm.clone()),
+                REGISTERS_DIR => Ok(self.register_nodes.stat().fm.clone()),
                 _ => match self.buffer_nodes.lookup_file_stat(parent_qid, child) {
                     Some(stat) => Ok(stat.fm.clone()),
                     None => Err(format!("{E_UNKNOWN_FILE}: {parent_qid} {child}")),
                 },
             },
 
+            REGISTERS_QID => match self.register_nodes.lookup_file_stat(child) {
+                Some(stat) => Ok(stat.fm.clone()),
+                None => Err(format!("{E_UNKNOWN_FILE}: {parent_qid} {child}")),
+            },
+
             qid if qid == BUFFERS_QID || self.buffer_nodes.is_known_buffer_qid(qid) => {
                 match self.buffer_nodes.lookup_file_stat(qid, child) {
                     Some(stat) => Ok(stat.fm.clone()),
@@ -443,7 +472,7 @@ impl Serve9p for AdFs {
 
         if qid == LOG_FILE_QID {
             self.buffer_nodes.log.add_client(cid);
-        } else if !TOP_LEVEL_QIDS.contains(&qid) {
+        } else if !TOP_LEVEL_QIDS.contains(&qid) && !self.register_nodes.is_register_qid(qid) {
             if let QidCheck::Unknown = self.buffer_nodes.check_if_known_qid(qid) {
                 return Err(format!("{E_UNKNOWN_FILE}: {qid}"));
             }
@@ -484,6 +513,11 @@ impl Serve9p for AdFs {
             return Ok(self.minibuffer_read(offset, count));
         } else if qid == LOG_FILE_QID {
             return Ok(self.buffer_nodes.log.events_since_last_read(cid));
+        } else if self.register_nodes.is_register_qid(qid) {
+            return match self.register_nodes.get_file_content(qid, offset, count) {
+                InternalRead::Immediate(content) => Ok(ReadOutcome::Immediate(content)),
+                _ => Err(format!("{E_UNKNOWN_FILE}: {qid}")),
+            };
         }
 
         if let QidCheck::EventFile { buf_qid } = self.buffer_nodes.check_if_known_qid(qid) {
@@ -515,8 +549,10 @@ impl Serve9p for AdFs {
                 self.minibuffer_stat.clone(),
                 self.control_file_stat.clone(),
                 self.buffer_nodes.stat().clone(),
+                self.register_nodes.stat(),
             ]),
             BUFFERS_QID => Ok(self.buffer_nodes.top_level_stats()),
+            REGISTERS_QID => Ok(self.register_nodes.file_stats()),
             qid => self
                 .buffer_nodes
                 .buffer_level_stats(qid)
@@ -559,7 +595,8 @@ impl Serve9p for AdFs {
             MINIBUFFER_QID => self.minibuffer_write(s),
             CURRENT_BUFFER_QID => self.set_active_buffer(s),
 
-            LOG_FILE_QID | INDEX_BUFFER_QID => Err(E_NOT_ALLOWED.to_string()),
+            LOG_FILE_QID | INDEX_BUFFER_QID | REGISTERS_QID => Err(E_NOT_ALLOWED.to_string()),
+            qid if self.register_nodes.is_register_qid(qid) => self.register_nodes.write(qid, s),
 
             qid => self.buffer_nodes.write(qid, s, offset),
         }
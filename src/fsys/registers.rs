@@ -0,0 +1,161 @@
+//! Register state for the fuse filesystem
+//!
+//! Each of the named registers a-z is exposed as a file of the same name along with the read
+//! only "search" and "edit" files holding the last search and Edit command that were run.
+//! Writing to a register appends to its content and truncating the file clears it.
+use crate::{
+    fsys::{
+        apply_offset, empty_dir_stat, empty_file_stat, InternalRead, Message, Req, Result,
+        E_NOT_ALLOWED, E_UNKNOWN_FILE, REGISTERS_DIR, REGISTERS_QID,
+    },
+    input::Event,
+};
+use ninep::fs::Stat;
+use std::{collections::BTreeMap, sync::mpsc::Sender, time::SystemTime};
+
+const SEARCH: &str = "search";
+const EDIT: &str = "edit";
+
+/// The number of register files (a-z along with search and edit)
+pub(super) const N_REGISTER_FILES: u64 = 28;
+
+/// The register and file name for the given register file qid
+fn register_and_fname(qid: u64) -> Option<(char, String)> {
+    match qid.checked_sub(REGISTERS_QID + 1)? {
+        n @ 0..=25 => {
+            let r = (b'a' + n as u8) as char;
+            Some((r, r.to_string()))
+        }
+        26 => Some(('/', SEARCH.to_string())),
+        27 => Some((':', EDIT.to_string())),
+        _ => None,
+    }
+}
+
+#[derive(Debug)]
+pub(super) struct RegisterNodes {
+    stat: Stat,
+    file_stats: BTreeMap<u64, (char, Stat)>,
+    tx: Sender<Event>,
+}
+
+impl RegisterNodes {
+    pub(super) fn new(tx: Sender<Event>) -> Self {
+        let file_stats = (REGISTERS_QID + 1..=REGISTERS_QID + N_REGISTER_FILES)
+            .filter_map(|qid| {
+                let (r, fname) = register_and_fname(qid)?;
+                Some((qid, (r, empty_file_stat(qid, &fname))))
+            })
+            .collect();
+
+        Self {
+            stat: empty_dir_stat(REGISTERS_QID, REGISTERS_DIR),
+            file_stats,
+            tx,
+        }
+    }
+
+    pub(super) fn stat(&self) -> Stat {
+        self.stat.clone()
+    }
+
+    pub(super) fn is_register_qid(&self, qid: u64) -> bool {
+        qid == REGISTERS_QID || self.file_stats.contains_key(&qid)
+    }
+
+    pub(super) fn file_stats(&mut self) -> Vec<Stat> {
+        let qids: Vec<u64> = self.file_stats.keys().copied().collect();
+
+        qids.into_iter()
+            .filter_map(|qid| self.refreshed_file_stat(qid))
+            .collect()
+    }
+
+    pub(super) fn lookup_file_stat(&mut self, name: &str) -> Option<Stat> {
+        let qid = self
+            .file_stats
+            .iter()
+            .find(|(_, (_, s))| s.fm.name == name)
+            .map(|(&qid, _)| qid)?;
+
+        self.refreshed_file_stat(qid)
+    }
+
+    pub(super) fn get_stat_for_qid(&mut self, qid: u64) -> Option<Stat> {
+        if qid == REGISTERS_QID {
+            return Some(self.stat());
+        }
+
+        self.refreshed_file_stat(qid)
+    }
+
+    fn refreshed_file_stat(&mut self, qid: u64) -> Option<Stat> {
+        let (r, stat) = self.file_stats.get_mut(&qid)?;
+        if let Ok(s) = Message::send(Req::ReadRegister { r: *r }, &self.tx) {
+            stat.n_bytes = s.len() as u64;
+        }
+
+        Some(stat.clone())
+    }
+
+    pub(super) fn get_file_content(
+        &mut self,
+        qid: u64,
+        offset: usize,
+        count: usize,
+    ) -> InternalRead {
+        let r = match self.file_stats.get(&qid) {
+            Some((r, _)) => *r,
+            None => return InternalRead::Unknown,
+        };
+
+        match Message::send(Req::ReadRegister { r }, &self.tx) {
+            Ok(s) => InternalRead::Immediate(apply_offset(s.as_bytes(), offset, count)),
+            Err(_) => InternalRead::Unknown,
+        }
+    }
+
+    pub(super) fn truncate(&mut self, qid: u64) {
+        if let Some((r, stat)) = self.file_stats.get_mut(&qid) {
+            if r.is_ascii_lowercase() {
+                stat.last_modified = SystemTime::now();
+                stat.n_bytes = 0;
+                _ = Message::send(Req::ClearRegister { r: *r }, &self.tx);
+            }
+        }
+    }
+
+    pub(super) fn write(&mut self, qid: u64, s: String) -> Result<usize> {
+        let (r, stat) = match self.file_stats.get_mut(&qid) {
+            Some((r, _)) if !r.is_ascii_lowercase() => return Err(E_NOT_ALLOWED.to_string()),
+            Some((r, stat)) => (*r, stat),
+            None => return Err(E_UNKNOWN_FILE.to_string()),
+        };
+        stat.last_modified = SystemTime::now();
+
+        let n_bytes = s.len();
+        match Message::send(Req::AppendRegister { r, s }, &self.tx) {
+            Ok(_) => Ok(n_bytes),
+            Err(e) => Err(format!("unable to write to register: {e}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    #[test_case(REGISTERS_QID + 1, Some(('a', "a")); "first register")]
+    #[test_case(REGISTERS_QID + 26, Some(('z', "z")); "last named register")]
+    #[test_case(REGISTERS_QID + 27, Some(('/', SEARCH)); "search")]
+    #[test_case(REGISTERS_QID + 28, Some((':', EDIT)); "edit")]
+    #[test_case(REGISTERS_QID, None; "registers directory")]
+    #[test_case(REGISTERS_QID + 29, None; "past the end")]
+    #[test]
+    fn register_and_fname_works(qid: u64, expected: Option<(char, &str)>) {
+        let res = register_and_fname(qid);
+
+        assert_eq!(res.as_ref().map(|(r, s)| (*r, s.as_str())), expected);
+    }
+}
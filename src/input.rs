@@ -1,9 +1,11 @@
 //! Fetching and parsing input from the user
 use crate::{
+    buffer::FileChunk,
     editor::{Action, Actions},
     fsys::Message,
     key::Input,
 };
+use std::path::PathBuf;
 
 /// An input event that can be processed by the editor event loop
 #[derive(Debug)]
@@ -12,10 +14,20 @@ pub enum Event {
     Message(Message),
     /// An [Input] from the user
     Input(Input),
+    /// Text pasted into the terminal by the user
+    Paste(String),
     /// An [Action] for the event loop to handle
     Action(Action),
     /// Multiple [Action]s to be handled in a batch
     Actions(Actions),
     /// A signal that our window size has changed
     WinsizeChanged { rows: usize, cols: usize },
+    /// A file or directory open in the editor has been modified on disk
+    PathChanged { path: PathBuf },
+    /// The next [FileChunk] of a file being loaded in the background
+    FileChunk {
+        id: usize,
+        load_id: u64,
+        chunk: FileChunk,
+    },
 }
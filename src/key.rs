@@ -88,6 +88,93 @@ impl Input {
     }
 }
 
+const NAMED_KEYS: [(Input, &str); 16] = [
+    (Input::Esc, "esc"),
+    (Input::Return, "ret"),
+    (Input::AltReturn, "a-ret"),
+    (Input::Tab, "tab"),
+    (Input::BackTab, "s-tab"),
+    (Input::Backspace, "bs"),
+    (Input::Del, "del"),
+    (Input::Home, "home"),
+    (Input::End, "end"),
+    (Input::PageUp, "pgup"),
+    (Input::PageDown, "pgdn"),
+    (Input::Arrow(Arrow::Up), "up"),
+    (Input::Arrow(Arrow::Down), "down"),
+    (Input::Arrow(Arrow::Left), "left"),
+    (Input::Arrow(Arrow::Right), "right"),
+    (Input::Char('<'), "lt"),
+];
+
+/// Render a sequence of keys as editable text. Printable characters are written as they are
+/// and all other keys are written as names inside of angle brackets, such as `<esc>` or `<c-w>`.
+/// Mouse events are dropped as their positions are unlikely to be meaningful when replayed.
+pub(crate) fn keys_to_string(keys: &[Input]) -> String {
+    let mut s = String::new();
+
+    for k in keys.iter() {
+        if let Some((_, name)) = NAMED_KEYS.iter().find(|(i, _)| i == k) {
+            s.push_str(&format!("<{name}>"));
+            continue;
+        }
+
+        match k {
+            Input::Char(c) => s.push(*c),
+            Input::Ctrl(c) => s.push_str(&format!("<c-{c}>")),
+            Input::Alt(c) => s.push_str(&format!("<a-{c}>")),
+            Input::CtrlAlt(c) => s.push_str(&format!("<c-a-{c}>")),
+            _ => (),
+        }
+    }
+
+    s
+}
+
+/// Parse a sequence of keys written using the notation produced by [keys_to_string]. Newlines
+/// are ignored so that the text can be wrapped when it is edited: `<ret>` is used for Return.
+pub(crate) fn parse_keys(s: &str) -> Result<Vec<Input>, String> {
+    let mut keys = Vec::new();
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\n' => (),
+            '<' => {
+                let name: String = chars.by_ref().take_while(|&c| c != '>').collect();
+                keys.push(parse_named_key(&name).ok_or_else(|| format!("unknown key: <{name}>"))?);
+            }
+            c => keys.push(Input::Char(c)),
+        }
+    }
+
+    Ok(keys)
+}
+
+fn parse_named_key(name: &str) -> Option<Input> {
+    if let Some((i, _)) = NAMED_KEYS.iter().find(|(_, n)| *n == name) {
+        return Some(*i);
+    }
+
+    let single = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+
+    if let Some(s) = name.strip_prefix("c-a-") {
+        single(s).map(Input::CtrlAlt)
+    } else if let Some(s) = name.strip_prefix("c-") {
+        single(s).map(Input::Ctrl)
+    } else if let Some(s) = name.strip_prefix("a-") {
+        single(s).map(Input::Alt)
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum MouseButton {
     Left,
@@ -205,3 +292,34 @@ impl MouseEvent {
         Some(MouseEvent { k, m, b, x, y })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    #[test_case(vec![Input::Char('x'), Input::Char('d')], "xd"; "plain characters")]
+    #[test_case(vec![Input::Char('i'), Input::Char('<'), Input::Esc], "i<lt><esc>"; "escaped angle bracket")]
+    #[test_case(vec![Input::Ctrl('w'), Input::Alt('j'), Input::CtrlAlt('k')], "<c-w><a-j><c-a-k>"; "modifiers")]
+    #[test_case(vec![Input::Arrow(Arrow::Up), Input::Return], "<up><ret>"; "named keys")]
+    #[test]
+    fn keys_round_trip(keys: Vec<Input>, s: &str) {
+        assert_eq!(keys_to_string(&keys), s);
+        assert_eq!(parse_keys(s), Ok(keys));
+    }
+
+    #[test]
+    fn parse_keys_ignores_newlines() {
+        assert_eq!(
+            parse_keys("x\nd\n"),
+            Ok(vec![Input::Char('x'), Input::Char('d')])
+        );
+    }
+
+    #[test_case("<nope>"; "unknown name")]
+    #[test_case("<c-ab>"; "modifier with multiple characters")]
+    #[test]
+    fn parse_keys_rejects_unknown_keys(s: &str) {
+        assert!(parse_keys(s).is_err());
+    }
+}
@@ -35,16 +35,17 @@ pub mod term;
 pub mod trie;
 pub mod ui;
 pub mod util;
+pub mod watcher;
 pub mod ziplist;
 
-pub use buffer::GapBuffer;
+pub use buffer::{GapBuffer, PieceTable};
 pub use config::Config;
 pub use editor::{Editor, EditorMode};
 pub use exec::{CachedStdin, Edit, Program};
 pub use log::LogBuffer;
 pub use plumb::PlumbingRules;
 
-use term::{disable_alternate_screen, disable_mouse_support, set_termios};
+use term::{disable_alternate_screen, disable_bracketed_paste, disable_mouse_support, set_termios};
 
 /// The environment variable to set to control logging within ad
 pub const LOG_LEVEL_ENV_VAR: &str = "AD_LOG";
@@ -107,6 +108,7 @@ macro_rules! die {
 pub(crate) fn restore_terminal_state(so: &mut Stdout) {
     disable_alternate_screen(so);
     disable_mouse_support(so);
+    disable_bracketed_paste(so);
     let t = match ORIGINAL_TERMIOS.get() {
         Some(t) => t,
         None => return,
@@ -4,7 +4,9 @@ use crate::{
     exec::{Addr, AddrBase},
     lsp::Pos,
 };
-use lsp_types::{InitializeResult, Location, Position, PositionEncodingKind, ServerCapabilities};
+use lsp_types::{
+    InitializeResult, Location, OneOf, Position, PositionEncodingKind, ServerCapabilities,
+};
 use tracing::warn;
 
 #[derive(Debug)]
@@ -37,6 +39,14 @@ impl Capabilities {
         })
     }
 
+    /// Whether or not the server responds to textDocument/documentSymbol requests.
+    pub(crate) fn document_symbols(&self) -> bool {
+        matches!(
+            self.inner.document_symbol_provider,
+            Some(OneOf::Left(true) | OneOf::Right(_))
+        )
+    }
+
     pub(crate) fn as_pretty_json(&self) -> Option<String> {
         serde_json::to_string_pretty(&self.inner).ok()
     }
@@ -169,6 +179,14 @@ impl Coords {
         (filepath, coords)
     }
 
+    pub(crate) fn from_range(range: lsp_types::Range, encoding: PositionEncoding) -> Self {
+        Coords {
+            encoding,
+            start: range.start,
+            end: range.end,
+        }
+    }
+
     pub fn line(&self) -> u32 {
         self.start.line
     }
@@ -2,7 +2,10 @@
 //! TODO:
 //!   - parse this from a config file
 //!   - provide a mechanism for mapping the root (see neovim's support for rust-analyzer)
-use crate::{buffer::Buffer, util::parent_dir_containing};
+use crate::{
+    buffer::{extensions_for, Buffer},
+    util::parent_dir_containing,
+};
 use std::path::Path;
 
 /// Configuration for running a given language server
@@ -40,14 +43,14 @@ pub fn built_in_configs() -> Vec<LspConfig> {
             lang: "rust".to_owned(),
             cmd: "rust-analyzer".to_owned(),
             args: Vec::new(),
-            extensions: vec!["rs".to_owned()],
+            extensions: extensions_for("rust"),
             roots: vec!["Cargo.toml".to_owned()],
         },
         LspConfig {
             lang: "dart".to_owned(),
             cmd: "dart".to_owned(),
             args: vec!["language-server".to_owned(), "--protocol=lsp".to_owned()],
-            extensions: vec!["dart".to_owned()],
+            extensions: extensions_for("dart"),
             roots: vec!["melos.yaml".to_owned(), "pubspec.yaml".to_owned()],
         },
     ]
@@ -1,6 +1,7 @@
 //! Traits and handlers for processing LSP messages
 use crate::{
     buffer::Buffers,
+    dot::Scope,
     editor::{Action, Actions, MbSelect, MbSelector, MiniBufferSelection, ViewPort},
     input::Event,
     lsp::{
@@ -12,7 +13,8 @@ use crate::{
     },
 };
 use lsp_types::{
-    GotoDefinitionParams, GotoDefinitionResponse, Location, TextDocumentIdentifier,
+    DocumentSymbol, DocumentSymbolResponse, FoldingRange, GotoDefinitionParams,
+    GotoDefinitionResponse, Location, Position, SymbolKind, TextDocumentIdentifier,
     TextDocumentPositionParams, Uri, WorkDoneProgressCreateParams,
 };
 use serde_json::Value;
@@ -254,6 +256,139 @@ impl LspRequest for lsp_types::request::HoverRequest {
     }
 }
 
+impl LspRequest for lsp_types::request::DocumentSymbolRequest {
+    type Pending = (Pos, Scope);
+    type Data = Pos;
+
+    fn prepare(Pos { file, .. }: Self::Data) -> Self::Params {
+        lsp_types::DocumentSymbolParams {
+            text_document: txt_doc_id(&file),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }
+    }
+
+    fn pending((pos, scope): Self::Pending) -> Pending {
+        Pending::DocumentSymbol(pos, scope)
+    }
+
+    fn handle_res(
+        lsp_id: usize,
+        res: Option<DocumentSymbolResponse>,
+        (pos, scope): Self::Pending,
+        man: &mut LspManager,
+    ) -> Option<Actions> {
+        let enc = man.clients.get(&lsp_id)?.position_encoding;
+        let p = Position {
+            line: pos.line,
+            character: pos.character,
+        };
+        let ranges: Vec<_> = match res {
+            None => Vec::new(),
+            Some(DocumentSymbolResponse::Flat(syms)) => syms
+                .into_iter()
+                .filter(|s| is_block_symbol(s.kind))
+                .map(|s| s.location.range)
+                .collect(),
+            Some(DocumentSymbolResponse::Nested(syms)) => {
+                let mut ranges = Vec::new();
+                nested_block_symbol_ranges(syms, &mut ranges);
+                ranges
+            }
+        };
+
+        if ranges.is_empty() {
+            man.send_status("no function or type symbols returned by the LSP server");
+            return None;
+        }
+
+        // The innermost symbol containing the cursor is the one that starts last
+        let range = ranges
+            .into_iter()
+            .filter(|r| r.start <= p && p <= r.end)
+            .max_by_key(|r| r.start);
+
+        match range {
+            Some(range) => Some(Actions::Single(Action::DotSetFromSymbol {
+                coords: Coords::from_range(range, enc),
+                scope,
+            })),
+            None => {
+                man.send_status("no enclosing symbol");
+                None
+            }
+        }
+    }
+}
+
+/// Symbols that define a block of code that can be selected as a text object
+fn is_block_symbol(kind: SymbolKind) -> bool {
+    [
+        SymbolKind::FUNCTION,
+        SymbolKind::METHOD,
+        SymbolKind::CONSTRUCTOR,
+        SymbolKind::CLASS,
+        SymbolKind::STRUCT,
+        SymbolKind::ENUM,
+        SymbolKind::INTERFACE,
+        SymbolKind::OBJECT,
+    ]
+    .contains(&kind)
+}
+
+fn nested_block_symbol_ranges(syms: Vec<DocumentSymbol>, ranges: &mut Vec<lsp_types::Range>) {
+    for s in syms {
+        if is_block_symbol(s.kind) {
+            ranges.push(s.range);
+        }
+        if let Some(children) = s.children {
+            nested_block_symbol_ranges(children, ranges);
+        }
+    }
+}
+
+impl LspRequest for lsp_types::request::FoldingRangeRequest {
+    type Pending = Pos;
+    type Data = Pos;
+
+    fn prepare(Pos { file, .. }: Self::Data) -> Self::Params {
+        lsp_types::FoldingRangeParams {
+            text_document: txt_doc_id(&file),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }
+    }
+
+    fn pending(pos: Self::Pending) -> Pending {
+        Pending::FoldingRange(pos)
+    }
+
+    fn handle_res(
+        _: usize,
+        res: Option<Vec<FoldingRange>>,
+        pos: Self::Pending,
+        man: &mut LspManager,
+    ) -> Option<Actions> {
+        // The innermost range containing the cursor is the one that starts last
+        let range = res?
+            .into_iter()
+            .filter(|r| r.start_line <= pos.line && pos.line <= r.end_line)
+            .filter(|r| r.start_line < r.end_line)
+            .max_by_key(|r| r.start_line);
+
+        match range {
+            Some(r) => Some(Actions::Single(Action::FoldLines {
+                first: r.start_line as usize,
+                last: r.end_line as usize,
+            })),
+            None => {
+                man.send_status("no folding range at dot");
+                None
+            }
+        }
+    }
+}
+
 impl LspRequest for lsp_types::request::Initialize {
     type Pending = (String, Vec<PendingParams>);
     type Data = String;
@@ -289,10 +424,11 @@ impl LspRequest for lsp_types::request::Initialize {
     fn prepare(root: Self::Data) -> Self::Params {
         use lsp_types::{
             ClientCapabilities, DiagnosticClientCapabilities,
-            DiagnosticWorkspaceClientCapabilities, GeneralClientCapabilities,
-            HoverClientCapabilities, InitializeParams, MarkupKind, NumberOrString,
-            PositionEncodingKind, TextDocumentClientCapabilities, Uri, WindowClientCapabilities,
-            WorkDoneProgressParams, WorkspaceClientCapabilities, WorkspaceFolder,
+            DiagnosticWorkspaceClientCapabilities, DocumentSymbolClientCapabilities,
+            FoldingRangeClientCapabilities, GeneralClientCapabilities, HoverClientCapabilities,
+            InitializeParams, MarkupKind, NumberOrString, PositionEncodingKind,
+            TextDocumentClientCapabilities, Uri, WindowClientCapabilities, WorkDoneProgressParams,
+            WorkspaceClientCapabilities, WorkspaceFolder,
         };
 
         let basename = root.split("/").last().unwrap_or_default();
@@ -327,6 +463,14 @@ impl LspRequest for lsp_types::request::Initialize {
                         dynamic_registration: Some(true),
                         content_format: Some(vec![MarkupKind::PlainText]),
                     }),
+                    document_symbol: Some(DocumentSymbolClientCapabilities {
+                        hierarchical_document_symbol_support: Some(true),
+                        ..Default::default()
+                    }),
+                    folding_range: Some(FoldingRangeClientCapabilities {
+                        line_folding_only: Some(true),
+                        ..Default::default()
+                    }),
                     // https://docs.rs/lsp-types/0.97.0/lsp_types/struct.TextDocumentClientCapabilities.html
                     ..Default::default()
                 }),
@@ -5,6 +5,7 @@
 use crate::{
     buffer::{Buffer, Buffers},
     die,
+    dot::Scope,
     editor::{Action, Actions, MbSelect, MbSelector, MiniBufferSelection, ViewPort},
     input::Event,
     lsp::{
@@ -92,6 +93,20 @@ impl LspManagerHandle {
             .map(|(id, caps)| (*id, caps.position_encoding))
     }
 
+    /// Whether or not the active client for the buffer reports the given capability.
+    fn has_capability_for(&self, b: &Buffer, f: fn(&Capabilities) -> bool) -> bool {
+        let lang = match self.config_for_buffer(b) {
+            Some(config) => &config.lang,
+            None => return false,
+        };
+
+        self.capabilities
+            .read()
+            .unwrap()
+            .get(lang)
+            .is_some_and(|(_, caps)| f(caps))
+    }
+
     fn config_for_buffer(&self, b: &Buffer) -> Option<&LspConfig> {
         let os_ext = b.path()?.extension()?;
         let ext = os_ext.to_str()?;
@@ -269,6 +284,48 @@ impl LspManagerHandle {
             self.send(id, PendingParams::FindReferences(enc.buffer_pos(b)))
         }
     }
+
+    /// Fold the innermost folding range reported by the LSP server that contains dot.
+    ///
+    /// Returns false if there is no attached LSP server for the buffer.
+    pub fn fold(&self, b: &Buffer) -> bool {
+        match self.lsp_id_and_encoding_for(b) {
+            Some((id, enc)) => {
+                if b.dirty {
+                    self.document_changed(b);
+                }
+                debug!("sending LSP textDocument/foldingRange ({id})");
+                self.send(id, PendingParams::FoldingRange(enc.buffer_pos(b)));
+                true
+            }
+
+            None => false,
+        }
+    }
+
+    /// Select the function or type definition surrounding dot using the document symbols
+    /// reported by the LSP server for the buffer.
+    ///
+    /// Returns false if there is no attached LSP server for the buffer or if the server does
+    /// not provide document symbols.
+    pub fn select_symbol(&self, b: &Buffer, scope: Scope) -> bool {
+        if !self.has_capability_for(b, Capabilities::document_symbols) {
+            return false;
+        }
+
+        match self.lsp_id_and_encoding_for(b) {
+            Some((id, enc)) => {
+                if b.dirty {
+                    self.document_changed(b);
+                }
+                debug!("sending LSP textDocument/documentSymbol ({id})");
+                self.send(id, PendingParams::DocumentSymbol(enc.buffer_pos(b), scope));
+                true
+            }
+
+            None => false,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -337,7 +394,8 @@ impl LspManager {
         use lsp_types::{
             notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument},
             request::{
-                GotoDeclaration, GotoDefinition, GotoTypeDefinition, HoverRequest, References,
+                DocumentSymbolRequest, FoldingRangeRequest, GotoDeclaration, GotoDefinition,
+                GotoTypeDefinition, HoverRequest, References,
             },
         };
 
@@ -360,6 +418,12 @@ impl LspManager {
             }
             PendingParams::Hover(pos) => HoverRequest::send(lsp_id, pos, (), self),
             PendingParams::FindReferences(pos) => References::send(lsp_id, pos, (), self),
+            PendingParams::DocumentSymbol(pos, scope) => {
+                DocumentSymbolRequest::send(lsp_id, pos.clone(), (pos, scope), self)
+            }
+            PendingParams::FoldingRange(pos) => {
+                FoldingRangeRequest::send(lsp_id, pos.clone(), pos, self)
+            }
         }
     }
 
@@ -388,7 +452,11 @@ impl LspManager {
         };
 
         let actions = match p {
+            DocumentSymbol(pos, scope) => {
+                req::DocumentSymbolRequest::handle(lsp_id, res, (pos, scope), self)
+            }
             FindReferences => req::References::handle(lsp_id, res, (), self),
+            FoldingRange(pos) => req::FoldingRangeRequest::handle(lsp_id, res, pos, self),
             GotoDeclaration => req::GotoDeclaration::handle(lsp_id, res, (), self),
             GotoDefinition => req::GotoDefinition::handle(lsp_id, res, (), self),
             GotoTypeDefinition => req::GotoTypeDefinition::handle(lsp_id, res, (), self),
@@ -511,7 +579,9 @@ pub(crate) enum PendingParams {
         path: String,
         content: String,
     },
+    DocumentSymbol(Pos, Scope),
     FindReferences(Pos),
+    FoldingRange(Pos),
     GotoDeclaration(Pos),
     GotoDefinition(Pos),
     GotoTypeDefinition(Pos),
@@ -520,7 +590,9 @@ pub(crate) enum PendingParams {
 
 #[derive(Debug)]
 pub(crate) enum Pending {
+    DocumentSymbol(Pos, Scope),
     FindReferences,
+    FoldingRange(Pos),
     GotoDeclaration,
     GotoDefinition,
     GotoTypeDefinition,
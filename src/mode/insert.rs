@@ -1,8 +1,8 @@
 //! vim style insert mode where most keys are directly modifying the buffer
 use crate::{
     dot::TextObject::*,
-    editor::{Action::*, Actions},
-    key::{Arrow::*, Input::*},
+    editor::{Action, Action::*, Actions},
+    key::{Arrow::*, Input, Input::*},
     keymap,
     mode::Mode,
     term::CurShape,
@@ -32,7 +32,7 @@ pub(crate) fn insert_mode() -> Mode {
     };
 
     // By default we just let the buffer try to handle this
-    keymap.set_default(|&i| Some(Actions::Single(RawInput { i })));
+    keymap.set_default(|&i| Some(Actions::Single(typed(i))));
 
     Mode {
         name: "INSERT".to_string(),
@@ -40,10 +40,20 @@ pub(crate) fn insert_mode() -> Mode {
         keymap,
         handle_expired_pending: |keys| {
             QueryResult::Val(if keys.len() == 1 {
-                Actions::Single(RawInput { i: keys[0] })
+                Actions::Single(typed(keys[0]))
             } else {
-                Actions::Multi(keys.iter().map(|&i| RawInput { i }).collect())
+                Actions::Multi(keys.iter().map(|&i| typed(i)).collect())
             })
         },
+        accepts_prefix: false,
+    }
+}
+
+/// Characters are inserted as typed text so that the buffer can auto-pair brackets and quotes
+/// and adjust indentation. All other input is handled by the buffer directly.
+fn typed(i: Input) -> Action {
+    match i {
+        Char(c) => InsertChar { c },
+        i => RawInput { i },
     }
 }
@@ -1,6 +1,6 @@
 //! Modal editing support.
 use crate::{
-    editor::Actions,
+    editor::{Actions, MAX_COUNT},
     key::Input,
     term::CurShape,
     trie::{QueryResult, Trie},
@@ -23,6 +23,8 @@ pub(crate) struct Mode {
     pub(crate) cur_shape: CurShape,
     pub(crate) keymap: Trie<Input, Actions>,
     handle_expired_pending: fn(&[Input]) -> QueryResult<Actions>,
+    /// Whether or not key sequences can be prefixed with a numeric count and register
+    accepts_prefix: bool,
 }
 
 impl fmt::Display for Mode {
@@ -38,32 +40,88 @@ impl Mode {
             cur_shape: CurShape::Block,
             keymap: Trie::from_pairs(Vec::new()).unwrap(),
             handle_expired_pending: |_| QueryResult::Missing,
+            accepts_prefix: false,
         }
     }
 
     pub fn handle_keys(&self, keys: &mut Vec<Input>) -> Option<Actions> {
-        match self.keymap.get(keys) {
-            QueryResult::Val(outcome) => {
-                keys.clear();
-                Some(outcome)
+        let prefix = if self.accepts_prefix {
+            // Wait for the name of a register following a '"'
+            parse_prefix(keys)?
+        } else {
+            Prefix::default()
+        };
+
+        // Wait for the rest of the key sequence following a prefix
+        let without_prefix = &keys[prefix.len..];
+        if without_prefix.is_empty() {
+            return None;
+        }
+
+        let outcome = match self.keymap.get(without_prefix) {
+            QueryResult::Val(outcome) => Some(outcome),
+            QueryResult::Partial => return None,
+            QueryResult::Missing => match (self.handle_expired_pending)(without_prefix) {
+                QueryResult::Val(outcome) => Some(outcome),
+                QueryResult::Missing => None,
+                QueryResult::Partial => return None,
+            },
+        };
+        keys.clear();
+
+        outcome.map(|actions| prefix.apply(actions))
+    }
+}
+
+/// A numeric count and/or register preceding a key sequence, such as `3` or `"a` in `"a3p`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Prefix {
+    count: Option<usize>,
+    register: Option<char>,
+    /// The number of keys making up the prefix
+    len: usize,
+}
+
+impl Prefix {
+    fn apply(&self, actions: Actions) -> Actions {
+        let actions = match self.count {
+            Some(n) => actions.with_count(n),
+            None => actions,
+        };
+
+        match self.register {
+            Some(r) => actions.with_register(r),
+            None => actions,
+        }
+    }
+}
+
+/// Parse a leading count and register from a sequence of keys. Counts may not start with a
+/// zero and are clamped to [MAX_COUNT]. None is returned if the keys end part way through
+/// selecting a register.
+fn parse_prefix(keys: &[Input]) -> Option<Prefix> {
+    let mut prefix = Prefix::default();
+
+    while let Some(k) = keys.get(prefix.len) {
+        match k {
+            Input::Char(c @ '0'..='9') if *c != '0' || prefix.count.is_some() => {
+                let d = *c as usize - '0' as usize;
+                let n = prefix.count.unwrap_or(0);
+                prefix.count = Some((n * 10 + d).min(MAX_COUNT));
             }
-            QueryResult::Partial => None,
-            QueryResult::Missing => {
-                let res = (self.handle_expired_pending)(keys);
-                match res {
-                    QueryResult::Val(outcome) => {
-                        keys.clear();
-                        Some(outcome)
-                    }
-                    QueryResult::Missing => {
-                        keys.clear();
-                        None
-                    }
-                    QueryResult::Partial => None,
+            Input::Char('"') if prefix.register.is_none() => match keys.get(prefix.len + 1)? {
+                Input::Char(r) => {
+                    prefix.register = Some(*r);
+                    prefix.len += 1;
                 }
-            }
+                _ => break,
+            },
+            _ => break,
         }
+        prefix.len += 1;
     }
+
+    Some(prefix)
 }
 
 /// Construct a new [Trie] based keymap
@@ -90,6 +148,12 @@ macro_rules! keymap {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        dot::TextObject::*,
+        editor::Action::*,
+        key::{Arrow::*, Input::*},
+    };
+    use simple_test_case::test_case;
 
     // This test will panic if any of the default keymaps end up with mappings that
     // collide internally. The Trie struct rejects overlapping or duplicate keys on
@@ -99,4 +163,94 @@ mod tests {
     fn mode_keymaps_have_no_collisions() {
         _ = modes();
     }
+
+    #[test_case("j", Some(Actions::Single(DotSet(Arr(Down), 1))); "no count")]
+    #[test_case("5j", Some(Actions::Single(DotSet(Arr(Down), 5))); "single count aware action")]
+    #[test_case("12j", Some(Actions::Single(DotSet(Arr(Down), 12))); "multiple digits")]
+    #[test_case("3w", Some(Actions::Multi(vec![DotExtendForward(Word, 3), DotCollapseLast])); "count aware sequence")]
+    #[test_case("2gg", Some(Actions::Single(DotSet(BufferStart, 2))); "multi key sequence")]
+    #[test_case("3u", Some(Actions::Multi(vec![Undo, Undo, Undo])); "repeated action")]
+    #[test_case("2p", Some(Actions::Multi(vec![NewEditLogTransaction, Paste, Paste, NewEditLogTransaction])); "repeated within a sequence")]
+    #[test_case("2c", Some(Actions::Multi(vec![NewEditLogTransaction, Delete, SetMode { m: "INSERT" }])); "mode changes are not repeated")]
+    #[test_case("2d", Some(Actions::Multi(vec![NewEditLogTransaction, Delete, NewEditLogTransaction])); "deleting dot is not repeated")]
+    #[test_case("99999999999j", Some(Actions::Single(DotSet(Arr(Down), MAX_COUNT))); "large counts are clamped")]
+    #[test_case("999999999999999999999999j", Some(Actions::Single(DotSet(Arr(Down), MAX_COUNT))); "counts overflowing usize are clamped")]
+    #[test]
+    fn normal_mode_counts_work(keys: &str, expected: Option<Actions>) {
+        let mode = normal_mode();
+        let mut keys: Vec<Input> = keys.chars().map(Char).collect();
+
+        assert_eq!(mode.handle_keys(&mut keys), expected);
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn repeated_actions_are_clamped_to_max_count() {
+        let mode = normal_mode();
+        let mut keys: Vec<Input> = "999999999u".chars().map(Char).collect();
+
+        assert_eq!(
+            mode.handle_keys(&mut keys),
+            Some(Actions::Multi(vec![Undo; MAX_COUNT]))
+        );
+        assert!(keys.is_empty());
+    }
+
+    #[test_case("\"ay", Some(Actions::Multi(vec![SelectRegister { r: 'a' }, Yank])); "register")]
+    #[test_case("\"a2p", Some(Actions::Multi(vec![SelectRegister { r: 'a' }, NewEditLogTransaction, Paste, Paste, NewEditLogTransaction])); "register then count")]
+    #[test_case("2\"ap", Some(Actions::Multi(vec![SelectRegister { r: 'a' }, NewEditLogTransaction, Paste, Paste, NewEditLogTransaction])); "count then register")]
+    #[test]
+    fn normal_mode_registers_work(keys: &str, expected: Option<Actions>) {
+        let mode = normal_mode();
+        let mut keys: Vec<Input> = keys.chars().map(Char).collect();
+
+        assert_eq!(mode.handle_keys(&mut keys), expected);
+        assert!(keys.is_empty());
+    }
+
+    #[test_case("@a", Some(Actions::Single(ReplayMacro { r: 'a' })); "replay macro")]
+    #[test_case("2@a", Some(Actions::Multi(vec![ReplayMacro { r: 'a' }, ReplayMacro { r: 'a' }])); "replay macro with count")]
+    #[test_case("@@", Some(Actions::Single(ExecuteDot)); "execute dot")]
+    #[test]
+    fn normal_mode_at_bindings_work(keys: &str, expected: Option<Actions>) {
+        let mode = normal_mode();
+        let mut keys: Vec<Input> = keys.chars().map(Char).collect();
+
+        assert_eq!(mode.handle_keys(&mut keys), expected);
+        assert!(keys.is_empty());
+    }
+
+    #[test_case("3"; "count")]
+    #[test_case("3g"; "count and partial sequence")]
+    #[test_case("\""; "register selection")]
+    #[test_case("\"a"; "register")]
+    #[test]
+    fn pending_prefixes_are_kept(keys: &str) {
+        let mode = normal_mode();
+        let mut keys: Vec<Input> = keys.chars().map(Char).collect();
+        let expected = keys.clone();
+
+        assert_eq!(mode.handle_keys(&mut keys), None);
+        assert_eq!(keys, expected);
+    }
+
+    #[test]
+    fn zero_is_not_a_count() {
+        let mode = normal_mode();
+        let mut keys = vec![Char('0')];
+
+        assert_eq!(mode.handle_keys(&mut keys), None);
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn insert_mode_does_not_accept_counts() {
+        let mode = insert::insert_mode();
+        let mut keys = vec![Char('3')];
+
+        assert_eq!(
+            mode.handle_keys(&mut keys),
+            Some(Actions::Single(InsertChar { c: '3' }))
+        );
+    }
 }
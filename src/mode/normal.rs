@@ -1,7 +1,8 @@
 //! vim style normal mode
 use crate::{
+    buffer::{JUMP_MARK, SURROUND_ANY_BRACKET, SURROUND_TAG},
     config_handle,
-    dot::TextObject::*,
+    dot::{Scope, TextObject::*},
     editor::{Action::*, Actions, ViewPort},
     key::{Arrow::*, Input::*},
     keymap,
@@ -20,6 +21,8 @@ pub(crate) fn normal_mode() -> Mode {
         // Modes
         [ leader, Char('b') ] => [ SelectBuffer ],
         [ Char('/') ] => [ SearchInCurrentBuffer ],
+        [ Char('?') ] => [ ReverseSearchInCurrentBuffer ],
+        [ leader, Char('/') ] => [ SelectLineInCurrentBuffer ],
         [ Char(':') ] => [ CommandMode ],
         [ Char('!') ] => [ RunMode ],
         [ Char('.') ] => [ SamMode ],
@@ -60,6 +63,10 @@ pub(crate) fn normal_mode() -> Mode {
         [ Char('%') ] => [ DotSet(BufferStart, 1), DotExtendForward(BufferEnd, 1) ],
         [ Char('{') ] => [ DotExtendBackward(Paragraph, 1), DotCollapseFirst ],
         [ Char('}') ] => [ DotExtendForward(Paragraph, 1), DotCollapseLast ],
+        // >> search
+        [ Char('n') ] => [ SearchNext ],
+        [ Char('N') ] => [ SearchPrevious ],
+        [ Esc ] => [ ClearSearchHighlights ],
 
         [ Char('g'), Char('g') ] => [ DotSet(BufferStart, 1) ],
         [ Char('g'), Char('e') ] => [ DotSet(BufferEnd, 1) ],
@@ -72,6 +79,7 @@ pub(crate) fn normal_mode() -> Mode {
         [ Char('g'), Char('d') ] => [ LspGotoDefinition ],
         [ Char('g'), Char('r') ] => [ LspReferences ],
         [ Char('g'), Char('t') ] => [ LspGotoTypeDefinition ],
+        [ Char('g'), Char('c') ] => [ ToggleComment ],
         [ Ctrl('k') ] => [ LspHover ],
 
         // Delimited pairs
@@ -87,6 +95,14 @@ pub(crate) fn normal_mode() -> Mode {
         [ Alt('i'), Char('\'') ] => [ DotSet(Delimited('\'', '\''), 1) ],
         [ Alt('i'), Char('/') ] => [ DotSet(Delimited('/', '/'), 1) ],
 
+        // Structural objects
+        [ Alt('i'), Char('t') ] => [ DotSet(IndentBlock(Scope::Inner), 1) ],
+        [ Alt('a'), Char('t') ] => [ DotSet(IndentBlock(Scope::Around), 1) ],
+        [ Alt('i'), Char(',') ] => [ DotSet(Argument(Scope::Inner), 1) ],
+        [ Alt('a'), Char(',') ] => [ DotSet(Argument(Scope::Around), 1) ],
+        [ Alt('i'), Char('f') ] => [ LspSelectSymbol { scope: Scope::Inner } ],
+        [ Alt('a'), Char('f') ] => [ LspSelectSymbol { scope: Scope::Around } ],
+
         // Extending dot
         // >> character positions
         [ Char('H') ] => [ DotExtendBackward(Character, 1) ],
@@ -114,10 +130,17 @@ pub(crate) fn normal_mode() -> Mode {
         [ Char('z'), Char('z') ] => [ SetViewPort(ViewPort::Center) ],
         [ Char('z'), Char('b') ] => [ SetViewPort(ViewPort::Bottom) ],
 
+        // Folding
+        [ Char('z'), Char('f') ] => [ FoldDot ],
+        [ Char('z'), Char('c') ] => [ LspFold ],
+        [ Char('z'), Char('i') ] => [ FoldIndent ],
+        [ Char('z'), Char('o') ] => [ Unfold ],
+        [ Char('z'), Char('R') ] => [ UnfoldAll ],
+
         // Window manipulation
         [ Alt('w') ] => [ PreviousWindowInColumn ],
-        [ Alt('a') ] => [ PreviousColumn ],
         [ Alt('s') ] => [ NextWindowInColumn ],
+        [ Alt('q') ] => [ PreviousColumn ],
         [ Alt('d') ] => [ NextColumn ],
         [ Alt('W') ] => [ DragWindow { direction: Up } ],
         [ Alt('A') ] => [ DragWindow { direction: Left } ],
@@ -125,12 +148,13 @@ pub(crate) fn normal_mode() -> Mode {
         [ Alt('D') ] => [ DragWindow { direction: Right } ],
 
         // Editing actions
-        [ Char('c') ] => [ Delete, SetMode { m: "INSERT" } ],
-        [ Char('d') ] => [ Delete ],
+        [ Char('c') ] => [ NewEditLogTransaction, Delete, SetMode { m: "INSERT" } ],
+        [ Char('d') ] => [ NewEditLogTransaction, Delete, NewEditLogTransaction ],
         [ Char('p') ] => [ NewEditLogTransaction, Paste, NewEditLogTransaction ],
         [ Char('y') ] => [ Yank ],
         [ Char('u') ] => [ Undo ],
         [ Char('U') ] => [ Redo ],
+        [ Alt('.') ] => [ RepeatLastEdit ],
 
         [ Ctrl('o') ] => [ JumpListBack ],
         [ Tab ] => [ JumpListForward ], // ctrl-i
@@ -139,11 +163,86 @@ pub(crate) fn normal_mode() -> Mode {
 
         [ Return ] => [ LoadDot { new_window: false } ],
         [ AltReturn ] => [ LoadDot { new_window: true } ],
-        [ Char('@') ] => [ ExecuteDot ],
+        [ Char('@'), Char('@') ] => [ ExecuteDot ],
         [ Char('*') ] => [ ExpandDot ],
 
     };
 
+    // Recording and replaying macros: upper case registers append to an existing macro
+    let macros = ('a'..='z')
+        .flat_map(|r| {
+            [
+                (vec![Char('q'), Char(r)], Actions::Single(RecordMacro { r })),
+                (
+                    vec![Char('q'), Char(r.to_ascii_uppercase())],
+                    Actions::Single(RecordMacro {
+                        r: r.to_ascii_uppercase(),
+                    }),
+                ),
+                (vec![Char('@'), Char(r)], Actions::Single(ReplayMacro { r })),
+            ]
+        })
+        .collect();
+    keymap
+        .extend_from_pairs(macros)
+        .expect("macro bindings to be valid");
+
+    // Setting and jumping to marks
+    let marks = ('a'..='z')
+        .flat_map(|name| {
+            [
+                (
+                    vec![Char('m'), Char(name)],
+                    Actions::Single(SetMark { name }),
+                ),
+                (
+                    vec![Char('\''), Char(name)],
+                    Actions::Single(JumpToMark { name }),
+                ),
+            ]
+        })
+        .chain([(
+            vec![Char('\''), Char('\'')],
+            Actions::Single(JumpToMark { name: JUMP_MARK }),
+        )])
+        .collect();
+    keymap
+        .extend_from_pairs(marks)
+        .expect("mark bindings to be valid");
+
+    // Surrounding dot: "sa" adds, "sd" deletes and "sc" changes the delimiters around dot
+    let delims = ['(', ')', '[', ']', '{', '}', '<', '>', '"', '\'', '`'];
+    let mut surround = Vec::new();
+    for from in delims
+        .into_iter()
+        .chain([SURROUND_TAG, SURROUND_ANY_BRACKET])
+    {
+        if !from.is_alphabetic() {
+            surround.push((
+                vec![Char('s'), Char('a'), Char(from)],
+                Actions::Single(SurroundAdd {
+                    s: from.to_string(),
+                }),
+            ));
+        }
+        surround.push((
+            vec![Char('s'), Char('d'), Char(from)],
+            Actions::Single(SurroundDelete { c: from }),
+        ));
+        for to in delims {
+            surround.push((
+                vec![Char('s'), Char('c'), Char(from), Char(to)],
+                Actions::Single(SurroundChange {
+                    from,
+                    to: to.to_string(),
+                }),
+            ));
+        }
+    }
+    keymap
+        .extend_from_pairs(surround)
+        .expect("surround bindings to be valid");
+
     keymap.set_default(|&i| match i {
         Mouse(_) | Arrow(_) | PageUp | PageDown => Some(Actions::Single(RawInput { i })),
         _ => None,
@@ -159,5 +258,6 @@ pub(crate) fn normal_mode() -> Mode {
                 .get(keys)
                 .map(|s| Actions::Single(ExecuteString { s: s.clone() }))
         },
+        accepts_prefix: true,
     }
 }
@@ -228,7 +228,7 @@ enum Pattern {
     IsFile(String),
     IsDir(String),
     Is(Field, String),
-    Matches(Field, Regex),
+    Matches(Field, Box<Regex>),
     Set(Field, String),
 }
 
@@ -520,8 +520,10 @@ impl FromStr for Rule {
                     "set" => rule.patterns.push(Pattern::Set(field, value)),
                     "matches" => rule.patterns.push(Pattern::Matches(
                         field,
-                        Regex::compile(&value)
-                            .map_err(|e| format!("malformed regex ({e:?}): {value}"))?,
+                        Box::new(
+                            Regex::compile(&value)
+                                .map_err(|e| format!("malformed regex ({e:?}): {value}"))?,
+                        ),
                     )),
                     _ => return Err(format!("unknown rule operation: {op}")),
                 }
@@ -668,7 +670,10 @@ mod tests {
                 Pattern::Is(Field::Src, "ad".to_string()),
                 Pattern::Is(Field::Dst, "editor".to_string()),
                 Pattern::Set(Field::Wdir, "/foo/bar".to_string()),
-                Pattern::Matches(Field::Data, Regex::compile(r#"(.+):(\d+):(\d+):"#).unwrap()),
+                Pattern::Matches(
+                    Field::Data,
+                    Box::new(Regex::compile(r#"(.+):(\d+):(\d+):"#).unwrap()),
+                ),
                 Pattern::Set(Field::Data, "$1:$2,$3".to_string()),
             ],
             actions: vec![
@@ -1,5 +1,9 @@
 //! A simple AST for parsing and manipulating regex strings
-use super::{next_char, CharClass, Error};
+use super::{
+    escaped_class, next_char,
+    unicode::{is_digit, is_word, Property},
+    CharClass, Error,
+};
 use crate::util::parse_num;
 use std::{iter::Peekable, mem::swap, str::Chars};
 
@@ -11,6 +15,31 @@ pub(super) enum Ast {
     Rep(Rep, Box<Ast>),
     Concat(Vec<Ast>),
     SubMatch(SmKind, Box<Ast>),
+    LookAround(LookAround, Box<Ast>),
+}
+
+/// Zero-width lookaround assertions. The wrapped expression is compiled into its own
+/// anchored program rather than being inlined into the parent program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum LookAround {
+    /// (?=...)
+    Ahead,
+    /// (?!...)
+    NegAhead,
+    /// (?<=...)
+    Behind,
+    /// (?<!...)
+    NegBehind,
+}
+
+impl LookAround {
+    pub(super) fn is_ahead(&self) -> bool {
+        matches!(self, LookAround::Ahead | LookAround::NegAhead)
+    }
+
+    pub(super) fn is_negated(&self) -> bool {
+        matches!(self, LookAround::NegAhead | LookAround::NegBehind)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -30,7 +59,7 @@ impl Ast {
 
     pub fn op_len(&self) -> usize {
         match self {
-            Ast::Comp(_) | Ast::Assertion(_) => 1,
+            Ast::Comp(_) | Ast::Assertion(_) | Ast::LookAround(_, _) => 1,
             Ast::Alt(nodes) => {
                 nodes.iter().map(|n| n.op_len()).sum::<usize>() + (nodes.len() - 1) * 2
             }
@@ -56,6 +85,9 @@ impl Ast {
                 nodes.iter_mut().for_each(|n| n.reverse());
             }
             Self::SubMatch(_, node) => node.reverse(),
+            // The direction a lookaround body is compiled in is determined by the kind
+            // of lookaround rather than the direction of the enclosing regex.
+            Self::LookAround(_, _) => (),
             _ => (),
         }
     }
@@ -65,7 +97,7 @@ impl Ast {
 
         while optimising {
             optimising = match self {
-                Ast::Comp(cmp) => cmp.replace_common_classes(),
+                Ast::Comp(cmp) => cmp.replace_property_classes(),
                 Ast::Concat(nodes) => compress_cats(nodes),
                 // TODO: extract common alt prefixes & dedup cases
                 _ => false,
@@ -73,6 +105,81 @@ impl Ast {
         }
     }
 
+    /// The comparisons that any match of this expression must begin with, along with whether
+    /// or not the expression is able to match the empty string. Returns None if a match is
+    /// able to begin with any character.
+    pub(super) fn first_comps(&self) -> Option<(Vec<Comp>, bool)> {
+        match self {
+            Ast::Comp(Comp::Any | Comp::TrueAny) => None,
+            Ast::Comp(c) => Some((vec![c.clone()], false)),
+            Ast::Assertion(_) | Ast::LookAround(_, _) => Some((vec![], true)),
+            Ast::SubMatch(_, node) | Ast::Rep(Rep::Plus(_), node) => node.first_comps(),
+            Ast::Rep(_, node) => node.first_comps().map(|(comps, _)| (comps, true)),
+
+            Ast::Alt(nodes) => {
+                let mut comps = Vec::new();
+                let mut nullable = false;
+                for node in nodes.iter() {
+                    let (cs, n) = node.first_comps()?;
+                    extend_unique(&mut comps, cs);
+                    nullable |= n;
+                }
+                Some((comps, nullable))
+            }
+
+            Ast::Concat(nodes) => {
+                let mut comps = Vec::new();
+                for node in nodes.iter() {
+                    let (cs, nullable) = node.first_comps()?;
+                    extend_unique(&mut comps, cs);
+                    if !nullable {
+                        return Some((comps, false));
+                    }
+                }
+                Some((comps, true))
+            }
+        }
+    }
+
+    /// The literal characters that any match of this expression must begin with.
+    pub(super) fn literal_prefix(&self) -> Vec<char> {
+        let mut prefix = Vec::new();
+        match self {
+            Ast::Comp(Comp::Char(c)) => prefix.push(*c),
+            Ast::Concat(nodes) => {
+                for node in nodes.iter() {
+                    match node {
+                        Ast::Comp(Comp::Char(c)) => prefix.push(*c),
+                        _ => break,
+                    }
+                }
+            }
+            _ => (),
+        }
+
+        prefix
+    }
+
+    /// The maximum number of characters that a match of this expression is able to consume,
+    /// or None if it is unbounded. Lookarounds count the characters they are able to read
+    /// along with one more for any assertion they contain that peeks at the next character.
+    pub(super) fn max_len(&self) -> Option<usize> {
+        match self {
+            Ast::Comp(_) => Some(1),
+            Ast::Assertion(_) => Some(0),
+            Ast::LookAround(_, node) => node.max_len().map(|n| n + 1),
+            Ast::SubMatch(_, node) | Ast::Rep(Rep::Quest(_), node) => node.max_len(),
+            Ast::Rep(_, node) => match node.max_len()? {
+                0 => Some(0),
+                _ => None,
+            },
+            Ast::Alt(nodes) => nodes
+                .iter()
+                .try_fold(0, |len, node| Some(len.max(node.max_len()?))),
+            Ast::Concat(nodes) => nodes.iter().map(|node| node.max_len()).sum(),
+        }
+    }
+
     fn contains_named_submatch(&self) -> bool {
         match self {
             Ast::SubMatch(SmKind::Named(_), _) => true,
@@ -109,6 +216,14 @@ impl Ast {
     }
 }
 
+fn extend_unique(comps: &mut Vec<Comp>, new: Vec<Comp>) {
+    for c in new.into_iter() {
+        if !comps.contains(&c) {
+            comps.push(c);
+        }
+    }
+}
+
 fn compress_cats(nodes: &mut Vec<Ast>) -> bool {
     let mut buf = Vec::new();
     swap(&mut buf, nodes);
@@ -149,6 +264,9 @@ pub(super) enum Comp {
     NonAlphaNumeric,
     WhiteSpace,
     NonWhiteSpace,
+    // Unicode properties and scripts: \p{...} and \P{...}
+    Prop(Property),
+    NonProp(Property),
 }
 
 impl Comp {
@@ -160,58 +278,43 @@ impl Comp {
             Comp::Class(cls) => cls.matches(ch),
             Comp::Any => ch != '\n',
             Comp::TrueAny => true,
-            Comp::Numeric => ch.is_ascii_digit(),
-            Comp::NonNumeric => !ch.is_ascii_digit(),
-            Comp::AlphaNumeric => ch.is_ascii_alphanumeric() || ch == '_',
-            Comp::NonAlphaNumeric => !(ch.is_ascii_alphanumeric() || ch == '_'),
+            Comp::Numeric => is_digit(ch),
+            Comp::NonNumeric => !is_digit(ch),
+            Comp::AlphaNumeric => is_word(ch),
+            Comp::NonAlphaNumeric => !is_word(ch),
             Comp::WhiteSpace => ch.is_whitespace(),
             Comp::NonWhiteSpace => !ch.is_whitespace(),
+            Comp::Prop(p) => p.matches(ch),
+            Comp::NonProp(p) => !p.matches(ch),
         }
     }
 
-    fn replace_common_classes(&mut self) -> bool {
-        match self {
-            Comp::Class(CharClass {
-                negated: false,
-                chars,
-                ranges,
-            }) if chars.is_empty() && ranges == &[('0', '9')] => {
-                *self = Comp::Numeric;
-                true
-            }
-
-            Comp::Class(CharClass {
-                negated: true,
-                chars,
-                ranges,
-            }) if chars.is_empty() && ranges == &[('0', '9')] => {
-                *self = Comp::NonNumeric;
-                true
-            }
+    fn from_property(prop: Property, negated: bool) -> Self {
+        match (prop, negated) {
+            (Property::Digit, false) => Comp::Numeric,
+            (Property::Digit, true) => Comp::NonNumeric,
+            (Property::Word, false) => Comp::AlphaNumeric,
+            (Property::Word, true) => Comp::NonAlphaNumeric,
+            (Property::WhiteSpace, false) => Comp::WhiteSpace,
+            (Property::WhiteSpace, true) => Comp::NonWhiteSpace,
+            (p, false) => Comp::Prop(p),
+            (p, true) => Comp::NonProp(p),
+        }
+    }
 
+    /// Classes containing only a single named class (e.g. "[\w]" or "[[:alpha:]]") can be
+    /// replaced by the named class itself. Negated classes are left as they are as they
+    /// never match a newline.
+    fn replace_property_classes(&mut self) -> bool {
+        match self {
             Comp::Class(CharClass {
                 negated: false,
                 chars,
                 ranges,
-            }) if chars == &['_']
-                && ranges.contains(&('0', '9'))
-                && ranges.contains(&('a', 'z'))
-                && ranges.contains(&('A', 'Z')) =>
-            {
-                *self = Comp::AlphaNumeric;
-                true
-            }
-
-            Comp::Class(CharClass {
-                negated: true,
-                chars,
-                ranges,
-            }) if chars == &['_']
-                && ranges.contains(&('0', '9'))
-                && ranges.contains(&('a', 'z'))
-                && ranges.contains(&('A', 'Z')) =>
-            {
-                *self = Comp::NonAlphaNumeric;
+                props,
+            }) if chars.is_empty() && ranges.is_empty() && props.len() == 1 => {
+                let (p, negated) = props[0];
+                *self = Comp::from_property(p, negated);
                 true
             }
 
@@ -382,7 +485,7 @@ enum ParseEnd {
 
 fn parse1(it: &mut Peekable<Chars<'_>>, root: &mut Vec<Ast>) -> Result<Option<ParseEnd>, Error> {
     match next_char(it)? {
-        Some((ch, true)) => handle_escaped(ch, root).map(|_| None),
+        Some((ch, true)) => handle_escaped(ch, it, root).map(|_| None),
         Some((ch, false)) => handle_char(ch, it, root),
         None => Ok(Some(ParseEnd::Eof)),
     }
@@ -425,26 +528,46 @@ fn handle_char(
 }
 
 /// Three variants of submatch / group are supported by this engine:
-///   1) "(...)"
-///       Capturing: the position of this sub-expression will be extracted as submatch
-///       and made available through a numeric index based on it's position within the
-///       regular expression.
-///   2) "(?<name>...)"
-///       Named capturing: the position of this subexpression will be extracted as a submatch
-///       and made available through the provided name rather than an index. To avoid confusion
-///       with mixing and matching named capture groups and positional ones, the presence of a
-///       named capture group will mark all unnamed capture groups within that regex to be
-///       treated as non-capturing. If they are required, they will also need to be named.
-///   3) "(?:...)"
-///       Non-capturing: allows for grouping and application of repetition / alternation
-///       of compund expressions without contributing to the captured sub-expressions.
+/// 1) "(...)"
+///    Capturing: the position of this sub-expression will be extracted as submatch
+///    and made available through a numeric index based on it's position within the
+///    regular expression.
+/// 2) "(?<name>...)"
+///    Named capturing: the position of this subexpression will be extracted as a submatch
+///    and made available through the provided name rather than an index. To avoid confusion
+///    with mixing and matching named capture groups and positional ones, the presence of a
+///    named capture group will mark all unnamed capture groups within that regex to be
+///    treated as non-capturing. If they are required, they will also need to be named.
+/// 3) "(?:...)"
+///    Non-capturing: allows for grouping and application of repetition / alternation
+///    of compund expressions without contributing to the captured sub-expressions.
+///
+/// Lookaround assertions share the same group syntax and are parsed here as well:
+/// "(?=...)", "(?!...)", "(?<=...)" and "(?<!...)". Any groups inside of a lookaround
+/// are treated as non-capturing.
 fn handle_subexp(it: &mut Peekable<Chars<'_>>, root: &mut Vec<Ast>) -> Result<(), Error> {
     let mut sub = Vec::new();
+    let mut look = None;
     let kind = match it.peek() {
         Some('?') => {
             it.next();
             match it.next() {
                 Some(':') => SmKind::NonCapturing,
+                Some('=') => {
+                    look = Some(LookAround::Ahead);
+                    SmKind::NonCapturing
+                }
+                Some('!') => {
+                    look = Some(LookAround::NegAhead);
+                    SmKind::NonCapturing
+                }
+                Some('<') if matches!(it.peek(), Some('=' | '!')) => {
+                    look = match it.next() {
+                        Some('=') => Some(LookAround::Behind),
+                        _ => Some(LookAround::NegBehind),
+                    };
+                    SmKind::NonCapturing
+                }
                 Some('<') => {
                     let mut name = String::new();
                     for ch in it.by_ref() {
@@ -474,7 +597,10 @@ fn handle_subexp(it: &mut Peekable<Chars<'_>>, root: &mut Vec<Ast>) -> Result<()
         },
     };
 
-    root.push(Ast::SubMatch(kind, Box::new(node)));
+    match look {
+        Some(look) => root.push(Ast::LookAround(look, Box::new(node))),
+        None => root.push(Ast::SubMatch(kind, Box::new(node))),
+    }
 
     Ok(())
 }
@@ -515,17 +641,19 @@ fn handle_alt(it: &mut Peekable<Chars<'_>>, root: &mut Vec<Ast>) -> Result<(), E
     Ok(())
 }
 
-fn handle_escaped(ch: char, root: &mut Vec<Ast>) -> Result<(), Error> {
+fn handle_escaped(
+    ch: char,
+    it: &mut Peekable<Chars<'_>>,
+    root: &mut Vec<Ast>,
+) -> Result<(), Error> {
     match ch {
         'b' => root.push(Ast::Assertion(Assertion::WordBoundary)),
         'B' => root.push(Ast::Assertion(Assertion::NonWordBoundary)),
 
-        'd' => root.push(Ast::Comp(Comp::Numeric)),
-        'D' => root.push(Ast::Comp(Comp::NonNumeric)),
-        'w' => root.push(Ast::Comp(Comp::AlphaNumeric)),
-        'W' => root.push(Ast::Comp(Comp::NonAlphaNumeric)),
-        's' => root.push(Ast::Comp(Comp::WhiteSpace)),
-        'S' => root.push(Ast::Comp(Comp::NonWhiteSpace)),
+        'd' | 'D' | 'w' | 'W' | 's' | 'S' | 'p' | 'P' => {
+            let (p, negated) = escaped_class(ch, it)?;
+            root.push(Ast::Comp(Comp::from_property(p, negated)));
+        }
 
         ch => root.push(Ast::Comp(Comp::Char(ch))),
     }
@@ -608,6 +736,10 @@ mod tests {
         Ast::Assertion(a)
     }
 
+    fn look(l: LookAround, s: Ast) -> Ast {
+        Ast::LookAround(l, Box::new(s))
+    }
+
     fn rep(r: super::Rep, c: char) -> Ast {
         Ast::Rep(r, Box::new(ch(c)))
     }
@@ -636,12 +768,39 @@ mod tests {
         cat(vec![nsub("bar", cat(vec![ch('f'), ch('o'), ch('o')])), ncsub(alt(vec![ch('a'), ch('b')]))]);
         "named sub expression should demote non named to non capturing"
     )]
+    #[test_case("a(?=b)", cat(vec![ch('a'), look(LookAround::Ahead, ch('b'))]); "lookahead")]
+    #[test_case("a(?!b)", cat(vec![ch('a'), look(LookAround::NegAhead, ch('b'))]); "negative lookahead")]
+    #[test_case("(?<=a)b", cat(vec![look(LookAround::Behind, ch('a')), ch('b')]); "lookbehind")]
+    #[test_case("(?<!a)b", cat(vec![look(LookAround::NegBehind, ch('a')), ch('b')]); "negative lookbehind")]
     #[test]
     fn parse_works(re: &str, expected: Ast) {
         let res = parse(re).unwrap();
         assert_eq!(res, expected);
     }
 
+    #[test_case("foo", Some((vec![Comp::Char('f')], false)); "literal")]
+    #[test_case("a*b", Some((vec![Comp::Char('a'), Comp::Char('b')], false)); "leading star")]
+    #[test_case("a*b?", Some((vec![Comp::Char('a'), Comp::Char('b')], true)); "nullable")]
+    #[test_case("(ab|cd)+", Some((vec![Comp::Char('a'), Comp::Char('c')], false)); "alternation")]
+    #[test_case("\\bfoo", Some((vec![Comp::Char('f')], false)); "assertion")]
+    #[test_case("a|.b", None; "any")]
+    #[test]
+    fn first_comps_works(re: &str, expected: Option<(Vec<Comp>, bool)>) {
+        let ast = parse(re).unwrap();
+        assert_eq!(ast.first_comps(), expected);
+    }
+
+    #[test_case("foo", "foo"; "literal")]
+    #[test_case("foo\\w+", "foo"; "literal then class")]
+    #[test_case("fo+", "f"; "literal then rep")]
+    #[test_case("(foo)", ""; "submatch")]
+    #[test_case("a|b", ""; "alternation")]
+    #[test]
+    fn literal_prefix_works(re: &str, expected: &str) {
+        let ast = parse(re).unwrap();
+        assert_eq!(ast.literal_prefix(), expected.chars().collect::<Vec<_>>());
+    }
+
     #[test_case("abc", "cba"; "lits only")]
     #[test_case("ab+c", "cb+a"; "lits with plus")]
     #[test_case("a*bc", "cba*"; "lits with star")]
@@ -649,6 +808,7 @@ mod tests {
     #[test_case("a(bc)+", "(cb)+a"; "repeated capture group")]
     #[test_case("a|b", "b|a"; "alts")]
     #[test_case("[Gg]oo+gle", "elgo+o[Gg]"; "with class and rep")]
+    #[test_case("a(?=bc)d", "d(?=bc)a"; "lookaround bodies are not reversed")]
     #[test]
     fn ast_reverse_works(re_fwd: &str, re_bck: &str) {
         let mut fwd_ast = parse(re_fwd).unwrap();
@@ -1,5 +1,5 @@
 //! The op-code compiler and optimised for the regex VM
-use super::ast::{Assertion, Ast, Comp, Greed, Rep, SmKind};
+use super::ast::{Assertion, Ast, Comp, Greed, LookAround, Rep, SmKind};
 use std::collections::BTreeSet;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -14,6 +14,7 @@ pub(super) type Prog = Vec<Inst>;
 pub(super) enum Op {
     Comp(Comp),
     Assertion(Assertion),
+    LookAround(usize),
     Split(usize, usize),
     Jump(usize),
     Save(usize),
@@ -176,10 +177,31 @@ impl Ast {
             Ast::SubMatch(kind, node) => {
                 Ops::Many(submatch_ops(kind, *node, offset, saves, reverse))
             }
+            Ast::LookAround(look, node) => Ops::One(lookaround_op(look, *node, saves, reverse)),
         }
     }
 }
 
+/// Lookaround bodies are compiled as their own anchored programs that are run by the VM
+/// when a thread reaches the resulting op. Lookbehind bodies are compiled in reverse so
+/// that they can be run over the input preceeding the current position, nearest first.
+///
+/// The VM only has access to the input as a stream of characters so whether the body is
+/// run against the input that has already been consumed or the input that is still to come
+/// depends on the direction of the parent program as well as the kind of lookaround.
+fn lookaround_op(look: LookAround, node: Ast, saves: &mut SmDetails, reverse: bool) -> Op {
+    let max_len = node.max_len();
+    let ops = compile(node, !look.is_ahead(), true);
+    saves.lookarounds.push(CompiledLookAround {
+        ops,
+        max_len,
+        negated: look.is_negated(),
+        stream_ahead: look.is_ahead() != reverse,
+    });
+
+    Op::LookAround(saves.lookarounds.len() - 1)
+}
+
 /// While we compile the AST into our VM opcodes we need to track how many saves we have
 /// encountered so far so that we can insert the correct save instructions around sub-matches.
 ///
@@ -189,22 +211,79 @@ impl Ast {
 struct SmDetails {
     n: usize,
     names: Vec<String>,
+    lookarounds: Vec<CompiledLookAround>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub(super) struct CompiledOps {
     pub(super) ops: Vec<Op>,
     pub(super) submatch_names: Vec<String>,
+    pub(super) lookarounds: Vec<CompiledLookAround>,
+    pub(super) prefilter: Option<Prefilter>,
 }
 
-pub(super) fn compile_ast(mut ast: Ast, reverse: bool) -> CompiledOps {
+/// Used by the VM to skip over input that is unable to begin a match while there
+/// are no threads part way through matching.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Prefilter {
+    /// Any match must begin with a character matching one of these
+    pub(super) first: Vec<Comp>,
+    /// Any match must begin with this literal string (may be empty)
+    pub(super) literal: Vec<char>,
+}
+
+impl Prefilter {
+    /// A prefilter is only possible for an expression that is unable to match the empty string
+    /// and that has a limited set of characters it can begin with.
+    fn try_from_ast(ast: &Ast) -> Option<Self> {
+        match ast.first_comps()? {
+            (_, true) => None,
+            (first, false) => Some(Self {
+                first,
+                literal: ast.literal_prefix(),
+            }),
+        }
+    }
+
+    #[inline]
+    pub(super) fn may_start_match(&self, ch: char) -> bool {
+        self.first.iter().any(|c| c.matches(ch))
+    }
+}
+
+/// The compiled body of a lookaround assertion, referenced by index from [Op::LookAround].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct CompiledLookAround {
+    pub(super) ops: CompiledOps,
+    /// The maximum number of characters the body is able to match (None if unbounded)
+    pub(super) max_len: Option<usize>,
+    pub(super) negated: bool,
+    /// Whether the body needs to run against the input still to come (rather than the
+    /// input that has already been consumed) in terms of the direction of the parent program.
+    pub(super) stream_ahead: bool,
+}
+
+pub(super) fn compile_ast(ast: Ast, reverse: bool) -> CompiledOps {
+    compile(ast, reverse, false)
+}
+
+fn compile(mut ast: Ast, reverse: bool, anchored: bool) -> CompiledOps {
     if reverse {
         ast.reverse();
     }
 
+    // Anchored programs are only run from a single position so they have nothing to skip and
+    // reverse programs are only used for single backwards searches from the cursor.
+    let prefilter = if anchored || reverse {
+        None
+    } else {
+        Prefilter::try_from_ast(&ast)
+    };
+
     let mut saves = SmDetails {
         n: 0,
         names: Vec::new(),
+        lookarounds: Vec::new(),
     };
     let prog = match ast.into_ops(0, &mut saves, reverse) {
         Ops::One(op) => vec![op],
@@ -217,6 +296,23 @@ pub(super) fn compile_ast(mut ast: Ast, reverse: bool) -> CompiledOps {
         (Op::Save(0), Op::Save(1))
     };
 
+    if anchored {
+        let mut full = vec![first_save];
+        full.extend(prog.into_iter().map(|op| match op {
+            Op::Jump(j) => Op::Jump(j + 1),
+            Op::Split(l1, l2) => Op::Split(l1 + 1, l2 + 1),
+            op => op,
+        }));
+        full.extend([second_save, Op::Match]);
+
+        return CompiledOps {
+            ops: full,
+            submatch_names: saves.names,
+            lookarounds: saves.lookarounds,
+            prefilter,
+        };
+    }
+
     // Compiled code for "@*?" to allow for unanchored matching.
     // Save(0) marks the beginning of the regex in the input
     // Save(1) marks the end of the regex in the input
@@ -238,6 +334,8 @@ pub(super) fn compile_ast(mut ast: Ast, reverse: bool) -> CompiledOps {
     CompiledOps {
         ops: full,
         submatch_names: saves.names,
+        lookarounds: saves.lookarounds,
+        prefilter,
     }
 }
 
@@ -296,40 +394,49 @@ fn inline_jumps(ops: &mut [Op], i: usize) -> bool {
 // - it doesn't follow a comparison instruction (pc wouldn't advance to it)
 // - nothing now jumps or splits to it
 fn strip_unreachable_instructions(ops: &mut Vec<Op>) {
-    let mut to_from: Vec<(usize, usize)> = Vec::with_capacity(ops.len());
     let mut jumps = BTreeSet::new();
-
-    for (i, op) in ops.iter().enumerate() {
+    for op in ops.iter() {
         match op {
             Op::Jump(j) => {
                 jumps.insert(*j);
-                to_from.push((*j, i));
-            }
-            Op::Split(l1, l2) => {
-                jumps.extend([*l1, *l2]);
-                to_from.push((*l1, i));
-                to_from.push((*l2, i));
             }
+            Op::Split(l1, l2) => jumps.extend([*l1, *l2]),
             _ => (),
         }
     }
 
-    for i in (1..ops.len() - 1).rev() {
-        if !ops[i - 1].is_control() || jumps.contains(&i) {
-            continue;
-        }
+    let unreachable: Vec<bool> = (0..ops.len())
+        .map(|i| i > 0 && i < ops.len() - 1 && ops[i - 1].is_control() && !jumps.contains(&i))
+        .collect();
+
+    // The new position of each instruction once the unreachable ones have been removed. Nothing
+    // jumps to an unreachable instruction so their entries are never used.
+    let mut n_removed = 0;
+    let new_pos: Vec<usize> = unreachable
+        .iter()
+        .enumerate()
+        .map(|(i, &u)| {
+            let pos = i - n_removed;
+            n_removed += u as usize;
+            pos
+        })
+        .collect();
+
+    let mut i = 0;
+    ops.retain(|_| {
+        i += 1;
+        !unreachable[i - 1]
+    });
 
-        for &(to, from) in to_from.iter() {
-            if to > i {
-                match &mut ops[from] {
-                    Op::Jump(x) => *x -= 1,
-                    Op::Split(x, _) if *x > i => *x -= 1,
-                    Op::Split(_, x) if *x > i => *x -= 1,
-                    _ => (),
-                }
+    for op in ops.iter_mut() {
+        match op {
+            Op::Jump(j) => *j = new_pos[*j],
+            Op::Split(l1, l2) => {
+                *l1 = new_pos[*l1];
+                *l2 = new_pos[*l2];
             }
+            _ => (),
         }
-        ops.remove(i);
     }
 }
 
@@ -374,6 +481,8 @@ mod tests {
     #[test_case("^foo", vec![BOL, c('f'), c('o'), c('o')]; "BOL then literals")]
     #[test_case("foo$", vec![c('f'), c('o'), c('o'), EOL]; "literals then EOL")]
     #[test_case("(?<xy>X|Y)(a|b)", vec![sv(2), sp(6, 8), c('X'), jmp(9), c('Y'), sv(3), sp(11, 13), c('a'), jmp(14), c('b')]; "named submatch demoting unnamed")]
+    #[test_case("a(?=b)c", vec![c('a'), Op::LookAround(0), c('c')]; "lookahead")]
+    #[test_case("(?<!a)b(?!c)", vec![Op::LookAround(0), c('b'), Op::LookAround(1)]; "multiple lookarounds")]
     #[test]
     fn ast_compile_works(re: &str, expected: Vec<Op>) {
         let ast = parse(re).unwrap();
@@ -402,4 +511,36 @@ mod tests {
 
         assert_eq!(prog, full);
     }
+
+    #[test_case("a(?=bc)", true, false, vec![sv(0), c('b'), c('c'), sv(1)]; "lookahead")]
+    #[test_case("a(?!bc)", true, true, vec![sv(0), c('b'), c('c'), sv(1)]; "negative lookahead")]
+    #[test_case("(?<=bc)a", false, false, vec![Op::RSave(1), c('c'), c('b'), Op::RSave(0)]; "lookbehind")]
+    #[test_case("(?<!bc)a", false, true, vec![Op::RSave(1), c('c'), c('b'), Op::RSave(0)]; "negative lookbehind")]
+    #[test]
+    fn lookaround_compile_works(
+        re: &str,
+        stream_ahead: bool,
+        negated: bool,
+        mut expected: Vec<Op>,
+    ) {
+        let ast = parse(re).unwrap();
+        let lookarounds = compile_ast(ast, false).lookarounds;
+        expected.push(Op::Match);
+
+        assert_eq!(lookarounds.len(), 1);
+        assert_eq!(lookarounds[0].ops.ops, expected);
+        assert_eq!(lookarounds[0].stream_ahead, stream_ahead);
+        assert_eq!(lookarounds[0].negated, negated);
+    }
+
+    #[test_case("a(?=b)"; "lookahead")]
+    #[test_case("(?<=b)a"; "lookbehind")]
+    #[test]
+    fn lookaround_stream_direction_flips_when_reversed(re: &str) {
+        let fwd = compile_ast(parse(re).unwrap(), false).lookarounds;
+        let rev = compile_ast(parse(re).unwrap(), true).lookarounds;
+
+        assert_eq!(fwd[0].ops, rev[0].ops);
+        assert_ne!(fwd[0].stream_ahead, rev[0].stream_ahead);
+    }
 }
@@ -0,0 +1,427 @@
+//! A lazily constructed DFA for scanning an input for the regions that contain a match.
+//!
+//! Each DFA state is the set of positions in the compiled VM program that threads would be
+//! waiting at after following all Split, Jump and Save ops. States and the transitions between
+//! them are only computed the first time they are needed and then cached, so that repeated
+//! scanning of the input is a table lookup per character rather than stepping every thread.
+//!
+//! For unanchored programs the "@*?" prelude is not part of the state: threads that would be
+//! started at each position are added when computing each transition instead. A state is then
+//! the set of positions held by threads that began earlier in the input, which means that an
+//! empty state marks a point where no partial match is in progress.
+//!
+//! As the DFA has no notion of thread priority or submatch positions it is only able to answer
+//! whether or not there is a match and roughly where it is: the VM is still needed to find the
+//! leftmost-longest match and its submatches. Programs containing assertions or lookarounds
+//! depend on the surrounding input rather than just the current character so they are always
+//! run on the VM.
+use super::{
+    ast::Comp,
+    compile::{Op, Prog},
+};
+use std::collections::{HashMap, VecDeque};
+
+/// Upper bound on the number of cached states before the cache is flushed
+const MAX_STATES: usize = 4096;
+/// Upper bound on the number of characters held back for the VM while searching for a match
+const MAX_WINDOW: usize = 64 * 1024;
+/// Sentinel for a transition that has not been computed yet
+const UNKNOWN: u32 = u32::MAX;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct LazyDfa {
+    /// The program positions (Comp and Match ops only) making up each state
+    states: Vec<Box<[usize]>>,
+    /// Lookup from a set of program positions to its state
+    ids: HashMap<Box<[usize]>, u32>,
+    /// Whether or not each state contains a Match op
+    matching: Vec<bool>,
+    /// Cached transitions for ASCII characters
+    ascii: Vec<[u32; 128]>,
+    /// Cached transitions for everything else
+    other: HashMap<(u32, char), u32>,
+    /// Program positions of the threads started at each position of the input for unanchored
+    /// programs (empty for anchored programs)
+    restart: Box<[usize]>,
+    /// Whether or not the threads in self.restart contain a Match op
+    restart_matches: bool,
+    /// Whether or not the program runs over its input in reverse
+    reverse: bool,
+    /// Scratch space used when computing closures
+    stack: Vec<usize>,
+    seen: Vec<bool>,
+}
+
+impl LazyDfa {
+    /// Returns None if the program is not supported by the DFA.
+    pub(super) fn try_new(prog: &Prog) -> Option<Self> {
+        if prog
+            .iter()
+            .any(|inst| matches!(inst.op, Op::Assertion(_) | Op::LookAround(_)))
+        {
+            return None;
+        }
+
+        let mut dfa = Self {
+            states: Vec::new(),
+            ids: HashMap::new(),
+            matching: Vec::new(),
+            ascii: Vec::new(),
+            other: HashMap::new(),
+            restart: Box::new([]),
+            restart_matches: false,
+            reverse: false,
+            stack: Vec::new(),
+            seen: vec![false; prog.len()],
+        };
+
+        // Unanchored programs begin with "@*?" followed by the body of the regex at pc 3
+        let unanchored = prog.len() > 3
+            && prog[0].op == Op::Split(3, 1)
+            && prog[1].op == Op::Comp(Comp::TrueAny);
+        if unanchored {
+            dfa.stack.push(3);
+            dfa.restart = dfa.closure(prog);
+            dfa.restart_matches = dfa.restart.iter().any(|&pc| prog[pc].op == Op::Match);
+            dfa.reverse = matches!(prog[3].op, Op::RSave(_));
+        }
+        dfa.add_start_state(prog);
+
+        Some(dfa)
+    }
+
+    /// Whether or not [LazyDfa::find_window] can be used with this DFA. Programs that are
+    /// anchored or able to match the empty string gain nothing from it.
+    pub(super) fn can_find_window(&self) -> bool {
+        !self.restart.is_empty() && !self.restart_matches
+    }
+
+    /// Run the DFA over the given input, returning as soon as a match is found.
+    pub(super) fn is_match<I>(&mut self, prog: &Prog, input: &mut I) -> bool
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        // The start state is always the first state
+        let mut s = 0;
+        if self.matching[s as usize] {
+            return true;
+        }
+
+        for (_, ch) in input {
+            s = self.next_state(prog, s, ch);
+            if self.matching[s as usize] {
+                return true;
+            } else if self.states[s as usize].is_empty() && self.restart.is_empty() {
+                return false;
+            }
+        }
+
+        false
+    }
+
+    /// Run the DFA over the given input until the end of the first match is found, storing the
+    /// input from the last point where no partial match was in progress in buf. The leftmost
+    /// match begins within buf so the VM can be run from there instead of from the start, and
+    /// the position it should be started from is returned.
+    ///
+    /// Returns None if there is no match in the input. If the window grows beyond
+    /// [MAX_WINDOW] characters then it is returned without the end of a match having been
+    /// found so that the VM can continue the search without holding more of the input.
+    pub(super) fn find_window<I>(
+        &mut self,
+        prog: &Prog,
+        input: &mut I,
+        buf: &mut VecDeque<(usize, char)>,
+    ) -> Option<usize>
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        debug_assert!(self.can_find_window());
+        let mut s = 0;
+
+        for (i, ch) in input {
+            if self.states[s as usize].is_empty() {
+                buf.clear();
+            }
+            buf.push_back((i, ch));
+
+            if buf.len() > MAX_WINDOW {
+                break;
+            }
+            s = self.next_state(prog, s, ch);
+            if self.matching[s as usize] {
+                break;
+            }
+        }
+
+        // Positions in reverse programs refer to the gap after the character at that index
+        let &(i, _) = buf.front()?;
+        if !self.matching[s as usize] && buf.len() <= MAX_WINDOW {
+            return None;
+        }
+
+        Some(if self.reverse { i + 1 } else { i })
+    }
+
+    fn add_start_state(&mut self, prog: &Prog) {
+        let pcs = if self.restart.is_empty() {
+            self.stack.push(0);
+            self.closure(prog)
+        } else {
+            Box::new([])
+        };
+        self.intern(prog, pcs);
+    }
+
+    #[inline]
+    fn next_state(&mut self, prog: &Prog, s: u32, ch: char) -> u32 {
+        let cached = if ch.is_ascii() {
+            self.ascii[s as usize][ch as usize]
+        } else {
+            self.other.get(&(s, ch)).copied().unwrap_or(UNKNOWN)
+        };
+
+        if cached != UNKNOWN {
+            return cached;
+        }
+
+        for &pc in self.states[s as usize].iter().chain(self.restart.iter()) {
+            if let Op::Comp(comp) = &prog[pc].op {
+                if comp.matches(ch) {
+                    self.stack.push(pc + 1);
+                }
+            }
+        }
+        let pcs = self.closure(prog);
+
+        // Once the cache is full we drop everything we know other than the start state and
+        // rebuild from the state we are moving to.
+        let (s, t) = if self.states.len() >= MAX_STATES && !self.ids.contains_key(&pcs) {
+            self.states.clear();
+            self.ids.clear();
+            self.matching.clear();
+            self.ascii.clear();
+            self.other.clear();
+            self.add_start_state(prog);
+            (None, self.intern(prog, pcs))
+        } else {
+            (Some(s), self.intern(prog, pcs))
+        };
+
+        if let Some(s) = s {
+            if ch.is_ascii() {
+                self.ascii[s as usize][ch as usize] = t;
+            } else {
+                self.other.insert((s, ch), t);
+            }
+        }
+
+        t
+    }
+
+    /// Follow all control flow ops from the positions on the stack, returning the sorted set
+    /// of Comp and Match ops that are reachable.
+    fn closure(&mut self, prog: &Prog) -> Box<[usize]> {
+        let mut pcs = Vec::new();
+
+        while let Some(pc) = self.stack.pop() {
+            if self.seen[pc] {
+                continue;
+            }
+            self.seen[pc] = true;
+
+            match prog[pc].op {
+                Op::Jump(l) => self.stack.push(l),
+                Op::Split(l1, l2) => self.stack.extend([l2, l1]),
+                Op::Save(_) | Op::RSave(_) => self.stack.push(pc + 1),
+                _ => pcs.push(pc),
+            }
+        }
+
+        self.seen.iter_mut().for_each(|b| *b = false);
+        pcs.sort_unstable();
+
+        pcs.into_boxed_slice()
+    }
+
+    fn intern(&mut self, prog: &Prog, pcs: Box<[usize]>) -> u32 {
+        if let Some(&id) = self.ids.get(&pcs) {
+            return id;
+        }
+
+        let id = self.states.len() as u32;
+        self.matching
+            .push(self.restart_matches || pcs.iter().any(|&pc| prog[pc].op == Op::Match));
+        self.ascii.push([UNKNOWN; 128]);
+        self.ids.insert(pcs.clone(), id);
+        self.states.push(pcs);
+
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regex::Regex;
+    use simple_test_case::test_case;
+
+    #[test_case("\\bfoo"; "word boundary")]
+    #[test_case("^foo"; "line start")]
+    #[test_case("foo(?=bar)"; "lookahead")]
+    #[test]
+    fn context_dependent_programs_are_not_supported(re: &str) {
+        let r = Regex::compile(re).unwrap();
+        assert!(LazyDfa::try_new(&r.prog).is_none());
+    }
+
+    #[test_case("foo", "a foo b", true; "literal")]
+    #[test_case("foo", "a fo b", false; "literal non matching")]
+    #[test_case("(abc)*d|(abc)*Z", "abcabcabcZ", true; "alternation")]
+    #[test_case("[0-9]+px", "width: 12px", true; "class")]
+    #[test_case("[0-9]+px", "width: 12em", false; "class non matching")]
+    #[test_case("a.*b", "a\nb", false; "dot does not match newline")]
+    #[test_case("a@*b", "a\nb", true; "true any")]
+    #[test_case("\\p{Greek}+", "abc αβγ", true; "unicode")]
+    #[test]
+    fn is_match_works(re: &str, s: &str, expected: bool) {
+        let r = Regex::compile(re).unwrap();
+        let mut dfa = LazyDfa::try_new(&r.prog).unwrap();
+
+        assert_eq!(dfa.is_match(&r.prog, &mut s.chars().enumerate()), expected);
+    }
+
+    /// A small xorshift PRNG so that the differential tests below are reproducible without
+    /// needing an additional dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+
+        fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+            items[self.next(items.len())]
+        }
+    }
+
+    fn random_pattern(rng: &mut Rng, depth: usize) -> String {
+        let atom = match rng.next(if depth == 0 { 3 } else { 5 }) {
+            0 => rng.pick(&["a", "b", "c"]).to_string(),
+            1 => rng.pick(&[".", "[a-c]", "[^b]", "\\w", "\\s"]).to_string(),
+            2 => rng.pick(&["ab", "ca", "x"]).to_string(),
+            3 => format!("({})", random_pattern(rng, depth - 1)),
+            _ => format!(
+                "({}|{})",
+                random_pattern(rng, depth - 1),
+                random_pattern(rng, depth - 1)
+            ),
+        };
+        let rep = rng.pick(&["", "", "*", "+", "?", "{1,2}"]);
+
+        match rng.next(3) {
+            0 if depth > 0 => format!("{atom}{rep}{}", random_pattern(rng, depth - 1)),
+            _ => format!("{atom}{rep}"),
+        }
+    }
+
+    #[test]
+    fn dfa_agrees_with_the_vm() {
+        let mut rng = Rng(0x2545_F491_4F6C_DD1D);
+
+        for _ in 0..2000 {
+            let re = random_pattern(&mut rng, 3);
+            let mut r = Regex::compile(&re).unwrap();
+            if LazyDfa::try_new(&r.prog).is_none() {
+                continue;
+            }
+
+            for _ in 0..10 {
+                let s: String = (0..rng.next(8))
+                    .map(|_| rng.pick(&["a", "b", "c", "x", " ", "\n"]))
+                    .collect();
+                let vm = r.match_str(&s).is_some();
+
+                assert_eq!(r.matches_str(&s), vm, "re={re:?} s={s:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn dfa_windows_agree_with_the_vm() {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+
+        for _ in 0..2000 {
+            let re = random_pattern(&mut rng, 3);
+            let mut r = Regex::compile(&re).unwrap();
+            let mut vm = r.clone().without_dfa();
+
+            for _ in 0..10 {
+                let s: String = (0..rng.next(16))
+                    .map(|_| rng.pick(&["a", "b", "c", "x", " ", "\n"]))
+                    .collect();
+                let expected: Vec<_> = vm.match_str_all(&s).map(|m| m.loc()).collect();
+                let locs: Vec<_> = r.match_str_all(&s).map(|m| m.loc()).collect();
+
+                assert_eq!(locs, expected, "re={re:?} s={s:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn find_window_starts_from_the_last_idle_position() {
+        let r = Regex::compile("a*b").unwrap();
+        let mut dfa = LazyDfa::try_new(&r.prog).unwrap();
+        let mut buf = VecDeque::new();
+
+        let sp = dfa.find_window(&r.prog, &mut "xx aab yy".chars().enumerate(), &mut buf);
+        let window: String = buf.iter().map(|&(_, c)| c).collect();
+
+        assert_eq!(sp, Some(3));
+        assert_eq!(window, "aab");
+    }
+
+    #[test]
+    fn find_window_returns_none_without_a_match() {
+        let r = Regex::compile("a*b").unwrap();
+        let mut dfa = LazyDfa::try_new(&r.prog).unwrap();
+        let mut buf = VecDeque::new();
+
+        let sp = dfa.find_window(&r.prog, &mut "xx aa yy".chars().enumerate(), &mut buf);
+
+        assert_eq!(sp, None);
+    }
+
+    #[test]
+    fn matches_longer_than_the_max_window_are_found() {
+        let s = format!("xx {}b", "a".repeat(MAX_WINDOW + 10));
+        let mut r = Regex::compile("a*b").unwrap();
+        let m = r.match_str(&s).unwrap();
+
+        assert_eq!(m.loc(), (3, s.len()));
+    }
+
+    #[test_case("(ab|c)*(a+|c*[a-c])", "xx  "; "split after stripped jump")]
+    #[test]
+    fn dfa_agrees_with_the_vm_for_known_cases(re: &str, s: &str) {
+        let mut r = Regex::compile(re).unwrap();
+        let vm = r.match_str(s).is_some();
+
+        assert_eq!(r.matches_str(s), vm);
+    }
+
+    #[test]
+    fn states_are_reused_across_runs() {
+        let r = Regex::compile("(a|b)*c").unwrap();
+        let mut dfa = LazyDfa::try_new(&r.prog).unwrap();
+
+        assert!(dfa.is_match(&r.prog, &mut "ababababc".chars().enumerate()));
+        let n_states = dfa.states.len();
+        assert!(dfa.is_match(&r.prog, &mut "babababac".chars().enumerate()));
+
+        assert_eq!(dfa.states.len(), n_states);
+    }
+}
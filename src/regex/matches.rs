@@ -1,5 +1,5 @@
 use super::vm::{Regex, N_SLOTS};
-use crate::buffer::{GapBuffer, IdxChars};
+use crate::buffer::{GapBuffer, IdxChars, Text};
 use std::{
     iter::{Enumerate, Skip},
     rc::Rc,
@@ -125,7 +125,7 @@ impl Match {
         (start, end)
     }
 
-    fn sub_loc_by_name(&self, name: &str) -> Option<(usize, usize)> {
+    pub(crate) fn sub_loc_by_name(&self, name: &str) -> Option<(usize, usize)> {
         let n = self.submatch_names.iter().position(|s| s == name)?;
         self.sub_loc(n + 1)
     }
@@ -184,6 +184,21 @@ impl<'a> IndexedChars for &'a GapBuffer {
     }
 }
 
+impl<'a> IndexedChars for &'a Text {
+    type I = IdxChars<'a>;
+
+    fn iter_from(&self, from: usize) -> Option<Self::I> {
+        if from >= self.len_chars() {
+            None
+        } else {
+            Some(
+                self.slice(from, self.len_chars())
+                    .indexed_chars(from, false),
+            )
+        }
+    }
+}
+
 /// An iterator over sequential, non overlapping matches of a Regex
 /// against a given input
 #[derive(Debug)]
@@ -203,9 +218,13 @@ where
     type Item = Match;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let m = self
-            .r
-            .match_iter(&mut self.it.iter_from(self.from)?, self.from)?;
+        let mut it = self.it.iter_from(self.from)?;
+        // Nothing precedes the first match attempt but subsequent ones carry on from where the
+        // previous match left off so that lookbehinds are able to see the earlier input
+        let m = match self.from {
+            0 => self.r.match_iter(&mut it, self.from)?,
+            _ => self.r.resume_match_iter(&mut it, self.from)?,
+        };
 
         let (_, from) = m.loc();
         if from == self.from {
@@ -4,10 +4,13 @@
 //! Thompson's original paper on writing a regex engine can be found here:
 //!   https://dl.acm.org/doi/pdf/10.1145/363347.363387
 use std::{iter::Peekable, str::Chars};
+use unicode::{Posix, Property};
 
 mod ast;
 mod compile;
+mod dfa;
 mod matches;
+mod unicode;
 mod vm;
 
 pub use matches::{Match, MatchIter};
@@ -38,73 +41,117 @@ pub enum Error {
     UnclosedGroupName(String),
     /// Invalid group qualifier following (?...)
     UnknownGroupQualifier(char),
+    /// Unknown Unicode property or POSIX class name
+    UnknownProperty(String),
 }
 
-/// Helper for converting characters to 0 based inicies for looking things up in caches.
-const fn char_ix(ch: char) -> usize {
-    ((ch as u16) & 0xFF) as usize
+/// Escape all regex meta-characters in the given string so that it can be used to construct a
+/// [Regex] that matches it literally.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for ch in s.chars() {
+        if matches!(
+            ch,
+            '*' | '+'
+                | '?'
+                | '.'
+                | '@'
+                | '('
+                | ')'
+                | '['
+                | ']'
+                | '{'
+                | '}'
+                | '|'
+                | '\\'
+                | '^'
+                | '$'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+
+    escaped
 }
 
-const fn init_escapes() -> [Option<char>; 256] {
+const fn init_escapes() -> [Option<char>; 128] {
     macro_rules! escape {
         ($escapes:expr, $($ch:expr),+) => {
-            $($escapes[char_ix($ch)] = Some($ch);)+
+            $($escapes[$ch as usize] = Some($ch);)+
         };
         ($escapes:expr, $($ch:expr => $esc:expr),+) => {
-            $($escapes[char_ix($ch)] = Some($esc);)+
+            $($escapes[$ch as usize] = Some($esc);)+
         };
     }
 
-    let mut escapes = [None; 256];
+    let mut escapes = [None; 128];
     escape!(escapes, '*', '+', '?', '.', '@', '(', ')', '[', ']', '{', '}', '|');
     escape!(escapes, '\\', '\'', '"', '^', '$', '-');
-    escape!(escapes, 'b', 'B', 'd', 'D', 'w', 'W', 's', 'S');
+    escape!(escapes, 'b', 'B', 'd', 'D', 'w', 'W', 's', 'S', 'p', 'P');
     escape!(escapes, 'n'=>'\n', 'r'=>'\r', 't'=>'\t');
 
     escapes
 }
 
-/// Supported escape sequences
-const ESCAPES: [Option<char>; 256] = init_escapes();
+/// Supported escape sequences (all of which are ASCII)
+const ESCAPES: [Option<char>; 128] = init_escapes();
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct CharClass {
     negated: bool,
     chars: Vec<char>,
     ranges: Vec<(char, char)>,
+    /// Named classes along with whether or not they are negated
+    props: Vec<(Property, bool)>,
 }
 
 impl CharClass {
     fn try_parse(it: &mut Peekable<Chars<'_>>) -> Result<Self, Error> {
-        let mut next = || next_char(it)?.ok_or(Error::InvalidClass);
-        let (mut ch, _) = next()?;
-
-        let negated = ch == '^';
-        if negated {
-            (ch, _) = next()?
-        };
-        let mut chars = vec![ch];
+        let negated = it.next_if_eq(&'^').is_some();
+        let mut chars = vec![];
         let mut ranges = vec![];
+        let mut props = vec![];
+        // A leading ']' is treated as a literal rather than closing the class and ranges
+        // are only permitted if the previous element of the class was a literal character.
+        let mut first = true;
+        let mut prev_was_char = false;
 
         loop {
-            let (ch, escaped) = next()?;
+            let (ch, escaped) = next_char(it)?.ok_or(Error::InvalidClass)?;
             match ch {
-                ']' if !escaped => break,
+                ']' if !escaped && !first => break,
+
+                '[' if !escaped && it.next_if_eq(&':').is_some() => {
+                    props.push(try_parse_posix(it)?);
+                    prev_was_char = false;
+                }
 
-                '-' if !escaped => {
+                '-' if !escaped && prev_was_char && it.peek() != Some(&']') => {
                     let start = chars.pop().ok_or(Error::InvalidClass)?;
-                    let (end, _) = next()?;
+                    let (end, _) = next_char(it)?.ok_or(Error::InvalidClass)?;
                     ranges.push((start, end));
+                    prev_was_char = false;
+                }
+
+                'd' | 'D' | 'w' | 'W' | 's' | 'S' | 'p' | 'P' if escaped => {
+                    props.push(escaped_class(ch, it)?);
+                    prev_was_char = false;
                 }
 
-                ch => chars.push(ch),
+                ch => {
+                    chars.push(ch);
+                    prev_was_char = true;
+                }
             }
+            first = false;
         }
 
         Ok(Self {
             negated,
             chars,
             ranges,
+            props,
         })
     }
 
@@ -119,7 +166,8 @@ impl CharClass {
             || self
                 .ranges
                 .iter()
-                .any(|&(start, end)| ch >= start && ch <= end);
+                .any(|&(start, end)| ch >= start && ch <= end)
+            || self.props.iter().any(|(p, neg)| p.matches(ch) != *neg);
 
         if self.negated {
             !res
@@ -141,23 +189,85 @@ fn next_char(it: &mut Peekable<Chars<'_>>) -> Result<Option<(char, bool)>, Error
         None => return Err(Error::InvalidEscape('\0')),
     };
 
-    match ESCAPES[char_ix(ch)] {
+    match ESCAPES.get(ch as usize).copied().flatten() {
         Some(ch) => Ok(Some((ch, true))),
         None => Err(Error::InvalidEscape(ch)),
     }
 }
 
+/// Parse the remainder of a POSIX class, e.g. "[:alpha:]" or "[:^alpha:]", following "[:"
+fn try_parse_posix(it: &mut Peekable<Chars<'_>>) -> Result<(Property, bool), Error> {
+    let negated = it.next_if_eq(&'^').is_some();
+    let mut name = String::new();
+
+    loop {
+        match it.next() {
+            Some(':') if it.next_if_eq(&']').is_some() => break,
+            Some(ch) => name.push(ch),
+            None => return Err(Error::InvalidClass),
+        }
+    }
+
+    Ok((Property::Posix(Posix::try_from_name(&name)?), negated))
+}
+
+/// Parse a class escape such as "\w" or "\p{Greek}" into a property and whether or not it
+/// is negated. The leading "\" and class character have already been consumed.
+fn escaped_class(ch: char, it: &mut Peekable<Chars<'_>>) -> Result<(Property, bool), Error> {
+    let prop = match ch {
+        'd' | 'D' => Property::Digit,
+        'w' | 'W' => Property::Word,
+        's' | 'S' => Property::WhiteSpace,
+        'p' | 'P' => {
+            let name = match it.next() {
+                Some('{') => {
+                    let mut name = String::new();
+                    loop {
+                        match it.next() {
+                            Some('}') => break,
+                            Some(ch) => name.push(ch),
+                            None => return Err(Error::InvalidClass),
+                        }
+                    }
+                    name
+                }
+                Some(ch) => ch.to_string(),
+                None => return Err(Error::InvalidEscape(ch)),
+            };
+            Property::try_from_name(&name)?
+        }
+        _ => return Err(Error::InvalidEscape(ch)),
+    };
+
+    Ok((prop, ch.is_ascii_uppercase()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use simple_test_case::test_case;
 
+    #[test_case("foo"; "plain")]
+    #[test_case("a.b*c"; "meta characters")]
+    #[test_case("fn foo(x: &[u8]) -> Option<usize> { x.get(0)? }"; "code")]
+    #[test_case("^$@|\\+"; "anchors and escapes")]
+    #[test]
+    fn escaped_strings_match_literally(s: &str) {
+        let mut r = Regex::compile(&escape(s)).unwrap();
+        let haystack = format!("prefix {s} suffix");
+        let m = r.match_str(&haystack).unwrap();
+
+        assert_eq!(m.str_match_text(&haystack), s);
+    }
+
     #[test_case("_", &['_'], &[]; "single underscore")]
     #[test_case("abc_", &['a', 'b', 'c', '_'], &[]; "chars")]
     #[test_case("a-z", &[], &[('a', 'z')]; "single range")]
     #[test_case("a-zA-Z", &[], &[('a', 'z'), ('A', 'Z')]; "multi range")]
     #[test_case("a-z_./]", &['_', '.', '/'], &[('a', 'z')]; "compound")]
     #[test_case("a-zA-Z_\\-.]", &['_', '-', '.'], &[('a', 'z'), ('A', 'Z')]; "compound escaped dash")]
+    #[test_case("]a", &[']', 'a'], &[]; "leading close bracket")]
+    #[test_case("a-", &['a', '-'], &[]; "trailing dash")]
     #[test]
     fn parsing_classes_works(raw: &str, chars: &[char], ranges: &[(char, char)]) {
         // The outer regex parser consumes the initial '[' before passing through so test cases
@@ -168,9 +278,69 @@ mod tests {
                 negated,
                 chars: chars.to_vec(),
                 ranges: ranges.to_vec(),
+                props: vec![],
             };
 
             assert_eq!(cls, expected, "negated={negated}");
         }
     }
+
+    #[test_case("\\w]", &[], &[(Property::Word, false)]; "word")]
+    #[test_case("\\W]", &[], &[(Property::Word, true)]; "non word")]
+    #[test_case("\\d-]", &['-'], &[(Property::Digit, false)]; "digit then dash")]
+    #[test_case("\\pL]", &[], &[(Property::Letter, false)]; "single letter property")]
+    #[test_case("\\p{Greek}]", &[], &[(Property::Script(unicode::Script::Greek), false)]; "script")]
+    #[test_case("\\P{Lu}_]", &['_'], &[(Property::Uppercase, true)]; "negated property")]
+    #[test_case("[:alpha:]_]", &['_'], &[(Property::Posix(Posix::Alpha), false)]; "posix")]
+    #[test_case("[:^digit:]]", &[], &[(Property::Posix(Posix::Digit), true)]; "negated posix")]
+    #[test]
+    fn parsing_named_classes_works(raw: &str, chars: &[char], props: &[(Property, bool)]) {
+        let cls = CharClass::try_parse(&mut raw.chars().peekable()).unwrap();
+        let expected = CharClass {
+            negated: false,
+            chars: chars.to_vec(),
+            ranges: vec![],
+            props: props.to_vec(),
+        };
+
+        assert_eq!(cls, expected);
+    }
+
+    #[test_case("[:klingon:]]"; "posix")]
+    #[test_case("\\p{Klingon}]"; "property")]
+    #[test]
+    fn unknown_named_classes_error(raw: &str) {
+        let res = CharClass::try_parse(&mut raw.chars().peekable());
+        assert!(matches!(res, Err(Error::UnknownProperty(_))), "{res:?}");
+    }
+
+    #[test]
+    fn unicode_class_cases_work() {
+        let haystack = include_str!("../../test-data/unicode-classes/unicode-classes.haystack");
+        let cases = include_str!("../../test-data/unicode-classes/unicode-classes.cases");
+
+        for case in cases.lines() {
+            let mut parts = case.split('\t');
+            let re = parts.next().unwrap();
+            let expected: Vec<&str> = parts.collect();
+
+            let mut r = Regex::compile(re).unwrap();
+            let matches: Vec<String> = r
+                .match_str_all(haystack)
+                .map(|m| m.str_match_text(haystack))
+                .filter(|s| !s.is_empty())
+                .collect();
+
+            assert_eq!(matches, expected, "re={re}");
+        }
+    }
+
+    // Non-ASCII characters used to alias ASCII escapes when looking them up
+    #[test]
+    fn non_ascii_characters_are_not_escapes() {
+        assert_eq!(
+            next_char(&mut "\\Ţ".chars().peekable()),
+            Err(Error::InvalidEscape('Ţ'))
+        );
+    }
 }
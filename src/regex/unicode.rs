@@ -0,0 +1,458 @@
+//! Unicode aware character classes for the regex engine.
+//!
+//! General categories are approximated using the predicates provided by the standard library
+//! on `char` (along with small tables for combining marks and connector punctuation) and
+//! scripts are approximated by the main Unicode blocks they occupy. This keeps the tables small
+//! while covering the cases that come up when editing text.
+use super::Error;
+
+/// A named class of characters that can be used via "\p{...}", "\P{...}" or "[[:name:]]".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Property {
+    /// \p{L}: letters
+    Letter,
+    /// \p{Lu}: upper case letters
+    Uppercase,
+    /// \p{Ll}: lower case letters
+    Lowercase,
+    /// \p{N}: numbers of any kind
+    Number,
+    /// \p{Nd} or \d: decimal digits
+    Digit,
+    /// \p{Z}: space, line and paragraph separators
+    Separator,
+    /// \p{White_Space} or \s: white space
+    WhiteSpace,
+    /// \w: letters, marks, decimal digits and connector punctuation
+    Word,
+    /// \p{Cc}: control characters
+    Control,
+    /// \p{Greek}, \p{Cyrillic} etc
+    Script(Script),
+    /// [[:alpha:]], [[:digit:]] etc
+    Posix(Posix),
+}
+
+impl Property {
+    /// Parse a property name as used in "\p{...}" or "\pL".
+    pub(super) fn try_from_name(name: &str) -> Result<Self, Error> {
+        let p = match name {
+            "L" | "Letter" | "Alphabetic" => Self::Letter,
+            "Lu" | "Uppercase_Letter" | "Uppercase" => Self::Uppercase,
+            "Ll" | "Lowercase_Letter" | "Lowercase" => Self::Lowercase,
+            "N" | "Number" => Self::Number,
+            "Nd" | "Decimal_Number" | "Digit" => Self::Digit,
+            "Z" | "Separator" => Self::Separator,
+            "White_Space" | "Whitespace" => Self::WhiteSpace,
+            "Cc" | "Control" => Self::Control,
+            name => Self::Script(Script::try_from_name(name)?),
+        };
+
+        Ok(p)
+    }
+
+    #[inline]
+    pub(super) fn matches(&self, ch: char) -> bool {
+        match self {
+            Self::Letter => is_letter(ch),
+            Self::Uppercase => ch.is_uppercase(),
+            Self::Lowercase => ch.is_lowercase(),
+            Self::Number => ch.is_numeric(),
+            Self::Digit => is_digit(ch),
+            Self::Separator => is_separator(ch),
+            Self::WhiteSpace => ch.is_whitespace(),
+            Self::Word => is_word(ch),
+            Self::Control => ch.is_control(),
+            Self::Script(s) => s.contains(ch),
+            Self::Posix(p) => p.matches(ch),
+        }
+    }
+}
+
+/// Unicode aware check for "\w": alphabetic characters, combining marks, decimal digits,
+/// connector punctuation such as "_" and the zero width joiners.
+#[inline]
+pub(super) fn is_word(ch: char) -> bool {
+    if ch.is_ascii() {
+        ch.is_ascii_alphanumeric() || ch == '_'
+    } else {
+        ch.is_alphabetic()
+            || is_digit(ch)
+            || is_mark(ch)
+            || is_connector(ch)
+            || matches!(ch, '\u{200C}' | '\u{200D}')
+    }
+}
+
+/// Check for the L general category.
+///
+/// Alphabetic characters also include letter numbers (such as "Ⅷ"), vowel signs and other
+/// combining marks used by alphabetic scripts, and circled letters (such as "Ⓐ") which are
+/// all excluded here.
+#[inline]
+pub(super) fn is_letter(ch: char) -> bool {
+    if ch.is_ascii() {
+        return ch.is_ascii_alphabetic();
+    }
+
+    ch.is_alphabetic()
+        && !ch.is_numeric()
+        && !is_mark(ch)
+        && !matches!(ch, '\u{24B6}'..='\u{24E9}' | '\u{1F130}'..='\u{1F189}')
+}
+
+/// Check for the M general category: combining marks.
+#[inline]
+pub(super) fn is_mark(ch: char) -> bool {
+    let cp = ch as u32;
+    cp >= 0x0300 && MARKS.iter().any(|&(start, end)| cp >= start && cp <= end)
+}
+
+/// Check for the Pc general category: connector punctuation such as "_" and "‿".
+#[inline]
+pub(super) fn is_connector(ch: char) -> bool {
+    matches!(
+        ch,
+        '_' | '\u{203F}' | '\u{2040}' | '\u{2054}' | '\u{FE33}' | '\u{FE34}' | '\u{FE4D}'
+            ..='\u{FE4F}' | '\u{FF3F}'
+    )
+}
+
+/// The ranges of combining marks: the blocks dedicated to combining characters along with
+/// the marks used by the scripts supported by "\p{...}".
+const MARKS: [(u32, u32); 37] = [
+    (0x0300, 0x036F), // combining diacritical marks
+    (0x0483, 0x0489), // cyrillic
+    (0x0591, 0x05BD), // hebrew
+    (0x05BF, 0x05BF),
+    (0x05C1, 0x05C2),
+    (0x05C4, 0x05C5),
+    (0x05C7, 0x05C7),
+    (0x0610, 0x061A), // arabic
+    (0x064B, 0x065F),
+    (0x0670, 0x0670),
+    (0x06D6, 0x06DC),
+    (0x06DF, 0x06E4),
+    (0x06E7, 0x06E8),
+    (0x06EA, 0x06ED),
+    (0x08CA, 0x08E1),
+    (0x08E3, 0x08FF),
+    (0x0900, 0x0903), // devanagari
+    (0x093A, 0x093C),
+    (0x093E, 0x094F),
+    (0x0951, 0x0957),
+    (0x0962, 0x0963),
+    (0xA8E0, 0xA8F1),
+    (0x0E31, 0x0E31), // thai
+    (0x0E34, 0x0E3A),
+    (0x0E47, 0x0E4E),
+    (0x1AB0, 0x1AFF), // combining diacritical marks extended
+    (0x1DC0, 0x1DFF), // combining diacritical marks supplement
+    (0x20D0, 0x20F0), // combining diacritical marks for symbols
+    (0x2DE0, 0x2DFF), // cyrillic extended-a
+    (0x302A, 0x302F), // ideographic tone marks
+    (0x3099, 0x309A), // kana voicing marks
+    (0xA66F, 0xA672), // cyrillic extended-b
+    (0xA674, 0xA67D),
+    (0xA69E, 0xA69F),
+    (0xFE00, 0xFE0F),   // variation selectors
+    (0xFE20, 0xFE2F),   // combining half marks
+    (0xE0100, 0xE01EF), // variation selectors supplement
+];
+
+/// Check for the Z general category: the Zs, Zl and Zp categories. Unlike white space this does
+/// not include control characters such as "\n" and "\t".
+#[inline]
+pub(super) fn is_separator(ch: char) -> bool {
+    matches!(
+        ch,
+        ' ' | '\u{00A0}' | '\u{1680}' | '\u{2000}'
+            ..='\u{200A}' | '\u{2028}' | '\u{2029}' | '\u{202F}' | '\u{205F}' | '\u{3000}'
+    )
+}
+
+/// Unicode aware check for "\d" (the Nd general category)
+#[inline]
+pub(super) fn is_digit(ch: char) -> bool {
+    if ch.is_ascii() {
+        return ch.is_ascii_digit();
+    }
+
+    let cp = ch as u32;
+    if (0x1D7CE..=0x1D7FF).contains(&cp) {
+        return true; // mathematical digits are a single run of 50
+    }
+
+    DIGIT_ZEROS.iter().any(|&zero| cp >= zero && cp < zero + 10)
+}
+
+/// The code points for zero in each run of ten decimal digits outside of ASCII.
+const DIGIT_ZEROS: [u32; 61] = [
+    0x0660, 0x06F0, 0x07C0, 0x0966, 0x09E6, 0x0A66, 0x0AE6, 0x0B66, 0x0BE6, 0x0C66, 0x0CE6, 0x0D66,
+    0x0DE6, 0x0E50, 0x0ED0, 0x0F20, 0x1040, 0x1090, 0x17E0, 0x1810, 0x1946, 0x19D0, 0x1A80, 0x1A90,
+    0x1B50, 0x1BB0, 0x1C40, 0x1C50, 0xA620, 0xA8D0, 0xA900, 0xA9D0, 0xA9F0, 0xAA50, 0xABF0, 0xFF10,
+    0x104A0, 0x10D30, 0x11066, 0x110F0, 0x11136, 0x111D0, 0x112F0, 0x11450, 0x114D0, 0x11650,
+    0x116C0, 0x11730, 0x118E0, 0x11950, 0x11C50, 0x11D50, 0x11DA0, 0x16A60, 0x16AC0, 0x16B50,
+    0x1E140, 0x1E2F0, 0x1E4F0, 0x1E950, 0x1FBF0,
+];
+
+/// Scripts supported by "\p{...}"
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Script {
+    Arabic,
+    Armenian,
+    Cyrillic,
+    Devanagari,
+    Greek,
+    Han,
+    Hangul,
+    Hebrew,
+    Hiragana,
+    Katakana,
+    Latin,
+    Thai,
+}
+
+impl Script {
+    fn try_from_name(name: &str) -> Result<Self, Error> {
+        let s = match name {
+            "Arabic" => Self::Arabic,
+            "Armenian" => Self::Armenian,
+            "Cyrillic" => Self::Cyrillic,
+            "Devanagari" => Self::Devanagari,
+            "Greek" => Self::Greek,
+            "Han" => Self::Han,
+            "Hangul" => Self::Hangul,
+            "Hebrew" => Self::Hebrew,
+            "Hiragana" => Self::Hiragana,
+            "Katakana" => Self::Katakana,
+            "Latin" => Self::Latin,
+            "Thai" => Self::Thai,
+            _ => return Err(Error::UnknownProperty(name.to_string())),
+        };
+
+        Ok(s)
+    }
+
+    fn ranges(&self) -> &'static [(u32, u32)] {
+        match self {
+            Self::Arabic => &[
+                (0x0600, 0x06FF),
+                (0x0750, 0x077F),
+                (0x08A0, 0x08FF),
+                (0xFB50, 0xFDFF),
+                (0xFE70, 0xFEFF),
+            ],
+            Self::Armenian => &[(0x0531, 0x058F), (0xFB13, 0xFB17)],
+            Self::Cyrillic => &[
+                (0x0400, 0x052F),
+                (0x1C80, 0x1C8F),
+                (0x2DE0, 0x2DFF),
+                (0xA640, 0xA69F),
+            ],
+            Self::Devanagari => &[(0x0900, 0x097F), (0xA8E0, 0xA8FF)],
+            // The Greek and Coptic block also holds the Coptic letters at 0x03E2..=0x03EF and
+            // a handful of characters that are common to all scripts
+            Self::Greek => &[
+                (0x0370, 0x0373),
+                (0x0375, 0x0377),
+                (0x037A, 0x037D),
+                (0x037F, 0x037F),
+                (0x0384, 0x0384),
+                (0x0386, 0x0386),
+                (0x0388, 0x03E1),
+                (0x03F0, 0x03FF),
+                (0x1F00, 0x1FFF),
+            ],
+            Self::Han => &[
+                (0x2E80, 0x2FDF),
+                (0x3005, 0x3007),
+                (0x3021, 0x3029),
+                (0x3400, 0x4DBF),
+                (0x4E00, 0x9FFF),
+                (0xF900, 0xFAFF),
+                (0x20000, 0x323AF),
+            ],
+            Self::Hangul => &[
+                (0x1100, 0x11FF),
+                (0x3130, 0x318F),
+                (0xA960, 0xA97F),
+                (0xAC00, 0xD7FF),
+            ],
+            Self::Hebrew => &[(0x0591, 0x05F4), (0xFB1D, 0xFB4F)],
+            Self::Hiragana => &[(0x3041, 0x309F)],
+            Self::Katakana => &[(0x30A0, 0x30FF), (0x31F0, 0x31FF), (0xFF66, 0xFF9D)],
+            Self::Latin => &[
+                (0x0041, 0x005A),
+                (0x0061, 0x007A),
+                (0x00AA, 0x00AA),
+                (0x00BA, 0x00BA),
+                (0x00C0, 0x00D6),
+                (0x00D8, 0x00F6),
+                (0x00F8, 0x024F),
+                (0x1E00, 0x1EFF),
+                (0x2C60, 0x2C7F),
+                (0xA720, 0xA7FF),
+                (0xFF21, 0xFF3A),
+                (0xFF41, 0xFF5A),
+            ],
+            Self::Thai => &[(0x0E01, 0x0E5B)],
+        }
+    }
+
+    #[inline]
+    fn contains(&self, ch: char) -> bool {
+        let cp = ch as u32;
+        self.ranges()
+            .iter()
+            .any(|&(start, end)| cp >= start && cp <= end)
+    }
+}
+
+/// POSIX character classes for use inside of a bracketed class, e.g. "[[:alpha:]_]".
+///
+/// These are Unicode aware in the same way as "\w", "\d" and "\s" with the exception of
+/// "[[:xdigit:]]", which only matches the ASCII hex digits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Posix {
+    Alnum,
+    Alpha,
+    Blank,
+    Cntrl,
+    Digit,
+    Graph,
+    Lower,
+    Print,
+    Punct,
+    Space,
+    Upper,
+    Word,
+    XDigit,
+}
+
+impl Posix {
+    pub(super) fn try_from_name(name: &str) -> Result<Self, Error> {
+        let p = match name {
+            "alnum" => Self::Alnum,
+            "alpha" => Self::Alpha,
+            "blank" => Self::Blank,
+            "cntrl" => Self::Cntrl,
+            "digit" => Self::Digit,
+            "graph" => Self::Graph,
+            "lower" => Self::Lower,
+            "print" => Self::Print,
+            "punct" => Self::Punct,
+            "space" => Self::Space,
+            "upper" => Self::Upper,
+            "word" => Self::Word,
+            "xdigit" => Self::XDigit,
+            _ => return Err(Error::UnknownProperty(name.to_string())),
+        };
+
+        Ok(p)
+    }
+
+    #[inline]
+    fn matches(&self, ch: char) -> bool {
+        // Zs: separators other than the line and paragraph separators
+        let is_space_separator = |ch| is_separator(ch) && !matches!(ch, '\u{2028}' | '\u{2029}');
+        let is_graph = |ch: char| !ch.is_whitespace() && !ch.is_control();
+
+        match self {
+            Self::Alnum => ch.is_alphabetic() || is_digit(ch),
+            Self::Alpha => ch.is_alphabetic(),
+            Self::Blank => ch == '\t' || is_space_separator(ch),
+            Self::Cntrl => ch.is_control(),
+            Self::Digit => is_digit(ch),
+            Self::Graph => is_graph(ch),
+            Self::Lower => ch.is_lowercase(),
+            Self::Print => is_graph(ch) || is_space_separator(ch),
+            Self::Punct => is_graph(ch) && !ch.is_alphanumeric() && !is_mark(ch),
+            Self::Space => ch.is_whitespace(),
+            Self::Upper => ch.is_uppercase(),
+            Self::Word => is_word(ch),
+            Self::XDigit => ch.is_ascii_hexdigit(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+
+    #[test_case('7', true; "ascii")]
+    #[test_case('٣', true; "arabic indic")]
+    #[test_case('३', true; "devanagari")]
+    #[test_case('７', true; "fullwidth")]
+    #[test_case('𝟘', true; "mathematical")]
+    #[test_case('½', false; "vulgar fraction")]
+    #[test_case('Ⅷ', false; "roman numeral")]
+    #[test_case('a', false; "letter")]
+    #[test]
+    fn is_digit_works(ch: char, expected: bool) {
+        assert_eq!(is_digit(ch), expected);
+    }
+
+    #[test_case("Greek", 'λ', true; "greek")]
+    #[test_case("Greek", 'l', false; "greek latin")]
+    #[test_case("Greek", 'Ϣ', false; "greek coptic")]
+    #[test_case("Latin", 'é', true; "latin accented")]
+    #[test_case("Cyrillic", 'Ж', true; "cyrillic")]
+    #[test_case("Han", '漢', true; "han")]
+    #[test_case("Hiragana", 'ひ', true; "hiragana")]
+    #[test_case("L", 'ß', true; "letter")]
+    #[test_case("L", 'Ⅷ', false; "letter number")]
+    #[test_case("L", 'Ⓐ', false; "circled letter")]
+    #[test_case("L", '\u{093E}', false; "vowel sign")]
+    #[test_case("Lu", 'Σ', true; "upper")]
+    #[test_case("Ll", 'Σ', false; "lower")]
+    #[test_case("N", '½', true; "number")]
+    #[test_case("Z", ' ', true; "separator space")]
+    #[test_case("Z", '\u{3000}', true; "separator ideographic space")]
+    #[test_case("Z", '\u{2029}', true; "separator paragraph")]
+    #[test_case("Z", '\n', false; "separator newline")]
+    #[test_case("Z", '\t', false; "separator tab")]
+    #[test_case("White_Space", '\n', true; "white space newline")]
+    #[test]
+    fn property_matches_works(name: &str, ch: char, expected: bool) {
+        let p = Property::try_from_name(name).unwrap();
+        assert_eq!(p.matches(ch), expected);
+    }
+
+    #[test_case('é', true; "letter")]
+    #[test_case('\u{0301}', true; "combining mark")]
+    #[test_case('\u{093E}', true; "vowel sign")]
+    #[test_case('‿', true; "connector punctuation")]
+    #[test_case('٣', true; "digit")]
+    #[test_case('½', false; "vulgar fraction")]
+    #[test_case('—', false; "dash")]
+    #[test]
+    fn is_word_works(ch: char, expected: bool) {
+        assert_eq!(is_word(ch), expected);
+    }
+
+    #[test_case(Posix::Alpha, 'é', true; "alpha")]
+    #[test_case(Posix::Alnum, '٣', true; "alnum")]
+    #[test_case(Posix::Digit, '٣', true; "digit")]
+    #[test_case(Posix::Upper, 'Σ', true; "upper")]
+    #[test_case(Posix::Lower, 'σ', true; "lower")]
+    #[test_case(Posix::Space, '\u{3000}', true; "space")]
+    #[test_case(Posix::Blank, '\u{2029}', false; "blank paragraph separator")]
+    #[test_case(Posix::Punct, '«', true; "punct")]
+    #[test_case(Posix::Punct, 'é', false; "punct letter")]
+    #[test_case(Posix::Word, '\u{0301}', true; "word")]
+    #[test_case(Posix::XDigit, '٣', false; "xdigit")]
+    #[test]
+    fn posix_matches_works(p: Posix, ch: char, expected: bool) {
+        assert_eq!(p.matches(ch), expected);
+    }
+
+    #[test]
+    fn unknown_property_is_an_error() {
+        assert_eq!(
+            Property::try_from_name("Klingon"),
+            Err(Error::UnknownProperty("Klingon".to_string()))
+        );
+    }
+}
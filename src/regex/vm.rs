@@ -8,17 +8,34 @@
 //! We make use of pre-allocated buffers for the Thread lists and track the
 //! index we are up to per-iteration as this results in roughly a 100x speed
 //! up from not having to allocate and free inside of the main loop.
+//!
+//! While there are no threads part way through a match, input that is unable to
+//! begin a match is skipped without running the VM (see [Prefilter]). Programs that
+//! do not depend on the surrounding input are first run as a lazily constructed DFA
+//! which is able to answer yes/no questions on its own and otherwise locates the
+//! region of the input containing the next match so that the VM is only run there.
+//!
+//! Lookaround assertions are handled by running a separate anchored Regex from
+//! the current position. As the VM only ever sees a stream of characters, the
+//! characters consumed so far are retained when the program contains a lookaround
+//! and any characters read ahead of the current position are buffered so that they
+//! can be replayed by the main loop.
 use super::{
     ast::{parse, Assertion},
-    compile::{compile_ast, optimise, CompiledOps, Inst, Op, Prog},
+    compile::{compile_ast, optimise, CompiledLookAround, CompiledOps, Inst, Op, Prefilter, Prog},
+    dfa::LazyDfa,
     matches::{Match, MatchIter},
     Error,
 };
-use crate::buffer::{Buffer, GapBuffer};
-use std::{mem::swap, rc::Rc};
+use crate::buffer::{Buffer, Text};
+use std::{collections::VecDeque, mem::swap, rc::Rc};
 
 pub(super) const N_SLOTS: usize = 30;
 
+/// The position of the "@" in the "@*?" prelude of unanchored programs which is responsible
+/// for starting new threads at each position in the input.
+const RESTART_PC: usize = 1;
+
 /// A regular expression engine designed for use within the ad text editor.
 ///
 /// This is a relatively naive implementation though it does have some
@@ -28,7 +45,7 @@ pub(super) const N_SLOTS: usize = 30;
 #[derive(Clone, PartialEq, Eq)]
 pub struct Regex {
     /// The compiled instructions for running the VM
-    prog: Prog,
+    pub(super) prog: Prog,
     /// Names to be used for extracting named submatches
     submatch_names: Rc<[String]>,
     /// Pre-allocated Thread list in priority order to handle leftmost-longest semantics
@@ -49,6 +66,85 @@ pub struct Regex {
     prev: Option<char>,
     /// Next character in the input after the one currently being processed
     next: Option<char>,
+    /// Sub-expressions for lookaround assertions referenced by Op::LookAround
+    lookarounds: Vec<LookAroundRegex>,
+    /// Characters consumed so far that lookarounds are able to read
+    history: History,
+    /// Characters read from the input ahead of the one currently being processed
+    ahead: VecDeque<(usize, char)>,
+    /// Used to skip input that is unable to start a match
+    prefilter: Option<Prefilter>,
+    /// Used in place of the VM when submatches are not required (if possible)
+    dfa: Option<LazyDfa>,
+}
+
+/// The characters consumed by the VM that lookarounds are able to read, along with their
+/// positions in the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct History {
+    chars: VecDeque<(usize, char)>,
+    /// How far back from the current position lookarounds are able to read, or None if
+    /// there is no limit
+    max_len: Option<usize>,
+}
+
+impl History {
+    fn new(lookarounds: &[CompiledLookAround]) -> Self {
+        // Assertions within the body are able to peek one character beyond it
+        let max_len = lookarounds
+            .iter()
+            .filter(|la| !la.stream_ahead)
+            .try_fold(0, |len, la| Some(len.max(la.max_len? + 1)));
+
+        Self {
+            chars: VecDeque::new(),
+            max_len,
+        }
+    }
+
+    /// Prepare for a new run of the VM from sp, keeping the characters before it if the run
+    /// is resuming from a previous one.
+    fn reset(&mut self, sp: usize, resume: bool) {
+        match self.chars.iter().rposition(|&(i, _)| i == sp) {
+            Some(ix) if resume => self.chars.truncate(ix),
+            None if resume => (),
+            _ => self.chars.clear(),
+        }
+        self.trim();
+    }
+
+    #[inline]
+    fn push(&mut self, i: usize, ch: char) {
+        if self.max_len != Some(0) {
+            self.chars.push_back((i, ch));
+        }
+    }
+
+    /// Drop any characters that are out of reach of lookarounds run from the position
+    /// following the most recent one.
+    fn trim(&mut self) {
+        if let Some(len) = self.max_len {
+            let excess = self.chars.len().saturating_sub(len + 1);
+            self.chars.drain(..excess);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LookAroundRegex {
+    re: Regex,
+    negated: bool,
+    stream_ahead: bool,
+}
+
+impl From<CompiledLookAround> for LookAroundRegex {
+    fn from(cl: CompiledLookAround) -> Self {
+        Self {
+            re: Regex::new(cl.ops),
+            negated: cl.negated,
+            stream_ahead: cl.stream_ahead,
+        }
+    }
 }
 
 impl std::fmt::Debug for Regex {
@@ -65,12 +161,8 @@ impl Regex {
     pub fn compile(re: &str) -> Result<Self, Error> {
         let mut ast = parse(re)?;
         ast.optimise();
-        let CompiledOps {
-            ops,
-            submatch_names,
-        } = compile_ast(ast, false);
 
-        Ok(Self::new(ops, submatch_names))
+        Ok(Self::new(compile_ast(ast, false)))
     }
 
     /// Attempt to compile the given regular expression into its reversed optimised VM opcode form.
@@ -81,15 +173,18 @@ impl Regex {
     pub fn compile_reverse(re: &str) -> Result<Self, Error> {
         let mut ast = parse(re)?;
         ast.optimise();
-        let CompiledOps {
-            ops,
-            submatch_names,
-        } = compile_ast(ast, true);
 
-        Ok(Self::new(ops, submatch_names))
+        Ok(Self::new(compile_ast(ast, true)))
     }
 
-    fn new(ops: Vec<Op>, submatch_names: Vec<String>) -> Self {
+    fn new(
+        CompiledOps {
+            ops,
+            submatch_names,
+            lookarounds,
+            prefilter,
+        }: CompiledOps,
+    ) -> Self {
         let prog: Prog = optimise(ops)
             .into_iter()
             .map(|op| Inst { op, gen: 0 })
@@ -99,6 +194,8 @@ impl Regex {
         let nlist = vec![Thread::default(); prog.len()].into_boxed_slice();
         let sms = vec![SubMatches::default(); prog.len()].into_boxed_slice();
         let free_sms = (1..prog.len()).collect();
+        let dfa = LazyDfa::try_new(&prog);
+        let history = History::new(&lookarounds);
 
         Self {
             prog,
@@ -109,6 +206,11 @@ impl Regex {
             p: 0,
             prev: None,
             next: None,
+            lookarounds: lookarounds.into_iter().map(Into::into).collect(),
+            history,
+            ahead: VecDeque::new(),
+            prefilter,
+            dfa,
 
             sms,
             free_sms,
@@ -134,7 +236,7 @@ impl Regex {
     }
 
     /// Iterate over all non-overlapping matches of this Regex for a given `Buffer` input.
-    pub fn match_buffer_all<'a, 'b>(&'a mut self, b: &'b Buffer) -> MatchIter<'a, &'b GapBuffer> {
+    pub fn match_buffer_all<'a, 'b>(&'a mut self, b: &'b Buffer) -> MatchIter<'a, &'b Text> {
         self.track_submatches = true;
         MatchIter {
             it: &b.txt,
@@ -150,7 +252,21 @@ impl Regex {
         I: Iterator<Item = (usize, char)>,
     {
         self.track_submatches = true;
-        self._match_iter(input, sp)
+        self._match_iter(input, sp, false)
+    }
+
+    /// Attempt to match this Regex against an input iterator that continues on from a
+    /// previous call to [Regex::match_iter] against the same text, returning the position of
+    /// the match and all submatches if successful.
+    ///
+    /// Unlike [Regex::match_iter], lookbehinds are able to see the text that was read by the
+    /// previous call so `sp` must be a position that it was started from or read up to.
+    pub fn resume_match_iter<I>(&mut self, input: &mut I, sp: usize) -> Option<Match>
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        self.track_submatches = true;
+        self._match_iter(input, sp, true)
     }
 
     /// Determine whether or not this Regex matches the input `&str` without searching for
@@ -167,7 +283,18 @@ impl Regex {
         I: Iterator<Item = (usize, char)>,
     {
         self.track_submatches = false;
-        self._match_iter(input, sp).is_some()
+        match self.dfa.as_mut() {
+            Some(dfa) => dfa.is_match(&self.prog, input),
+            None => self._match_iter(input, sp, false).is_some(),
+        }
+    }
+
+    /// Run all matching on the VM, even if the program could make use of the lazy DFA. This is
+    /// only intended for comparing the two in tests and benchmarks.
+    #[doc(hidden)]
+    pub fn without_dfa(mut self) -> Self {
+        self.dfa = None;
+        self
     }
 
     /// This is the main VM implementation that is used by all other matching methods on Regex.
@@ -178,16 +305,30 @@ impl Regex {
     ///  - The Match returned in this case will always point to the null string at the start
     ///    of the string and should only be used for conversion to a bool in `matches_*`
     ///    methods.
-    fn _match_iter<I>(&mut self, input: &mut I, mut sp: usize) -> Option<Match>
+    ///
+    /// If `resume` is set then the characters read by the previous run that come before `sp`
+    /// are kept so that they remain visible to lookbehinds.
+    fn _match_iter<I>(&mut self, input: &mut I, mut sp: usize, resume: bool) -> Option<Match>
     where
         I: Iterator<Item = (usize, char)>,
     {
         let mut sub_matches = [0; N_SLOTS];
-        self.free_sms = (1..self.prog.len()).collect();
-        self.sms[0] = SubMatches {
-            refs: 1,
-            inner: [0; N_SLOTS],
-        };
+        self.reset_sms();
+
+        self.history.reset(sp, resume);
+        self.ahead.clear();
+        self.prev = None;
+        self.next = None;
+
+        // The DFA leaves the input from the point where the leftmost match might begin in
+        // self.ahead so that the VM only needs to run over the region containing the match
+        if let Some(dfa) = self.dfa.as_mut().filter(|dfa| dfa.can_find_window()) {
+            sp = dfa.find_window(&self.prog, input, &mut self.ahead)?;
+        }
+
+        if self.prefilter.is_some() {
+            sp = self.skip_to_candidate(input)?;
+        }
 
         // We bump the generation to ensure we don't collide with anything from
         // a previous run while initialising the VM.
@@ -195,7 +336,7 @@ impl Regex {
         // When setting up the initial threads we have our prelude which uses "@" so we provide a
         // null byte for the initial character as it is not needed and it avoids us having to make
         // the "ch" param of add_thread optional.
-        self.add_thread(Thread::default(), sp, '\0', true);
+        self.add_thread(Thread::default(), sp, '\0', true, input);
         swap(&mut self.clist, &mut self.nlist);
         self.gen += 1;
 
@@ -205,16 +346,23 @@ impl Regex {
         self.p = 0;
         let mut matched = false;
 
-        let mut it = input.peekable();
-        self.prev = None;
-        self.next = None;
-
-        while let Some((i, ch)) = it.next() {
+        while let Some((i, ch)) = self.ahead.pop_front().or_else(|| input.next()) {
             sp = i;
-            self.next = it.peek().map(|(_, c)| *c);
+            if self.ahead.is_empty() {
+                self.ahead.extend(input.next());
+            }
+            self.next = self.ahead.front().map(|(_, c)| *c);
+            self.history.push(i, ch);
+
+            // Set if no threads other than those started at the current position survive
+            let mut idle = false;
 
             for i in 0..n {
-                if let Some(sm) = self.step_thread(i, sp, ch) {
+                if self.clist[i].pc == RESTART_PC {
+                    idle = self.p == 0;
+                }
+
+                if let Some(sm) = self.step_thread(i, sp, ch, input) {
                     if !self.track_submatches {
                         return Some(Match::synthetic(0, 0));
                     }
@@ -242,10 +390,41 @@ impl Regex {
             }
 
             self.p = 0;
+
+            // Any match still to be found starts after this point so lookbehinds are no longer
+            // able to see anything further back than their length from here
+            if idle {
+                self.history.trim();
+            }
+
+            if idle && self.prefilter.is_some() {
+                let next_sp = self.ahead.front().map(|&(i, _)| i);
+                match self.skip_to_candidate(input) {
+                    // The next character might start a match so we carry on as normal
+                    Some(i) if Some(i) == next_sp => (),
+
+                    // Restart from the next position that might start a match
+                    Some(i) => {
+                        self.reset_sms();
+                        self.add_thread(Thread::default(), i, '\0', true, input);
+                        swap(&mut self.clist, &mut self.nlist);
+                        self.gen += 1;
+                        n = self.p;
+                        self.p = 0;
+                    }
+
+                    // Nothing left in the input is able to start a match
+                    None => {
+                        n = 0;
+                        break;
+                    }
+                }
+            }
         }
 
         self.prev = None;
         self.next = None;
+        self.ahead.clear();
 
         // Check to see if the final pass had a match which would be better than any
         // that we have so far.
@@ -267,8 +446,53 @@ impl Regex {
         })
     }
 
+    /// Consume input that is unable to start a match, returning the position of the next
+    /// character that might. That character is left at the front of self.ahead.
+    fn skip_to_candidate<I>(&mut self, input: &mut I) -> Option<usize>
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        let pf = self.prefilter.as_ref()?;
+        let lit = &pf.literal;
+
+        loop {
+            let (i, ch) = self.ahead.pop_front().or_else(|| input.next())?;
+
+            if pf.may_start_match(ch) {
+                if lit.len() > 1 {
+                    while self.ahead.len() < lit.len() - 1 {
+                        match input.next() {
+                            Some(next) => self.ahead.push_back(next),
+                            None => break,
+                        }
+                    }
+                }
+
+                let literal_follows = self
+                    .ahead
+                    .iter()
+                    .zip(lit.iter().skip(1))
+                    .filter(|((_, c), l)| c == *l)
+                    .count()
+                    == lit.len().saturating_sub(1);
+
+                if literal_follows {
+                    self.ahead.push_front((i, ch));
+                    return Some(i);
+                }
+            }
+
+            self.prev = Some(ch);
+            self.history.push(i, ch);
+            self.history.trim();
+        }
+    }
+
     #[inline]
-    fn step_thread(&mut self, i: usize, sp: usize, ch: char) -> Option<usize> {
+    fn step_thread<I>(&mut self, i: usize, sp: usize, ch: char, input: &mut I) -> Option<usize>
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
         let t = &self.clist[i];
         match &self.prog[t.pc].op {
             // If comparisons and their assertions hold then queue the resulting threads
@@ -277,7 +501,7 @@ impl Regex {
                     self.sm_dec_ref(t.sm);
                     return None;
                 }
-                _ => self.add_thread(thread(t.pc + 1, t.sm), sp, ch, false),
+                _ => self.add_thread(thread(t.pc + 1, t.sm), sp, ch, false, input),
             },
 
             Op::Match => return Some(t.sm),
@@ -291,7 +515,10 @@ impl Regex {
     }
 
     #[inline]
-    fn add_thread(&mut self, t: Thread, sp: usize, ch: char, initial: bool) {
+    fn add_thread<I>(&mut self, t: Thread, sp: usize, ch: char, initial: bool, input: &mut I)
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
         if self.prog[t.pc].gen == self.gen {
             self.sm_dec_ref(t.sm);
             return; // already on the list we are currently building
@@ -307,47 +534,106 @@ impl Regex {
                 Some(a) => assert_thread(l1, t.sm, a),
                 None => thread(l1, t.sm),
             };
-            self.add_thread(th, sp, ch, initial);
+            self.add_thread(th, sp, ch, initial, input);
         } else if let Op::Split(l1, l2) = self.prog[t.pc].op {
             self.sms[t.sm].refs += 1;
             let (t1, t2) = match t.assertion {
                 Some(a) => (assert_thread(l1, t.sm, a), assert_thread(l2, t.sm, a)),
                 None => (thread(l1, t.sm), thread(l2, t.sm)),
             };
-            self.add_thread(t1, sp, ch, initial);
-            self.add_thread(t2, sp, ch, initial);
+            self.add_thread(t1, sp, ch, initial, input);
+            self.add_thread(t2, sp, ch, initial, input);
         } else if let Op::Assertion(a) = self.prog[t.pc].op {
-            self.add_thread(assert_thread(t.pc + 1, t.sm, a), sp, ch, initial);
+            self.add_thread(assert_thread(t.pc + 1, t.sm, a), sp, ch, initial, input);
+        } else if let Op::LookAround(k) = self.prog[t.pc].op {
+            if self.lookaround_holds(k, input) {
+                let th = match t.assertion {
+                    Some(a) => assert_thread(t.pc + 1, t.sm, a),
+                    None => thread(t.pc + 1, t.sm),
+                };
+                self.add_thread(th, sp, ch, initial, input);
+            } else {
+                self.sm_dec_ref(t.sm);
+            }
         } else if let Op::Save(s) = self.prog[t.pc].op {
-            self.handle_save(t, s, sp, ch, initial, false)
+            self.handle_save(t, s, sp, ch, initial, false, input)
         } else if let Op::RSave(s) = self.prog[t.pc].op {
-            self.handle_save(t, s, sp, ch, initial, true)
+            self.handle_save(t, s, sp, ch, initial, true, input)
         } else {
             self.nlist[self.p] = t;
             self.p += 1;
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     #[inline]
-    fn handle_save(&mut self, t: Thread, s: usize, sp: usize, ch: char, initial: bool, rev: bool) {
-        if (!rev && s % 2 == 0) || (rev && s % 2 == 1) {
+    fn handle_save<I>(
+        &mut self,
+        t: Thread,
+        s: usize,
+        sp: usize,
+        ch: char,
+        initial: bool,
+        rev: bool,
+        input: &mut I,
+    ) where
+        I: Iterator<Item = (usize, char)>,
+    {
+        if (!rev && s.is_multiple_of(2)) || (rev && !s.is_multiple_of(2)) {
             let sm = self.sm_update(t.sm, s, sp, initial, rev);
             let th = match t.assertion {
                 Some(a) => assert_thread(t.pc + 1, sm, a),
                 None => thread(t.pc + 1, sm),
             };
-            self.add_thread(th, sp, ch, initial);
+            self.add_thread(th, sp, ch, initial, input);
         } else {
             match t.assertion {
                 Some(a) if !a.holds_for(self.prev, ch, self.next) => self.sm_dec_ref(t.sm),
                 _ => {
                     let sm = self.sm_update(t.sm, s, sp, initial, rev);
-                    self.add_thread(thread(t.pc + 1, sm), sp, ch, initial);
+                    self.add_thread(thread(t.pc + 1, sm), sp, ch, initial, input);
                 }
             }
         }
     }
 
+    /// Run the body of the given lookaround from the current position in the input.
+    ///
+    /// As with "^" and "\b", the start of the input stream is treated as the start of
+    /// the text so lookbehinds are unable to see anything before the position that
+    /// matching started from unless the match was resumed from a previous run (see
+    /// [Regex::resume_match_iter]).
+    fn lookaround_holds<I>(&mut self, k: usize, input: &mut I) -> bool
+    where
+        I: Iterator<Item = (usize, char)>,
+    {
+        let input: &mut dyn Iterator<Item = (usize, char)> = input;
+        let la = &mut self.lookarounds[k];
+        let matched = if la.stream_ahead {
+            let mut it = BufferedChars {
+                buf: &mut self.ahead,
+                input,
+                pos: 0,
+            };
+            la.re.matches_iter(&mut it, 0)
+        } else {
+            let mut history = self.history.chars.iter().rev().map(|&(_, c)| c).enumerate();
+            let mut it: &mut dyn Iterator<Item = (usize, char)> = &mut history;
+            la.re.matches_iter(&mut it, 0)
+        };
+
+        matched != la.negated
+    }
+
+    /// Release all submatch tracking state ready for starting new threads.
+    fn reset_sms(&mut self) {
+        self.free_sms = (1..self.prog.len()).collect();
+        self.sms[0] = SubMatches {
+            refs: 1,
+            inner: [0; N_SLOTS],
+        };
+    }
+
     #[inline]
     fn sm_dec_ref(&mut self, i: usize) {
         if !self.track_submatches {
@@ -392,6 +678,31 @@ impl Regex {
     }
 }
 
+/// Reads through the characters buffered ahead of the current position in the input,
+/// pulling more from the underlying input as needed so that they can be replayed by
+/// the main VM loop.
+///
+/// The underlying input is type erased to avoid infinitely recursive monomorphisation when
+/// lookarounds are nested.
+struct BufferedChars<'a> {
+    buf: &'a mut VecDeque<(usize, char)>,
+    input: &'a mut dyn Iterator<Item = (usize, char)>,
+    pos: usize,
+}
+
+impl Iterator for BufferedChars<'_> {
+    type Item = (usize, char);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pos == self.buf.len() {
+            self.buf.push_back(self.input.next()?);
+        }
+        self.pos += 1;
+
+        self.buf.get(self.pos - 1).copied()
+    }
+}
+
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 struct SubMatches {
     /// How many threads are currently pointing at this SubMatches
@@ -506,6 +817,26 @@ mod tests {
     #[test_case("\\b(in|for)\\b", "bob for", Some("for"); "word boundary for alt match not at BOF")]
     #[test_case("[a-zA-Z0-9_\\-./@]+\\.jpe?g", "glenda_space_medium.jpg", Some("glenda_space_medium.jpg"); "complex group")]
     #[test_case("[a-zA-Z¡-￿0-9_\\-./@]+", "foo-bar_99.pdf", Some("foo-bar_99.pdf"); "multibyte group")]
+    #[test_case("\\w+", "naïve café", Some("naïve"); "unicode word")]
+    #[test_case("\\d+", "x٣٤y", Some("٣٤"); "unicode digits")]
+    #[test_case("\\p{Greek}+", "abc αβγ", Some("αβγ"); "unicode script")]
+    #[test_case("\\PL+", "abc 123 def", Some(" 123 "); "negated unicode property")]
+    #[test_case("\\p{Z}+", "a\n\t\u{3000} b", Some("\u{3000} "); "unicode separators exclude control characters")]
+    #[test_case("[[:upper:]][[:lower:]]+", "foo Bar", Some("Bar"); "posix classes")]
+    #[test_case("foo(?=bar)", "foobaz foobar", Some("foo"); "lookahead")]
+    #[test_case("foo(?=bar)", "foobaz foobaz", None; "lookahead non matching")]
+    #[test_case("foo(?!bar)", "foobar foobaz", Some("foo"); "negative lookahead")]
+    #[test_case("foo(?!bar)", "foobar", None; "negative lookahead non matching")]
+    #[test_case("(?<=bar)foo", "bazfoo barfoo", Some("foo"); "lookbehind")]
+    #[test_case("(?<=bar)foo", "bazfoo", None; "lookbehind non matching")]
+    #[test_case("(?<!bar)foo", "barfoo bazfoo", Some("foo"); "negative lookbehind")]
+    #[test_case("(?<!bar)foo", "barfoo", None; "negative lookbehind non matching")]
+    #[test_case("\\w+(?=\\()", "let x = foo(bar)", Some("foo"); "lookahead with classes")]
+    #[test_case("(?<=\\$)[0-9]+", "costs 12 or $42", Some("42"); "lookbehind with classes")]
+    #[test_case("a(?=b(?!c))", "abc abd", Some("a"); "nested lookaround")]
+    #[test_case("(?<=a)(?=b)", "xab", Some(""); "zero width lookarounds only")]
+    #[test_case("(x(?=y)|xz)", "xz", Some("xz"); "lookahead in alternation")]
+    #[test_case("(?<=[0-9]{3})px", "12px 123px", Some("px"); "lookbehind with repetition")]
     #[test]
     fn match_works(re: &str, s: &str, expected: Option<&str>) {
         let mut r = Regex::compile(re).unwrap();
@@ -530,6 +861,10 @@ mod tests {
         Some("his");
         "multiline intput"
     )]
+    #[test_case("foo(?=bar)", "foobar foobaz", Some("foo"); "lookahead")]
+    #[test_case("foo(?!bar)", "foobaz foobar", Some("foo"); "negative lookahead")]
+    #[test_case("(?<=bar)foo", "barfoo bazfoo", Some("foo"); "lookbehind")]
+    #[test_case("(?<!bar)foo", "bazfoo barfoo", Some("foo"); "negative lookbehind")]
     #[test]
     fn rev_match_works(re: &str, s: &str, expected: Option<&str>) {
         use crate::exec::IterBoundedChars;
@@ -552,6 +887,14 @@ mod tests {
     #[test_case("foo|bar|baz", "bazbarfoobar", &["baz", "bar", "foo", "bar"]; "alts back to back in s")]
     #[test_case("(foo|bar|baz)", "foo foobar barfoo baz", &["foo", "foo", "bar", "bar", "foo", "baz"]; "alts in parens")]
     #[test_case("\\b(foo|bar|baz)\\b", "foo foobar barfoo baz", &["foo", "baz"]; "alts with word boundaries")]
+    #[test_case("[0-9]+(?=px)", "12px 3em 45px", &["12", "45"]; "lookahead")]
+    #[test_case("(?<!-)[0-9]+", "12 -3 45", &["12", "45"]; "negative lookbehind")]
+    #[test_case("foo", "xxfooyyyyyfoo", &["foo", "foo"]; "skipping to literal prefix")]
+    #[test_case("foo", "fofofoo", &["foo"]; "partial literal prefix")]
+    #[test_case("(?<=a)b", "xxab cb ab", &["b", "b"]; "lookbehind after skipped input")]
+    #[test_case("\\bbar", "foobar bar", &["bar"]; "word boundary after skipped input")]
+    #[test_case("(?<=a)a", "aaa", &["a", "a"]; "lookbehind over consecutive matches")]
+    #[test_case("(?<=[0-9])[0-9]", "1234", &["2", "3", "4"]; "lookbehind into previous match")]
     #[test]
     fn match_all_works(re: &str, s: &str, expected: &[&str]) {
         let mut r = Regex::compile(re).unwrap();
@@ -560,6 +903,29 @@ mod tests {
         assert_eq!(&matches, expected);
     }
 
+    #[test_case("[0-9]+", Some(0); "no lookarounds")]
+    #[test_case("c(?=ab)", Some(0); "lookahead only")]
+    #[test_case("(?<=ab)c", Some(3); "lookbehind")]
+    #[test_case("(?<=a|xyz)c", Some(4); "lookbehind with alternation")]
+    #[test_case("(?<=\\ba?)c(?<!xy)", Some(3); "multiple lookbehinds")]
+    #[test_case("(?<=a.*)c", None; "unbounded lookbehind")]
+    #[test]
+    fn history_max_len_works(re: &str, expected: Option<usize>) {
+        let r = Regex::compile(re).unwrap();
+        assert_eq!(r.history.max_len, expected);
+    }
+
+    #[test]
+    fn history_only_holds_what_lookbehinds_can_reach() {
+        let mut r = Regex::compile("(?<=ab)c").unwrap();
+        let s = format!("{}{}", "xabcy".repeat(1000), "y".repeat(1000));
+        let matches: Vec<_> = r.match_str_all(&s).map(|m| m.str_match_text(&s)).collect();
+
+        assert_eq!(matches.len(), 1000);
+        assert!(matches.iter().all(|m| m == "c"));
+        assert!(r.history.chars.len() <= 4, "{:?}", r.history);
+    }
+
     #[test]
     fn dot_star_works() {
         let mut r = Regex::compile(".*").unwrap();
@@ -19,6 +19,8 @@ const ENABLE_MOUSE_SUPPORT: &str = "\x1b[?1000h\x1b[?1002h\x1b[?1015h\x1b[?1006h
 const DISABLE_MOUSE_SUPPORT: &str = "\x1b[?1006l\x1b[?1015l\x1b[?1002l\x1b[?1000l";
 const ENABLE_ALTERNATE_SCREEN: &str = "\x1b[?1049h";
 const DISABLE_ALTERNATE_SCREEN: &str = "\x1b[?1049l";
+const ENABLE_BRACKETED_PASTE: &str = "\x1b[?2004h";
+const DISABLE_BRACKETED_PASTE: &str = "\x1b[?2004l";
 
 /// Used for storing and checking whether or not we've received a signal that our window
 /// size has changed.
@@ -45,7 +47,7 @@ pub unsafe fn register_signal_handler() {
     }
 
     let mut sa_ptr = *maybe_sa.as_mut_ptr();
-    sa_ptr.sa_sigaction = handle_win_size_change as sighandler_t;
+    sa_ptr.sa_sigaction = handle_win_size_change as *const () as sighandler_t;
     sa_ptr.sa_flags = SA_SIGINFO;
 
     if libc::sigaction(SIGWINCH, &sa_ptr as *const _, ptr::null_mut()) == -1 {
@@ -211,6 +213,24 @@ pub(crate) fn disable_mouse_support(stdout: &mut Stdout) {
     }
 }
 
+pub(crate) fn enable_bracketed_paste(stdout: &mut Stdout) {
+    if let Err(e) = stdout.write_all(ENABLE_BRACKETED_PASTE.as_bytes()) {
+        panic!("unable to enable bracketed paste: {e}");
+    }
+    if let Err(e) = stdout.flush() {
+        panic!("unable to enable bracketed paste: {e}");
+    }
+}
+
+pub(crate) fn disable_bracketed_paste(stdout: &mut Stdout) {
+    if let Err(e) = stdout.write_all(DISABLE_BRACKETED_PASTE.as_bytes()) {
+        panic!("unable to disable bracketed paste: {e}");
+    }
+    if let Err(e) = stdout.flush() {
+        panic!("unable to disable bracketed paste: {e}");
+    }
+}
+
 pub(crate) fn enable_alternate_screen(stdout: &mut Stdout) {
     if let Err(e) = stdout.write_all(ENABLE_ALTERNATE_SCREEN.as_bytes()) {
         panic!("unable to enable alternate screen: {e}");
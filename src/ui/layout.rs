@@ -26,6 +26,22 @@ pub(crate) struct Layout {
     pub(super) cols: ZipList<Column>,
     /// Known Buffer views that are not currently active
     pub(super) views: Vec<View>,
+    /// Search matches to highlight within the visible region of a buffer
+    pub(crate) search_highlights: Option<SearchHighlights>,
+}
+
+/// The matches of the current search within a region of a buffer.
+///
+/// The buffer version and region are tracked so that the search only needs to be run again
+/// when the buffer is modified or scrolled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct SearchHighlights {
+    pub(crate) id: BufferId,
+    pub(crate) version: usize,
+    pub(crate) from: usize,
+    pub(crate) to: usize,
+    /// Sorted character ranges (end exclusive) of each match
+    pub(crate) matches: Vec<(usize, usize)>,
 }
 
 impl Layout {
@@ -43,6 +59,7 @@ impl Layout {
             screen_cols,
             cols: ziplist![Column::new(screen_rows, screen_cols, &[id])],
             views: vec![],
+            search_highlights: None,
         }
     }
 
@@ -498,11 +515,12 @@ impl Layout {
         let c = b.dot.active_cur();
         let (y, x) = c.as_yx(b);
 
-        if view.row_off > 0 && y == view.row_off + rows - 1 {
-            b.dot.set_active_cur(Cur::from_yx(y - 1, x, b));
+        if view.row_off > 0 && b.rows_between(view.row_off, y) == rows - 1 {
+            let y = b.prev_visible_line(y).unwrap_or_default();
+            b.dot.set_active_cur(Cur::from_yx(y, x, b));
         }
 
-        view.row_off = view.row_off.saturating_sub(1);
+        view.row_off = b.prev_visible_line(view.row_off).unwrap_or_default();
         view.clamp_scroll(b, rows, cols);
     }
 
@@ -514,13 +532,17 @@ impl Layout {
         let c = b.dot.active_cur();
         let (y, x) = c.as_yx(b);
 
-        if y == view.row_off && view.row_off < b.txt.len_lines() - 1 {
-            b.dot.set_active_cur(Cur::from_yx(y + 1, x, b));
-            b.dot.clamp_idx(b.txt.len_chars());
-            b.xdot.clamp_idx(b.txt.len_chars());
+        if b.visible_line(y) == view.row_off && view.row_off < b.txt.len_lines() - 1 {
+            if let Some(y) = b.next_visible_line(y) {
+                b.dot.set_active_cur(Cur::from_yx(y, x, b));
+                b.dot.clamp_idx(b.txt.len_chars());
+                b.xdot.clamp_idx(b.txt.len_chars());
+            }
         }
 
-        view.row_off += 1;
+        view.row_off = b
+            .next_visible_line(view.row_off)
+            .unwrap_or(view.row_off + 1);
         view.clamp_scroll(b, rows, cols);
     }
 
@@ -642,7 +664,10 @@ impl Layout {
         view.rx = rx;
         b.cached_rx = rx;
 
-        let y = min(y.saturating_sub(y_offset) + view.row_off, b.len_lines()).saturating_sub(1);
+        // Folded lines are displayed as a single row so we need to walk down from the top of
+        // the view rather than offsetting directly into the buffer
+        let row = y.saturating_sub(y_offset).saturating_sub(1);
+        let y = b.line_n_rows_below(min(view.row_off, b.len_lines().saturating_sub(1)), row);
         let mut cur = Cur::from_yx(y, b.x_from_provided_rx(y, view.rx), b);
         cur.clamp_idx(b.txt.len_chars());
 
@@ -750,7 +775,7 @@ impl View {
         let (_, w_sgncol) = b.sign_col_dims();
         let (y, _) = b.dot.active_cur().as_yx(b);
         let x = self.rx - self.col_off + w_sgncol;
-        let y = y - self.row_off;
+        let y = b.rows_between(self.row_off, y);
 
         (x, y)
     }
@@ -780,12 +805,15 @@ impl View {
         self.rx = self.rx_from_x(b, y, x);
         b.cached_rx = self.rx;
 
+        // The first line of the view is never hidden inside of a fold
+        self.row_off = b.visible_line(self.row_off);
+
         if y < self.row_off {
-            self.row_off = y;
+            self.row_off = b.visible_line(y);
         }
 
-        if y >= self.row_off + screen_rows {
-            self.row_off = y - screen_rows + 1;
+        if b.rows_between(self.row_off, y) >= screen_rows {
+            self.row_off = b.line_n_rows_above(y, screen_rows - 1);
         }
 
         if self.rx < self.col_off {
@@ -808,9 +836,9 @@ impl View {
         let (y, _) = b.dot.active_cur().as_yx(b);
 
         self.row_off = match vp {
-            ViewPort::Top => y,
-            ViewPort::Center => y.saturating_sub(screen_rows / 2),
-            ViewPort::Bottom => y.saturating_sub(screen_rows),
+            ViewPort::Top => b.visible_line(y),
+            ViewPort::Center => b.line_n_rows_above(y, screen_rows / 2),
+            ViewPort::Bottom => b.line_n_rows_above(y, screen_rows),
         };
 
         self.clamp_scroll(b, screen_rows, screen_cols);
@@ -863,6 +891,7 @@ mod tests {
             screen_cols: n_cols,
             cols: ZipList::try_from_iter(cols).unwrap(),
             views: vec![],
+            search_highlights: None,
         };
         ws.update_screen_size(n_rows, n_cols);
 
@@ -1074,6 +1103,24 @@ mod tests {
         );
     }
 
+    #[test_case(1, 0, 0; "before the fold")]
+    #[test_case(2, 1, 1; "on the fold")]
+    #[test_case(3, 6, 2; "after the fold")]
+    #[test_case(7, 9, 5; "past the end of the buffer")]
+    #[test]
+    fn cur_from_screen_coords_skips_folded_lines(y: usize, expected_line: usize, row: usize) {
+        let mut ws = test_windows(&[1], 80, 100);
+        let txt: String = (0..10).map(|n| format!("line {n}\n")).collect();
+        *ws.active_buffer_mut() = Buffer::new_unnamed(0, txt.trim_end());
+        ws.active_buffer_mut().fold(1, 5);
+
+        let (_, cur) = ws.cur_from_screen_coords(5, y, true);
+        assert_eq!(cur.as_yx(ws.active_buffer()).0, expected_line);
+
+        ws.active_buffer_mut().dot = Dot::from(cur);
+        assert_eq!(ws.focused_view().ui_xy(ws.active_buffer()).1, row);
+    }
+
     // NOTE: there was a bug around misunderstanding terminal "cells" in relation to
     //       wide unicode characters
     //       - https://github.com/crossterm-rs/crossterm/issues/458
@@ -7,6 +7,7 @@ pub(crate) enum TokenType {
     Dot,
     Load,
     Execute,
+    Search,
     Default,
 }
 
@@ -22,6 +23,7 @@ impl<'a> Token<'a> {
             TokenType::Dot => format!("{}{}", Style::Bg(cs.dot_bg), self.s),
             TokenType::Load => format!("{}{}", Style::Bg(cs.load_bg), self.s),
             TokenType::Execute => format!("{}{}", Style::Bg(cs.exec_bg), self.s),
+            TokenType::Search => format!("{}{}", Style::Bg(cs.search_bg), self.s),
             TokenType::Default => format!("{}{}{}", Style::Bg(cs.bg), Style::Fg(cs.fg), self.s),
         }
     }
@@ -11,7 +11,7 @@ mod layout;
 mod lex;
 mod tui;
 
-pub(crate) use layout::Layout;
+pub(crate) use layout::{Layout, SearchHighlights};
 pub use tui::Tui;
 
 pub(crate) trait UserInterface {
@@ -9,8 +9,9 @@ use crate::{
     key::{Input, MouseButton, MouseEvent},
     restore_terminal_state,
     term::{
-        clear_screen, enable_alternate_screen, enable_mouse_support, enable_raw_mode, get_termios,
-        get_termsize, register_signal_handler, win_size_changed, CurShape,
+        clear_screen, enable_alternate_screen, enable_bracketed_paste, enable_mouse_support,
+        enable_raw_mode, get_termios, get_termsize, register_signal_handler, win_size_changed,
+        CurShape,
     },
     term::{Cursor, Style},
     ui::{
@@ -37,6 +38,10 @@ const VLINE: &str = "│";
 const TSTR: &str = "├";
 const XSTR: &str = "┼";
 
+// Bracketed paste markers: "ESC [ 200 ~" and "ESC [ 201 ~"
+const PASTE_START: &str = "200";
+const PASTE_END: &[u8] = b"\x1b[201~";
+
 fn box_draw_str(s: &str, cs: &ColorScheme) -> String {
     format!("{}{}{s}", Style::Fg(cs.minibuffer_hl), Style::Bg(cs.bg))
 }
@@ -135,13 +140,22 @@ impl Tui {
 
     fn render_status_bar(&self, cs: &ColorScheme, mode_name: &str, b: &Buffer) -> String {
         let lstatus = format!(
-            "{} {} - {} lines {}",
+            "{} {} - {} lines {}{}",
             mode_name,
             b.display_name(),
             b.len_lines(),
-            if b.dirty { "[+]" } else { "" }
+            if b.dirty { "[+]" } else { "" },
+            b.load_status().unwrap_or_default()
         );
-        let rstatus = b.dot.addr(b);
+        // Only files have an on disk format to show
+        let format = if b.kind.is_hex() {
+            "HEX ".to_string()
+        } else if b.kind.is_file() {
+            format!("{} ", b.format)
+        } else {
+            String::new()
+        };
+        let rstatus = format!("{format}{}", b.dot.addr(b));
         let width = self.screen_cols - lstatus.len();
 
         format!(
@@ -194,8 +208,7 @@ impl Tui {
             let width = self.screen_cols;
 
             for i in mb.top..=mb.bottom {
-                let (rline, _) =
-                    raw_rline_unchecked(b, &View::new(0), i, 0, self.screen_cols, None);
+                let (rline, _) = raw_rline_unchecked(b, &View::new(0), i, 0, self.screen_cols, &[]);
                 let len = min(self.screen_cols, rline.len());
                 if i == mb.selected_line_idx {
                     lines.push(format!(
@@ -251,6 +264,7 @@ impl UserInterface for Tui {
         }));
 
         enable_mouse_support(&mut self.stdout);
+        enable_bracketed_paste(&mut self.stdout);
         enable_alternate_screen(&mut self.stdout);
 
         // SAFETY: we only register our signal handler once
@@ -462,15 +476,21 @@ impl<'a> ColIter<'a> {
 
         let (w_lnum, _) = b.sign_col_dims();
         let rng = if is_focus { self.load_exec_range } else { None };
+        let search_matches = match &self.layout.search_highlights {
+            Some(hl) if hl.id == b.id => hl.matches.as_slice(),
+            _ => &[],
+        };
 
         Some(WinIter {
             y: 0,
+            file_row: b.visible_line(w.view.row_off),
             w_lnum,
             n_cols: self.n_cols,
             b,
             w,
             cs: self.cs,
             load_exec_range: rng,
+            search_matches,
         })
     }
 }
@@ -501,12 +521,14 @@ impl Iterator for ColIter<'_> {
 
 struct WinIter<'a> {
     y: usize,
+    file_row: usize,
     w_lnum: usize,
     n_cols: usize,
     b: &'a Buffer,
     w: &'a Window,
     cs: &'a ColorScheme,
     load_exec_range: Option<(bool, Range)>,
+    search_matches: &'a [(usize, usize)],
 }
 
 impl Iterator for WinIter<'_> {
@@ -516,7 +538,11 @@ impl Iterator for WinIter<'_> {
         if self.y >= self.w.n_rows {
             return None;
         }
-        let file_row = self.y + self.w.view.row_off;
+        // Folds are rendered as a single row so the next row to render depends on whether or
+        // not the current one is folded
+        let file_row = self.file_row;
+        let fold = self.b.fold_containing(file_row);
+        self.file_row = fold.map(|(_, last)| last).unwrap_or(file_row) + 1;
         self.y += 1;
 
         let line = if file_row >= self.b.len_lines() {
@@ -534,22 +560,26 @@ impl Iterator for WinIter<'_> {
         } else {
             // +2 for the leading space and vline chars
             let padding = self.w_lnum + 2;
+            let summary = match fold {
+                Some((first, last)) => format!(" ··· {} lines ", last - first + 1),
+                None => String::new(),
+            };
+            let n_summary = summary.chars().count();
+            let (summary, n_cols) = if padding + n_summary < self.n_cols {
+                (summary, self.n_cols - n_summary)
+            } else {
+                (String::new(), self.n_cols)
+            };
 
             format!(
-                "{}{} {:>width$}{VLINE}{}{}",
+                "{}{} {:>width$}{VLINE}{}{}{}{summary}{}",
                 Style::Fg(self.cs.signcol_fg),
                 Style::Bg(self.cs.bg),
                 file_row + 1,
                 Style::Fg(self.cs.fg),
-                styled_rline_unchecked(
-                    self.b,
-                    &self.w.view,
-                    file_row,
-                    padding,
-                    self.n_cols,
-                    self.load_exec_range,
-                    self.cs
-                ),
+                self.styled_rline_unchecked(file_row, padding, n_cols),
+                Style::Fg(self.cs.comment),
+                Style::Fg(self.cs.fg),
                 width = self.w_lnum
             )
         };
@@ -558,6 +588,100 @@ impl Iterator for WinIter<'_> {
     }
 }
 
+impl WinIter<'_> {
+    /// The render representation of a given line, truncated to fit within the
+    /// available screen space.
+    /// This includes tab expansion and any styling that might be applied but not
+    /// trailing \r\n or screen clearing escape codes.
+    fn styled_rline_unchecked(&self, y: usize, lpad: usize, screen_cols: usize) -> String {
+        let (b, cs) = (self.b, self.cs);
+        let map_line_range = |lr| match lr {
+            // LineRange is an inclusive range so we need to insert after `end` if its
+            // not the end of the line
+            LineRange::Partial { start, end, .. } => (start, end + 1),
+            LineRange::FromStart { end, .. } => (0, end + 1),
+            LineRange::ToEnd { start, .. } => (start, usize::MAX),
+            LineRange::Full { .. } => (0, usize::MAX),
+        };
+
+        let dot_range = b.dot.line_range(y, b).map(map_line_range);
+        let load_exec_range = match self.load_exec_range {
+            Some((is_load, rng)) if !b.dot.contains_range(&rng) => {
+                rng.line_range(y, b).map(|lr| (is_load, map_line_range(lr)))
+            }
+            _ => None,
+        };
+
+        // Dot, the load/exec range and any search matches on this line are mapped to the
+        // rendered line together so that it only needs to be rendered once
+        let mut ranges: Vec<_> = dot_range
+            .into_iter()
+            .chain(load_exec_range.map(|(_, lr)| lr))
+            .collect();
+        let n_fixed = ranges.len();
+
+        if !self.search_matches.is_empty() {
+            let line_start = b.txt.line_to_char(y);
+            let line_end = line_start + b.txt.line_len_chars(y);
+            let i = self
+                .search_matches
+                .partition_point(|&(_, end)| end <= line_start);
+
+            for &(start, end) in &self.search_matches[i..] {
+                if start >= line_end {
+                    break;
+                }
+                ranges.push((start.saturating_sub(line_start), end - line_start));
+            }
+        }
+
+        let (rline, mut ranges) =
+            raw_rline_unchecked(b, &self.w.view, y, lpad, screen_cols, &ranges);
+        let rline = rline.replace("\x1b", char::REPLACEMENT_CHARACTER.to_string().as_str());
+        let search_ranges = ranges.split_off(n_fixed);
+        let mut ranges = ranges.into_iter();
+        let dot_range = dot_range.and_then(|_| ranges.next().flatten());
+        let load_exec_range = load_exec_range
+            .and_then(|(is_load, _)| ranges.next().flatten().map(|lr| (is_load, lr)));
+
+        let mut raw_tks = Tokens::Single(Token {
+            ty: TokenType::Default,
+            s: &rline,
+        });
+
+        // Search matches are highlighted first so that dot is always shown on top of them
+        for (start, end) in search_ranges.into_iter().flatten() {
+            raw_tks = Tokens::Multi(raw_tks.with_highlighted_dot(start, end, TokenType::Search));
+        }
+
+        let mut tks = match dot_range {
+            Some((start, end)) => raw_tks.with_highlighted_dot(start, end, TokenType::Dot),
+            None => match raw_tks {
+                Tokens::Single(tk) => vec![tk],
+                Tokens::Multi(tks) => tks,
+            },
+        };
+
+        if let Some((is_load, (start, end))) = load_exec_range {
+            let ty = if is_load {
+                TokenType::Load
+            } else {
+                TokenType::Execute
+            };
+            tks = Tokens::Multi(tks).with_highlighted_dot(start, end, ty);
+        }
+
+        let mut buf = String::new();
+        for tk in tks.into_iter() {
+            buf.push_str(&tk.render(cs));
+        }
+
+        buf.push_str(&Style::Bg(cs.bg).to_string());
+
+        buf
+    }
+}
+
 fn render_pending(keys: &[Input]) -> String {
     let mut s = String::new();
     for k in keys {
@@ -602,49 +726,40 @@ fn num_cols(chars: &[char]) -> usize {
 /// available screen space.
 /// This includes tab expansion but not any styling that might be applied,
 /// trailing \r\n or screen clearing escape codes.
-/// If a dot range is provided then the character offsets used will be adjusted
-/// to account for expanded tab characters, returning None if self.col_off would
-/// mean that the requested range is not currently visible.
+/// Each of the provided character ranges within the line is mapped to the
+/// corresponding range of the rendered line to account for expanded tab
+/// characters, giving None for ranges that self.col_off means are not
+/// currently visible.
 fn raw_rline_unchecked(
     b: &Buffer,
     view: &View,
     y: usize,
     lpad: usize,
     screen_cols: usize,
-    dot_range: Option<(usize, usize)>,
-) -> (String, Option<(usize, usize)>) {
+    ranges: &[(usize, usize)],
+) -> (String, Vec<Option<(usize, usize)>>) {
     let max_cols = screen_cols - lpad;
     let tabstop = config_handle!().tabstop;
     let mut rline = Vec::with_capacity(max_cols);
+    // The offset within rline of each character in the line following col_off
+    let mut offsets = Vec::with_capacity(max_cols);
     // Iterating over characters not bytes as we need to account for multi-byte utf8
     let line = b.txt.line(y);
     let mut it = line.chars().skip(view.col_off);
 
-    let mut update_dot = dot_range.is_some();
-    let (mut start, mut end) = dot_range.unwrap_or_default();
-
-    if update_dot && view.col_off > end {
-        update_dot = false; // we're past the requested range
-    } else {
-        start = start.saturating_sub(view.col_off);
-        end = end.saturating_sub(view.col_off);
-    }
-
     let mut rline_cols = 0;
 
     while rline_cols <= max_cols {
         match it.next() {
             Some('\n') | None => break,
             Some('\t') => {
-                if rline.len() < start {
-                    start += tabstop - 1;
-                }
-                if rline.len() < end {
-                    end = end.saturating_add(tabstop - 1);
-                }
+                offsets.push(rline.len());
                 rline.append(&mut [' '].repeat(tabstop));
             }
-            Some(c) => rline.push(c),
+            Some(c) => {
+                offsets.push(rline.len());
+                rline.push(c);
+            }
         }
 
         rline_cols = num_cols(&rline);
@@ -670,81 +785,22 @@ fn raw_rline_unchecked(
     rline.append(&mut [' '].repeat(padding));
     let s = rline.into_iter().collect();
 
-    if update_dot {
-        start = min(start, n_chars);
-        end = min(end, n_chars);
-        (s, Some((start, end)))
-    } else {
-        (s, None)
-    }
-}
-
-/// The render representation of a given line, truncated to fit within the
-/// available screen space.
-/// This includes tab expansion and any styling that might be applied but not
-/// trailing \r\n or screen clearing escape codes.
-fn styled_rline_unchecked(
-    b: &Buffer,
-    view: &View,
-    y: usize,
-    lpad: usize,
-    screen_cols: usize,
-    load_exec_range: Option<(bool, Range)>,
-    cs: &ColorScheme,
-) -> String {
-    let map_line_range = |lr| match lr {
-        // LineRange is an inclusive range so we need to insert after `end` if its
-        // not the end of the line
-        LineRange::Partial { start, end, .. } => (start, end + 1),
-        LineRange::FromStart { end, .. } => (0, end + 1),
-        LineRange::ToEnd { start, .. } => (start, usize::MAX),
-        LineRange::Full { .. } => (0, usize::MAX),
+    let offset = |idx: usize| {
+        let i = idx.saturating_sub(view.col_off);
+        min(offsets.get(i).copied().unwrap_or(n_chars), n_chars)
     };
-
-    let dot_range = b.dot.line_range(y, b).map(map_line_range);
-    let (rline, dot_range) = raw_rline_unchecked(b, view, y, lpad, screen_cols, dot_range);
-    let rline = rline.replace("\x1b", char::REPLACEMENT_CHARACTER.to_string().as_str());
-
-    let raw_tks = Tokens::Single(Token {
-        ty: TokenType::Default,
-        s: &rline,
-    });
-
-    let mut tks = match dot_range {
-        Some((start, end)) => raw_tks.with_highlighted_dot(start, end, TokenType::Dot),
-        None => match raw_tks {
-            Tokens::Single(tk) => vec![tk],
-            Tokens::Multi(tks) => tks,
-        },
-    };
-
-    match load_exec_range {
-        Some((is_load, rng)) if !b.dot.contains_range(&rng) => {
-            if let Some(lr) = rng.line_range(y, b).map(map_line_range) {
-                if let (_, Some((start, end))) =
-                    raw_rline_unchecked(b, view, y, lpad, screen_cols, Some(lr))
-                {
-                    let ty = if is_load {
-                        TokenType::Load
-                    } else {
-                        TokenType::Execute
-                    };
-                    tks = Tokens::Multi(tks).with_highlighted_dot(start, end, ty);
-                }
+    let ranges = ranges
+        .iter()
+        .map(|&(start, end)| {
+            if view.col_off > end {
+                None // we're past the requested range
+            } else {
+                Some((offset(start), offset(end)))
             }
-        }
-
-        _ => (),
-    }
-
-    let mut buf = String::new();
-    for tk in tks.into_iter() {
-        buf.push_str(&tk.render(cs));
-    }
-
-    buf.push_str(&Style::Bg(cs.bg).to_string());
+        })
+        .collect();
 
-    buf
+    (s, ranges)
 }
 
 /// Spawn a thread to read from stdin and process user input to send Events to
@@ -753,8 +809,8 @@ fn spawn_input_thread(tx: Sender<Event>) -> JoinHandle<()> {
     let mut stdin = stdin();
 
     spawn(move || loop {
-        if let Some(key) = try_read_input(&mut stdin) {
-            _ = tx.send(Event::Input(key));
+        if let Some(event) = try_read_input(&mut stdin) {
+            _ = tx.send(event);
         } else if win_size_changed() {
             let (rows, cols) = get_termsize();
             _ = tx.send(Event::WinsizeChanged { rows, cols });
@@ -771,32 +827,49 @@ fn try_read_char(stdin: &mut Stdin) -> Option<char> {
     }
 }
 
-fn try_read_input(stdin: &mut Stdin) -> Option<Input> {
+fn try_read_input(stdin: &mut Stdin) -> Option<Event> {
     let c = try_read_char(stdin)?;
 
     // Normal key press
     match Input::from_char(c) {
         Input::Esc => (),
-        key => return Some(key),
+        key => return Some(Event::Input(key)),
     }
 
     let c2 = match try_read_char(stdin) {
         Some(c2) => c2,
-        None => return Some(Input::Esc),
+        None => return Some(Event::Input(Input::Esc)),
     };
     let c3 = match try_read_char(stdin) {
         Some(c3) => c3,
-        None => return Some(Input::try_from_seq2(c, c2).unwrap_or(Input::Esc)),
+        None => {
+            let key = Input::try_from_seq2(c, c2).unwrap_or(Input::Esc);
+            return Some(Event::Input(key));
+        }
     };
 
     if let Some(key) = Input::try_from_seq2(c2, c3) {
-        return Some(key);
+        return Some(Event::Input(key));
     }
 
     if c2 == '[' && c3.is_ascii_digit() {
-        if let Some('~') = try_read_char(stdin) {
-            if let Some(key) = Input::try_from_bracket_tilde(c3) {
-                return Some(key);
+        let mut code = String::from(c3);
+        loop {
+            match try_read_char(stdin) {
+                Some(c) if c.is_ascii_digit() => code.push(c),
+                Some('~') => break,
+                _ => return Some(Event::Input(Input::Esc)),
+            }
+        }
+
+        if code == PASTE_START {
+            return Some(Event::Paste(read_paste(stdin)));
+        }
+
+        let mut it = code.chars();
+        if let (Some(c), None) = (it.next(), it.next()) {
+            if let Some(key) = Input::try_from_bracket_tilde(c) {
+                return Some(Event::Input(key));
             }
         }
     }
@@ -820,10 +893,35 @@ fn try_read_input(stdin: &mut Stdin) -> Option<Input> {
         let nums: Vec<usize> = s.split(';').map(|s| s.parse::<usize>().unwrap()).collect();
         let (b, x, y) = (nums[0], nums[1], nums[2]);
 
-        return MouseEvent::try_from_raw(b, x, y, m).map(Input::Mouse);
+        return MouseEvent::try_from_raw(b, x, y, m).map(|m| Event::Input(Input::Mouse(m)));
+    }
+
+    Some(Event::Input(Input::Esc))
+}
+
+/// Read the text of a bracketed paste up until the "ESC [ 201 ~" sequence that marks its end.
+///
+/// Terminals send line breaks within pasted text as carriage returns so they are converted
+/// back to newlines.
+fn read_paste(stdin: &mut Stdin) -> String {
+    let mut buf = Vec::new();
+    let mut byte = [0; 1];
+
+    while !buf.ends_with(PASTE_END) {
+        match stdin.read(&mut byte) {
+            Ok(1) => buf.push(byte[0]),
+            Ok(_) => (), // timed out waiting for the rest of the paste
+            Err(_) => break,
+        }
     }
 
-    Some(Input::Esc)
+    if buf.ends_with(PASTE_END) {
+        buf.truncate(buf.len() - PASTE_END.len());
+    }
+
+    String::from_utf8_lossy(&buf)
+        .replace("\r\n", "\n")
+        .replace('\r', "\n")
 }
 
 #[cfg(test)]
@@ -851,9 +949,22 @@ mod tests {
         let mut view = View::new(0);
         view.col_off = col_off;
 
-        let (line, dot_range) = raw_rline_unchecked(&b, &view, 0, 0, 18, dot_range);
+        let (line, mut ranges) = raw_rline_unchecked(&b, &view, 0, 0, 18, dot_range.as_slice());
 
-        assert_eq!(dot_range, expected_dot_range);
+        assert_eq!(ranges.pop().flatten(), expected_dot_range);
         assert_eq!(line, expected_line);
     }
+
+    #[test]
+    fn raw_line_unchecked_maps_each_range() {
+        let b = Buffer::new_unnamed(0, "a\tb\tc");
+        let mut view = View::new(0);
+        view.col_off = 2;
+
+        let (line, ranges) =
+            raw_rline_unchecked(&b, &view, 0, 0, 18, &[(0, 1), (2, 3), (4, usize::MAX)]);
+
+        assert_eq!(line, "b    c            ");
+        assert_eq!(ranges, vec![None, Some((0, 1)), Some((5, 6))]);
+    }
 }
@@ -1,8 +1,13 @@
 //! Utility functions
 use crate::editor::built_in_commands;
 use std::{
+    env,
+    fs::{self, File, OpenOptions, Permissions},
+    io::{self, ErrorKind, Write},
     iter::Peekable,
-    path::Path,
+    os::unix::fs::{chown, MetadataExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    process,
     str::Chars,
     sync::{Arc, LockResult, RwLock, RwLockReadGuard},
 };
@@ -75,9 +80,358 @@ pub(crate) fn normalize_line_endings(mut s: String) -> String {
     s.replace("\r", "\n")
 }
 
+/// The path to a directory used by ad for storing its own state, e.g. "~/.ad/backup".
+pub(crate) fn ad_dir(name: &str) -> PathBuf {
+    let home = env::var("HOME").unwrap();
+    Path::new(&home).join(".ad").join(name)
+}
+
+/// The location of path when mirrored under root, e.g. "/home/me/foo.txt" under "/tmp/backup"
+/// becomes "/tmp/backup/home/me/foo.txt".
+pub(crate) fn mirrored_path(root: &Path, path: &Path) -> PathBuf {
+    root.join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Marker used in the names of the temporary files created by [atomic_write]
+const ATOMIC_WRITE_TMP_MARKER: &str = ".ad-tmp-";
+
+/// Whether or not path is a temporary file created by [atomic_write] (in this or any other ad
+/// process).
+pub(crate) fn is_atomic_write_tmp(path: &Path) -> bool {
+    let fname = match path.file_name().and_then(|f| f.to_str()) {
+        Some(fname) => fname,
+        None => return false,
+    };
+
+    match fname.rsplit_once(ATOMIC_WRITE_TMP_MARKER) {
+        Some((name, pid)) => {
+            name.starts_with('.') && !pid.is_empty() && pid.chars().all(|c| c.is_ascii_digit())
+        }
+        None => false,
+    }
+}
+
+/// Write contents to a temporary file alongside path and then rename it into place so that a
+/// failed write never leaves a partially written file behind.
+///
+/// Symlinks are followed so that the file they point to is replaced rather than the link
+/// itself, and the permissions and ownership of any existing file are preserved. Files with
+/// multiple hard links, and files in directories where the temporary file can not be created,
+/// are written in place instead.
+pub(crate) fn atomic_write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    write_file(path, contents, None)
+}
+
+/// The same as [atomic_write] but the file is only readable and writable by the current user,
+/// regardless of the permissions of any existing file.
+pub(crate) fn atomic_write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    write_file(path, contents, Some(0o600))
+}
+
+fn write_file(path: &Path, contents: &[u8], mode: Option<u32>) -> io::Result<()> {
+    let target = resolve_symlinks(path)?;
+    let meta = match target.metadata() {
+        Ok(meta) => Some(meta),
+        Err(e) if e.kind() == ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+    let mode = mode.or(meta.as_ref().map(|m| m.mode() & 0o7777));
+
+    // Renaming over a file with other hard links would detach it from them
+    if meta.as_ref().is_some_and(|m| m.nlink() > 1) {
+        return write_in_place(&target, contents, mode);
+    }
+
+    let fname = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(ErrorKind::InvalidInput, "path is not a file"))?;
+    let tmp = target.with_file_name(format!(
+        ".{}{ATOMIC_WRITE_TMP_MARKER}{}",
+        fname.to_string_lossy(),
+        process::id()
+    ));
+
+    let mut f = match OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode.unwrap_or(0o666))
+        .open(&tmp)
+    {
+        Ok(f) => f,
+        Err(e) => {
+            warn!(path=%target.display(), "unable to create temp file, writing in place: {e}");
+            return write_in_place(&target, contents, mode);
+        }
+    };
+
+    let res = (|| {
+        f.write_all(contents)?;
+        if let Some(mode) = mode {
+            // The mode given on creation is subject to the umask
+            f.set_permissions(Permissions::from_mode(mode))?;
+        }
+        if let Some(meta) = meta.as_ref() {
+            // Only root can give away ownership so failing to set the owner is not an error
+            if let Err(e) = chown(&tmp, Some(meta.uid()), Some(meta.gid())) {
+                warn!(path=%target.display(), "unable to preserve file ownership: {e}");
+            }
+        }
+        f.sync_all()?;
+
+        fs::rename(&tmp, &target)
+    })();
+
+    if res.is_err() {
+        _ = fs::remove_file(&tmp);
+        return res;
+    }
+
+    // Make sure that the rename itself is persisted
+    match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => File::open(dir)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+fn write_in_place(path: &Path, contents: &[u8], mode: Option<u32>) -> io::Result<()> {
+    let mut f = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(mode.unwrap_or(0o666))
+        .open(path)?;
+    f.write_all(contents)?;
+    if let Some(mode) = mode {
+        f.set_permissions(Permissions::from_mode(mode))?;
+    }
+
+    f.sync_all()
+}
+
+/// Follow any symlinks at the end of path (including dangling ones) to the path that they
+/// ultimately point to.
+fn resolve_symlinks(path: &Path) -> io::Result<PathBuf> {
+    // The same limit on the number of links followed as used by Linux
+    const MAX_LINKS: usize = 40;
+    let mut path = path.to_path_buf();
+
+    for _ in 0..MAX_LINKS {
+        match path.symlink_metadata() {
+            Ok(m) if m.file_type().is_symlink() => {
+                let link = fs::read_link(&path)?;
+                path = match path.parent() {
+                    Some(dir) => dir.join(link),
+                    None => link,
+                };
+            }
+            Ok(_) => return Ok(path),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(path),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(io::Error::new(
+        ErrorKind::InvalidInput,
+        "too many levels of symbolic links",
+    ))
+}
+
 /// Locate the first parent directory containing a target file
 pub(crate) fn parent_dir_containing<'a>(initial: &'a Path, target: &str) -> Option<&'a Path> {
     initial
         .ancestors()
         .find(|&p| p.is_dir() && p.join(target).exists())
 }
+
+/// A uniquely named path in the system temp directory for use in tests. Whatever is created
+/// at the path is removed when this is dropped.
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct TempPath(PathBuf);
+
+#[cfg(test)]
+impl TempPath {
+    pub(crate) fn new(name: &str) -> Self {
+        static NEXT: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = NEXT.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        Self(env::temp_dir().join(format!("ad-test-{name}-{}-{n}", process::id())))
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempPath {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl AsRef<Path> for TempPath {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if self.0.is_dir() {
+            _ = fs::remove_dir_all(&self.0);
+        } else {
+            _ = fs::remove_file(&self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use simple_test_case::test_case;
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("ad-util-test-{name}-{}", process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    #[test]
+    fn mirrored_path_works() {
+        let p = mirrored_path(Path::new("/tmp/backup"), Path::new("/home/me/foo.txt"));
+        assert_eq!(p, Path::new("/tmp/backup/home/me/foo.txt"));
+    }
+
+    #[test_case("/tmp/.foo.txt.ad-tmp-1234", true; "temp file")]
+    #[test_case("/tmp/foo.txt", false; "regular file")]
+    #[test_case("/tmp/foo.ad-tmp-1234", false; "not hidden")]
+    #[test_case("/tmp/.foo.ad-tmp-", false; "missing pid")]
+    #[test_case("/tmp/.foo.ad-tmp-12a", false; "invalid pid")]
+    #[test]
+    fn is_atomic_write_tmp_works(path: &str, expected: bool) {
+        assert_eq!(is_atomic_write_tmp(Path::new(path)), expected);
+    }
+
+    #[test]
+    fn atomic_write_creates_new_files() {
+        let dir = temp_dir("new");
+        let path = dir.join("new.txt");
+        atomic_write(&path, b"hello").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"hello");
+        assert_eq!(
+            fs::read_dir(&dir).unwrap().count(),
+            1,
+            "temp file left behind"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn atomic_write_preserves_permissions() {
+        let dir = temp_dir("perms");
+        let path = dir.join("script.sh");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o750)).unwrap();
+
+        atomic_write(&path, b"new").unwrap();
+        let mode = path.metadata().unwrap().permissions().mode();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(mode & 0o777, 0o750);
+    }
+
+    #[test]
+    fn atomic_write_private_restricts_permissions() {
+        let dir = temp_dir("private");
+        let new = dir.join("new.txt");
+        let existing = dir.join("existing.txt");
+        fs::write(&existing, "old").unwrap();
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o644)).unwrap();
+
+        atomic_write_private(&new, b"new").unwrap();
+        atomic_write_private(&existing, b"new").unwrap();
+        let new_mode = new.metadata().unwrap().permissions().mode();
+        let existing_mode = existing.metadata().unwrap().permissions().mode();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(new_mode & 0o777, 0o600);
+        assert_eq!(existing_mode & 0o777, 0o600);
+    }
+
+    #[test]
+    fn atomic_write_follows_symlinks() {
+        let dir = temp_dir("symlink");
+        let target = dir.join("target.txt");
+        let link = dir.join("link.txt");
+        fs::write(&target, "old").unwrap();
+        symlink(&target, &link).unwrap();
+
+        atomic_write(&link, b"new").unwrap();
+        let is_link = link.symlink_metadata().unwrap().file_type().is_symlink();
+        let content = fs::read_to_string(&target).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(is_link, "symlink was replaced");
+        assert_eq!(content, "new");
+    }
+
+    #[test]
+    fn atomic_write_follows_dangling_symlinks() {
+        let dir = temp_dir("dangling");
+        let link = dir.join("link.txt");
+        symlink("target.txt", &link).unwrap();
+
+        atomic_write(&link, b"new").unwrap();
+        let is_link = link.symlink_metadata().unwrap().file_type().is_symlink();
+        let content = fs::read_to_string(dir.join("target.txt")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(is_link, "symlink was replaced");
+        assert_eq!(content, "new");
+    }
+
+    #[test]
+    fn atomic_write_preserves_hard_links() {
+        let dir = temp_dir("hardlink");
+        let path = dir.join("a.txt");
+        let other = dir.join("b.txt");
+        fs::write(&path, "old").unwrap();
+        fs::hard_link(&path, &other).unwrap();
+
+        atomic_write(&path, b"new").unwrap();
+        let content = fs::read_to_string(&other).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(content, "new");
+    }
+
+    #[test]
+    fn atomic_write_falls_back_to_writing_in_place() {
+        let dir = temp_dir("in-place");
+        let path = dir.join("file.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        let ino = path.metadata().unwrap().ino();
+
+        // Block the temp file from being created
+        let tmp = dir.join(format!(
+            ".file.txt{ATOMIC_WRITE_TMP_MARKER}{}",
+            process::id()
+        ));
+        fs::create_dir(&tmp).unwrap();
+
+        atomic_write(&path, b"new").unwrap();
+        let meta = path.metadata().unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(content, "new");
+        assert_eq!(meta.ino(), ino);
+        assert_eq!(meta.permissions().mode() & 0o777, 0o640);
+    }
+}
@@ -0,0 +1,344 @@
+//! Watching open files and directories for changes made outside of the editor.
+//!
+//! Rather than watching files directly we watch the directories containing them: most
+//! editors and tools (including ad itself) save files by renaming a new file into place,
+//! which would remove a watch on the original file. Events for entries that are not of
+//! interest (including the temporary files used when saving) are filtered out on the watcher
+//! thread so that they never reach the editor.
+use crate::{input::Event, util::is_atomic_write_tmp};
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::{CString, OsStr},
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread::{spawn, JoinHandle},
+};
+use tracing::{debug, error, warn};
+
+const WATCH_MASK: u32 = libc::IN_CLOSE_WRITE
+    | libc::IN_MOVED_TO
+    | libc::IN_MOVED_FROM
+    | libc::IN_CREATE
+    | libc::IN_DELETE;
+/// The fixed size portion of an inotify_event: wd, mask, cookie and len
+const EVENT_HEADER_LEN: usize = 16;
+const BUF_LEN: usize = 64 * 1024;
+
+/// The paths that the editor currently has open.
+#[derive(Debug, Default)]
+struct State {
+    /// Watch descriptors along with the directory they are watching
+    wds: HashMap<i32, PathBuf>,
+    /// Files open in a buffer
+    files: HashSet<PathBuf>,
+    /// Directories open in a buffer
+    dirs: HashSet<PathBuf>,
+}
+
+impl State {
+    /// Whether or not a change to path should be reported to the editor.
+    fn is_relevant(&self, path: &Path) -> bool {
+        if is_atomic_write_tmp(path) {
+            return false;
+        }
+
+        self.files.contains(path) || path.parent().is_some_and(|p| self.dirs.contains(p))
+    }
+}
+
+/// A handle to a background thread that notifies the editor of changes to open files and
+/// directories using inotify.
+///
+/// Dropping the Watcher stops the background thread and closes the inotify instance.
+#[derive(Debug)]
+pub(crate) struct Watcher {
+    fd: i32,
+    /// An eventfd used to tell the background thread to exit
+    stop_fd: i32,
+    state: Arc<Mutex<State>>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Watcher {
+    /// Spawn the watcher thread which will send [Event::PathChanged] events on tx.
+    pub(crate) fn spawn(tx: Sender<Event>) -> io::Result<Self> {
+        // SAFETY: inotify_init1 has no memory safety requirements
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: eventfd has no memory safety requirements
+        let stop_fd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if stop_fd == -1 {
+            let err = io::Error::last_os_error();
+            // SAFETY: fd is a valid file descriptor that is not used elsewhere
+            unsafe { libc::close(fd) };
+            return Err(err);
+        }
+
+        let state: Arc<Mutex<State>> = Default::default();
+        let thread_state = state.clone();
+
+        let handle = spawn(move || {
+            let mut buf = vec![0u8; BUF_LEN];
+            loop {
+                match wait_for_events(fd, stop_fd) {
+                    Ok(true) => (),
+                    Ok(false) => return,
+                    Err(err) => {
+                        error!("unable to poll for inotify events: {err}");
+                        return;
+                    }
+                }
+
+                // SAFETY: buf is valid for writes of up to BUF_LEN bytes
+                let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, BUF_LEN) };
+                if n == -1 {
+                    let err = io::Error::last_os_error();
+                    if err.kind() == io::ErrorKind::Interrupted {
+                        continue;
+                    }
+                    error!("unable to read inotify events: {err}");
+                    return;
+                }
+
+                let paths = {
+                    let state = thread_state.lock().unwrap();
+                    parse_events(&buf[..n as usize])
+                        .into_iter()
+                        .flat_map(|(wd, name)| Some(state.wds.get(&wd)?.join(name)))
+                        .filter(|p| state.is_relevant(p))
+                        .collect::<HashSet<_>>()
+                };
+
+                for path in paths {
+                    debug!(path=%path.display(), "path changed on disk");
+                    if tx.send(Event::PathChanged { path }).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Ok(Self {
+            fd,
+            stop_fd,
+            state,
+            handle: Some(handle),
+        })
+    }
+
+    /// Update the set of files and directories being watched, returning early if they are
+    /// unchanged.
+    pub(crate) fn set_watched(&self, files: HashSet<PathBuf>, dirs: HashSet<PathBuf>) {
+        let mut state = self.state.lock().unwrap();
+        if state.files == files && state.dirs == dirs {
+            return;
+        }
+
+        let wanted: HashSet<&Path> = files
+            .iter()
+            .flat_map(|p| p.parent())
+            .chain(dirs.iter().map(|p| p.as_path()))
+            .collect();
+
+        state.wds.retain(|&wd, dir| {
+            if wanted.contains(dir.as_path()) {
+                return true;
+            }
+            // SAFETY: inotify_rm_watch has no memory safety requirements
+            unsafe { libc::inotify_rm_watch(self.fd, wd) };
+            false
+        });
+
+        let watched: HashSet<PathBuf> = state.wds.values().cloned().collect();
+        for dir in wanted.into_iter().filter(|d| !watched.contains(*d)) {
+            match add_watch(self.fd, dir) {
+                Ok(wd) => _ = state.wds.insert(wd, dir.to_path_buf()),
+                Err(e) => warn!(dir=%dir.display(), "unable to watch directory: {e}"),
+            }
+        }
+
+        state.files = files;
+        state.dirs = dirs;
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        let one = 1u64.to_ne_bytes();
+        // SAFETY: one is valid for reads of 8 bytes which is the size eventfd expects
+        unsafe { libc::write(self.stop_fd, one.as_ptr() as *const libc::c_void, one.len()) };
+        if let Some(handle) = self.handle.take() {
+            _ = handle.join();
+        }
+
+        // SAFETY: the background thread has exited so nothing else is using either fd
+        unsafe {
+            libc::close(self.fd);
+            libc::close(self.stop_fd);
+        }
+    }
+}
+
+/// Block until there are inotify events to read from fd (returning true) or the watcher has
+/// been asked to stop via stop_fd (returning false).
+fn wait_for_events(fd: i32, stop_fd: i32) -> io::Result<bool> {
+    let mut fds = [
+        libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: stop_fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    loop {
+        // SAFETY: fds is valid for the number of entries passed
+        let n = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) };
+        if n == -1 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+
+        return Ok(fds[1].revents == 0);
+    }
+}
+
+fn add_watch(fd: i32, dir: &Path) -> io::Result<i32> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    // SAFETY: path is a valid nul terminated string
+    let wd = unsafe { libc::inotify_add_watch(fd, path.as_ptr(), WATCH_MASK) };
+    if wd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(wd)
+}
+
+/// Parse the watch descriptor and file name from each of the inotify_event structs in buf.
+fn parse_events(buf: &[u8]) -> Vec<(i32, &OsStr)> {
+    let mut events = Vec::new();
+    let mut i = 0;
+
+    while i + EVENT_HEADER_LEN <= buf.len() {
+        let wd = i32::from_ne_bytes(buf[i..i + 4].try_into().unwrap());
+        let len = u32::from_ne_bytes(buf[i + 12..i + 16].try_into().unwrap()) as usize;
+        let start = i + EVENT_HEADER_LEN;
+        let end = (start + len).min(buf.len());
+
+        // The name is padded with nul bytes to align the following event
+        let name = &buf[start..end];
+        let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(name.len())];
+        if !name.is_empty() {
+            events.push((wd, OsStr::from_bytes(name)));
+        }
+
+        i = end;
+    }
+
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{
+        env, fs, process,
+        sync::mpsc::{channel, TryRecvError},
+        time::Duration,
+    };
+
+    fn raw_event(wd: i32, name: &str) -> Vec<u8> {
+        let mut name = name.as_bytes().to_vec();
+        name.resize(name.len().next_multiple_of(16), 0);
+
+        let mut buf = Vec::new();
+        buf.extend(wd.to_ne_bytes());
+        buf.extend(libc::IN_CLOSE_WRITE.to_ne_bytes());
+        buf.extend(0u32.to_ne_bytes());
+        buf.extend((name.len() as u32).to_ne_bytes());
+        buf.extend(name);
+
+        buf
+    }
+
+    #[test]
+    fn parse_events_works() {
+        let mut buf = raw_event(1, "foo.txt");
+        buf.extend(raw_event(2, "a-much-longer-file-name.rs"));
+
+        let events = parse_events(&buf);
+
+        assert_eq!(
+            events,
+            vec![
+                (1, OsStr::new("foo.txt")),
+                (2, OsStr::new("a-much-longer-file-name.rs"))
+            ]
+        );
+    }
+
+    #[test]
+    fn changes_to_watched_files_are_reported() {
+        let dir = env::temp_dir().join(format!("ad-watcher-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let (watched, ignored) = (dir.join("watched.txt"), dir.join("ignored.txt"));
+
+        let (tx, rx) = channel();
+        let w = Watcher::spawn(tx).unwrap();
+        w.set_watched(HashSet::from([watched.clone()]), HashSet::new());
+
+        fs::write(&ignored, "ignored").unwrap();
+        fs::write(&watched, "watched").unwrap();
+        let evt = rx.recv_timeout(Duration::from_secs(5));
+        fs::remove_dir_all(&dir).unwrap();
+
+        match evt {
+            Ok(Event::PathChanged { path }) => assert_eq!(path, watched),
+            evt => panic!("unexpected event: {evt:?}"),
+        }
+    }
+
+    #[test]
+    fn atomic_write_temp_files_are_ignored() {
+        let dir = env::temp_dir().join(format!("ad-watcher-tmp-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let (tmp, other) = (dir.join(".foo.txt.ad-tmp-123"), dir.join("other.txt"));
+
+        let (tx, rx) = channel();
+        let w = Watcher::spawn(tx).unwrap();
+        w.set_watched(HashSet::new(), HashSet::from([dir.clone()]));
+
+        fs::write(&tmp, "tmp").unwrap();
+        fs::write(&other, "other").unwrap();
+        let evt = rx.recv_timeout(Duration::from_secs(5));
+        fs::remove_dir_all(&dir).unwrap();
+
+        match evt {
+            Ok(Event::PathChanged { path }) => assert_eq!(path, other),
+            evt => panic!("unexpected event: {evt:?}"),
+        }
+    }
+
+    #[test]
+    fn dropping_the_watcher_stops_the_background_thread() {
+        let (tx, rx) = channel();
+        let w = Watcher::spawn(tx).unwrap();
+        drop(w);
+
+        // The thread holds the only other Sender so the channel disconnects once it exits
+        assert!(matches!(rx.try_recv(), Err(TryRecvError::Disconnected)));
+    }
+}
@@ -116,10 +116,7 @@ impl<T> ZipList<T> {
     {
         let mut it = iter.into_iter();
 
-        let focus = match it.next() {
-            Some(t) => t,
-            None => return None,
-        };
+        let focus = it.next()?;
 
         Some(Self {
             up: VecDeque::default(),